@@ -0,0 +1,259 @@
+//! Achievements earned by playing, kept separately for each key binding profile so
+//! players sharing a computer each have their own.
+
+use crate::game::{ClearKind, EventHandler, Game, GameEvent};
+use crate::paths;
+
+use anyhow::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{read_to_string, write};
+use std::path::Path;
+
+const ACHIEVEMENTS_FILE: &str = "achievements.toml";
+/// Tetrises or T-spins in a row for `Achievement::BackToBack`. Smaller clears between
+/// them break the run, while shapes which clear nothing don't.
+const BACK_TO_BACK_CLEARS: usize = 5;
+/// Shapes clearing lines in a row for `Achievement::Combo`.
+const COMBO_CLEARS: usize = 5;
+/// Lines to clear for `Achievement::Sprint`, within `SPRINT_MS`.
+const SPRINT_LINES: usize = 40;
+const SPRINT_MS: u64 = 60_000;
+/// Level to reach for `Achievement::LevelTen`.
+const LEVEL: usize = 10;
+/// Games to finish for `Achievement::Regular`.
+const GAMES: usize = 100;
+
+/// Something worth celebrating which a player can do once.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Achievement {
+    FirstTetris,
+    TSpin,
+    BackToBack,
+    Combo,
+    PerfectClear,
+    Sprint,
+    LevelTen,
+    Regular,
+}
+
+impl Achievement {
+    /// Every achievement, in the order they are listed.
+    pub const ALL: [Achievement; 8] = [
+        Achievement::FirstTetris,
+        Achievement::TSpin,
+        Achievement::BackToBack,
+        Achievement::Combo,
+        Achievement::PerfectClear,
+        Achievement::Sprint,
+        Achievement::LevelTen,
+        Achievement::Regular,
+    ];
+
+    /// Name shown when it's unlocked and on the trophy screen.
+    pub fn name(self) -> &'static str {
+        match self {
+            Achievement::FirstTetris => "First Tetris",
+            Achievement::TSpin => "Spin Doctor",
+            Achievement::BackToBack => "Back-to-Back x5",
+            Achievement::Combo => "Combo x5",
+            Achievement::PerfectClear => "Spotless",
+            Achievement::Sprint => "Sub-1:00 Sprint",
+            Achievement::LevelTen => "Double Digits",
+            Achievement::Regular => "Regular",
+        }
+    }
+
+    /// What to do to unlock it.
+    pub fn description(self) -> &'static str {
+        match self {
+            Achievement::FirstTetris => "Clear four lines at once",
+            Achievement::TSpin => "Clear lines with a T-spin",
+            Achievement::BackToBack => "5 tetrises or T-spins in a row",
+            Achievement::Combo => "Clear lines with 5 shapes in a row",
+            Achievement::PerfectClear => "Clear the whole board",
+            Achievement::Sprint => "40 lines in under a minute",
+            Achievement::LevelTen => "Reach level 10",
+            Achievement::Regular => "Finish 100 games",
+        }
+    }
+}
+
+/// One profile's achievements.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Progress {
+    /// Games finished, towards `Achievement::Regular`.
+    pub games: usize,
+    /// Achievements unlocked, in the order they were.
+    pub unlocked: Vec<Achievement>,
+}
+
+/// Every profile's achievements, by profile name.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Achievements {
+    profiles: BTreeMap<String, Progress>,
+}
+
+impl Achievements {
+    /// Loads the achievements file, which is empty if it is missing or invalid.
+    pub fn load() -> Self {
+        Self::load_from(&paths::data_path(ACHIEVEMENTS_FILE))
+    }
+
+    /// Loads achievements from a file written by `save_to`, or returns none if it is
+    /// missing or invalid.
+    ///
+    /// # Parameters
+    ///
+    /// - `path`: Path of the file.
+    pub fn load_from(path: &Path) -> Self {
+        read_to_string(path)
+            .ok()
+            .and_then(|s| toml::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    /// Writes the achievements file.
+    pub fn save(&self) -> Result<()> {
+        self.save_to(&paths::data_path(ACHIEVEMENTS_FILE))
+    }
+
+    /// Writes the achievements to a file, which `load_from` can read back.
+    ///
+    /// # Parameters
+    ///
+    /// - `path`: Path of the file, which is replaced if it exists.
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Returns a profile's achievements, which are empty for a profile which hasn't
+    /// finished a game or unlocked anything yet.
+    ///
+    /// # Parameters
+    ///
+    /// - `profile`: Name of the profile.
+    pub fn progress(&self, profile: &str) -> Progress {
+        self.profiles.get(profile).cloned().unwrap_or_default()
+    }
+
+    /// Unlocks an achievement for a profile.
+    ///
+    /// # Parameters
+    ///
+    /// - `profile`: Name of the profile.
+    /// - `achievement`: The achievement earned.
+    ///
+    /// # Return Value
+    ///
+    /// `true` if the profile hadn't unlocked it before.
+    pub fn unlock(&mut self, profile: &str, achievement: Achievement) -> bool {
+        let progress = self.profiles.entry(profile.to_string()).or_default();
+        if progress.unlocked.contains(&achievement) {
+            return false;
+        }
+        progress.unlocked.push(achievement);
+        true
+    }
+
+    /// Counts a finished game for a profile, unlocking `Achievement::Regular` once
+    /// enough have been finished.
+    ///
+    /// # Parameters
+    ///
+    /// - `profile`: Name of the profile.
+    ///
+    /// # Return Value
+    ///
+    /// `Achievement::Regular` if this game unlocked it.
+    pub fn record_game(&mut self, profile: &str) -> Option<Achievement> {
+        let progress = self.profiles.entry(profile.to_string()).or_default();
+        progress.games += 1;
+        if progress.games >= GAMES && self.unlock(profile, Achievement::Regular) {
+            Some(Achievement::Regular)
+        } else {
+            None
+        }
+    }
+}
+
+/// Watches one game for achievements earned during it.
+#[derive(Debug, Clone, Default)]
+pub struct Tracker {
+    /// Tetrises and T-spins since the last smaller clear.
+    back_to_back: usize,
+    /// Achievements earned so far this game.
+    earned: Vec<Achievement>,
+    /// Achievements earned since the last `take`.
+    new: Vec<Achievement>,
+}
+
+impl Tracker {
+    /// Creates a new `Tracker` for a game which hasn't started.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn earn(&mut self, achievement: Achievement) {
+        if !self.earned.contains(&achievement) {
+            self.earned.push(achievement);
+            self.new.push(achievement);
+        }
+    }
+
+    /// Checks for achievements which depend on how long the game has taken. Should be
+    /// called after the game's events have been dispatched.
+    ///
+    /// # Parameters
+    ///
+    /// - `game`: The game being watched.
+    pub fn update(&mut self, game: &Game) {
+        if game.get_lines() >= SPRINT_LINES && game.time_ms() < SPRINT_MS {
+            self.earn(Achievement::Sprint);
+        }
+    }
+
+    /// Returns the achievements earned since the last call, each only once a game.
+    pub fn take(&mut self) -> Vec<Achievement> {
+        std::mem::take(&mut self.new)
+    }
+}
+
+impl EventHandler for Tracker {
+    fn handle_event(&mut self, event: &GameEvent) {
+        match *event {
+            GameEvent::LinesCleared {
+                kind,
+                t_spin,
+                combo,
+                perfect_clear,
+                ..
+            } => {
+                if kind == ClearKind::Tetris {
+                    self.earn(Achievement::FirstTetris);
+                }
+                if t_spin {
+                    self.earn(Achievement::TSpin);
+                }
+                if kind == ClearKind::Tetris || t_spin {
+                    self.back_to_back += 1;
+                    if self.back_to_back >= BACK_TO_BACK_CLEARS {
+                        self.earn(Achievement::BackToBack);
+                    }
+                } else {
+                    self.back_to_back = 0;
+                }
+                if combo + 1 >= COMBO_CLEARS {
+                    self.earn(Achievement::Combo);
+                }
+                if perfect_clear {
+                    self.earn(Achievement::PerfectClear);
+                }
+            }
+            GameEvent::LevelUp(level) if level >= LEVEL => self.earn(Achievement::LevelTen),
+            _ => {}
+        }
+    }
+}
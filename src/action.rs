@@ -0,0 +1,37 @@
+/// Something the player can do to the current shape.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    SoftDrop,
+    HardDrop,
+    RotateCw,
+    RotateCcw,
+    Hold,
+}
+
+impl Action {
+    /// Every action, in the order they are listed on the controls screen.
+    pub const ALL: [Action; 7] = [
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::SoftDrop,
+        Action::HardDrop,
+        Action::RotateCcw,
+        Action::RotateCw,
+        Action::Hold,
+    ];
+
+    /// Description shown on the controls screen.
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::MoveLeft => "Move tetromino left",
+            Action::MoveRight => "Move tetromino right",
+            Action::SoftDrop => "Soft drop",
+            Action::HardDrop => "Hard drop",
+            Action::RotateCw => "Rotate tetromino clockwise",
+            Action::RotateCcw => "Rotate tetromino counterclockwise",
+            Action::Hold => "Hold tetromino",
+        }
+    }
+}
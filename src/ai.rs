@@ -0,0 +1,643 @@
+//! Computer players, which decide where each shape goes and then play the moves to get
+//! it there, like a player pressing keys. The built-in bot places each shape where a
+//! heuristic rates the board best, and others can be plugged in through the `Bot`
+//! trait.
+
+use crate::action::Action;
+use crate::game::{EventHandler, Game, GameEvent};
+use crate::gameboard::Gameboard;
+use crate::tetromino::{Tetromino, TetrominoKind};
+use crate::{GAMEBOARD_HEIGHT, GAMEBOARD_HIDDEN_ROWS, GAMEBOARD_WIDTH};
+
+use anyhow::*;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{read_to_string, write};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Which cells of the board hold blocks, row by row from the top.
+pub type Grid = [[bool; GAMEBOARD_WIDTH]; GAMEBOARD_HEIGHT];
+
+/// File the weights found by `wgputris train` are kept in, in the config directory.
+pub const WEIGHTS_FILE: &str = "weights.toml";
+
+/// Weights the built-in bot plays with, if trained ones have been loaded.
+static TRAINED: Mutex<Option<Weights>> = Mutex::new(None);
+
+/// How strongly each feature of a board counts towards rating it. Features which make
+/// a board worse have negative weights.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Weights {
+    /// Per row of the columns' heights added together.
+    pub height: f64,
+    /// Per row cleared on the way to the board.
+    pub lines: f64,
+    /// Per empty cell with a block somewhere above it.
+    pub holes: f64,
+    /// Per row of difference between the heights of neighbouring columns.
+    pub bumpiness: f64,
+}
+
+impl Default for Weights {
+    /// Weights tuned by Yiyuan Lee's genetic algorithm, which clear lines steadily
+    /// without ever setting up a Tetris.
+    fn default() -> Self {
+        Self {
+            height: -0.510066,
+            lines: 0.760666,
+            holes: -0.35663,
+            bumpiness: -0.184483,
+        }
+    }
+}
+
+impl Weights {
+    /// Loads weights written by `save`.
+    ///
+    /// # Parameters
+    ///
+    /// - `path`: The weights file.
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(toml::from_str(&read_to_string(path)?)?)
+    }
+
+    /// Writes the weights to a file.
+    ///
+    /// # Parameters
+    ///
+    /// - `path`: The weights file.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Returns the weights the built-in bot plays with: those passed to `use_trained`,
+    /// or the defaults.
+    pub fn trained() -> Self {
+        let trained = TRAINED.lock().unwrap_or_else(|e| e.into_inner());
+        trained.unwrap_or_default()
+    }
+
+    /// Makes the built-in bot play with these weights from now on, e.g. ones found by
+    /// training.
+    pub fn use_trained(self) {
+        *TRAINED.lock().unwrap_or_else(|e| e.into_inner()) = Some(self);
+    }
+
+    /// Rates a board, higher being better.
+    ///
+    /// # Parameters
+    ///
+    /// - `grid`: The board after placing shapes.
+    /// - `lines`: Rows cleared while placing them.
+    fn rate(&self, grid: &Grid, lines: usize) -> f64 {
+        let mut heights = [0; GAMEBOARD_WIDTH];
+        let mut holes = 0;
+        for (x, height) in heights.iter_mut().enumerate() {
+            let top = (0..GAMEBOARD_HEIGHT).find(|&y| grid[y][x]);
+            if let Some(top) = top {
+                *height = GAMEBOARD_HEIGHT - top;
+                holes += (top..GAMEBOARD_HEIGHT).filter(|&y| !grid[y][x]).count();
+            }
+        }
+        let height: usize = heights.iter().sum();
+        let bumpiness: usize = heights
+            .windows(2)
+            .map(|pair| (pair[0] as i32 - pair[1] as i32).unsigned_abs() as usize)
+            .sum();
+        self.height * height as f64
+            + self.lines * lines as f64
+            + self.holes * holes as f64
+            + self.bumpiness * bumpiness as f64
+    }
+}
+
+/// How well the computer plays.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    /// Every difficulty, easiest first.
+    pub const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard];
+
+    /// Name shown in menus.
+    pub fn label(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Normal => "Normal",
+            Difficulty::Hard => "Hard",
+        }
+    }
+
+    /// How many shapes ahead the computer plans: the current shape alone, or the next
+    /// one too.
+    pub fn depth(self) -> usize {
+        match self {
+            Difficulty::Easy => 1,
+            Difficulty::Normal | Difficulty::Hard => 2,
+        }
+    }
+
+    /// Milliseconds of game time the computer waits after a shape spawns before its
+    /// first move, as a person would take to react.
+    pub fn reaction_ms(self) -> u64 {
+        match self {
+            Difficulty::Easy => 800,
+            Difficulty::Normal => 350,
+            Difficulty::Hard => 120,
+        }
+    }
+
+    /// Milliseconds of game time between the computer's moves.
+    pub fn move_ms(self) -> u64 {
+        match self {
+            Difficulty::Easy => 160,
+            Difficulty::Normal => 80,
+            Difficulty::Hard => 30,
+        }
+    }
+}
+
+/// Where to put the current shape, as the moves which get it there from where it
+/// spawns.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Placement {
+    /// Whether to swap the current shape for the held one first.
+    pub hold: bool,
+    /// Quarter turns clockwise from the spawn orientation, from 0 to 3.
+    pub rotation: u8,
+    /// Columns to move, negative to the left.
+    pub shift: i32,
+}
+
+impl Placement {
+    /// Returns the actions which make the placement, ending with a hard drop. Three
+    /// quarter turns are made as one turn the other way.
+    pub fn actions(&self) -> Vec<Action> {
+        let mut actions = Vec::new();
+        if self.hold {
+            actions.push(Action::Hold);
+        }
+        match self.rotation {
+            0 => {}
+            3 => actions.push(Action::RotateCcw),
+            turns => actions.extend((0..turns).map(|_| Action::RotateCw)),
+        }
+        let step = if self.shift < 0 {
+            Action::MoveLeft
+        } else {
+            Action::MoveRight
+        };
+        actions.extend((0..self.shift.abs()).map(|_| step));
+        actions.push(Action::HardDrop);
+        actions
+    }
+}
+
+/// A place a shape can be dropped, and the board it leaves.
+struct Landing {
+    rotation: u8,
+    shift: i32,
+    /// Cells the shape locked in, sorted.
+    cells: [(i32, i32); 4],
+    grid: Grid,
+    lines: usize,
+}
+
+/// Returns `true` if a shape fits on the board, where cells in the hidden rows are
+/// always empty.
+fn fits(grid: &Grid, shape: &Tetromino) -> bool {
+    shape.board_locs().iter().all(|&(x, y)| {
+        (0..GAMEBOARD_WIDTH as i32).contains(&x)
+            && (-(GAMEBOARD_HIDDEN_ROWS as i32)..GAMEBOARD_HEIGHT as i32).contains(&y)
+            && (y < 0 || !grid[y as usize][x as usize])
+    })
+}
+
+/// Returns every place a shape can reach by turning where it spawns, sliding sideways
+/// and dropping, with the board left after each. Places which would lock the shape in
+/// the hidden rows, and so top out, are left out.
+///
+/// # Parameters
+///
+/// - `grid`: The board.
+/// - `kind`: The shape to place.
+fn landings(grid: &Grid, kind: TetrominoKind) -> Vec<Landing> {
+    let (spawn_x, spawn_y) = Gameboard::new().get_spawn_loc();
+    let mut landings = Vec::new();
+    let rotations = if kind == TetrominoKind::O { 1 } else { 4 };
+    for rotation in 0..rotations {
+        let mut shape = Tetromino::new(kind);
+        shape.set_pos(spawn_x, spawn_y);
+        // Turned the same way as `Placement::actions` turns it, with no kicks
+        let turned = match rotation {
+            0 => true,
+            3 => {
+                shape.rotate_ccw();
+                fits(grid, &shape)
+            }
+            _ => (0..rotation).all(|_| {
+                shape.rotate_cw();
+                fits(grid, &shape)
+            }),
+        };
+        if !turned || !fits(grid, &shape) {
+            continue;
+        }
+        landings.extend(land(grid, shape, rotation, 0));
+        for &direction in &[-1, 1] {
+            let mut slid = shape;
+            let mut shift = 0;
+            loop {
+                slid.add_pos(direction, 0);
+                if !fits(grid, &slid) {
+                    break;
+                }
+                shift += direction;
+                landings.extend(land(grid, slid, rotation, shift));
+            }
+        }
+    }
+    landings
+}
+
+/// Drops a shape straight down and locks it, clearing any full rows.
+fn land(grid: &Grid, mut shape: Tetromino, rotation: u8, shift: i32) -> Option<Landing> {
+    loop {
+        shape.add_pos(0, 1);
+        if !fits(grid, &shape) {
+            shape.add_pos(0, -1);
+            break;
+        }
+    }
+    let mut cells = shape.board_locs();
+    cells.sort_unstable();
+    let mut grid = *grid;
+    for &(x, y) in cells.iter() {
+        if y < 0 {
+            return None;
+        }
+        grid[y as usize][x as usize] = true;
+    }
+    let kept: Vec<[bool; GAMEBOARD_WIDTH]> = grid
+        .iter()
+        .copied()
+        .filter(|row| !row.iter().all(|&cell| cell))
+        .collect();
+    let lines = GAMEBOARD_HEIGHT - kept.len();
+    let mut cleared = [[false; GAMEBOARD_WIDTH]; GAMEBOARD_HEIGHT];
+    cleared[lines..].copy_from_slice(&kept);
+    Some(Landing {
+        rotation,
+        shift,
+        cells,
+        grid: cleared,
+        lines,
+    })
+}
+
+/// Returns the placement which locks a shape in the given cells, if it can get there
+/// by turning, sliding and dropping.
+///
+/// # Parameters
+///
+/// - `board`: The board.
+/// - `kind`: The shape to place.
+/// - `hold`: Whether the shape is the one swapped in from the hold slot.
+/// - `cells`: Cells of the board the shape should lock in.
+pub fn placement_for(
+    board: &Grid,
+    kind: TetrominoKind,
+    hold: bool,
+    mut cells: [(i32, i32); 4],
+) -> Option<Placement> {
+    cells.sort_unstable();
+    landings(board, kind)
+        .into_iter()
+        .find(|landing| landing.cells == cells)
+        .map(|landing| Placement {
+            hold,
+            rotation: landing.rotation,
+            shift: landing.shift,
+        })
+}
+
+/// Returns the best rating of a board reachable by placing each shape in turn, or
+/// `None` if one of them can't be placed without topping out.
+fn best_rating(
+    grid: &Grid,
+    kinds: &[TetrominoKind],
+    lines: usize,
+    weights: &Weights,
+) -> Option<f64> {
+    match kinds.split_first() {
+        None => Some(weights.rate(grid, lines)),
+        Some((&kind, rest)) => landings(grid, kind)
+            .iter()
+            .filter_map(|landing| best_rating(&landing.grid, rest, lines + landing.lines, weights))
+            .max_by(|a, b| a.total_cmp(b)),
+    }
+}
+
+/// What a bot is shown when a shape spawns: everything a player can see on screen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BotView {
+    /// Which cells of the board hold locked blocks, row by row from the top.
+    pub board: Grid,
+    /// The shape to place, at its spawn position and orientation.
+    pub current: TetrominoKind,
+    /// Shapes to come after it, soonest first.
+    pub queue: Vec<TetrominoKind>,
+    /// The held shape, if any.
+    pub hold: Option<TetrominoKind>,
+    /// Whether the current shape may still be swapped into the hold slot.
+    pub can_hold: bool,
+}
+
+impl BotView {
+    /// Returns what a game shows of itself.
+    ///
+    /// # Parameters
+    ///
+    /// - `game`: The game the bot plays.
+    pub fn of(game: &Game) -> Self {
+        Self {
+            board: game.occupied(),
+            current: game.current_kind(),
+            queue: vec![game.next_kind()],
+            hold: game.hold_kind(),
+            can_hold: game.can_hold(),
+        }
+    }
+}
+
+/// What a bot decided to do with the current shape.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    /// Put it in a place, making the moves of `Placement::actions`.
+    Place(Placement),
+    /// Make these moves, one after another, e.g. to slide under an overhang. Anything
+    /// left unmade when the shape locks is dropped.
+    Actions(Vec<Action>),
+}
+
+/// Something which decides where shapes go, which a `Cpu` plays games with.
+///
+/// Bots from other crates can be plugged in with `register_bot`, and are then offered
+/// alongside the built-in one wherever a computer player is picked.
+pub trait Bot {
+    /// Decides what to do with the current shape, once it has spawned.
+    ///
+    /// # Parameters
+    ///
+    /// - `view`: The board and shapes in play.
+    fn decide(&mut self, view: &BotView) -> Decision;
+}
+
+/// Creates a bot for a difficulty, which bots may ignore.
+pub type BotFactory = Box<dyn Fn(Difficulty) -> Box<dyn Bot> + Send>;
+
+/// Name of the built-in bot, `Heuristic`.
+pub const HEURISTIC_BOT: &str = "Heuristic";
+
+/// Bots registered with `register_bot`, by name.
+static BOTS: Mutex<Vec<(String, BotFactory)>> = Mutex::new(Vec::new());
+
+/// Adds a bot to those a computer player can be picked from, replacing any registered
+/// under the same name. Call it before the game starts.
+///
+/// # Parameters
+///
+/// - `name`: Name the bot is listed and saved under.
+/// - `factory`: Creates the bot for each game it plays.
+pub fn register_bot(name: &str, factory: impl Fn(Difficulty) -> Box<dyn Bot> + Send + 'static) {
+    let mut bots = BOTS.lock().unwrap_or_else(|e| e.into_inner());
+    bots.retain(|(registered, _)| registered != name);
+    bots.push((name.to_string(), Box::new(factory)));
+}
+
+/// Returns the name of every bot which can be picked, the built-in one first.
+pub fn bot_names() -> Vec<String> {
+    let bots = BOTS.lock().unwrap_or_else(|e| e.into_inner());
+    std::iter::once(HEURISTIC_BOT.to_string())
+        .chain(bots.iter().map(|(name, _)| name.clone()))
+        .collect()
+}
+
+/// Creates the bot registered under a name, or the built-in one if there is none.
+///
+/// # Parameters
+///
+/// - `name`: Name the bot was registered under.
+/// - `difficulty`: How well it should play.
+pub fn create_bot(name: &str, difficulty: Difficulty) -> Box<dyn Bot> {
+    let bots = BOTS.lock().unwrap_or_else(|e| e.into_inner());
+    match bots.iter().find(|(registered, _)| *registered == name) {
+        Some((_, factory)) => factory(difficulty),
+        None => Box::new(Heuristic::new(Weights::trained(), difficulty.depth())),
+    }
+}
+
+/// The built-in bot, which tries every place it can reach for the current shape, and
+/// optionally the next, and picks the one leaving the best rated board.
+#[derive(Debug, Clone)]
+pub struct Heuristic {
+    pub weights: Weights,
+    /// How many shapes ahead to plan, 1 for the current shape alone.
+    pub depth: usize,
+}
+
+impl Heuristic {
+    /// Creates a new `Heuristic` bot.
+    ///
+    /// # Parameters
+    ///
+    /// - `weights`: How boards are rated.
+    /// - `depth`: How many shapes ahead to plan, 1 for the current shape alone.
+    pub fn new(weights: Weights, depth: usize) -> Self {
+        Self { weights, depth }
+    }
+}
+
+impl Bot for Heuristic {
+    fn decide(&mut self, view: &BotView) -> Decision {
+        match plan(view, &self.weights, self.depth) {
+            Some(placement) => Decision::Place(placement),
+            // Every place tops out, so it may as well be quick about it
+            None => Decision::Actions(vec![Action::HardDrop]),
+        }
+    }
+}
+
+/// Finds the best place for the current shape, looking at the held and upcoming
+/// shapes too.
+///
+/// # Parameters
+///
+/// - `view`: The board and shapes in play.
+/// - `weights`: How boards are rated.
+/// - `depth`: How many shapes ahead to plan, 1 for the current shape alone.
+///
+/// # Return Value
+///
+/// The placement, or `None` if every place for the shape tops out.
+pub fn plan(view: &BotView, weights: &Weights, depth: usize) -> Option<Placement> {
+    let depth = depth.max(1);
+    let mut options = vec![(false, vec![view.current])];
+    if view.can_hold {
+        match view.hold {
+            Some(held) => options.push((true, vec![held])),
+            // Holding with nothing held brings the first shape of the queue in
+            None if !view.queue.is_empty() => options.push((true, Vec::new())),
+            None => {}
+        }
+    }
+    let mut best: Option<(f64, Placement)> = None;
+    for (hold, mut kinds) in options {
+        let queue = if hold && view.hold.is_none() {
+            &view.queue[..]
+        } else {
+            kinds.extend(view.queue.iter().copied());
+            &kinds[..]
+        };
+        let kinds = &queue[..depth.min(queue.len())];
+        for landing in landings(&view.board, kinds[0]) {
+            let rating = match best_rating(&landing.grid, &kinds[1..], landing.lines, weights) {
+                Some(rating) => rating,
+                None => continue,
+            };
+            if best.is_none_or(|(best, _)| rating > best) {
+                best = Some((
+                    rating,
+                    Placement {
+                        hold,
+                        rotation: landing.rotation,
+                        shift: landing.shift,
+                    },
+                ));
+            }
+        }
+    }
+    best.map(|(_, placement)| placement)
+}
+
+/// A computer player, which plays a game by its moves alone, so its game is recorded
+/// and replayed like anyone else's. Its bot decides where shapes go, while its
+/// difficulty sets how quickly it moves.
+///
+/// Its game's events have to be passed to it, so it knows when each shape locks.
+pub struct Cpu {
+    name: String,
+    bot: Box<dyn Bot>,
+    difficulty: Difficulty,
+    /// Moves still to make for the current shape.
+    actions: VecDeque<Action>,
+    /// Whether the bot has decided what to do with the current shape.
+    planned: bool,
+    /// Game time the next move is due, once the current shape has been seen.
+    due_ms: Option<u64>,
+}
+
+impl Cpu {
+    /// Creates a new `Cpu` playing with the built-in bot.
+    ///
+    /// # Parameters
+    ///
+    /// - `difficulty`: How well it plays.
+    pub fn new(difficulty: Difficulty) -> Self {
+        Self::named(HEURISTIC_BOT, difficulty)
+    }
+
+    /// Creates a new `Cpu` playing with a bot registered with `register_bot`, or the
+    /// built-in one if there is none by that name.
+    ///
+    /// # Parameters
+    ///
+    /// - `name`: Name the bot was registered under.
+    /// - `difficulty`: How well it plays.
+    pub fn named(name: &str, difficulty: Difficulty) -> Self {
+        let name = if bot_names().iter().any(|registered| registered == name) {
+            name
+        } else {
+            HEURISTIC_BOT
+        };
+        Self::with_bot(name, create_bot(name, difficulty), difficulty)
+    }
+
+    /// Creates a new `Cpu` playing with any bot.
+    ///
+    /// # Parameters
+    ///
+    /// - `name`: Name of the bot, shown to players.
+    /// - `bot`: Decides where shapes go.
+    /// - `difficulty`: How quickly it moves.
+    pub fn with_bot(name: &str, bot: Box<dyn Bot>, difficulty: Difficulty) -> Self {
+        Self {
+            name: name.to_string(),
+            bot,
+            difficulty,
+            actions: VecDeque::new(),
+            planned: false,
+            due_ms: None,
+        }
+    }
+
+    /// Returns the name of the bot playing.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns how well the computer plays.
+    pub fn difficulty(&self) -> Difficulty {
+        self.difficulty
+    }
+
+    /// Makes every move due by the game's current time. Called each frame before the
+    /// game is advanced.
+    ///
+    /// # Parameters
+    ///
+    /// - `game`: The computer's game.
+    pub fn update(&mut self, game: &mut Game) {
+        if game.game_over {
+            return;
+        }
+        let now = game.time_ms();
+        let reaction_ms = self.difficulty.reaction_ms();
+        let mut due_ms = *self.due_ms.get_or_insert(now + reaction_ms);
+        while due_ms <= now {
+            if !self.planned {
+                let actions = match self.bot.decide(&BotView::of(game)) {
+                    Decision::Place(placement) => placement.actions(),
+                    Decision::Actions(actions) => actions,
+                };
+                self.actions = actions.into();
+                self.planned = true;
+            }
+            match self.actions.pop_front() {
+                Some(action) => game.process_action(action),
+                None => break,
+            }
+            due_ms += self.difficulty.move_ms();
+        }
+        self.due_ms = Some(due_ms);
+    }
+}
+
+impl EventHandler for Cpu {
+    fn handle_event(&mut self, event: &GameEvent) {
+        if let GameEvent::PieceLocked { .. } = event {
+            // Shapes may lock by gravity before every move is made, and the rest
+            // would be wasted on the next one
+            self.actions.clear();
+            self.planned = false;
+            self.due_ms = None;
+        }
+    }
+}
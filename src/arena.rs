@@ -0,0 +1,471 @@
+//! Boards and what is drawn over them as entities in an ECS world, so a match is put
+//! together from components instead of being written out for each mode. A board is an
+//! entity carrying its game, with components for where its garbage goes and whether
+//! it celebrates, and may be played by a computer player added with `add_computer`.
+//! Confetti, glows and HUD widgets are entities of their own, which point at the board
+//! they belong to.
+//!
+//! What happens within a board, where its shape falls and what it lands on, stays in
+//! `Game`, which replays and netplay check input by input. The systems here decide what
+//! each game is told and when it steps, what passes between boards, and what is drawn.
+
+use crate::ai::Cpu;
+use crate::attack::Attack;
+use crate::celebration::{self, Confetti, CELEBRATION_VERTICES, CONFETTI_MS, GLOW_MS};
+use crate::game::{ClearKind, EventHandler, Game, GameEvent};
+use crate::replay::Input;
+use crate::stats::Stats;
+use crate::{Vertex, VERTEX_COUNT};
+
+use bevy_ecs::prelude::*;
+use bytemuck::Zeroable;
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::collections::HashMap;
+
+/// A board in play, and the totals of its game.
+#[derive(Component)]
+pub struct Board {
+    pub game: Game,
+    pub stats: Stats,
+}
+
+/// Number of blocks a board is drawn right of the usual position, as passed to
+/// `Game::render`. Boards are extracted from left to right.
+#[derive(Component, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Shift(pub i32);
+
+/// Inputs for a board's game, e.g. from a keyboard or over the network, applied in
+/// order on the next `Arena::update`.
+#[derive(Component, Debug, Default)]
+pub struct Controls {
+    pub inputs: Vec<Input>,
+}
+
+/// Computer players, by the board each plays. Bots don't have to be `Send`, so these
+/// are kept as non-send data on the thread which updates the arena, instead of as
+/// components.
+#[derive(Default)]
+struct Computers(HashMap<Entity, Cpu>);
+
+/// Sends the garbage a board's clears make to another board.
+#[derive(Component, Debug)]
+pub struct Garbage {
+    pub attack: Attack,
+    /// The board the garbage goes to.
+    pub target: Entity,
+}
+
+/// Throws confetti and a glow over a board for its tetrises and perfect clears. Only
+/// the latest is shown, so a new one starts over.
+#[derive(Component, Debug, Copy, Clone, Default)]
+pub struct Celebrates;
+
+/// Something drawn over a board until it has faded away, when it is despawned. Timed
+/// in the board's game time, like a `Celebration`.
+#[derive(Component, Debug)]
+pub struct Particle {
+    /// The board it is drawn over.
+    pub board: Entity,
+    /// Game time it appeared.
+    pub started_ms: u64,
+    pub kind: ParticleKind,
+}
+
+/// What a particle looks like.
+#[derive(Debug, Clone)]
+pub enum ParticleKind {
+    /// A piece of confetti, which falls for `CONFETTI_MS`. Not drawn with reduced
+    /// motion.
+    Confetti(Confetti),
+    /// A glow around the board's border for `GLOW_MS`, gold for a perfect clear.
+    Glow { perfect_clear: bool },
+}
+
+impl ParticleKind {
+    /// Returns how long the particle is shown for, in milliseconds of game time.
+    fn lifetime_ms(&self) -> u64 {
+        match self {
+            ParticleKind::Confetti(_) => CONFETTI_MS,
+            ParticleKind::Glow { .. } => GLOW_MS,
+        }
+    }
+}
+
+/// A line of text shown beside a board, below its previews.
+#[derive(Component, Debug)]
+pub struct Widget {
+    /// The board it is shown beside.
+    pub board: Entity,
+    /// Lines are shown in order of `row`, top first.
+    pub row: usize,
+    pub kind: WidgetKind,
+}
+
+/// What a widget shows.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WidgetKind {
+    /// The same text all the time.
+    Text(String),
+    /// Rows of garbage the board has sent. Hidden unless it sends garbage.
+    Sent,
+    /// How fast the board's game runs. Hidden while it runs in real time.
+    Speed,
+}
+
+impl WidgetKind {
+    /// Returns the widget's text, or `None` if it is hidden.
+    ///
+    /// # Parameters
+    ///
+    /// - `game`: The game of the widget's board.
+    /// - `garbage`: Where the board's garbage goes, if it sends any.
+    fn text(&self, game: &Game, garbage: Option<&Garbage>) -> Option<String> {
+        match self {
+            WidgetKind::Text(text) => Some(text.clone()),
+            WidgetKind::Sent => garbage.map(|garbage| format!("Sent: {}", garbage.attack.sent())),
+            WidgetKind::Speed if game.speed() != 1.0 => Some(format!("Speed: {}x", game.speed())),
+            WidgetKind::Speed => None,
+        }
+    }
+}
+
+/// What one board looks like, as of the last `Arena::update`.
+#[derive(Debug, Clone)]
+pub struct BoardFrame {
+    pub board: Entity,
+    pub shift: i32,
+    /// The board, as filled in by `Game::render`, `VERTEX_COUNT` long.
+    pub vertices: Vec<Vertex>,
+    /// The particles over the board, `CELEBRATION_VERTICES` long.
+    pub effects: Vec<Vertex>,
+    /// Text of the board's widgets, top first.
+    pub hud: Vec<String>,
+}
+
+/// Every board as extracted for drawing, from left to right.
+#[derive(Resource, Default)]
+struct Frame(Vec<BoardFrame>);
+
+/// How boards are extracted for drawing.
+#[derive(Resource)]
+struct View {
+    /// Number of upcoming shapes shown, as passed to `Game::render`.
+    previews: usize,
+    /// `false` for reduced motion, which leaves out animations and confetti.
+    animate: bool,
+}
+
+/// Picks the hole in each batch of garbage and throws confetti, seeded so an arena
+/// plays out the same way every time.
+#[derive(Resource)]
+struct Rolls(StdRng);
+
+/// A world of boards, and the systems which play them a frame at a time.
+pub struct Arena {
+    world: World,
+    /// Plays a frame of every board, then extracts them for drawing.
+    schedule: Schedule,
+}
+
+impl Arena {
+    /// Creates a new `Arena`, with no boards yet.
+    ///
+    /// # Parameters
+    ///
+    /// - `seed`: Seed for where garbage holes are and how confetti flies.
+    pub fn new(seed: u64) -> Self {
+        let mut world = World::new();
+        world.insert_resource(Rolls(StdRng::seed_from_u64(seed)));
+        world.insert_resource(View {
+            previews: 1,
+            animate: true,
+        });
+        world.init_resource::<Frame>();
+        world.init_non_send_resource::<Computers>();
+        let mut schedule = Schedule::default();
+        schedule.add_systems(
+            (
+                apply_controls,
+                play_computers,
+                step_games,
+                dispatch_events,
+                send_garbage,
+                expire_particles,
+                extract_boards,
+            )
+                .chain(),
+        );
+        Self { world, schedule }
+    }
+
+    /// Adds a board, and returns its entity for adding more components to with
+    /// `world_mut`.
+    ///
+    /// # Parameters
+    ///
+    /// - `game`: The board's game.
+    /// - `shift`: Number of blocks the board is drawn right of the usual position.
+    pub fn spawn_board(&mut self, game: Game, shift: i32) -> Entity {
+        let board = Board {
+            game,
+            stats: Stats::new(),
+        };
+        self.world.spawn((board, Shift(shift))).id()
+    }
+
+    /// Lets a computer player play a board, in place of any which played it before.
+    ///
+    /// # Parameters
+    ///
+    /// - `board`: The board, as returned by `spawn_board`.
+    /// - `cpu`: The computer player.
+    pub fn add_computer(&mut self, board: Entity, cpu: Cpu) {
+        let mut computers = self.world.non_send_resource_mut::<Computers>();
+        computers.0.insert(board, cpu);
+    }
+
+    /// Returns a board added with `spawn_board`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `entity` isn't a board.
+    pub fn board(&self, entity: Entity) -> &Board {
+        self.world.get(entity).expect("not a board")
+    }
+
+    /// Returns a board added with `spawn_board`, to change.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `entity` isn't a board.
+    pub fn board_mut(&mut self, entity: Entity) -> &mut Board {
+        self.world
+            .get_mut::<Board>(entity)
+            .expect("not a board")
+            .into_inner()
+    }
+
+    /// Returns the world, to look at entities and components other than boards.
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    /// Returns the world, to add entities and components.
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.world
+    }
+
+    /// Sets how boards are extracted for drawing from the next `update` on.
+    ///
+    /// # Parameters
+    ///
+    /// - `previews`: Number of upcoming shapes shown, as passed to `Game::render`.
+    /// - `animate`: `false` for reduced motion, which leaves out animations and
+    ///   confetti.
+    pub fn set_view(&mut self, previews: usize, animate: bool) {
+        self.world.insert_resource(View { previews, animate });
+    }
+
+    /// Plays a frame of every board, and extracts them for drawing.
+    pub fn update(&mut self) {
+        self.schedule.run(&mut self.world);
+    }
+
+    /// Returns every board as extracted by the last `update`, from left to right.
+    pub fn frame(&self) -> &[BoardFrame] {
+        &self.world.resource::<Frame>().0
+    }
+}
+
+/// Notes the latest tetris or perfect clear in a board's events.
+#[derive(Default)]
+struct Cheer {
+    /// Whether the latest was a perfect clear, or `None` if there was neither.
+    perfect_clear: Option<bool>,
+}
+
+impl EventHandler for Cheer {
+    fn handle_event(&mut self, event: &GameEvent) {
+        if let GameEvent::LinesCleared {
+            kind,
+            perfect_clear,
+            ..
+        } = *event
+        {
+            if kind == ClearKind::Tetris || perfect_clear {
+                self.perfect_clear = Some(perfect_clear);
+            }
+        }
+    }
+}
+
+/// A board, and whatever its events are passed to besides its totals.
+type Listeners<'a> = (
+    Entity,
+    &'a mut Board,
+    Option<&'a mut Garbage>,
+    Has<Celebrates>,
+);
+
+/// Applies the inputs waiting for each board's game.
+fn apply_controls(mut boards: Query<(&mut Board, &mut Controls)>) {
+    for (mut board, mut controls) in boards.iter_mut() {
+        for input in controls.inputs.drain(..) {
+            board.game.apply_input(&input);
+        }
+    }
+}
+
+/// Lets computer players make their moves.
+fn play_computers(mut computers: NonSendMut<Computers>, mut boards: Query<&mut Board>) {
+    for (&entity, cpu) in computers.0.iter_mut() {
+        if let Ok(mut board) = boards.get_mut(entity) {
+            cpu.update(&mut board.game);
+        }
+    }
+}
+
+/// Moves every game on by the time since its last frame, so its shape falls, and
+/// locks once it lands.
+fn step_games(mut boards: Query<&mut Board>) {
+    for mut board in boards.iter_mut() {
+        board.game.process_game_loop();
+    }
+}
+
+/// Passes each board's events to its totals, computer player and garbage, and throws
+/// confetti over boards which celebrate.
+fn dispatch_events(
+    mut commands: Commands,
+    mut rolls: ResMut<Rolls>,
+    mut computers: NonSendMut<Computers>,
+    mut boards: Query<Listeners>,
+    particles: Query<(Entity, &Particle)>,
+) {
+    for (entity, mut board, mut garbage, celebrates) in boards.iter_mut() {
+        let Board { game, stats } = &mut *board;
+        let mut cheer = Cheer::default();
+        let mut handlers: Vec<&mut dyn EventHandler> = vec![stats, &mut cheer];
+        if let Some(cpu) = computers.0.get_mut(&entity) {
+            handlers.push(cpu);
+        }
+        if let Some(garbage) = &mut garbage {
+            handlers.push(&mut garbage.attack);
+        }
+        game.dispatch_events(&mut handlers);
+        let perfect_clear = match cheer.perfect_clear {
+            Some(perfect_clear) if celebrates => perfect_clear,
+            _ => continue,
+        };
+        for (particle, _) in particles.iter().filter(|(_, p)| p.board == entity) {
+            commands.entity(particle).despawn();
+        }
+        let started_ms = game.time_ms();
+        let glow = ParticleKind::Glow { perfect_clear };
+        let confetti = Confetti::throw(&mut rolls.0, perfect_clear);
+        for kind in std::iter::once(glow).chain(confetti.into_iter().map(ParticleKind::Confetti)) {
+            commands.spawn(Particle {
+                board: entity,
+                started_ms,
+                kind,
+            });
+        }
+    }
+}
+
+/// Adds the garbage each board sent to the board it targets.
+fn send_garbage(
+    mut rolls: ResMut<Rolls>,
+    mut senders: Query<&mut Garbage>,
+    mut boards: Query<&mut Board>,
+) {
+    for mut garbage in senders.iter_mut() {
+        let rows = garbage.attack.take();
+        if rows == 0 {
+            continue;
+        }
+        if let Ok(mut board) = boards.get_mut(garbage.target) {
+            // Each batch of garbage has its hole in a random column
+            board.game.add_garbage(rows, rolls.0.gen());
+        }
+    }
+}
+
+/// Despawns particles which have faded away, or whose board has gone or started a new
+/// game.
+fn expire_particles(
+    mut commands: Commands,
+    particles: Query<(Entity, &Particle)>,
+    boards: Query<&Board>,
+) {
+    for (entity, particle) in particles.iter() {
+        let expired = match boards.get(particle.board) {
+            Ok(board) => {
+                let time_ms = board.game.time_ms();
+                time_ms < particle.started_ms
+                    || time_ms - particle.started_ms >= particle.kind.lifetime_ms()
+            }
+            Err(_) => true,
+        };
+        if expired {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+/// Draws every board with its particles, and writes out its widgets.
+fn extract_boards(
+    view: Res<View>,
+    mut frame: ResMut<Frame>,
+    boards: Query<(Entity, &Board, &Shift, Option<&Garbage>)>,
+    particles: Query<&Particle>,
+    widgets: Query<&Widget>,
+) {
+    let mut extracted: Vec<BoardFrame> = boards
+        .iter()
+        .map(|(entity, board, &Shift(shift), garbage)| {
+            let game = &board.game;
+            let mut vertices = vec![Vertex::zeroed(); VERTEX_COUNT];
+            game.render(&mut vertices, shift, view.previews);
+            if view.animate {
+                game.animate_spawn(&mut vertices);
+                game.animate_rotation(&mut vertices);
+            }
+            let mut effects = vec![Vertex::zeroed(); CELEBRATION_VERTICES];
+            if !game.game_over {
+                let (glow, confetti) = effects.split_at_mut(celebration::GLOW_VERTICES);
+                let mut quads = confetti.chunks_mut(6);
+                for particle in particles.iter().filter(|p| p.board == entity) {
+                    let age_ms = game.time_ms() - particle.started_ms;
+                    match &particle.kind {
+                        ParticleKind::Glow { perfect_clear } => {
+                            celebration::render_glow(glow, shift, *perfect_clear, age_ms)
+                        }
+                        ParticleKind::Confetti(piece) if view.animate => {
+                            if let Some(quad) = quads.next() {
+                                piece.render(quad, shift, age_ms);
+                            }
+                        }
+                        ParticleKind::Confetti(_) => {}
+                    }
+                }
+            }
+            let mut lines: Vec<&Widget> = widgets.iter().filter(|w| w.board == entity).collect();
+            lines.sort_by_key(|widget| widget.row);
+            let hud = lines
+                .iter()
+                .filter_map(|widget| widget.kind.text(game, garbage))
+                .collect();
+            BoardFrame {
+                board: entity,
+                shift,
+                vertices,
+                effects,
+                hud,
+            }
+        })
+        .collect();
+    extracted.sort_by_key(|board| board.shift);
+    frame.0 = extracted;
+}
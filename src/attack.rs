@@ -0,0 +1,193 @@
+//! Garbage sent between the games of a versus match, and the tables of how much each
+//! kind of line clear sends.
+
+use crate::game::{EventHandler, GameEvent};
+
+use anyhow::*;
+use serde::{Deserialize, Serialize};
+use std::fs::read_to_string;
+use std::path::Path;
+
+/// File a custom attack table is loaded from, in the config directory.
+pub const ATTACK_FILE: &str = "attack.toml";
+/// The guideline attack table, as a file to load or copy.
+pub const GUIDELINE_TABLE: &str = include_str!("../assets/attack/guideline.toml");
+
+/// Which attack table versus matches are played with.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AttackPreset {
+    /// Garbage for clearing several rows at once, and nothing else.
+    Classic,
+    /// Extra garbage for T-spins, combos and perfect clears, as in most modern games.
+    Guideline,
+    /// The table in `ATTACK_FILE`.
+    Custom,
+}
+
+impl AttackPreset {
+    /// Every preset, in the order they are listed.
+    pub const ALL: [AttackPreset; 3] = [
+        AttackPreset::Classic,
+        AttackPreset::Guideline,
+        AttackPreset::Custom,
+    ];
+
+    /// Name shown in menus.
+    pub fn label(self) -> &'static str {
+        match self {
+            AttackPreset::Classic => "Classic",
+            AttackPreset::Guideline => "Guideline",
+            AttackPreset::Custom => "Custom",
+        }
+    }
+}
+
+/// Rows of garbage sent for each kind of line clear. Anything missing from a file
+/// is taken from the classic table.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AttackTable {
+    /// Rows sent for clearing 1, 2, 3 or 4 rows at once.
+    pub lines: [u32; 4],
+    /// Rows sent for a T-spin clearing 1, 2 or 3 rows, instead of `lines`.
+    pub t_spins: [u32; 3],
+    /// Rows added to the second clear in a row, the third and so on. The last entry
+    /// is used for longer runs.
+    pub combos: Vec<u32>,
+    /// Rows added to a clear which leaves the board empty.
+    pub perfect_clear: u32,
+}
+
+impl Default for AttackTable {
+    fn default() -> Self {
+        Self::classic()
+    }
+}
+
+impl AttackTable {
+    /// Returns the classic table, which sends one row for a double, two for a triple
+    /// and four for a tetris.
+    pub fn classic() -> Self {
+        Self {
+            lines: [0, 1, 2, 4],
+            t_spins: [0, 1, 2],
+            combos: Vec::new(),
+            perfect_clear: 0,
+        }
+    }
+
+    /// Returns the guideline table, from `GUIDELINE_TABLE`.
+    pub fn guideline() -> Self {
+        Self::parse(GUIDELINE_TABLE).expect("The guideline attack table is invalid")
+    }
+
+    /// Reads a table from the text of a file, such as `GUIDELINE_TABLE`.
+    ///
+    /// # Parameters
+    ///
+    /// - `text`: The table in TOML.
+    pub fn parse(text: &str) -> Result<Self> {
+        Ok(toml::from_str(text)?)
+    }
+
+    /// Loads a table from a file, e.g. `ATTACK_FILE`.
+    ///
+    /// # Parameters
+    ///
+    /// - `path`: The table file.
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::parse(&read_to_string(path)?)
+    }
+
+    /// Returns the rows of garbage a line clear sends.
+    ///
+    /// # Parameters
+    ///
+    /// - `n`: Number of rows cleared at once.
+    /// - `t_spin`: `true` if the shape was a T turned into place.
+    /// - `combo`: Number of clears in a row just before this one.
+    /// - `perfect_clear`: `true` if the clear left the board empty.
+    pub fn rows(&self, n: usize, t_spin: bool, combo: usize, perfect_clear: bool) -> u32 {
+        if n == 0 {
+            return 0;
+        }
+        let mut rows = if t_spin {
+            self.t_spins[n.min(self.t_spins.len()) - 1]
+        } else {
+            self.lines[n.min(self.lines.len()) - 1]
+        };
+        if combo > 0 {
+            rows += self
+                .combos
+                .get(combo - 1)
+                .or_else(|| self.combos.last())
+                .copied()
+                .unwrap_or(0);
+        }
+        if perfect_clear {
+            rows += self.perfect_clear;
+        }
+        rows
+    }
+}
+
+/// Counts the garbage one side of a versus match sends, from its game's events, for
+/// the other side's game to receive.
+#[derive(Debug, Clone)]
+pub struct Attack {
+    table: AttackTable,
+    /// Garbage sent, as a percentage of what `table` gives, e.g. from a handicap.
+    percent: u32,
+    /// Garbage earned but not yet taken, in hundredths of a row, so scaled attacks
+    /// carry parts of a row over to the next clear.
+    pending: u32,
+    /// Whole rows taken so far.
+    sent: usize,
+}
+
+impl Attack {
+    /// Creates a new `Attack` with nothing sent yet.
+    ///
+    /// # Parameters
+    ///
+    /// - `table`: Rows of garbage sent for each kind of line clear.
+    /// - `percent`: Garbage sent, as a percentage of what `table` gives.
+    pub fn new(table: AttackTable, percent: u32) -> Self {
+        Self {
+            table,
+            percent,
+            pending: 0,
+            sent: 0,
+        }
+    }
+
+    /// Returns the whole rows of garbage earned since the last call, for the other
+    /// side's game to receive, keeping any part of a row for later.
+    pub fn take(&mut self) -> usize {
+        let rows = self.pending / 100;
+        self.pending %= 100;
+        self.sent += rows as usize;
+        rows as usize
+    }
+
+    /// Returns the rows of garbage taken so far.
+    pub fn sent(&self) -> usize {
+        self.sent
+    }
+}
+
+impl EventHandler for Attack {
+    fn handle_event(&mut self, event: &GameEvent) {
+        if let GameEvent::LinesCleared {
+            n,
+            t_spin,
+            combo,
+            perfect_clear,
+            ..
+        } = *event
+        {
+            self.pending += self.table.rows(n, t_spin, combo, perfect_clear) * self.percent;
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use wgputris::ai::{Cpu, Difficulty};
+use wgputris::game::Game;
+use wgputris::stats::Stats;
+
+/// How long the title screen has to be left alone before a demo starts.
+pub const IDLE_SECS: f64 = 30.0;
+/// How much game time a demo lasts before going back to the title screen.
+const DEMO_MS: u64 = 90_000;
+
+/// A game played by the computer while nobody is playing, as arcade machines do
+/// between customers.
+pub struct Demo {
+    game: Game,
+    stats: Stats,
+    cpu: Cpu,
+}
+
+impl Demo {
+    /// Creates a new `Demo` with a fresh game of random shapes.
+    pub fn new() -> Self {
+        Self {
+            game: Game::new(),
+            stats: Stats::new(),
+            cpu: Cpu::new(Difficulty::Hard),
+        }
+    }
+
+    /// Makes the computer's moves and advances the game by a frame.
+    pub fn update(&mut self) {
+        self.cpu.update(&mut self.game);
+        self.game.process_game_loop();
+        self.game
+            .dispatch_events(&mut [&mut self.stats, &mut self.cpu]);
+    }
+
+    /// Returns `true` once the demo has shown enough, or the computer topped out.
+    pub fn is_finished(&self) -> bool {
+        self.game.game_over || self.game.time_ms() >= DEMO_MS
+    }
+
+    /// Returns the game being played.
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    /// Returns the totals of the game being played.
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+}
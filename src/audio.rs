@@ -0,0 +1,730 @@
+use crate::config::Config;
+use anyhow::*;
+use rand::seq::SliceRandom;
+use rodio::buffer::SamplesBuffer;
+use rodio::{OutputStream, OutputStreamHandle, Sample, Sink, Source};
+use std::fs::{read_dir, read_to_string, File};
+use std::io::{BufReader, Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use wgputris::game::{ClearKind, EventHandler, GameEvent};
+use wgputris::rhythm;
+use wgputris::tetromino::TetrominoKind;
+
+static TETRIS_SONG: &[u8] = include_bytes!("../assets/tetris.ogg");
+/// Tempo of the built-in song, which loops after exactly 24 bars.
+const TETRIS_SONG_BPM: f64 = 149.31;
+const SAMPLE_RATE: u32 = 48000;
+/// Directory searched for sound files replacing the built-in effects.
+const SOUND_PACK_DIR: &str = "sounds";
+/// Directory scanned for music tracks which replace the built-in song.
+const MUSIC_DIR: &str = "music";
+/// File extensions tried, in order, when looking for a sound in `SOUND_PACK_DIR`.
+const SOUND_EXTENSIONS: [&str; 2] = ["ogg", "wav"];
+/// Extension of a text file next to a track holding its tempo in beats per minute, for
+/// tracks without a BPM tag, e.g. WAV files.
+const BPM_EXTENSION: &str = "bpm";
+/// How much of the start of a track is searched for its BPM tag, in bytes.
+const TAG_SEARCH_BYTES: u64 = 64 * 1024;
+/// How much faster the music plays for each level gained.
+const TEMPO_PER_LEVEL: f32 = 0.05;
+/// Upper bound on the music speed so it stays listenable at high levels.
+const MAX_TEMPO: f32 = 1.5;
+/// Stack height in rows at which the tension layer starts to fade in.
+const TENSION_START_HEIGHT: usize = 10;
+/// Stack height in rows at which the tension layer reaches full volume.
+const TENSION_FULL_HEIGHT: usize = 16;
+/// Largest change in tension layer gain per sample, giving a fade of about a second.
+const TENSION_FADE_STEP: f32 = 1.0 / SAMPLE_RATE as f32;
+/// Stack height in rows at which the audio cue warning tone plays.
+const CUE_WARNING_HEIGHT: usize = 15;
+/// Length of the per-piece spawn tone in milliseconds.
+const CUE_MS: u32 = 80;
+/// One bar of the pulsing bass line layered over the music when the stack is high.
+const TENSION_NOTES: &[(f32, u32)] = &[
+    (110.0, 100),
+    (0.0, 50),
+    (110.0, 100),
+    (0.0, 50),
+    (155.6, 100),
+    (0.0, 50),
+    (110.0, 100),
+    (0.0, 50),
+];
+
+/// A short sound effect.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Sfx {
+    Move,
+    Rotate,
+    SoftLock,
+    HardDrop,
+    LineClear,
+    Tetris,
+    LevelUp,
+    Hold,
+    GameOver,
+}
+
+impl Sfx {
+    /// Every sound effect, in the order they are stored by `Audio`.
+    pub const ALL: [Sfx; 9] = [
+        Sfx::Move,
+        Sfx::Rotate,
+        Sfx::SoftLock,
+        Sfx::HardDrop,
+        Sfx::LineClear,
+        Sfx::Tetris,
+        Sfx::LevelUp,
+        Sfx::Hold,
+        Sfx::GameOver,
+    ];
+
+    /// File name, without extension, used to replace this effect from `SOUND_PACK_DIR`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Sfx::Move => "move",
+            Sfx::Rotate => "rotate",
+            Sfx::SoftLock => "soft_lock",
+            Sfx::HardDrop => "hard_drop",
+            Sfx::LineClear => "line_clear",
+            Sfx::Tetris => "tetris",
+            Sfx::LevelUp => "level_up",
+            Sfx::Hold => "hold",
+            Sfx::GameOver => "game_over",
+        }
+    }
+
+    /// The built-in tune for this effect as `(frequency in Hz, duration in ms)` notes.
+    /// A frequency of 0 is a rest.
+    fn notes(self) -> &'static [(f32, u32)] {
+        match self {
+            Sfx::Move => &[(440.0, 25)],
+            Sfx::Rotate => &[(660.0, 20), (880.0, 20)],
+            Sfx::SoftLock => &[(220.0, 40)],
+            Sfx::HardDrop => &[(330.0, 15), (110.0, 60)],
+            Sfx::LineClear => &[(523.0, 60), (659.0, 60), (784.0, 90)],
+            Sfx::Tetris => &[
+                (523.0, 60),
+                (659.0, 60),
+                (784.0, 60),
+                (1047.0, 60),
+                (0.0, 30),
+                (1047.0, 180),
+            ],
+            Sfx::LevelUp => &[(392.0, 80), (523.0, 80), (659.0, 80), (784.0, 160)],
+            Sfx::Hold => &[(587.0, 30), (440.0, 30)],
+            Sfx::GameOver => &[(392.0, 200), (330.0, 200), (262.0, 200), (196.0, 400)],
+        }
+    }
+}
+
+/// Decoded samples ready to be handed to the mixer.
+#[derive(Clone)]
+struct Sound {
+    channels: u16,
+    sample_rate: u32,
+    samples: Vec<f32>,
+}
+
+impl Sound {
+    /// Decodes a sound file fully into memory.
+    ///
+    /// # Parameters
+    ///
+    /// - `path`: Path to an OGG Vorbis or WAV file.
+    fn load(path: &Path) -> Result<Self> {
+        let decoder = rodio::Decoder::new(BufReader::new(File::open(path)?))?;
+        Ok(Self {
+            channels: decoder.channels(),
+            sample_rate: decoder.sample_rate(),
+            samples: decoder.convert_samples().collect(),
+        })
+    }
+
+    /// Loads the sound for an effect from `SOUND_PACK_DIR`, falling back to the
+    /// built-in tune if there is no replacement or it can't be decoded.
+    ///
+    /// # Parameters
+    ///
+    /// - `sfx`: The effect to load.
+    fn for_sfx(sfx: Sfx) -> Self {
+        for extension in SOUND_EXTENSIONS.iter() {
+            let path = Path::new(SOUND_PACK_DIR)
+                .join(sfx.name())
+                .with_extension(extension);
+            if !path.exists() {
+                continue;
+            }
+            match Sound::load(&path) {
+                Ok(sound) => return sound,
+                Err(e) => eprintln!("Failed to load {}: {}", path.display(), e),
+            }
+        }
+        Sound::synthesize(sfx.notes())
+    }
+
+    /// Renders a sequence of square wave notes, each with a linear decay.
+    ///
+    /// # Parameters
+    ///
+    /// - `notes`: `(frequency in Hz, duration in ms)` pairs; a frequency of 0 is a rest.
+    fn synthesize(notes: &[(f32, u32)]) -> Self {
+        let mut samples = Vec::new();
+        for &(freq, ms) in notes {
+            let len = SAMPLE_RATE * ms / 1000;
+            for n in 0..len {
+                let envelope = 1.0 - n as f32 / len as f32;
+                let phase = (freq * n as f32 / SAMPLE_RATE as f32).fract();
+                let value = if freq == 0.0 {
+                    0.0
+                } else if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                };
+                samples.push(value * envelope * 0.15);
+            }
+        }
+        Self {
+            channels: 1,
+            sample_rate: SAMPLE_RATE,
+            samples,
+        }
+    }
+}
+
+/// Wraps a source so its playback speed can be changed while it plays, by scaling the
+/// sample rate reported to the mixer. The speed factor is stored as `f32` bits.
+struct Tempo<I> {
+    input: I,
+    factor: Arc<AtomicU32>,
+}
+
+impl<I> Iterator for Tempo<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    type Item = I::Item;
+
+    #[inline]
+    fn next(&mut self) -> Option<I::Item> {
+        self.input.next()
+    }
+}
+
+impl<I> Source for Tempo<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        let factor = f32::from_bits(self.factor.load(Ordering::Relaxed));
+        (self.input.sample_rate() as f32 * factor) as u32
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Counts the samples a source has played, so the position of the music can be
+/// followed from the game, e.g. to keep rhythm mode on the beat.
+struct Position<I> {
+    input: I,
+    played: Arc<AtomicU64>,
+}
+
+impl<I> Iterator for Position<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    type Item = I::Item;
+
+    #[inline]
+    fn next(&mut self) -> Option<I::Item> {
+        let sample = self.input.next();
+        if sample.is_some() {
+            self.played.fetch_add(1, Ordering::Relaxed);
+        }
+        sample
+    }
+}
+
+impl<I> Source for Position<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
+
+/// Fades a source towards a target gain which can be changed while it plays. The
+/// target is stored as `f32` bits.
+struct Fader<I> {
+    input: I,
+    target: Arc<AtomicU32>,
+    gain: f32,
+}
+
+impl<I> Iterator for Fader<I>
+where
+    I: Source<Item = f32>,
+{
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        let target = f32::from_bits(self.target.load(Ordering::Relaxed));
+        self.gain += (target - self.gain).clamp(-TENSION_FADE_STEP, TENSION_FADE_STEP);
+        self.input.next().map(|sample| sample * self.gain)
+    }
+}
+
+impl<I> Source for Fader<I>
+where
+    I: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
+
+/// Pitch of the spawn tone for each piece when audio cues are enabled, rising through
+/// a pentatonic scale so neighbouring pieces are easy to tell apart.
+fn cue_pitch(kind: TetrominoKind) -> f32 {
+    match kind {
+        TetrominoKind::O => 262.0,
+        TetrominoKind::I => 294.0,
+        TetrominoKind::S => 330.0,
+        TetrominoKind::Z => 392.0,
+        TetrominoKind::L => 440.0,
+        TetrominoKind::J => 523.0,
+        TetrominoKind::T => 587.0,
+    }
+}
+
+/// Lists the music tracks in `MUSIC_DIR` in a random order.
+///
+/// # Return Value
+///
+/// Paths of every playable file, or an empty `Vec` if the directory doesn't exist.
+fn scan_music() -> Vec<PathBuf> {
+    let mut tracks: Vec<PathBuf> = match read_dir(MUSIC_DIR) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.extension()
+                    .and_then(|extension| extension.to_str())
+                    .is_some_and(|extension| SOUND_EXTENSIONS.contains(&extension))
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    tracks.shuffle(&mut rand::thread_rng());
+    tracks
+}
+
+/// Reads the tempo of a music track, from its BPM tag or else from a file of the same
+/// name with the `BPM_EXTENSION` extension.
+///
+/// # Parameters
+///
+/// - `path`: Path of the track.
+///
+/// # Return Value
+///
+/// The tempo in beats per minute, or `None` if the track doesn't say.
+fn track_bpm(path: &Path) -> Option<f64> {
+    let mut start = Vec::new();
+    File::open(path)
+        .and_then(|file| file.take(TAG_SEARCH_BYTES).read_to_end(&mut start))
+        .ok()?;
+    rhythm::vorbis_bpm(&start).or_else(|| {
+        read_to_string(path.with_extension(BPM_EXTENSION))
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    })
+}
+
+/// A piece of music which can be played.
+#[derive(Debug, Clone, PartialEq)]
+enum Track {
+    /// The song built into the game.
+    Builtin,
+    File(PathBuf),
+}
+
+/// Owns the audio output device, the background music and the sound effects.
+pub struct Audio {
+    _stream: OutputStream,
+    stream_handle: OutputStreamHandle,
+    music: Sink,
+    music_volume: f32,
+    music_tempo: Arc<AtomicU32>,
+    /// Samples of the current track played so far, over every channel.
+    music_played: Arc<AtomicU64>,
+    /// Samples of the current track played each second, over every channel.
+    music_rate: u32,
+    tracks: Vec<PathBuf>,
+    current_track: usize,
+    /// The track looped from its start for rhythm mode, or `None` while the playlist
+    /// plays on.
+    rhythm_track: Option<Track>,
+    tension: Sink,
+    tension_gain: Arc<AtomicU32>,
+    sounds: Vec<Sound>,
+    sfx_volume: f32,
+    audio_cues: bool,
+}
+
+impl Audio {
+    /// Opens the default output device and starts the background music.
+    ///
+    /// # Parameters
+    ///
+    /// - `config`: Config to take the initial volumes from.
+    pub fn new(config: &Config) -> Result<Self> {
+        let (stream, stream_handle) = OutputStream::try_default()?;
+        let music_tempo = Arc::new(AtomicU32::new(1.0f32.to_bits()));
+        let music = Sink::try_new(&stream_handle).map_err(|e| anyhow!("{:?}", e))?;
+
+        let tension_gain = Arc::new(AtomicU32::new(0.0f32.to_bits()));
+        let tension_bar = Sound::synthesize(TENSION_NOTES);
+        let tension_source = Fader {
+            input: Tempo {
+                input: SamplesBuffer::new(
+                    tension_bar.channels,
+                    tension_bar.sample_rate,
+                    tension_bar.samples,
+                )
+                .repeat_infinite(),
+                factor: music_tempo.clone(),
+            },
+            target: tension_gain.clone(),
+            gain: 0.0,
+        };
+        let tension = Sink::try_new(&stream_handle).map_err(|e| anyhow!("{:?}", e))?;
+        tension.append(tension_source);
+        tension.play();
+
+        let sounds = Sfx::ALL.iter().map(|sfx| Sound::for_sfx(*sfx)).collect();
+
+        let mut audio = Self {
+            _stream: stream,
+            stream_handle,
+            music,
+            music_volume: 1.0,
+            music_tempo,
+            music_played: Arc::new(AtomicU64::new(0)),
+            music_rate: SAMPLE_RATE,
+            tracks: scan_music(),
+            current_track: 0,
+            rhythm_track: None,
+            tension,
+            tension_gain,
+            sounds,
+            sfx_volume: 1.0,
+            audio_cues: false,
+        };
+        audio.apply_config(config);
+        audio.start_track();
+        Ok(audio)
+    }
+
+    /// Replaces the music sink with one playing the current track, or the built-in song
+    /// on loop if there are no tracks. In rhythm mode, the rhythm track plays on loop
+    /// instead.
+    fn try_start_track(&mut self) -> Result<()> {
+        let music = Sink::try_new(&self.stream_handle).map_err(|e| anyhow!("{:?}", e))?;
+        let track = match (&self.rhythm_track, self.tracks.get(self.current_track)) {
+            (Some(track), _) => track.clone(),
+            (None, Some(path)) => Track::File(path.clone()),
+            (None, None) => Track::Builtin,
+        };
+        match (track, self.rhythm_track.is_some()) {
+            (Track::File(path), false) => self.append_music(
+                &music,
+                rodio::Decoder::new(BufReader::new(File::open(path)?))?,
+            ),
+            (Track::File(path), true) => self.append_music(
+                &music,
+                rodio::Decoder::new_looped(BufReader::new(File::open(path)?))?,
+            ),
+            (Track::Builtin, _) => self.append_music(
+                &music,
+                rodio::Decoder::new_looped(Cursor::new(TETRIS_SONG))?,
+            ),
+        }
+        music.set_volume(self.music_volume);
+        if self.music.is_paused() {
+            music.pause();
+        }
+        self.music = music;
+        Ok(())
+    }
+
+    /// Queues a decoded track on a music sink, following its tempo and position.
+    ///
+    /// # Parameters
+    ///
+    /// - `music`: The sink to play it on.
+    /// - `source`: The track.
+    fn append_music<S>(&mut self, music: &Sink, source: S)
+    where
+        S: Source + Send + 'static,
+        S::Item: Sample + Send,
+    {
+        self.music_rate = source.channels() as u32 * source.sample_rate();
+        // Samples still counted by the last track's source go to a counter no one reads
+        self.music_played = Arc::new(AtomicU64::new(0));
+        music.append(Tempo {
+            input: Position {
+                input: source,
+                played: self.music_played.clone(),
+            },
+            factor: self.music_tempo.clone(),
+        });
+    }
+
+    /// Starts playing the current track, dropping any tracks which fail to play.
+    fn start_track(&mut self) {
+        while let Err(e) = self.try_start_track() {
+            if self.tracks.is_empty() || self.rhythm_track.is_some() {
+                eprintln!("Failed to play music: {}", e);
+                return;
+            }
+            let path = self.tracks.remove(self.current_track);
+            eprintln!("Failed to play {}: {}", path.display(), e);
+            if self.current_track >= self.tracks.len() {
+                self.current_track = 0;
+            }
+        }
+    }
+
+    /// Moves forwards or backwards through the playlist.
+    ///
+    /// # Parameters
+    ///
+    /// - `offset`: Number of tracks to move by, negative to go backwards.
+    ///
+    /// # Return Value
+    ///
+    /// The name of the track now playing, or `None` if there is no playlist.
+    pub fn skip_track(&mut self, offset: isize) -> Option<String> {
+        // Rhythm mode's game is kept in time with its track
+        if self.tracks.is_empty() || self.rhythm_track.is_some() {
+            return None;
+        }
+        let len = self.tracks.len() as isize;
+        self.current_track = (self.current_track as isize + offset).rem_euclid(len) as usize;
+        self.start_track();
+        self.tracks
+            .get(self.current_track)
+            .and_then(|path| path.file_stem())
+            .map(|name| name.to_string_lossy().into_owned())
+    }
+
+    /// Advances to the next track once the current one has finished. Should be called
+    /// once per frame.
+    pub fn update(&mut self) {
+        if !self.tracks.is_empty() && self.rhythm_track.is_none() && self.music.empty() {
+            self.skip_track(1);
+        }
+    }
+
+    /// Applies the volume and mute settings from the config.
+    ///
+    /// # Parameters
+    ///
+    /// - `config`: Config to take the volumes from.
+    pub fn apply_config(&mut self, config: &Config) {
+        let volume = if config.muted { 0.0 } else { config.volume };
+        self.music_volume = volume * config.music_volume;
+        self.music.set_volume(self.music_volume);
+        self.tension.set_volume(volume * config.music_volume);
+        self.sfx_volume = volume * config.sfx_volume;
+        self.audio_cues = config.audio_cues;
+    }
+
+    /// Plays a sound effect once, mixed over the music.
+    ///
+    /// # Parameters
+    ///
+    /// - `sfx`: The effect to play.
+    pub fn play(&self, sfx: Sfx) {
+        self.play_sound(&self.sounds[sfx as usize]);
+    }
+
+    fn play_sound(&self, sound: &Sound) {
+        if self.sfx_volume <= 0.0 {
+            return;
+        }
+        let source = SamplesBuffer::new(sound.channels, sound.sample_rate, sound.samples.clone())
+            .amplify(self.sfx_volume);
+        // A lost output device shouldn't interrupt the game
+        let _ = self.stream_handle.play_raw(source);
+    }
+
+    /// Resumes the background music at its normal tempo, e.g. when a new game starts.
+    pub fn reset_music(&self) {
+        self.set_music_tempo(1.0);
+        self.set_stack_height(0);
+        self.music.play();
+        self.tension.play();
+    }
+
+    /// Starts looping a track from its start for rhythm mode, at its normal tempo
+    /// whatever the level. The first track in the playlist from the current one which
+    /// has a tempo is picked, or the built-in song if none have.
+    ///
+    /// # Return Value
+    ///
+    /// The tempo of the track in beats per minute.
+    pub fn start_rhythm(&mut self) -> f64 {
+        let len = self.tracks.len();
+        let found = (0..len)
+            .map(|offset| (self.current_track + offset) % len)
+            .find_map(|i| Some((i, track_bpm(&self.tracks[i])?)));
+        let bpm = match found {
+            Some((i, bpm)) => {
+                self.current_track = i;
+                self.rhythm_track = Some(Track::File(self.tracks[i].clone()));
+                bpm
+            }
+            None => {
+                self.rhythm_track = Some(Track::Builtin);
+                TETRIS_SONG_BPM
+            }
+        };
+        self.set_music_tempo(1.0);
+        if let Err(e) = self.try_start_track() {
+            eprintln!("Failed to play music for rhythm mode: {}", e);
+            self.rhythm_track = Some(Track::Builtin);
+            self.start_track();
+            return TETRIS_SONG_BPM;
+        }
+        bpm
+    }
+
+    /// Goes back to the playlist after rhythm mode, starting the current track over.
+    pub fn stop_rhythm(&mut self) {
+        if self.rhythm_track.take().is_some() {
+            self.start_track();
+        }
+    }
+
+    /// Returns how far the current track has played, in milliseconds of the track.
+    /// Playing on from the start after looping, it keeps counting up.
+    pub fn music_position_ms(&self) -> f64 {
+        self.music_played.load(Ordering::Relaxed) as f64 * 1000.0 / self.music_rate as f64
+    }
+
+    /// Fades the tension layer in or out to match how close the stack is to the top.
+    ///
+    /// # Parameters
+    ///
+    /// - `height`: Stack height in rows from the bottom of the board.
+    pub fn set_stack_height(&self, height: usize) {
+        let gain = height.saturating_sub(TENSION_START_HEIGHT) as f32
+            / (TENSION_FULL_HEIGHT - TENSION_START_HEIGHT) as f32;
+        self.tension_gain
+            .store(gain.min(1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    /// Changes the playback speed of the background music.
+    ///
+    /// # Parameters
+    ///
+    /// - `tempo`: Speed multiplier, clamped between 1.0 and `MAX_TEMPO`.
+    pub fn set_music_tempo(&self, tempo: f32) {
+        let tempo = tempo.clamp(1.0, MAX_TEMPO);
+        self.music_tempo.store(tempo.to_bits(), Ordering::Relaxed);
+    }
+}
+
+impl EventHandler for Audio {
+    /// Plays the sound effect matching a game event, or updates the music for events
+    /// which have no sound of their own.
+    fn handle_event(&mut self, event: &GameEvent) {
+        let sfx = match *event {
+            GameEvent::Spawned(kind) => {
+                if self.audio_cues {
+                    self.play_sound(&Sound::synthesize(&[(cue_pitch(kind), CUE_MS)]));
+                }
+                return;
+            }
+            GameEvent::StackHeight(height) => {
+                self.set_stack_height(height);
+                if self.audio_cues && height >= CUE_WARNING_HEIGHT {
+                    self.play_sound(&Sound::synthesize(&[(1760.0, 60), (0.0, 40), (1760.0, 60)]));
+                }
+                return;
+            }
+            GameEvent::Moved => Sfx::Move,
+            GameEvent::Rotated => Sfx::Rotate,
+            GameEvent::PieceLocked { hard_drop: false } => Sfx::SoftLock,
+            GameEvent::PieceLocked { hard_drop: true } => Sfx::HardDrop,
+            GameEvent::LinesCleared {
+                kind: ClearKind::Tetris,
+                ..
+            } => Sfx::Tetris,
+            GameEvent::LinesCleared { .. } => Sfx::LineClear,
+            GameEvent::ScoreChanged(_) => return,
+            GameEvent::LevelUp(level) => {
+                // Rhythm mode speeds up by beat, keeping the music's tempo
+                if self.rhythm_track.is_none() {
+                    self.set_music_tempo(1.0 + TEMPO_PER_LEVEL * (level - 1) as f32);
+                }
+                Sfx::LevelUp
+            }
+            GameEvent::Held => Sfx::Hold,
+            GameEvent::GameOver => {
+                self.music.pause();
+                self.tension.pause();
+                Sfx::GameOver
+            }
+        };
+        self.play(sfx);
+    }
+}
@@ -0,0 +1,233 @@
+use anyhow::*;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use web_time::Instant;
+use wgputris::action::Action;
+use wgputris::game::{DebugInfo, Game};
+use wgputris::stats::Stats;
+use wgputris::{GAMEBOARD_HEIGHT, GAMEBOARD_HIDDEN_ROWS, GAMEBOARD_WIDTH};
+
+/// Number of allocations made by the whole program so far.
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// The system allocator, counting each allocation so the benchmark can report them.
+struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Time spent in each part of the benchmark.
+#[derive(Default)]
+struct Timings {
+    /// Choosing where the bot puts each shape.
+    bot: Duration,
+    /// Performing the bot's actions on the game.
+    actions: Duration,
+    /// Advancing the game, which spawns shapes and clears lines.
+    simulation: Duration,
+    /// Passing the game's events to its totals.
+    events: Duration,
+}
+
+/// Where the bot decided to put a shape.
+struct Placement {
+    /// Number of clockwise rotations.
+    rotations: usize,
+    /// Number of blocks to move right, or left if negative.
+    shift: i32,
+}
+
+/// Plays games with a simple bot as fast as possible, and prints how fast the game
+/// logic ran and how much it allocated.
+///
+/// # Parameters
+///
+/// - `games`: Number of games to play.
+/// - `max_pieces`: Most shapes placed in one game, as the bot may never top out.
+/// - `seed`: Seed of the first game. Each game after it uses the next seed.
+pub fn run(games: usize, max_pieces: usize, seed: u64) -> Result<()> {
+    let mut timings = Timings::default();
+    let mut totals = Stats::new();
+    let allocations_before = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+
+    for game_seed in seed..seed + games as u64 {
+        let stats = play(Game::with_seed(game_seed), max_pieces, &mut timings);
+        totals.score += stats.score;
+        totals.lines += stats.lines;
+        totals.pieces += stats.pieces;
+    }
+
+    let elapsed = start.elapsed().as_secs_f64();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations_before;
+    println!("Games: {}", games);
+    println!("Pieces: {}", totals.pieces);
+    println!("Lines: {}", totals.lines);
+    println!(
+        "Average score: {:.0}",
+        totals.score as f64 / games.max(1) as f64
+    );
+    println!("Time: {:.3} s", elapsed);
+    println!(
+        "Pieces per second: {:.0}",
+        totals.pieces as f64 / elapsed.max(f64::EPSILON)
+    );
+    println!(
+        "Allocations: {} ({:.1} per piece)",
+        allocations,
+        allocations as f64 / totals.pieces.max(1) as f64
+    );
+    for (name, time) in [
+        ("bot", timings.bot),
+        ("actions", timings.actions),
+        ("simulation", timings.simulation),
+        ("events", timings.events),
+    ] {
+        println!(
+            "  {:<10} {:>9.3} s {:>5.1}%",
+            name,
+            time.as_secs_f64(),
+            100.0 * time.as_secs_f64() / elapsed.max(f64::EPSILON)
+        );
+    }
+    Ok(())
+}
+
+/// Plays one game with the bot until it tops out or places `max_pieces` shapes.
+///
+/// # Parameters
+///
+/// - `game`: The game to play.
+/// - `max_pieces`: Most shapes to place.
+/// - `timings`: Time spent in each part, added to.
+///
+/// # Return Value
+///
+/// Totals of the finished game.
+fn play(mut game: Game, max_pieces: usize, timings: &mut Timings) -> Stats {
+    let mut stats = Stats::new();
+    while !game.game_over && stats.pieces < max_pieces {
+        let now = Instant::now();
+        let placement = plan(&game.debug_info());
+        timings.bot += now.elapsed();
+
+        let now = Instant::now();
+        for _ in 0..placement.rotations {
+            game.process_action(Action::RotateCw);
+        }
+        let direction = if placement.shift < 0 {
+            Action::MoveLeft
+        } else {
+            Action::MoveRight
+        };
+        for _ in 0..placement.shift.abs() {
+            game.process_action(direction);
+        }
+        game.process_action(Action::HardDrop);
+        timings.actions += now.elapsed();
+
+        // The next shape spawns on the next step
+        let now = Instant::now();
+        game.advance(1);
+        timings.simulation += now.elapsed();
+
+        let now = Instant::now();
+        game.dispatch_events(&mut [&mut stats]);
+        timings.events += now.elapsed();
+    }
+    stats
+}
+
+/// Picks where to put the current shape by trying every rotation and column and
+/// scoring the board each leaves behind.
+///
+/// # Parameters
+///
+/// - `info`: State of the game, including the board and the current shape.
+fn plan(info: &DebugInfo) -> Placement {
+    let mut shape = info.shape;
+    let mut best = Placement {
+        rotations: 0,
+        shift: 0,
+    };
+    let mut best_score = f64::MIN;
+    for rotations in 0..4 {
+        for shift in -(GAMEBOARD_WIDTH as i32)..=GAMEBOARD_WIDTH as i32 {
+            let mut shifted = shape;
+            shifted.add_pos(shift, 0);
+            let mut locs = shifted.board_locs();
+            if !fits(&info.occupied, &locs) {
+                continue;
+            }
+            while fits(&info.occupied, &locs.map(|(x, y)| (x, y + 1))) {
+                locs = locs.map(|(x, y)| (x, y + 1));
+            }
+            // Locking in the hidden rows tops out
+            if locs.iter().any(|&(_, y)| y < 0) {
+                continue;
+            }
+            let score = evaluate(&info.occupied, &locs);
+            if score > best_score {
+                best_score = score;
+                best = Placement { rotations, shift };
+            }
+        }
+        shape.rotate_cw();
+    }
+    best
+}
+
+/// Returns whether blocks at `locs` are all on the board or in the hidden rows above
+/// it, and in empty cells.
+fn fits(occupied: &[[bool; GAMEBOARD_WIDTH]; GAMEBOARD_HEIGHT], locs: &[(i32, i32); 4]) -> bool {
+    locs.iter().all(|&(x, y)| {
+        (0..GAMEBOARD_WIDTH as i32).contains(&x)
+            && (-(GAMEBOARD_HIDDEN_ROWS as i32)..GAMEBOARD_HEIGHT as i32).contains(&y)
+            && (y < 0 || !occupied[y as usize][x as usize])
+    })
+}
+
+/// Scores the board left by placing blocks at `locs`, higher being better: lines
+/// cleared count for it, while height, holes and bumpiness count against it.
+fn evaluate(occupied: &[[bool; GAMEBOARD_WIDTH]; GAMEBOARD_HEIGHT], locs: &[(i32, i32); 4]) -> f64 {
+    let mut board = *occupied;
+    for &(x, y) in locs.iter() {
+        board[y as usize][x as usize] = true;
+    }
+    let is_full = |row: &&[bool; GAMEBOARD_WIDTH]| row.iter().all(|cell| *cell);
+    let lines = board.iter().filter(is_full).count();
+
+    // Full rows are about to be cleared, so they are left out
+    let mut heights = [0; GAMEBOARD_WIDTH];
+    let mut holes = 0;
+    for (x, height) in heights.iter_mut().enumerate() {
+        let mut column = board.iter().filter(|row| !is_full(row)).map(|row| row[x]);
+        if let Some(y) = column.position(|cell| cell) {
+            *height = GAMEBOARD_HEIGHT - lines - y;
+            holes += column.filter(|cell| !cell).count();
+        }
+    }
+    let height: usize = heights.iter().sum();
+    let bumpiness: usize = heights
+        .windows(2)
+        .map(|pair| (pair[0] as i32 - pair[1] as i32).unsigned_abs() as usize)
+        .sum();
+    0.76 * lines as f64 - 0.51 * height as f64 - 0.36 * holes as f64 - 0.18 * bumpiness as f64
+}
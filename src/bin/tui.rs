@@ -0,0 +1,175 @@
+//! Plays wgputris in a terminal, drawing the board with coloured block characters, e.g.
+//! to play over SSH or to check the rules on a machine without a GPU.
+
+use anyhow::*;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::style::{Color, Print, ResetColor, SetForegroundColor};
+use crossterm::{cursor, execute, queue, terminal};
+use std::io::{stdout, Stdout, Write};
+use std::time::Duration;
+use wgputris::action::Action;
+use wgputris::game::Game;
+use wgputris::stats::Stats;
+use wgputris::tetromino::Tetromino;
+use wgputris::{paths, GAMEBOARD_HEIGHT, GAMEBOARD_WIDTH};
+
+/// Time between frames.
+const FRAME_TIME: Duration = Duration::from_millis(16);
+/// Column the side panel starts at, right of the board and its walls.
+const PANEL_X: u16 = GAMEBOARD_WIDTH as u16 * 2 + 6;
+
+/// Puts the terminal back as it was when dropped, even if the game fails part way.
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn new(out: &mut Stdout) -> Result<Self> {
+        terminal::enable_raw_mode().context("Failed to set up the terminal")?;
+        execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(Self)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = execute!(stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Returns the action bound to a key. Terminals don't report keys being released, so
+/// every press and key repeat performs its action once, with no DAS or held soft drop.
+///
+/// # Parameters
+///
+/// - `code`: The key which was pressed.
+fn action(code: KeyCode) -> Option<Action> {
+    match code {
+        KeyCode::Left => Some(Action::MoveLeft),
+        KeyCode::Right => Some(Action::MoveRight),
+        KeyCode::Down => Some(Action::SoftDrop),
+        KeyCode::Char(' ') => Some(Action::HardDrop),
+        KeyCode::Up | KeyCode::Char('x') => Some(Action::RotateCw),
+        KeyCode::Char('z') => Some(Action::RotateCcw),
+        KeyCode::Char('c') => Some(Action::Hold),
+        _ => None,
+    }
+}
+
+/// Converts a block colour to the nearest terminal colour.
+fn color([r, g, b, _]: [f32; 4]) -> Color {
+    Color::Rgb {
+        r: (r * 255.0) as u8,
+        g: (g * 255.0) as u8,
+        b: (b * 255.0) as u8,
+    }
+}
+
+/// Draws the board, score and upcoming shapes.
+///
+/// # Parameters
+///
+/// - `out`: The terminal.
+/// - `game`: The game to draw.
+/// - `stats`: Totals of the game.
+fn draw(out: &mut Stdout, game: &Game, stats: &Stats) -> Result<()> {
+    for (y, row) in game.field().iter().enumerate() {
+        queue!(out, cursor::MoveTo(0, y as u16), Print("<!"))?;
+        for cell in row.iter() {
+            match cell {
+                Some(block) => queue!(out, SetForegroundColor(color(*block)), Print("██"))?,
+                None => queue!(out, ResetColor, Print(" ."))?,
+            }
+        }
+        queue!(out, ResetColor, Print("!>"))?;
+    }
+    let floor = format!("<!{}!>", "=".repeat(GAMEBOARD_WIDTH * 2));
+    queue!(
+        out,
+        cursor::MoveTo(0, GAMEBOARD_HEIGHT as u16),
+        Print(floor)
+    )?;
+
+    let shape_name = |shape: Option<Tetromino>| match shape {
+        Some(shape) => format!("{:?}", shape.kind()),
+        None => String::from("-"),
+    };
+    let panel = [
+        format!("Score: {}", stats.score),
+        format!("Level: {}  Lines: {}", stats.level, stats.lines),
+        format!(
+            "Next: {}",
+            shape_name(Some(Tetromino::new(game.next_kind())))
+        ),
+        format!("Hold: {}", shape_name(game.hold_kind().map(Tetromino::new))),
+        String::new(),
+        String::from("Left/Right: Move  Down: Soft drop"),
+        String::from("Space: Hard drop  Up/X/Z: Rotate"),
+        String::from("C: Hold  R: Restart  Q: Quit"),
+        String::new(),
+        String::from(if game.game_over { "Game Over." } else { "" }),
+    ];
+    for (y, line) in panel.iter().enumerate() {
+        queue!(
+            out,
+            cursor::MoveTo(PANEL_X, y as u16),
+            terminal::Clear(terminal::ClearType::UntilNewLine),
+            Print(line)
+        )?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Saves the replay of a game, so it can be watched in the windowed game.
+fn save_replay(game: &Game) {
+    if let Err(e) = game.replay().save() {
+        eprintln!("Failed to save replay: {}", e);
+    }
+}
+
+fn main() -> Result<()> {
+    paths::init(std::env::args().any(|arg| arg == "--portable"));
+    let mut out = stdout();
+    let _guard = TerminalGuard::new(&mut out)?;
+    execute!(out, terminal::Clear(terminal::ClearType::All))?;
+
+    let mut game = Game::new();
+    let mut stats = Stats::new();
+    loop {
+        while event::poll(Duration::ZERO)? {
+            let key = match event::read()? {
+                Event::Key(key) if key.kind != KeyEventKind::Release => key,
+                _ => continue,
+            };
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => {
+                    if !game.game_over {
+                        save_replay(&game);
+                    }
+                    return Ok(());
+                }
+                KeyCode::Char('r') => {
+                    if !game.game_over {
+                        save_replay(&game);
+                    }
+                    game = Game::new();
+                    stats = Stats::new();
+                }
+                code => {
+                    if let (Some(action), false) = (action(code), game.game_over) {
+                        game.process_action(action);
+                    }
+                }
+            }
+        }
+        if !game.game_over {
+            game.process_game_loop();
+            if game.game_over {
+                save_replay(&game);
+            }
+        }
+        game.dispatch_events(&mut [&mut stats]);
+        draw(&mut out, &game, &stats)?;
+        std::thread::sleep(FRAME_TIME);
+    }
+}
@@ -0,0 +1,124 @@
+use anyhow::*;
+use std::collections::VecDeque;
+use std::fs::{create_dir_all, File};
+use std::path::PathBuf;
+use std::time::Duration;
+use web_time::{Instant, SystemTime, UNIX_EPOCH};
+use wgputris::paths;
+use wgputris::Vertex;
+
+/// Directory GIF clips are saved to.
+const CLIP_DIR: &str = "clips";
+/// Size of captured frames in pixels, a quarter of the window.
+const FRAME_WIDTH: usize = 240;
+const FRAME_HEIGHT: usize = 136;
+/// Frames captured per second.
+const CAPTURE_FPS: u32 = 10;
+/// Length of the clip kept in the buffer, in seconds.
+const CLIP_SECS: u32 = 5;
+/// Colour the window is cleared to, which shows through wherever nothing is drawn.
+const CLEAR_COLOR: [f32; 3] = [0.2, 0.267, 0.333];
+
+/// Keeps the last few seconds of gameplay as small frames, drawn on the CPU from the
+/// same vertices sent to the GPU, so they can be saved as an animated GIF.
+pub struct Recorder {
+    frames: VecDeque<Vec<u8>>,
+    last_capture: Option<Instant>,
+}
+
+impl Recorder {
+    /// Creates a new `Recorder` with no frames.
+    pub fn new() -> Self {
+        Self {
+            frames: VecDeque::new(),
+            last_capture: None,
+        }
+    }
+
+    /// Adds a frame to the buffer if it's time for the next one, dropping the oldest
+    /// once the buffer holds `CLIP_SECS` worth.
+    ///
+    /// # Parameters
+    ///
+    /// - `vertices`: Vertices of the frame, as quads of six vertices each.
+    /// - `view_width`: Width of the view in world units.
+    pub fn capture(&mut self, vertices: &[Vertex], view_width: f32) {
+        let interval = Duration::from_secs(1) / CAPTURE_FPS;
+        if self.last_capture.is_some_and(|t| t.elapsed() < interval) {
+            return;
+        }
+        self.last_capture = Some(Instant::now());
+        if self.frames.len() == (CAPTURE_FPS * CLIP_SECS) as usize {
+            self.frames.pop_front();
+        }
+        self.frames
+            .push_back(rasterize(vertices, view_width, FRAME_WIDTH, FRAME_HEIGHT));
+    }
+
+    /// Forgets every captured frame, e.g. when a new game starts.
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    /// Saves the buffered frames as an animated GIF.
+    ///
+    /// # Return Value
+    ///
+    /// The path of the new file.
+    pub fn export(&self) -> Result<PathBuf> {
+        if self.frames.is_empty() {
+            bail!("Nothing to save yet");
+        }
+        let clip_dir = paths::data_path(CLIP_DIR);
+        create_dir_all(&clip_dir)?;
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let path = clip_dir.join(format!("{}.gif", timestamp));
+        let mut encoder = gif::Encoder::new(
+            File::create(&path)?,
+            FRAME_WIDTH as u16,
+            FRAME_HEIGHT as u16,
+            &[],
+        )?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+        for pixels in &self.frames {
+            // Frames only have a handful of colours, so the fastest quantization is plenty
+            let mut frame =
+                gif::Frame::from_rgb_speed(FRAME_WIDTH as u16, FRAME_HEIGHT as u16, pixels, 30);
+            frame.delay = (100 / CAPTURE_FPS) as u16;
+            encoder.write_frame(&frame)?;
+        }
+        Ok(path)
+    }
+}
+
+/// Draws quads as flat coloured rectangles into an RGB image.
+///
+/// # Parameters
+///
+/// - `vertices`: Vertices of the frame, as quads of six vertices each.
+/// - `view_width`: Width of the view in world units.
+/// - `width`, `height`: Size of the image in pixels.
+pub fn rasterize(vertices: &[Vertex], view_width: f32, width: usize, height: usize) -> Vec<u8> {
+    let mut pixels = vec![CLEAR_COLOR; width * height];
+    let scale = width as f32 / view_width;
+    for quad in vertices.chunks(6) {
+        let color = quad[0].color;
+        let x0 = quad.iter().map(|v| v.position[0]).fold(f32::MAX, f32::min);
+        let x1 = quad.iter().map(|v| v.position[0]).fold(f32::MIN, f32::max);
+        let y0 = quad.iter().map(|v| v.position[1]).fold(f32::MAX, f32::min);
+        let y1 = quad.iter().map(|v| v.position[1]).fold(f32::MIN, f32::max);
+        let to_pixels = |v: f32, max: usize| ((v * scale).round().max(0.0) as usize).min(max);
+        for y in to_pixels(y0, height)..to_pixels(y1, height) {
+            for x in to_pixels(x0, width)..to_pixels(x1, width) {
+                let pixel = &mut pixels[x + y * width];
+                for (channel, value) in pixel.iter_mut().enumerate() {
+                    *value += (color[channel] - *value) * color[3];
+                }
+            }
+        }
+    }
+    pixels
+        .iter()
+        .flat_map(|pixel| pixel.iter().map(|c| (c.clamp(0.0, 1.0) * 255.0) as u8))
+        .collect()
+}
@@ -0,0 +1,294 @@
+//! A burst of confetti over the board and a glow around its border, celebrating
+//! tetrises and perfect clears. Perfect clears get twice the confetti and a gold glow.
+//!
+//! Celebrations are timed in game time, so they stop while the game is paused and play
+//! at the game's speed.
+
+use crate::game::{ClearKind, EventHandler, GameEvent};
+use crate::palette;
+use crate::Vertex;
+use crate::{BLOCK_SIZE, GAMEBOARD_HEIGHT, GAMEBOARD_OFFSET, GAMEBOARD_WIDTH};
+
+use rand::prelude::*;
+use rand::rngs::StdRng;
+
+/// Pieces of confetti thrown for a tetris.
+const TETRIS_CONFETTI: usize = 40;
+/// Most pieces of confetti thrown at once, for a perfect clear.
+const MAX_CONFETTI: usize = 2 * TETRIS_CONFETTI;
+/// Number of vertices `render_glow` fills.
+pub const GLOW_VERTICES: usize = 24;
+/// Number of vertices `Celebration::render` fills for one board.
+pub const CELEBRATION_VERTICES: usize = GLOW_VERTICES + 6 * MAX_CONFETTI;
+/// How long confetti stays on screen, in milliseconds. It fades out over the last
+/// third.
+pub const CONFETTI_MS: u64 = 1500;
+/// How long the border glows for, in milliseconds.
+pub const GLOW_MS: u64 = 1000;
+/// Width of the glow around the board, in pixels.
+const GLOW_WIDTH: f32 = BLOCK_SIZE as f32 / 2.0;
+/// Colours of the glow after a tetris and a perfect clear.
+const TETRIS_GLOW: [f32; 3] = [0.0, 1.0, 1.0];
+const PERFECT_CLEAR_GLOW: [f32; 3] = [1.0, 0.84, 0.0];
+/// Width of a piece of confetti, in pixels.
+const CONFETTI_SIZE: f32 = BLOCK_SIZE as f32 / 4.0;
+/// Speed confetti is thrown up at, in pixels a second.
+const THROW_SPEED: (f32, f32) = (150.0, 300.0);
+/// Fastest sideways speed of confetti, in pixels a second.
+const DRIFT_SPEED: f32 = 60.0;
+/// How quickly confetti falls back down, in pixels a second squared.
+const CONFETTI_GRAVITY: f32 = 400.0;
+/// Colours confetti is picked from.
+const CONFETTI_COLORS: [[f32; 3]; 5] = [
+    [1.0, 0.3, 0.3],
+    [1.0, 0.84, 0.0],
+    [0.3, 1.0, 0.4],
+    [0.3, 0.6, 1.0],
+    [1.0, 0.4, 1.0],
+];
+
+/// One piece of confetti, thrown from the bottom of the board.
+#[derive(Debug, Clone)]
+pub struct Confetti {
+    /// Where it was thrown from, in pixels from the board's top left.
+    start: (f32, f32),
+    /// Speed it was thrown at, in pixels a second, with up negative.
+    velocity: (f32, f32),
+    color: [f32; 3],
+}
+
+impl Confetti {
+    /// Throws the confetti of one celebration from along the bottom of the board.
+    ///
+    /// # Parameters
+    ///
+    /// - `rng`: Picks where each piece is thrown from, how fast and its colour.
+    /// - `perfect_clear`: `true` for the bigger celebration of a perfect clear.
+    pub fn throw(rng: &mut impl Rng, perfect_clear: bool) -> Vec<Confetti> {
+        let count = if perfect_clear {
+            MAX_CONFETTI
+        } else {
+            TETRIS_CONFETTI
+        };
+        let width = (GAMEBOARD_WIDTH as u32 * BLOCK_SIZE) as f32;
+        let height = (GAMEBOARD_HEIGHT as u32 * BLOCK_SIZE) as f32;
+        (0..count)
+            .map(|_| Confetti {
+                start: (rng.gen_range(0.0, width), height),
+                velocity: (
+                    rng.gen_range(-DRIFT_SPEED, DRIFT_SPEED),
+                    -rng.gen_range(THROW_SPEED.0, THROW_SPEED.1),
+                ),
+                color: *CONFETTI_COLORS.choose(rng).unwrap(),
+            })
+            .collect()
+    }
+
+    /// Fills a quad for the piece as it flies, fading out towards `CONFETTI_MS`.
+    ///
+    /// # Parameters
+    ///
+    /// - `quad`: Vertices to fill, 6 long.
+    /// - `shift`: Number of blocks to move it right by, as for `Game::render`.
+    /// - `age_ms`: Game time since it was thrown, less than `CONFETTI_MS`.
+    pub fn render(&self, quad: &mut [Vertex], shift: i32, age_ms: u64) {
+        let (left, top) = board_corner(shift);
+        let secs = age_ms as f32 / 1000.0;
+        let fade_ms = CONFETTI_MS as f32 / 3.0;
+        let alpha = ((CONFETTI_MS - age_ms) as f32 / fade_ms).min(1.0);
+        let x = left + self.start.0 + self.velocity.0 * secs;
+        let y = top + self.start.1 + self.velocity.1 * secs + CONFETTI_GRAVITY * secs * secs / 2.0;
+        let color = [self.color[0], self.color[1], self.color[2], alpha];
+        let corners = [
+            (x, y),
+            (x + CONFETTI_SIZE, y),
+            (x + CONFETTI_SIZE, y + CONFETTI_SIZE),
+            (x + CONFETTI_SIZE, y + CONFETTI_SIZE),
+            (x, y + CONFETTI_SIZE),
+            (x, y),
+        ];
+        for (vertex, &(x, y)) in quad.iter_mut().zip(corners.iter()) {
+            *vertex = Vertex {
+                position: [x, y, 0.0],
+                tex_coords: palette::solid_tex_coords(),
+                color,
+            };
+        }
+    }
+}
+
+/// The confetti and glow of one tetris or perfect clear.
+#[derive(Debug, Clone)]
+struct Burst {
+    /// Game time it started, or `None` until the next `Celebration::update`.
+    started_ms: Option<u64>,
+    perfect_clear: bool,
+    pieces: Vec<Confetti>,
+}
+
+/// Watches one game for tetrises and perfect clears to celebrate. Only the latest is
+/// shown, so a new one starts over.
+#[derive(Debug, Clone)]
+pub struct Celebration {
+    rng: StdRng,
+    burst: Option<Burst>,
+    /// Game time of the last `update`.
+    time_ms: u64,
+}
+
+impl Celebration {
+    /// Creates a new `Celebration`, with nothing to celebrate yet.
+    pub fn new() -> Self {
+        Self {
+            rng: StdRng::from_entropy(),
+            burst: None,
+            time_ms: 0,
+        }
+    }
+
+    /// Starts a celebration, thrown from along the bottom of the board.
+    ///
+    /// # Parameters
+    ///
+    /// - `perfect_clear`: `true` for the bigger celebration of a perfect clear.
+    fn start(&mut self, perfect_clear: bool) {
+        let pieces = Confetti::throw(&mut self.rng, perfect_clear);
+        self.burst = Some(Burst {
+            started_ms: None,
+            perfect_clear,
+            pieces,
+        });
+    }
+
+    /// Starts celebrations found in the events dispatched since the last call at the
+    /// game's current time, and ends finished ones. Should be called after the game's
+    /// events have been dispatched.
+    ///
+    /// # Parameters
+    ///
+    /// - `time_ms`: The game's time. An earlier time than last call, as when a new
+    ///   game starts, ends the celebration.
+    pub fn update(&mut self, time_ms: u64) {
+        if time_ms < self.time_ms {
+            self.burst = None;
+        }
+        self.time_ms = time_ms;
+        if let Some(burst) = &mut self.burst {
+            let started_ms = *burst.started_ms.get_or_insert(time_ms);
+            if time_ms - started_ms >= CONFETTI_MS.max(GLOW_MS) {
+                self.burst = None;
+            }
+        }
+    }
+
+    /// Returns `true` while a celebration is showing.
+    pub fn is_active(&self) -> bool {
+        self.burst.is_some()
+    }
+
+    /// Returns renderable vertices of the glow and confetti, drawn over the board.
+    ///
+    /// # Parameters
+    ///
+    /// - `buf`: Vertices to fill, `CELEBRATION_VERTICES` long.
+    /// - `shift`: Number of blocks to move everything right by, as for `Game::render`.
+    /// - `confetti`: `false` to only draw the glow, for reduced motion.
+    pub fn render(&self, buf: &mut [Vertex], shift: i32, confetti: bool) {
+        use bytemuck::Zeroable;
+        buf.copy_from_slice(&[Vertex::zeroed(); CELEBRATION_VERTICES]);
+        let burst = match &self.burst {
+            Some(burst) => burst,
+            None => return,
+        };
+        let age_ms = self.time_ms - burst.started_ms.unwrap_or(self.time_ms);
+        let (glow, pieces) = buf.split_at_mut(GLOW_VERTICES);
+        if age_ms < GLOW_MS {
+            render_glow(glow, shift, burst.perfect_clear, age_ms);
+        }
+        if !confetti || age_ms >= CONFETTI_MS {
+            return;
+        }
+        for (piece, quad) in burst.pieces.iter().zip(pieces.chunks_mut(6)) {
+            piece.render(quad, shift, age_ms);
+        }
+    }
+}
+
+impl Default for Celebration {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventHandler for Celebration {
+    fn handle_event(&mut self, event: &GameEvent) {
+        if let GameEvent::LinesCleared {
+            kind,
+            perfect_clear,
+            ..
+        } = *event
+        {
+            if kind == ClearKind::Tetris || perfect_clear {
+                self.start(perfect_clear);
+            }
+        }
+    }
+}
+
+/// Fills four strips around the board, fading from its edge to clear, and fading out
+/// altogether towards `GLOW_MS`.
+///
+/// # Parameters
+///
+/// - `buf`: Vertices to fill, `GLOW_VERTICES` long.
+/// - `shift`: Number of blocks to move it right by, as for `Game::render`.
+/// - `perfect_clear`: `true` for the gold glow of a perfect clear.
+/// - `age_ms`: Game time since the glow started, less than `GLOW_MS`.
+pub fn render_glow(buf: &mut [Vertex], shift: i32, perfect_clear: bool, age_ms: u64) {
+    let color = if perfect_clear {
+        PERFECT_CLEAR_GLOW
+    } else {
+        TETRIS_GLOW
+    };
+    let alpha = 1.0 - age_ms as f32 / GLOW_MS as f32;
+    let (left, top) = board_corner(shift);
+    let right = left + (GAMEBOARD_WIDTH as u32 * BLOCK_SIZE) as f32;
+    let bottom = top + (GAMEBOARD_HEIGHT as u32 * BLOCK_SIZE) as f32;
+    let inner = [(left, top), (right, top), (right, bottom), (left, bottom)];
+    let outer = [
+        (left - GLOW_WIDTH, top - GLOW_WIDTH),
+        (right + GLOW_WIDTH, top - GLOW_WIDTH),
+        (right + GLOW_WIDTH, bottom + GLOW_WIDTH),
+        (left - GLOW_WIDTH, bottom + GLOW_WIDTH),
+    ];
+    for (side, quad) in buf.chunks_mut(6).enumerate() {
+        let next = (side + 1) % 4;
+        // Each strip joins the next at the corners, so the frame has no gaps
+        let corners = [
+            (outer[side], 0.0),
+            (outer[next], 0.0),
+            (inner[next], alpha),
+            (inner[next], alpha),
+            (inner[side], alpha),
+            (outer[side], 0.0),
+        ];
+        for (vertex, &((x, y), alpha)) in quad.iter_mut().zip(corners.iter()) {
+            *vertex = Vertex {
+                position: [x, y, 0.0],
+                tex_coords: palette::solid_tex_coords(),
+                color: [color[0], color[1], color[2], alpha],
+            };
+        }
+    }
+}
+
+/// Returns the top left corner of the board, in pixels.
+///
+/// # Parameters
+///
+/// - `shift`: Number of blocks the board is moved right by, as for `Game::render`.
+fn board_corner(shift: i32) -> (f32, f32) {
+    let left = ((GAMEBOARD_OFFSET.0 as i32 + shift) * BLOCK_SIZE as i32) as f32;
+    let top = (GAMEBOARD_OFFSET.1 as u32 * BLOCK_SIZE) as f32;
+    (left, top)
+}
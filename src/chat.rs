@@ -0,0 +1,143 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+use web_time::Instant;
+use winit::event::VirtualKeyCode;
+
+/// Lines kept to scroll back through.
+const SCROLLBACK: usize = 100;
+/// Lines shown at once.
+const VISIBLE_LINES: usize = 6;
+/// How long the chat stays on screen after a line arrives, while not typing.
+const SHOW_FOR: Duration = Duration::from_secs(8);
+/// Longest message, in characters, which can be sent or is shown.
+const MAX_LENGTH: usize = 120;
+
+/// Text chat with the other player in an online room or match.
+pub struct Chat {
+    /// Every line kept, oldest first.
+    lines: VecDeque<String>,
+    /// When the latest line arrived.
+    updated: Option<Instant>,
+    /// The message being typed, while typing.
+    draft: Option<String>,
+    /// Lines scrolled back from the latest.
+    scroll: usize,
+}
+
+impl Chat {
+    /// Creates a new, empty `Chat`.
+    pub fn new() -> Self {
+        Self {
+            lines: VecDeque::new(),
+            updated: None,
+            draft: None,
+            scroll: 0,
+        }
+    }
+
+    /// Forgets every line, e.g. when setting up a new match.
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Adds a line, dropping the oldest once there are more than `SCROLLBACK`.
+    ///
+    /// # Parameters
+    ///
+    /// - `from`: Who said it.
+    /// - `text`: What they said. Control characters are removed and anything past
+    ///   `MAX_LENGTH` is cut off.
+    pub fn push(&mut self, from: &str, text: &str) {
+        let text: String = text
+            .chars()
+            .filter(|c| !c.is_control())
+            .take(MAX_LENGTH)
+            .collect();
+        self.lines.push_back(format!("{}: {}", from, text));
+        if self.lines.len() > SCROLLBACK {
+            self.lines.pop_front();
+        }
+        self.updated = Some(Instant::now());
+        self.scroll = 0;
+    }
+
+    /// Returns `true` while a message is being typed, so keys go to the chat instead of
+    /// the game.
+    pub fn is_typing(&self) -> bool {
+        self.draft.is_some()
+    }
+
+    /// Starts typing a message.
+    pub fn start_typing(&mut self) {
+        self.draft = Some(String::new());
+    }
+
+    /// Throws away the message being typed.
+    pub fn stop_typing(&mut self) {
+        self.draft = None;
+        self.scroll = 0;
+    }
+
+    /// Adds a typed character to the message being typed.
+    ///
+    /// # Parameters
+    ///
+    /// - `c`: The character.
+    pub fn type_char(&mut self, c: char) {
+        if let Some(draft) = &mut self.draft {
+            if !c.is_control() && draft.chars().count() < MAX_LENGTH {
+                draft.push(c);
+            }
+        }
+    }
+
+    /// Handles a key press while typing.
+    ///
+    /// # Parameters
+    ///
+    /// - `key`: The key which was pressed.
+    ///
+    /// # Return Value
+    ///
+    /// The message to send, once enter is pressed on one which isn't blank.
+    pub fn process_input(&mut self, key: VirtualKeyCode) -> Option<String> {
+        match key {
+            VirtualKeyCode::Return => {
+                let message = self.draft.take()?;
+                self.scroll = 0;
+                return Some(message).filter(|message| !message.trim().is_empty());
+            }
+            VirtualKeyCode::Escape => self.stop_typing(),
+            VirtualKeyCode::Back => {
+                if let Some(draft) = &mut self.draft {
+                    draft.pop();
+                }
+            }
+            VirtualKeyCode::PageUp => {
+                let most = self.lines.len().saturating_sub(VISIBLE_LINES);
+                self.scroll = (self.scroll + VISIBLE_LINES).min(most);
+            }
+            VirtualKeyCode::PageDown => self.scroll = self.scroll.saturating_sub(VISIBLE_LINES),
+            _ => {}
+        }
+        None
+    }
+
+    /// Returns the lines to draw, oldest first, ending with the message being typed.
+    /// Without typing, lines only show for a while after the latest arrives.
+    pub fn visible_lines(&self) -> Vec<String> {
+        let recent = self
+            .updated
+            .is_some_and(|updated| updated.elapsed() < SHOW_FOR);
+        if !recent && !self.is_typing() {
+            return Vec::new();
+        }
+        let end = self.lines.len() - self.scroll;
+        let start = end.saturating_sub(VISIBLE_LINES);
+        let mut lines: Vec<String> = self.lines.range(start..end).cloned().collect();
+        if let Some(draft) = &self.draft {
+            lines.push(format!("Say: {}_", draft));
+        }
+        lines
+    }
+}
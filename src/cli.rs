@@ -0,0 +1,266 @@
+use anyhow::*;
+use clap::{Parser, Subcommand, ValueEnum};
+use std::path::{Path, PathBuf};
+use wgputris::game::{EventHandler, Game};
+use wgputris::netplay::{DEFAULT_PORT, DEFAULT_WEB_PORT};
+use wgputris::replay::{Playback, Replay};
+use wgputris::sequence::Sequence;
+use wgputris::stats::Stats;
+
+/// Command line options, for launching straight into a particular setup when testing
+/// or speedrunning.
+#[derive(Debug, Parser)]
+#[command(version, about = "Tetris with wgpu in Rust")]
+pub struct Args {
+    /// Keep config and data beside the executable, e.g. to run from a USB stick
+    #[arg(long)]
+    pub portable: bool,
+    /// Start a game straight away instead of showing the title screen
+    #[arg(long, value_enum)]
+    pub mode: Option<LaunchMode>,
+    /// Deal every game's shapes from this seed, so each game gets the same shapes
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// Practice a board setup shared as a fumen string, e.g. v115@...
+    #[arg(long, value_name = "FUMEN", conflicts_with_all = ["mode", "replay"])]
+    pub fumen: Option<String>,
+    /// Practice with shapes dealt in this order, e.g. "LJSZTOI repeat"
+    #[arg(long, value_parser = Sequence::parse, conflicts_with_all = ["mode", "replay"])]
+    pub sequence: Option<Sequence>,
+    /// Watch a saved replay
+    #[arg(long, value_name = "FILE", conflicts_with = "mode")]
+    pub replay: Option<PathBuf>,
+    /// Play against a bot speaking the Tetris Bot Protocol, run by this command or
+    /// listening at tcp://HOST:PORT
+    #[arg(long, value_name = "COMMAND", conflicts_with_all = ["mode", "replay", "fumen", "sequence"])]
+    pub bot: Option<String>,
+    /// Open in a window, even if fullscreen was last used
+    #[arg(long, conflicts_with = "fullscreen")]
+    pub windowed: bool,
+    /// Open fullscreen
+    #[arg(long)]
+    pub fullscreen: bool,
+    /// Size of the window in pixels, e.g. 1280x720
+    #[arg(long, value_name = "WxH", value_parser = parse_size)]
+    pub size: Option<(u32, u32)>,
+    /// Only try this graphics backend
+    #[arg(long, value_enum)]
+    pub backend: Option<Backend>,
+    /// Simulate the game without a window or sound and print the result: the replay
+    /// if one is given, otherwise a game in which no keys are pressed
+    #[arg(long)]
+    pub headless: bool,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Tools run instead of the game.
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Play many games with a simple bot and report how fast the game logic runs.
+    /// Games are dealt from --seed onwards, or from 0
+    Bench {
+        /// Number of games to play
+        #[arg(long, default_value_t = 1000)]
+        games: usize,
+        /// Most shapes placed in one game, as the bot may never top out
+        #[arg(long, default_value_t = 500)]
+        max_pieces: usize,
+    },
+    /// Evolve the computer player's weights by playing many games without a window,
+    /// and save the best for the computer to play with. Games are dealt from --seed
+    /// onwards, or from 0
+    Train {
+        /// Number of sets of weights alive at once
+        #[arg(long, default_value_t = 100)]
+        population: usize,
+        /// Number of generations to breed
+        #[arg(long, default_value_t = 10)]
+        generations: usize,
+        /// Games each set of weights plays per generation
+        #[arg(long, default_value_t = 5)]
+        games: usize,
+        /// Most shapes placed in one game, as good weights may never top out
+        #[arg(long, default_value_t = 500)]
+        max_pieces: usize,
+    },
+    /// Check a score claimed for a replay by playing the replay again, as a
+    /// leaderboard would before accepting the score. Exits with an error if the replay
+    /// doesn't reach the score
+    Verify {
+        /// The replay
+        replay: PathBuf,
+        /// The score claimed
+        #[arg(long)]
+        score: usize,
+    },
+    /// Render a saved replay to an MP4 video, without a window. Needs ffmpeg on the
+    /// PATH
+    Export {
+        /// The replay
+        replay: PathBuf,
+        /// File to write, by default the replay's path ending in .mp4
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// Frames per second of the video
+        #[arg(long, default_value_t = 60)]
+        fps: u32,
+        /// Height of the video in pixels
+        #[arg(long, default_value_t = 720)]
+        height: u32,
+    },
+    /// Run a lobby server, where players find each other for online matches
+    Server {
+        /// Port to listen for players on
+        #[arg(long, default_value_t = DEFAULT_PORT)]
+        port: u16,
+        /// Port to listen for players in a browser on
+        #[arg(long, default_value_t = DEFAULT_WEB_PORT)]
+        web_port: u16,
+    },
+}
+
+/// A kind of game which can be started from the command line.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum LaunchMode {
+    Marathon,
+    Versus,
+}
+
+/// A graphics backend which can be picked from the command line.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum Backend {
+    Vulkan,
+    Metal,
+    Dx12,
+    Dx11,
+}
+
+impl Backend {
+    /// Returns the backend as wgpu's bit flag.
+    pub fn bit(self) -> wgpu::BackendBit {
+        match self {
+            Backend::Vulkan => wgpu::BackendBit::VULKAN,
+            Backend::Metal => wgpu::BackendBit::METAL,
+            Backend::Dx12 => wgpu::BackendBit::DX12,
+            Backend::Dx11 => wgpu::BackendBit::DX11,
+        }
+    }
+}
+
+/// What to show once the game has started.
+pub enum Launch {
+    Menu,
+    Game {
+        versus: bool,
+    },
+    Practice {
+        fumen: Option<String>,
+        sequence: Option<Sequence>,
+    },
+    Replay(PathBuf),
+    /// A match against the computer player with this name.
+    Cpu(String),
+}
+
+impl Args {
+    /// Returns what the options ask to show once the game has started.
+    pub fn launch(&self) -> Launch {
+        if self.fumen.is_some() || self.sequence.is_some() {
+            Launch::Practice {
+                fumen: self.fumen.clone(),
+                sequence: self.sequence.clone(),
+            }
+        } else if let Some(path) = &self.replay {
+            Launch::Replay(path.clone())
+        } else if let Some(bot) = &self.bot {
+            Launch::Cpu(bot.clone())
+        } else {
+            match self.mode {
+                Some(mode) => Launch::Game {
+                    versus: mode == LaunchMode::Versus,
+                },
+                None => Launch::Menu,
+            }
+        }
+    }
+
+    /// Returns whether the window should open fullscreen.
+    ///
+    /// # Parameters
+    ///
+    /// - `saved`: Whether it was fullscreen when the game was last closed.
+    pub fn fullscreen(&self, saved: bool) -> bool {
+        (saved || self.fullscreen) && !self.windowed
+    }
+}
+
+/// Parses a window size written as `WIDTHxHEIGHT`.
+fn parse_size(size: &str) -> std::result::Result<(u32, u32), String> {
+    let parse = |part: Option<&str>| part.and_then(|part| part.trim().parse::<u32>().ok());
+    let mut parts = size.split('x');
+    match (parse(parts.next()), parse(parts.next()), parts.next()) {
+        (Some(width), Some(height), None) if width > 0 && height > 0 => Ok((width, height)),
+        _ => Err(format!("expected a size like 1280x720, not {}", size)),
+    }
+}
+
+/// Plays a game to the end without a window or sound and prints its totals.
+///
+/// # Parameters
+///
+/// - `args`: The command line options, which pick the replay, fumen or seed to play.
+pub fn run_headless(args: &Args) -> Result<()> {
+    let mut stats = Stats::new();
+    let time_ms = if let Some(path) = &args.replay {
+        let replay = Replay::load(path)
+            .with_context(|| format!("Failed to load replay {}", path.display()))?;
+        let length_ms = replay.length_ms;
+        let mut playback = Playback::new(replay)?;
+        playback.advance_to(length_ms);
+        playback.dispatch_events(&mut []);
+        stats = playback.stats().clone();
+        playback.game().time_ms()
+    } else {
+        let seed = args.seed.unwrap_or_else(rand::random);
+        let mut game = match &args.fumen {
+            Some(fumen) => Game::from_fumen(seed, fumen)?,
+            None => Game::with_seed(seed),
+        };
+        if let Some(sequence) = &args.sequence {
+            game.set_sequence(sequence.clone());
+        }
+        println!("Seed: {}", seed);
+        while !game.game_over {
+            game.advance(1);
+            game.dispatch_events(&mut [&mut stats as &mut dyn EventHandler]);
+        }
+        game.time_ms()
+    };
+    println!("Score: {}", stats.score);
+    println!("Lines: {}", stats.lines);
+    println!("Level: {}", stats.level);
+    println!("Pieces: {}", stats.pieces);
+    println!("Time: {:.3} s", time_ms as f64 / 1000.0);
+    Ok(())
+}
+
+/// Plays a replay again without a window and prints its totals if it reaches the score
+/// claimed for it.
+///
+/// # Parameters
+///
+/// - `path`: The replay file.
+/// - `score`: The score claimed.
+pub fn run_verify(path: &Path, score: usize) -> Result<()> {
+    let replay =
+        Replay::load(path).with_context(|| format!("Failed to load replay {}", path.display()))?;
+    let stats = replay
+        .verify(score)
+        .with_context(|| format!("Rejected {}", path.display()))?;
+    println!(
+        "Verified: {} points, {} lines, level {}",
+        stats.score, stats.lines, stats.level
+    );
+    Ok(())
+}
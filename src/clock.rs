@@ -0,0 +1,66 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use web_time::Instant;
+
+/// A source of the current time, so games and replays can be driven by a clock other
+/// than the system's, e.g. in tests. Clocks move between threads with their games, as
+/// the components of an `Arena` have to.
+pub trait Clock: Send + Sync {
+    /// Returns the time since some fixed point, which only ever increases.
+    fn now(&self) -> Duration;
+}
+
+/// The system's monotonic clock.
+pub struct RealClock {
+    start: Instant,
+}
+
+impl RealClock {
+    /// Creates a new `RealClock`, which counts from now.
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Default for RealClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for RealClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// A clock which only moves when told to. Clones share the same time, so a test can
+/// keep one and hand another to the game.
+#[derive(Debug, Clone, Default)]
+pub struct MockClock {
+    now: Arc<Mutex<Duration>>,
+}
+
+impl MockClock {
+    /// Creates a new `MockClock` at time zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Moves the clock forwards.
+    ///
+    /// # Parameters
+    ///
+    /// - `duration`: How far to move it.
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Duration {
+        *self.now.lock().unwrap()
+    }
+}
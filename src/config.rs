@@ -0,0 +1,498 @@
+use crate::input::KeyProfile;
+use crate::{LAYOUT_HEIGHT, LAYOUT_WIDTH};
+use anyhow::*;
+use serde::{Deserialize, Serialize};
+use std::fs::{read_to_string, write};
+use wgputris::ai::{self, Difficulty};
+use wgputris::attack::{self, AttackPreset, AttackTable};
+use wgputris::game::{HoldLimit, MAX_PREVIEWS};
+use wgputris::handicap::Handicap;
+use wgputris::kicks::{self, KickPreset, KickTable};
+use wgputris::palette::{Palette, Skin};
+use wgputris::paths;
+use wgputris::speed::{self, SpeedCurve, SpeedPreset};
+
+const CONFIG_FILE: &str = "config.toml";
+/// Smallest and largest sizes of text and the HUD, as multiples of their usual size.
+pub const MIN_UI_SCALE: f32 = 0.75;
+pub const MAX_UI_SCALE: f32 = 2.0;
+
+/// User settings which persist between runs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Master volume from 0.0 to 1.0.
+    pub volume: f32,
+    /// Music volume from 0.0 to 1.0, scaled by `volume`.
+    pub music_volume: f32,
+    /// Sound effect volume from 0.0 to 1.0, scaled by `volume`.
+    pub sfx_volume: f32,
+    pub muted: bool,
+    /// Play a tone for each spawned piece and warn when the stack is high.
+    pub audio_cues: bool,
+    /// Speak game events and the selected menu line with the system's text to speech.
+    pub screen_reader: bool,
+    /// Soft drop starts on one press and stops on the next, instead of being held.
+    pub toggle_soft_drop: bool,
+    /// Show chat from other players online. Turning it off mutes them.
+    pub chat: bool,
+    /// Frames inputs are held back for in online matches, `0` for none, picked on the
+    /// online screen.
+    pub input_delay: u32,
+    /// Show the mode, score and time played in the player's Discord status.
+    pub discord_status: bool,
+    /// Random id lobby servers keep this player's rating under.
+    pub player_id: u64,
+    /// Highest score reached in a single player game.
+    pub best_score: usize,
+    /// Name of the active entry in `profiles`.
+    pub profile: String,
+    /// Names of the profiles used by player 1 and player 2 in versus.
+    pub versus_profiles: [String; 2],
+    /// Handicaps of player 1 and player 2 in versus, also used against the computer.
+    pub versus_handicaps: [Handicap; 2],
+    /// How much garbage each kind of line clear sends in versus.
+    pub attack_table: AttackPreset,
+    /// How often shapes may be held in single player and local versus games. Online
+    /// matches always allow once per piece.
+    pub hold_limit: HoldLimit,
+    /// Which wall kicks shapes turn with in single player and local versus games.
+    /// Online matches always turn without them.
+    pub kick_table: KickPreset,
+    /// Gravity, lock delay and ARE by level in single player games. Online matches
+    /// always use the classic timings.
+    pub speed_curve: SpeedPreset,
+    /// Gravity, lock delay and ARE by level in local versus games.
+    pub versus_speed_curve: SpeedPreset,
+    /// Write practice save states to the data directory too, so they can be loaded in
+    /// later sessions.
+    pub save_states_to_disk: bool,
+    /// How well the computer plays in versus against it.
+    pub cpu_difficulty: Difficulty,
+    /// Name of the bot the computer plays with, see `wgputris::ai::register_bot`.
+    pub cpu_bot: String,
+    /// Names of the bots playing on the left and right in exhibition matches.
+    pub exhibition_bots: [String; 2],
+    /// How well each side plays in exhibition matches.
+    pub exhibition_difficulties: [Difficulty; 2],
+    pub window: WindowConfig,
+    pub graphics: GraphicsConfig,
+    pub twitch: TwitchConfig,
+    pub telemetry: TelemetryConfig,
+    pub profiles: Vec<KeyProfile>,
+}
+
+/// Where the window was and how big it was when the game last closed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowConfig {
+    /// Inner size in physical pixels.
+    pub width: u32,
+    pub height: u32,
+    /// Outer position in physical pixels, or `None` to let the system place it.
+    pub x: Option<i32>,
+    pub y: Option<i32>,
+    /// Name of the monitor the window was on, used when going fullscreen.
+    pub monitor: Option<String>,
+    pub fullscreen: bool,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            width: 960,
+            height: 544,
+            x: None,
+            y: None,
+            monitor: None,
+            fullscreen: false,
+        }
+    }
+}
+
+/// How the block texture is sampled when it's drawn bigger or smaller than it is.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TextureFilter {
+    /// Each pixel stays a sharp square, which suits pixel art.
+    Nearest,
+    /// Pixels blend smoothly into each other.
+    Linear,
+}
+
+/// Resolution the game is drawn at before it's scaled to fit the window. Fixed
+/// resolutions have the layout's shape, so the board keeps its proportions on any
+/// display, with black bars filling the rest of the window.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Resolution {
+    /// The window's own resolution, times `render_scale`.
+    #[serde(rename = "window")]
+    Window,
+    #[serde(rename = "540p")]
+    P540,
+    #[serde(rename = "720p")]
+    P720,
+    #[serde(rename = "1080p")]
+    P1080,
+    #[serde(rename = "1440p")]
+    P1440,
+}
+
+impl Resolution {
+    /// Every resolution, in the order they are listed.
+    pub const ALL: [Resolution; 5] = [
+        Resolution::Window,
+        Resolution::P540,
+        Resolution::P720,
+        Resolution::P1080,
+        Resolution::P1440,
+    ];
+
+    /// Name shown in menus.
+    pub fn label(self) -> &'static str {
+        match self {
+            Resolution::Window => "Window",
+            Resolution::P540 => "540p",
+            Resolution::P720 => "720p",
+            Resolution::P1080 => "1080p",
+            Resolution::P1440 => "1440p",
+        }
+    }
+
+    /// Returns the width and height drawn at in pixels, or `None` to follow the window.
+    pub fn size(self) -> Option<(u32, u32)> {
+        let height = match self {
+            Resolution::Window => return None,
+            Resolution::P540 => 540,
+            Resolution::P720 => 720,
+            Resolution::P1080 => 1080,
+            Resolution::P1440 => 1440,
+        };
+        Some((height * LAYOUT_WIDTH / LAYOUT_HEIGHT, height))
+    }
+}
+
+/// Graphics options. `low_power` and `render_scale` help weak graphics hardware, and
+/// are read when the game starts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GraphicsConfig {
+    /// Prefer an integrated GPU over a discrete one, e.g. to save battery.
+    pub low_power: bool,
+    /// Fraction of the window's resolution the game is drawn at, from 0.25 to 1.0.
+    /// Lower is faster on weak GPUs, but blurrier.
+    pub render_scale: f32,
+    pub texture_filter: TextureFilter,
+    /// Resolution the game is drawn at. Fixed resolutions ignore `render_scale`.
+    pub resolution: Resolution,
+    /// Size of text and the HUD, as a multiple of their usual size, from
+    /// `MIN_UI_SCALE` to `MAX_UI_SCALE`. Boards keep their size.
+    pub ui_scale: f32,
+    /// Number of upcoming shapes shown beside the board, up to `MAX_PREVIEWS`. None
+    /// at all makes for a "no preview" challenge.
+    pub previews: usize,
+    /// Colours shapes are drawn in.
+    pub palette: Palette,
+    /// Stamp a symbol on each block showing which kind of shape it came from.
+    pub block_symbols: bool,
+    /// Draw bright outlined blocks on solid black, and the replay ghost brighter,
+    /// whatever the palette.
+    pub high_contrast: bool,
+    /// Skip animations, such as new shapes fading in, confetti and the board filling
+    /// up after topping out.
+    pub reduced_motion: bool,
+}
+
+impl Default for GraphicsConfig {
+    fn default() -> Self {
+        Self {
+            low_power: false,
+            render_scale: 1.0,
+            texture_filter: TextureFilter::Linear,
+            resolution: Resolution::Window,
+            ui_scale: 1.0,
+            previews: 1,
+            palette: Palette::Classic,
+            block_symbols: false,
+            high_contrast: false,
+            reduced_motion: false,
+        }
+    }
+}
+
+impl GraphicsConfig {
+    /// Returns the size of text and the HUD, kept within the allowed range in case the
+    /// file was edited by hand.
+    pub fn ui_scale(&self) -> f32 {
+        self.ui_scale.clamp(MIN_UI_SCALE, MAX_UI_SCALE)
+    }
+
+    /// Returns the number of upcoming shapes shown, kept within the allowed range in
+    /// case the file was edited by hand.
+    pub fn previews(&self) -> usize {
+        self.previews.min(MAX_PREVIEWS)
+    }
+
+    /// Returns how blocks look with these settings.
+    pub fn skin(&self) -> Skin {
+        Skin {
+            palette: self.palette,
+            patterns: self.block_symbols,
+            high_contrast: self.high_contrast,
+        }
+    }
+}
+
+/// Settings of the Twitch control panel, for streams where chat votes on chaos in
+/// single player games.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TwitchConfig {
+    /// Channel whose chat is read, without the `#`.
+    pub channel: String,
+    /// Which votes chat may cast.
+    pub garbage_votes: bool,
+    pub speed_votes: bool,
+    pub piece_votes: bool,
+    /// How long each vote lasts, in seconds.
+    pub vote_secs: u64,
+    /// Time from one vote's effect to the next vote starting, in seconds.
+    pub cooldown_secs: u64,
+}
+
+impl Default for TwitchConfig {
+    fn default() -> Self {
+        Self {
+            channel: String::new(),
+            garbage_votes: true,
+            speed_votes: true,
+            piece_votes: true,
+            vote_secs: 30,
+            cooldown_secs: 15,
+        }
+    }
+}
+
+/// Whether anonymous usage data is shared, see `telemetry`. Off unless turned on.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TelemetryConfig {
+    pub enabled: bool,
+    /// Where the data is posted, an `http://` URL. Left empty, the data is only kept
+    /// in the data directory.
+    pub endpoint: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            music_volume: 1.0,
+            sfx_volume: 1.0,
+            muted: false,
+            audio_cues: false,
+            screen_reader: false,
+            toggle_soft_drop: false,
+            chat: true,
+            input_delay: 0,
+            discord_status: false,
+            player_id: rand::random(),
+            best_score: 0,
+            profile: KeyProfile::arrows().name,
+            versus_profiles: [KeyProfile::wasd().name, KeyProfile::arrows().name],
+            versus_handicaps: [Handicap::default(); 2],
+            attack_table: AttackPreset::Classic,
+            hold_limit: HoldLimit::OncePerPiece,
+            kick_table: KickPreset::Srs,
+            speed_curve: SpeedPreset::Classic,
+            versus_speed_curve: SpeedPreset::Classic,
+            save_states_to_disk: false,
+            cpu_difficulty: Difficulty::Normal,
+            cpu_bot: String::from(ai::HEURISTIC_BOT),
+            exhibition_bots: [
+                String::from(ai::HEURISTIC_BOT),
+                String::from(ai::HEURISTIC_BOT),
+            ],
+            exhibition_difficulties: [Difficulty::Normal, Difficulty::Hard],
+            window: WindowConfig::default(),
+            graphics: GraphicsConfig::default(),
+            twitch: TwitchConfig::default(),
+            telemetry: TelemetryConfig::default(),
+            profiles: KeyProfile::defaults(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config file, falling back to defaults if it is missing or invalid.
+    pub fn load() -> Self {
+        let mut config: Self = read_to_string(paths::config_file(CONFIG_FILE))
+            .ok()
+            .and_then(|s| toml::from_str(&s).ok())
+            .unwrap_or_default();
+        // Add any built in profiles which are newer than the config file
+        for profile in KeyProfile::defaults() {
+            if !config.profiles.iter().any(|p| p.name == profile.name) {
+                config.profiles.push(profile);
+            }
+        }
+        config
+    }
+
+    /// Returns the active key binding profile, or the first one if `profile` doesn't
+    /// name any of them.
+    pub fn key_profile(&self) -> &KeyProfile {
+        self.profiles
+            .iter()
+            .find(|p| p.name == self.profile)
+            .or_else(|| self.profiles.first())
+            .expect("No key profiles configured")
+    }
+
+    /// Returns the active key binding profile for modification.
+    pub fn key_profile_mut(&mut self) -> &mut KeyProfile {
+        let index = self
+            .profiles
+            .iter()
+            .position(|p| p.name == self.profile)
+            .unwrap_or(0);
+        &mut self.profiles[index]
+    }
+
+    /// Returns a player's key binding profile in versus, or the active profile if the
+    /// name doesn't match any of them.
+    ///
+    /// # Parameters
+    ///
+    /// - `player`: 0 for player 1, 1 for player 2.
+    pub fn versus_profile(&self, player: usize) -> &KeyProfile {
+        self.profiles
+            .iter()
+            .find(|p| p.name == self.versus_profiles[player])
+            .unwrap_or_else(|| self.key_profile())
+    }
+
+    /// Returns the name of the bot the computer plays with, or the built-in one if
+    /// `cpu_bot` names a bot which is no longer registered.
+    pub fn cpu_bot_name(&self) -> String {
+        registered_bot(&self.cpu_bot)
+    }
+
+    /// Returns the name of the bot playing one side of exhibition matches, or the
+    /// built-in one if it is no longer registered.
+    ///
+    /// # Parameters
+    ///
+    /// - `side`: 0 for the left, 1 for the right.
+    pub fn exhibition_bot_name(&self, side: usize) -> String {
+        registered_bot(&self.exhibition_bots[side])
+    }
+
+    /// Returns the kick table picked in the settings. A custom table is loaded from
+    /// `kicks.toml` in the config directory, which starts as a copy of the example
+    /// table, and falls back to no kicks if it can't be read.
+    pub fn kick_table(&self) -> KickTable {
+        match self.kick_table {
+            KickPreset::None => KickTable::none(),
+            KickPreset::Srs => KickTable::srs().clone(),
+            KickPreset::Custom => {
+                let path = paths::config_file(kicks::KICKS_FILE);
+                if !path.exists() {
+                    if let Err(e) = write(&path, kicks::EXAMPLE_TABLE) {
+                        eprintln!("Failed to write {}: {:#}", path.display(), e);
+                    }
+                }
+                KickTable::load(&path).unwrap_or_else(|e| {
+                    eprintln!("Failed to load {}: {:#}", path.display(), e);
+                    KickTable::none()
+                })
+            }
+        }
+    }
+
+    /// Returns the speed curve picked in the settings for single player or local
+    /// versus games, or `None` for the classic timings. A custom curve is loaded from
+    /// `speed.toml` in the config directory, which starts as a copy of the guideline
+    /// curve, and falls back to the classic timings if it can't be read.
+    ///
+    /// # Parameters
+    ///
+    /// - `versus`: `true` for local versus games.
+    pub fn speed_curve(&self, versus: bool) -> Option<SpeedCurve> {
+        let preset = if versus {
+            self.versus_speed_curve
+        } else {
+            self.speed_curve
+        };
+        match preset {
+            SpeedPreset::Classic => None,
+            SpeedPreset::Nes => Some(SpeedCurve::nes()),
+            SpeedPreset::Guideline => Some(SpeedCurve::guideline()),
+            SpeedPreset::Tgm => Some(SpeedCurve::tgm()),
+            SpeedPreset::Custom => {
+                let path = paths::config_file(speed::SPEED_FILE);
+                if !path.exists() {
+                    if let Err(e) = write(&path, speed::GUIDELINE_CURVE) {
+                        eprintln!("Failed to write {}: {:#}", path.display(), e);
+                    }
+                }
+                SpeedCurve::load(&path)
+                    .map_err(|e| eprintln!("Failed to load {}: {:#}", path.display(), e))
+                    .ok()
+            }
+        }
+    }
+
+    /// Returns the attack table picked in the settings. A custom table is loaded from
+    /// `attack.toml` in the config directory, which starts as a copy of the guideline
+    /// table, and falls back to the guideline table if it can't be read.
+    pub fn attack_table(&self) -> AttackTable {
+        match self.attack_table {
+            AttackPreset::Classic => AttackTable::classic(),
+            AttackPreset::Guideline => AttackTable::guideline(),
+            AttackPreset::Custom => {
+                let path = paths::config_file(attack::ATTACK_FILE);
+                if !path.exists() {
+                    if let Err(e) = write(&path, attack::GUIDELINE_TABLE) {
+                        eprintln!("Failed to write {}: {:#}", path.display(), e);
+                    }
+                }
+                AttackTable::load(&path).unwrap_or_else(|e| {
+                    eprintln!("Failed to load {}: {:#}", path.display(), e);
+                    AttackTable::guideline()
+                })
+            }
+        }
+    }
+
+    /// Creates the computer player for versus against it, with the bot and difficulty
+    /// picked in the settings.
+    pub fn cpu(&self) -> ai::Cpu {
+        ai::Cpu::named(&self.cpu_bot, self.cpu_difficulty)
+    }
+
+    /// Creates the computer players for an exhibition match, left then right, with the
+    /// bots and difficulties picked in the settings.
+    pub fn exhibition_cpus(&self) -> [ai::Cpu; 2] {
+        [0, 1].map(|side| {
+            ai::Cpu::named(
+                &self.exhibition_bots[side],
+                self.exhibition_difficulties[side],
+            )
+        })
+    }
+
+    /// Writes the config file.
+    pub fn save(&self) -> Result<()> {
+        write(paths::config_file(CONFIG_FILE), toml::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+/// Returns a bot's name if it is registered, otherwise the built-in bot's.
+fn registered_bot(name: &str) -> String {
+    ai::bot_names()
+        .into_iter()
+        .find(|registered| registered == name)
+        .unwrap_or_else(|| ai::HEURISTIC_BOT.to_string())
+}
@@ -0,0 +1,147 @@
+use anyhow::*;
+use std::backtrace::Backtrace;
+use std::fs::{remove_file, write};
+use std::panic::{self, PanicHookInfo};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use wgputris::paths;
+use wgputris::replay::{Playback, Replay};
+
+/// File the game in progress is saved to when the game crashes.
+const CRASH_REPLAY: &str = "crash.replay";
+/// File the crash report is written to, for attaching to bug reports.
+const CRASH_REPORT: &str = "crash-report.txt";
+/// Number of the game's last inputs listed in the crash report.
+const REPORT_INPUTS: usize = 20;
+
+/// Replay of the single player game in progress, kept up to date each frame so the panic
+/// hook can save it.
+static CURRENT_GAME: Mutex<Option<Replay>> = Mutex::new(None);
+
+/// Installs a panic hook which saves the game in progress and writes a crash report,
+/// before the usual panic message is printed.
+pub fn install_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        // The hook may run while the game is being tracked, so the lock isn't waited on
+        let replay = CURRENT_GAME.try_lock().ok().and_then(|game| game.clone());
+        let replay_path = paths::data_path(CRASH_REPLAY);
+        match &replay {
+            Some(replay) => {
+                if let Err(e) = replay.save_to(&replay_path) {
+                    eprintln!("Failed to save the game in progress: {}", e);
+                }
+            }
+            // Don't offer a game saved by an older crash
+            None => {
+                let _ = remove_file(&replay_path);
+            }
+        }
+        let path = paths::data_path(CRASH_REPORT);
+        match write(&path, report(info, replay.as_ref())) {
+            Ok(()) => eprintln!("Crash report saved to {}", path.display()),
+            Err(e) => eprintln!("Failed to write crash report: {}", e),
+        }
+        default_hook(info);
+    }));
+}
+
+/// Keeps track of the game in progress, which is saved if the game crashes.
+///
+/// # Parameters
+///
+/// - `replay`: Replay of the game in progress, or `None` if no game can be resumed.
+pub fn track(replay: Option<&Replay>) {
+    let mut current = match CURRENT_GAME.lock() {
+        Ok(current) => current,
+        Err(_) => return,
+    };
+    match (current.as_mut(), replay) {
+        // Copying every input each frame adds up over a long game, so only the length
+        // changes until there's a new input
+        (Some(current), Some(replay))
+            if current.seed == replay.seed
+                && current.inputs.len() == replay.inputs.len()
+                && current.length_ms <= replay.length_ms =>
+        {
+            current.length_ms = replay.length_ms;
+        }
+        (_, replay) => *current = replay.cloned(),
+    }
+}
+
+/// Returns the text of a crash report.
+///
+/// # Parameters
+///
+/// - `info`: Where and why the game panicked.
+/// - `replay`: Replay of the game in progress, if any.
+fn report(info: &PanicHookInfo, replay: Option<&Replay>) -> String {
+    let mut report = format!(
+        "wgputris {} crashed on {}\n\n{}\n\n",
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS,
+        info
+    );
+    match replay {
+        Some(replay) => {
+            report += &format!(
+                "Game seed {} at {} ms, saved to {}\nLast inputs:\n",
+                replay.seed, replay.length_ms, CRASH_REPLAY
+            );
+            let first = replay.inputs.len().saturating_sub(REPORT_INPUTS);
+            for (time_ms, input) in &replay.inputs[first..] {
+                report += &format!("  {} ms: {:?}\n", time_ms, input);
+            }
+        }
+        None => report += "No single player game was in progress\n",
+    }
+    report += &format!("\nBacktrace:\n{}\n", Backtrace::force_capture());
+    report
+}
+
+/// A game which was in progress when the game last crashed.
+pub struct Crash {
+    pub replay: Replay,
+    /// Path of the crash report, which may be missing if it couldn't be written.
+    pub report: PathBuf,
+}
+
+impl Crash {
+    /// Returns the game saved by the last crash, if there is one.
+    pub fn load() -> Option<Self> {
+        let path = paths::data_path(CRASH_REPLAY);
+        if !path.exists() {
+            return None;
+        }
+        match Replay::load(&path) {
+            Ok(replay) => Some(Self {
+                replay,
+                report: paths::data_path(CRASH_REPORT),
+            }),
+            Err(e) => {
+                eprintln!("Failed to load the game saved by the last crash: {}", e);
+                let _ = remove_file(&path);
+                None
+            }
+        }
+    }
+
+    /// Simulates the saved game back to where it crashed.
+    ///
+    /// # Return Value
+    ///
+    /// The playback at the point of the crash, whose game and totals can be played on.
+    pub fn resume(&self) -> Result<Playback> {
+        let mut playback = Playback::new(self.replay.clone())?;
+        playback.advance_to(self.replay.length_ms);
+        playback.dispatch_events(&mut []);
+        Ok(playback)
+    }
+
+    /// Deletes the saved game, so it isn't offered again. The crash report is kept
+    /// until the next crash replaces it.
+    pub fn discard(&self) {
+        let _ = remove_file(paths::data_path(CRASH_REPLAY));
+    }
+}
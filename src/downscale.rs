@@ -0,0 +1,271 @@
+/// Lowest render scale allowed, below which the game is too blurry to play.
+const MIN_SCALE: f32 = 0.25;
+
+/// Draws the game into a texture smaller than the window and stretches it over the
+/// window, for GPUs too slow to fill every pixel of a large window each frame. A fixed
+/// resolution can be picked instead, which is scaled to fit the window with black bars
+/// either side if the window is a different shape.
+pub struct Downscaler {
+    /// Fraction of the window's resolution the texture is.
+    scale: f32,
+    /// Width and height of the texture, whatever the window's size, or `None` to
+    /// follow `scale`.
+    fixed: Option<(u32, u32)>,
+    /// Width and height of the window in pixels.
+    window: (u32, u32),
+    format: wgpu::TextureFormat,
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+    width: u32,
+    height: u32,
+}
+
+impl Downscaler {
+    /// Creates a new `Downscaler`, or `None` if the game is drawn at the window's full
+    /// resolution.
+    ///
+    /// # Parameters
+    ///
+    /// - `device`: The graphics device.
+    /// - `format`: Format of the swap chain.
+    /// - `scale`: Fraction of the window's resolution to draw at.
+    /// - `fixed`: Width and height to draw at instead, or `None` to follow `scale`.
+    /// - `width`: Width of the window in pixels.
+    /// - `height`: Height of the window in pixels.
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        scale: f32,
+        fixed: Option<(u32, u32)>,
+        width: u32,
+        height: u32,
+    ) -> Option<Self> {
+        if scale >= 1.0 && fixed.is_none() {
+            return None;
+        }
+        let scale = scale.max(MIN_SCALE);
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::SampledTexture {
+                        multisampled: false,
+                        dimension: wgpu::TextureViewDimension::D2,
+                        component_type: wgpu::TextureComponentType::Float,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler { comparison: false },
+                    count: None,
+                },
+            ],
+            label: Some("downscale_bind_group_layout"),
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let vs_module =
+            device.create_shader_module(wgpu::include_spirv!("../shaders/blit.vert.spv"));
+        let fs_module =
+            device.create_shader_module(wgpu::include_spirv!("../shaders/blit.frag.spv"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Downscale Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Downscale Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex_stage: wgpu::ProgrammableStageDescriptor {
+                module: &vs_module,
+                entry_point: "main",
+            },
+            fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+                module: &fs_module,
+                entry_point: "main",
+            }),
+            rasterization_state: Some(wgpu::RasterizationStateDescriptor {
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: wgpu::CullMode::None,
+                ..Default::default()
+            }),
+            color_states: &[wgpu::ColorStateDescriptor {
+                format,
+                color_blend: wgpu::BlendDescriptor::REPLACE,
+                alpha_blend: wgpu::BlendDescriptor::REPLACE,
+                write_mask: wgpu::ColorWrite::ALL,
+            }],
+            primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+            depth_stencil_state: None,
+            vertex_state: wgpu::VertexStateDescriptor {
+                index_format: wgpu::IndexFormat::Uint16,
+                vertex_buffers: &[],
+            },
+            sample_count: 1,
+            sample_mask: !0,
+            alpha_to_coverage_enabled: false,
+        });
+
+        let (view, bind_group, texture_width, texture_height) = Self::create_target(
+            device,
+            format,
+            &bind_group_layout,
+            &sampler,
+            fixed.unwrap_or((
+                (width as f32 * scale) as u32,
+                (height as f32 * scale) as u32,
+            )),
+        );
+        Some(Self {
+            scale,
+            fixed,
+            window: (width, height),
+            format,
+            pipeline,
+            bind_group_layout,
+            sampler,
+            view,
+            bind_group,
+            width: texture_width,
+            height: texture_height,
+        })
+    }
+
+    /// Creates the texture drawn into, and the bind group which samples it.
+    ///
+    /// # Parameters
+    ///
+    /// - `size`: Width and height of the texture in pixels.
+    fn create_target(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        (width, height): (u32, u32),
+    ) -> (wgpu::TextureView, wgpu::BindGroup, u32, u32) {
+        let width = width.max(1);
+        let height = height.max(1);
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("downscale_target"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+            label: Some("downscale_bind_group"),
+        });
+        (view, bind_group, width, height)
+    }
+
+    /// Recreates the texture drawn into for a new window size.
+    ///
+    /// # Parameters
+    ///
+    /// - `device`: The graphics device.
+    /// - `width`: New width of the window in pixels.
+    /// - `height`: New height of the window in pixels.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.window = (width, height);
+        if self.fixed.is_some() {
+            // The texture stays the same size, and is only drawn somewhere else
+            return;
+        }
+        let (view, bind_group, width, height) = Self::create_target(
+            device,
+            self.format,
+            &self.bind_group_layout,
+            &self.sampler,
+            (
+                (width as f32 * self.scale) as u32,
+                (height as f32 * self.scale) as u32,
+            ),
+        );
+        self.view = view;
+        self.bind_group = bind_group;
+        self.width = width;
+        self.height = height;
+    }
+
+    /// Returns the width and height in pixels of the texture drawn into.
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Returns the fixed width and height drawn at, or `None` if they follow the
+    /// window's size.
+    pub fn fixed(&self) -> Option<(u32, u32)> {
+        self.fixed
+    }
+
+    /// Returns the texture to draw the game into instead of the swap chain.
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    /// Stretches everything drawn into the texture over the frame. A fixed resolution
+    /// keeps its shape, as large as fits in the middle of the frame.
+    ///
+    /// # Parameters
+    ///
+    /// - `encoder`: Encoder to record the draw into.
+    /// - `frame`: The swap chain frame to draw to.
+    pub fn blit(&self, encoder: &mut wgpu::CommandEncoder, frame: &wgpu::TextureView) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                attachment: frame,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        if self.fixed.is_some() {
+            let (window_width, window_height) = (self.window.0 as f32, self.window.1 as f32);
+            let scale = (window_width / self.width as f32).min(window_height / self.height as f32);
+            let (width, height) = (self.width as f32 * scale, self.height as f32 * scale);
+            render_pass.set_viewport(
+                (window_width - width) / 2.0,
+                (window_height - height) / 2.0,
+                width,
+                height,
+                0.0,
+                1.0,
+            );
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
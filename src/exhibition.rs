@@ -0,0 +1,203 @@
+use crate::VERSUS_SHIFTS;
+use bevy_ecs::entity::Entity;
+use web_time::Instant;
+use wgputris::ai::{Cpu, Difficulty};
+use wgputris::arena::{Arena, BoardFrame, Celebrates, Garbage, Widget, WidgetKind};
+use wgputris::attack::{Attack, AttackTable};
+use wgputris::game::Game;
+use wgputris::stats::Stats;
+
+/// Speeds an exhibition can be watched at, as multiples of real time.
+const SPEEDS: [f64; 5] = [0.5, 1.0, 2.0, 4.0, 8.0];
+/// Index in `SPEEDS` of real time.
+const REAL_TIME: usize = 1;
+/// How long the result of a match is shown before the next starts.
+const NEXT_MATCH_SECS: f64 = 3.0;
+
+/// Versus matches between two computer players, one after another, for watching bots
+/// play each other or comparing one bot with another.
+pub struct Exhibition {
+    /// The boards of the current match.
+    arena: Arena,
+    /// The boards on the left and right.
+    boards: [Entity; 2],
+    /// Names and difficulties of the computer players on the left and right, which
+    /// play each match afresh.
+    players: [(String, Difficulty); 2],
+    /// Rows of garbage sent for each kind of line clear.
+    table: AttackTable,
+    /// Matches won by each side so far.
+    wins: [usize; 2],
+    /// Index in `SPEEDS` of the speed both games run at.
+    speed: usize,
+    /// When the last match ended, while its result is shown.
+    finished_at: Option<Instant>,
+}
+
+impl Exhibition {
+    /// Creates a new `Exhibition` and starts its first match.
+    ///
+    /// # Parameters
+    ///
+    /// - `cpus`: The computer players on the left and right.
+    /// - `table`: Rows of garbage sent for each kind of line clear.
+    pub fn new(cpus: [Cpu; 2], table: AttackTable) -> Self {
+        let players = [0, 1].map(|side| {
+            let cpu = &cpus[side];
+            (cpu.name().to_string(), cpu.difficulty())
+        });
+        let (arena, boards) = new_match(rand::random(), SPEEDS[REAL_TIME], cpus, &table);
+        Self {
+            arena,
+            boards,
+            players,
+            table,
+            wins: [0, 0],
+            speed: REAL_TIME,
+            finished_at: None,
+        }
+    }
+
+    /// Starts a new match between the same players, keeping the number of wins.
+    pub fn restart(&mut self) {
+        let players = &self.players;
+        let cpus = [0, 1].map(|side| Cpu::named(&players[side].0, players[side].1));
+        let (arena, boards) = new_match(rand::random(), self.speed(), cpus, &self.table);
+        self.arena = arena;
+        self.boards = boards;
+        self.finished_at = None;
+    }
+
+    /// Makes both computers' moves and advances both games by a frame, or starts the
+    /// next match once the last one's result has been shown.
+    ///
+    /// # Parameters
+    ///
+    /// - `previews`: Number of upcoming shapes shown, as passed to `Game::render`.
+    /// - `animate`: `false` for reduced motion, which leaves out animations and
+    ///   confetti.
+    pub fn update(&mut self, previews: usize, animate: bool) {
+        if let Some(finished_at) = self.finished_at {
+            if finished_at.elapsed().as_secs_f64() >= NEXT_MATCH_SECS {
+                self.restart();
+            }
+            return;
+        }
+        self.arena.set_view(previews, animate);
+        self.arena.update();
+        let over = self.games().map(|game| game.game_over);
+        if over[0] || over[1] {
+            // Topping out on the same frame is a draw
+            if over[0] != over[1] {
+                self.wins[over[0] as usize] += 1;
+            }
+            self.finished_at = Some(Instant::now());
+        }
+    }
+
+    /// Returns the speed both games run at, as a multiple of real time.
+    pub fn speed(&self) -> f64 {
+        SPEEDS[self.speed]
+    }
+
+    /// Steps the speed up or down.
+    ///
+    /// # Parameters
+    ///
+    /// - `direction`: `1` to speed up, `-1` to slow down.
+    pub fn change_speed(&mut self, direction: i32) {
+        self.speed = (self.speed as i32 + direction).clamp(0, SPEEDS.len() as i32 - 1) as usize;
+        let speed = self.speed();
+        for &board in self.boards.iter() {
+            self.arena.board_mut(board).game.set_speed(speed);
+        }
+    }
+
+    /// Returns the games on the left and right.
+    pub fn games(&self) -> [&Game; 2] {
+        self.boards.map(|board| &self.arena.board(board).game)
+    }
+
+    /// Returns the totals of the games on the left and right.
+    pub fn stats(&self) -> [&Stats; 2] {
+        self.boards.map(|board| &self.arena.board(board).stats)
+    }
+
+    /// Returns the boards on the left and right as drawn after the last `update`, with
+    /// their celebrations and the garbage each has sent.
+    pub fn frame(&self) -> &[BoardFrame] {
+        self.arena.frame()
+    }
+
+    /// Returns the names of both computer players, shown over their boards.
+    pub fn names(&self) -> [String; 2] {
+        [0, 1].map(|side| {
+            let (name, difficulty) = &self.players[side];
+            format!("{} ({})", name, difficulty.label())
+        })
+    }
+
+    /// Returns the score of the exhibition so far and the speed, plus the result of a
+    /// match which has just ended.
+    pub fn status(&self) -> String {
+        let mut status = format!(
+            "Wins {} - {}  Speed {}x",
+            self.wins[0],
+            self.wins[1],
+            self.speed()
+        );
+        if self.finished_at.is_some() {
+            let names = self.names();
+            let [left, right] = self.games();
+            let result = match (left.game_over, right.game_over) {
+                (true, false) => format!("{} wins!", names[1]),
+                (false, true) => format!("{} wins!", names[0]),
+                _ => String::from("Draw!"),
+            };
+            status = format!("{}\n{}", result, status);
+        }
+        status
+    }
+}
+
+/// Sets up the boards of a match, which send each other garbage and celebrate their
+/// tetrises.
+///
+/// # Parameters
+///
+/// - `seed`: Seed the match's shapes are dealt from, and its garbage holes picked by.
+/// - `speed`: How fast game time passes compared to real time.
+/// - `cpus`: The computer players on the left and right.
+/// - `table`: Rows of garbage sent for each kind of line clear.
+fn new_match(seed: u64, speed: f64, cpus: [Cpu; 2], table: &AttackTable) -> (Arena, [Entity; 2]) {
+    let mut arena = Arena::new(seed);
+    // Both sides get the same shapes, so neither wins by luck of the draw
+    let boards = [0, 1].map(|side| arena.spawn_board(new_game(seed, speed), VERSUS_SHIFTS[side]));
+    for (side, cpu) in IntoIterator::into_iter(cpus).enumerate() {
+        let (board, target) = (boards[side], boards[1 - side]);
+        let attack = Attack::new(table.clone(), 100);
+        arena.add_computer(board, cpu);
+        let world = arena.world_mut();
+        world
+            .entity_mut(board)
+            .insert((Garbage { attack, target }, Celebrates));
+        world.spawn(Widget {
+            board,
+            row: 0,
+            kind: WidgetKind::Sent,
+        });
+    }
+    (arena, boards)
+}
+
+/// Creates one side's game of a match.
+///
+/// # Parameters
+///
+/// - `seed`: Seed the match's shapes are dealt from.
+/// - `speed`: How fast game time passes compared to real time.
+fn new_game(seed: u64, speed: f64) -> Game {
+    let mut game = Game::with_seed(seed);
+    game.set_speed(speed);
+    game
+}
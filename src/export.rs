@@ -0,0 +1,88 @@
+//! Renders a replay to an MP4 video without a window, for sharing runs without screen
+//! recording software. Frames are drawn on the CPU like clips, at a fixed timestep so
+//! the video plays at exactly the game's speed however long drawing takes, and piped
+//! to `ffmpeg` to encode.
+
+use crate::capture;
+use crate::config::Config;
+use crate::{LAYOUT_HEIGHT, LAYOUT_WIDTH, VIEW_WIDTH};
+use anyhow::*;
+use bytemuck::Zeroable;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use wgputris::replay::{Playback, Replay};
+use wgputris::{Vertex, VERTEX_COUNT};
+
+/// Extra time the final board stays on screen at the end of the video, in milliseconds.
+const END_HOLD_MS: u64 = 2000;
+
+/// Renders a replay to an MP4 file with `ffmpeg`, which must be on the `PATH`.
+///
+/// # Parameters
+///
+/// - `path`: The replay file.
+/// - `output`: File to write, or `None` for the replay's path ending in `.mp4`.
+/// - `fps`: Frames per second of the video.
+/// - `height`: Height of the video in pixels. The width keeps the window's aspect
+///   ratio.
+pub fn run(path: &Path, output: Option<PathBuf>, fps: u32, height: u32) -> Result<()> {
+    if fps == 0 || height == 0 {
+        bail!("The frame rate and height must be above 0");
+    }
+    let replay =
+        Replay::load(path).with_context(|| format!("Failed to load replay {}", path.display()))?;
+    let output = output.unwrap_or_else(|| path.with_extension("mp4"));
+    let length_ms = replay.length_ms;
+    let mut playback = Playback::new(replay)?;
+    let graphics = Config::load().graphics;
+    let (skin, previews) = (graphics.skin(), graphics.previews());
+
+    // Video encoders need an even number of pixels each way
+    let height = (height as usize + 1) & !1;
+    let width = (height * LAYOUT_WIDTH as usize / LAYOUT_HEIGHT as usize + 1) & !1;
+    let mut ffmpeg = Command::new("ffmpeg")
+        .args(["-y", "-loglevel", "error"])
+        .args(["-f", "rawvideo", "-pix_fmt", "rgb24"])
+        .args(["-s", &format!("{}x{}", width, height)])
+        .args(["-r", &fps.to_string(), "-i", "-"])
+        .args(["-c:v", "libx264", "-pix_fmt", "yuv420p"])
+        .arg(&output)
+        .stdin(Stdio::piped())
+        .spawn()
+        .context("Failed to run ffmpeg, which must be installed to export videos")?;
+    let mut stdin = ffmpeg.stdin.take().unwrap();
+
+    let mut vertices = vec![Vertex::zeroed(); VERTEX_COUNT];
+    let frames = (length_ms + END_HOLD_MS) * fps as u64 / 1000;
+    for frame in 0..=frames {
+        playback.advance_to(frame * 1000 / fps as u64);
+        playback.dispatch_events(&mut []);
+        let game = playback.game();
+        game.render(&mut vertices, 0, previews);
+        if !graphics.reduced_motion {
+            game.animate_spawn(&mut vertices);
+            game.animate_rotation(&mut vertices);
+        }
+        skin.apply(&mut vertices);
+        let pixels = capture::rasterize(&vertices, VIEW_WIDTH, width, height);
+        if stdin.write_all(&pixels).is_err() {
+            // ffmpeg quit early, and says why below
+            break;
+        }
+        if frame % (fps as u64 * 10) == 0 {
+            eprint!(
+                "\rRendered {} of {} s",
+                frame / fps as u64,
+                frames / fps as u64
+            );
+        }
+    }
+    drop(stdin);
+    eprintln!();
+    if !ffmpeg.wait()?.success() {
+        bail!("ffmpeg failed to encode {}", output.display());
+    }
+    println!("Saved {}", output.display());
+    Ok(())
+}
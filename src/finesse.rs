@@ -0,0 +1,428 @@
+//! Finesse training: placing shapes on an empty board with as few key presses as
+//! possible. Holding a move key until the shape reaches the wall counts as one press,
+//! as it does for a player using auto shift.
+
+use crate::action::Action;
+use crate::game::{EventHandler, Game, GameEvent};
+use crate::gameboard::Gameboard;
+use crate::sequence::Sequence;
+use crate::tetromino::{Tetromino, TetrominoKind};
+use crate::{GAMEBOARD_HEIGHT, GAMEBOARD_HIDDEN_ROWS, GAMEBOARD_WIDTH};
+
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::collections::{HashSet, VecDeque};
+
+/// Most patterns listed as the session's weakest.
+const WEAKEST: usize = 3;
+
+/// A key press which moves or turns the shape.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Press {
+    Left,
+    Right,
+    /// Moving left, held until the shape reaches the wall.
+    DasLeft,
+    /// Moving right, held until the shape reaches the wall.
+    DasRight,
+    RotateCw,
+    RotateCcw,
+}
+
+impl Press {
+    /// Every press, in the order they're tried when searching for the fewest.
+    pub const ALL: [Press; 6] = [
+        Press::DasLeft,
+        Press::DasRight,
+        Press::Left,
+        Press::Right,
+        Press::RotateCw,
+        Press::RotateCcw,
+    ];
+
+    /// Description shown when suggesting the fewest presses.
+    pub fn label(self) -> &'static str {
+        match self {
+            Press::Left => "Left",
+            Press::Right => "Right",
+            Press::DasLeft => "Hold left",
+            Press::DasRight => "Hold right",
+            Press::RotateCw => "Clockwise",
+            Press::RotateCcw => "Counterclockwise",
+        }
+    }
+
+    /// Makes the press on a shape on an empty board.
+    ///
+    /// # Return Value
+    ///
+    /// `true` if the shape moved or turned.
+    fn apply(self, shape: &mut Tetromino) -> bool {
+        let mut moved = *shape;
+        match self {
+            Press::Left | Press::DasLeft => moved.add_pos(-1, 0),
+            Press::Right | Press::DasRight => moved.add_pos(1, 0),
+            Press::RotateCw => moved.rotate_cw(),
+            Press::RotateCcw => moved.rotate_ccw(),
+        }
+        if !fits(&moved) {
+            return false;
+        }
+        *shape = moved;
+        if let Press::DasLeft | Press::DasRight = self {
+            while self.apply(shape) {}
+        }
+        true
+    }
+}
+
+/// Returns `true` if a shape is inside the walls, floor and hidden rows of the board.
+fn fits(shape: &Tetromino) -> bool {
+    shape.board_locs().iter().all(|&(x, y)| {
+        (0..GAMEBOARD_WIDTH as i32).contains(&x)
+            && (-(GAMEBOARD_HIDDEN_ROWS as i32)..GAMEBOARD_HEIGHT as i32).contains(&y)
+    })
+}
+
+/// Returns the cells a shape covers once dropped to the floor of an empty board,
+/// sorted.
+fn landed(shape: &Tetromino) -> [(i32, i32); 4] {
+    let bottom = shape
+        .board_locs()
+        .iter()
+        .map(|&(_, y)| y)
+        .max()
+        .unwrap_or(0);
+    let mut cells = shape
+        .board_locs()
+        .map(|(x, y)| (x, y + GAMEBOARD_HEIGHT as i32 - 1 - bottom));
+    cells.sort_unstable();
+    cells
+}
+
+/// Returns a shape where it spawns.
+fn spawned(kind: TetrominoKind) -> Tetromino {
+    let (x, y) = Gameboard::new().get_spawn_loc();
+    let mut shape = Tetromino::new(kind);
+    shape.set_pos(x, y);
+    shape
+}
+
+/// A place the player is asked to put a shape on an empty board.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub kind: TetrominoKind,
+    /// Quarter turns clockwise from the spawn orientation, from 0 to 3.
+    pub rotation: u8,
+    /// Cells the shape covers on the floor, sorted.
+    pub cells: [(i32, i32); 4],
+}
+
+impl Target {
+    /// Returns every place each shape can be put, counting orientations which cover
+    /// the same cells once.
+    pub fn all() -> Vec<Target> {
+        let mut targets: Vec<Target> = Vec::new();
+        for &kind in TetrominoKind::ALL.iter() {
+            for rotation in 0..4 {
+                // Turned where it spawns, as a player turns it
+                let mut shape = spawned(kind);
+                if !(0..rotation).all(|_| Press::RotateCw.apply(&mut shape)) {
+                    continue;
+                }
+                Press::DasLeft.apply(&mut shape);
+                loop {
+                    let cells = landed(&shape);
+                    if !targets.iter().any(|target| target.cells == cells) {
+                        targets.push(Target {
+                            kind,
+                            rotation,
+                            cells,
+                        });
+                    }
+                    if !Press::Right.apply(&mut shape) {
+                        break;
+                    }
+                }
+            }
+        }
+        targets
+    }
+
+    /// Describes the target, e.g. "T turned clockwise in columns 5-6".
+    pub fn label(&self) -> String {
+        let orientation = match (self.kind, self.rotation) {
+            (TetrominoKind::O, _) => "",
+            (TetrominoKind::I, 0) | (TetrominoKind::S, 0) | (TetrominoKind::Z, 0) => " flat",
+            (TetrominoKind::I, _) | (TetrominoKind::S, _) | (TetrominoKind::Z, _) => " upright",
+            (_, 0) => " as it spawns",
+            (_, 1) => " turned clockwise",
+            (_, 2) => " upside down",
+            _ => " turned counterclockwise",
+        };
+        let left = self.cells[0].0 + 1;
+        let right = self.cells[3].0 + 1;
+        format!(
+            "{:?}{} in columns {}-{}",
+            self.kind, orientation, left, right
+        )
+    }
+
+    /// Returns one of the shortest ways of pressing keys to get the shape from where it
+    /// spawns to the target.
+    pub fn fewest_presses(&self) -> Vec<Press> {
+        let start = spawned(self.kind);
+        let mut seen = HashSet::new();
+        seen.insert(start.board_locs());
+        let mut queue = VecDeque::new();
+        queue.push_back((start, Vec::new()));
+        while let Some((shape, presses)) = queue.pop_front() {
+            if landed(&shape) == self.cells {
+                return presses;
+            }
+            for &press in Press::ALL.iter() {
+                let mut next = shape;
+                if press.apply(&mut next) && seen.insert(next.board_locs()) {
+                    let mut next_presses = presses.clone();
+                    next_presses.push(press);
+                    queue.push_back((next, next_presses));
+                }
+            }
+        }
+        // Every target is found from the spawn, as `all` found it that way
+        Vec::new()
+    }
+}
+
+/// How often a pattern was placed with the fewest presses.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Tally {
+    pub attempts: usize,
+    pub correct: usize,
+}
+
+impl Tally {
+    /// Returns the share of attempts which were correct, from 0.0 to 1.0.
+    pub fn accuracy(&self) -> f64 {
+        self.correct as f64 / self.attempts.max(1) as f64
+    }
+}
+
+/// How the player placed one target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attempt {
+    pub target: Target,
+    /// Whether the shape landed on the target.
+    pub placed: bool,
+    /// Keys pressed to move, turn or hold the shape.
+    pub presses: usize,
+    /// One of the shortest ways of reaching the target.
+    pub fewest: Vec<Press>,
+}
+
+impl Attempt {
+    /// Returns `true` if the shape landed on the target with the fewest presses.
+    pub fn is_correct(&self) -> bool {
+        self.placed && self.presses <= self.fewest.len()
+    }
+}
+
+/// A session of finesse training, which asks for one target at a time on an empty
+/// board and keeps count of how each pattern went.
+///
+/// Its games' events have to be passed to it, and each key pressed to `press`.
+pub struct Trainer {
+    rng: StdRng,
+    targets: Vec<Target>,
+    target: Target,
+    /// Keys pressed for the current target.
+    presses: usize,
+    /// Whether the shape for the current target has locked.
+    locked: bool,
+    /// How the session went for each target, in the order of `targets`.
+    tallies: Vec<Tally>,
+    last: Option<Attempt>,
+}
+
+impl Trainer {
+    /// Creates a new `Trainer` and picks its first target.
+    ///
+    /// # Parameters
+    ///
+    /// - `seed`: Seed targets are picked from.
+    pub fn new(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let targets = Target::all();
+        let target = *targets.choose(&mut rng).expect("No finesse targets");
+        Self {
+            rng,
+            tallies: vec![Tally::default(); targets.len()],
+            targets,
+            target,
+            presses: 0,
+            locked: false,
+            last: None,
+        }
+    }
+
+    /// Returns the place the player is asked to put the current shape.
+    pub fn target(&self) -> &Target {
+        &self.target
+    }
+
+    /// Returns how the last target went, if any has been tried.
+    pub fn last(&self) -> Option<&Attempt> {
+        self.last.as_ref()
+    }
+
+    /// Returns a game with an empty board and the current target's shape in play.
+    pub fn game(&mut self) -> Game {
+        let mut game = Game::with_seed(self.rng.gen());
+        game.set_sequence(Sequence {
+            kinds: vec![self.target.kind],
+            repeat: true,
+        });
+        game
+    }
+
+    /// Counts a key pressed for the current target. Drops aren't counted, as every
+    /// placement ends with one.
+    ///
+    /// # Parameters
+    ///
+    /// - `action`: The action of the key, once when it goes down.
+    pub fn press(&mut self, action: Action) {
+        if !matches!(action, Action::SoftDrop | Action::HardDrop) {
+            self.presses += 1;
+        }
+    }
+
+    /// Checks where the shape landed once it has locked, and moves on to a new target.
+    ///
+    /// # Parameters
+    ///
+    /// - `game`: The game from `game`, which the target was placed in.
+    ///
+    /// # Return Value
+    ///
+    /// How the target went, or `None` if the shape hasn't locked yet.
+    pub fn finish(&mut self, game: &Game) -> Option<Attempt> {
+        if !self.locked {
+            return None;
+        }
+        let mut cells = Vec::new();
+        for (y, row) in game.occupied().iter().enumerate() {
+            for (x, &filled) in row.iter().enumerate() {
+                if filled {
+                    cells.push((x as i32, y as i32));
+                }
+            }
+        }
+        cells.sort_unstable();
+        let attempt = Attempt {
+            target: self.target,
+            placed: cells == self.target.cells,
+            presses: self.presses,
+            fewest: self.target.fewest_presses(),
+        };
+        if let Some(index) = self.targets.iter().position(|t| *t == self.target) {
+            let tally = &mut self.tallies[index];
+            tally.attempts += 1;
+            tally.correct += attempt.is_correct() as usize;
+        }
+        self.target = *self
+            .targets
+            .choose(&mut self.rng)
+            .expect("No finesse targets");
+        self.presses = 0;
+        self.locked = false;
+        self.last = Some(attempt.clone());
+        Some(attempt)
+    }
+
+    /// Returns the session's tally over every target.
+    pub fn total(&self) -> Tally {
+        self.tallies
+            .iter()
+            .fold(Tally::default(), |total, tally| Tally {
+                attempts: total.attempts + tally.attempts,
+                correct: total.correct + tally.correct,
+            })
+    }
+
+    /// Returns the patterns missed most this session, least accurate first.
+    pub fn weakest(&self) -> Vec<(Target, Tally)> {
+        let mut missed: Vec<(Target, Tally)> = self
+            .targets
+            .iter()
+            .copied()
+            .zip(self.tallies.iter().copied())
+            .filter(|(_, tally)| tally.correct < tally.attempts)
+            .collect();
+        missed.sort_by(|(_, a), (_, b)| {
+            a.accuracy()
+                .total_cmp(&b.accuracy())
+                .then(b.attempts.cmp(&a.attempts))
+        });
+        missed.truncate(WEAKEST);
+        missed
+    }
+
+    /// Returns the lines shown beside the board: the target, how the last one went,
+    /// and the session's accuracy and weakest patterns.
+    pub fn hud(&self) -> Vec<String> {
+        let mut lines = vec![format!("Place: {}", self.target.label())];
+        if let Some(last) = &self.last {
+            lines.push(if last.is_correct() {
+                String::from("Last: Perfect")
+            } else if !last.placed {
+                format!("Last: Missed {}", last.target.label())
+            } else {
+                format!(
+                    "Last: {} presses, {} needed",
+                    last.presses,
+                    last.fewest.len()
+                )
+            });
+            if !last.is_correct() {
+                let fewest: Vec<&str> = last.fewest.iter().map(|press| press.label()).collect();
+                lines.push(format!(
+                    "  Fewest: {}",
+                    if fewest.is_empty() {
+                        String::from("Just drop")
+                    } else {
+                        fewest.join(", ")
+                    }
+                ));
+            }
+        }
+        let total = self.total();
+        lines.push(format!(
+            "Accuracy: {}/{} ({:.0}%)",
+            total.correct,
+            total.attempts,
+            100.0 * total.accuracy()
+        ));
+        let weakest = self.weakest();
+        if !weakest.is_empty() {
+            lines.push(String::from("Weakest:"));
+            for (target, tally) in weakest {
+                lines.push(format!(
+                    "  {}: {}/{}",
+                    target.label(),
+                    tally.correct,
+                    tally.attempts
+                ));
+            }
+        }
+        lines
+    }
+}
+
+impl EventHandler for Trainer {
+    fn handle_event(&mut self, event: &GameEvent) {
+        if let GameEvent::PieceLocked { .. } = event {
+            self.locked = true;
+        }
+    }
+}
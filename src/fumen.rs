@@ -0,0 +1,303 @@
+use crate::tetromino::TetrominoKind;
+use crate::GAMEBOARD_HEIGHT;
+use anyhow::*;
+
+/// Prefix of fumen data in the current (v1.15) format.
+const VERSION_PREFIX: &str = "v115@";
+/// Characters fumen data is written with, each holding six bits.
+const ENCODE_TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+/// Fumen fields are always 10 blocks wide.
+const FIELD_WIDTH: usize = 10;
+/// Rows of a fumen field above the floor, three more than are visible in the game.
+const FIELD_ROWS: usize = 23;
+/// Blocks of a fumen field, including the garbage row below the floor.
+const FIELD_BLOCKS: usize = FIELD_WIDTH * (FIELD_ROWS + 1);
+/// Rows at the top of a fumen field which don't exist on the gameboard.
+const HIDDEN_ROWS: usize = FIELD_ROWS - GAMEBOARD_HEIGHT;
+/// A field with nothing changed, written as a single run of blocks.
+const UNCHANGED_FIELD: usize = 8 * FIELD_BLOCKS + FIELD_BLOCKS - 1;
+/// Comment characters are packed in base 96, starting from the space character.
+const COMMENT_BASE: usize = 96;
+/// Comment prefix of the piece queue, as understood by other fumen tools.
+const QUEUE_PREFIX: &str = "#Q=";
+/// fumen inserts this character every so often so long data can wrap, to be ignored.
+const LINE_BREAK: char = '?';
+/// Number of characters between line breaks.
+const LINE_LENGTH: usize = 47;
+
+/// A block of a fumen field.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Cell {
+    /// A block which was part of a tetromino.
+    Piece(TetrominoKind),
+    /// A grey block which was never part of a tetromino.
+    Garbage,
+}
+
+impl Cell {
+    /// Returns the number fumen uses for a block, `0` being empty.
+    fn to_code(cell: Option<Cell>) -> usize {
+        match cell {
+            None => 0,
+            Some(Cell::Piece(TetrominoKind::I)) => 1,
+            Some(Cell::Piece(TetrominoKind::L)) => 2,
+            Some(Cell::Piece(TetrominoKind::O)) => 3,
+            Some(Cell::Piece(TetrominoKind::Z)) => 4,
+            Some(Cell::Piece(TetrominoKind::T)) => 5,
+            Some(Cell::Piece(TetrominoKind::J)) => 6,
+            Some(Cell::Piece(TetrominoKind::S)) => 7,
+            Some(Cell::Garbage) => 8,
+        }
+    }
+
+    /// The reverse of `to_code`.
+    fn from_code(code: usize) -> Result<Option<Cell>> {
+        let kind = match code {
+            0 => return Ok(None),
+            1 => TetrominoKind::I,
+            2 => TetrominoKind::L,
+            3 => TetrominoKind::O,
+            4 => TetrominoKind::Z,
+            5 => TetrominoKind::T,
+            6 => TetrominoKind::J,
+            7 => TetrominoKind::S,
+            8 => return Ok(Some(Cell::Garbage)),
+            _ => bail!("Invalid block in fumen: {}", code),
+        };
+        Ok(Some(Cell::Piece(kind)))
+    }
+}
+
+/// A board setup which can be shared as a fumen string, the format used by the fumen
+/// editor and most other Tetris tools. Only the first page of a fumen is used.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page {
+    /// Blocks of the gameboard, row by row from the top.
+    pub field: Vec<Option<Cell>>,
+    pub hold: Option<TetrominoKind>,
+    pub current: Option<TetrominoKind>,
+    /// Upcoming tetrominoes, soonest first.
+    pub next: Vec<TetrominoKind>,
+}
+
+impl Page {
+    /// Writes the page as a fumen string, with the piece queue in its comment.
+    pub fn encode(&self) -> String {
+        let mut data = Vec::new();
+
+        // Each block is stored as its difference to the previous page, which is empty
+        let mut codes = [0; FIELD_BLOCKS];
+        for (i, cell) in self.field.iter().enumerate() {
+            codes[HIDDEN_ROWS * FIELD_WIDTH + i] = Cell::to_code(*cell);
+        }
+        let mut start = 0;
+        while start < FIELD_BLOCKS {
+            let run = codes[start..]
+                .iter()
+                .take_while(|&&c| c == codes[start])
+                .count();
+            push_value(&mut data, (codes[start] + 8) * FIELD_BLOCKS + run - 1, 2);
+            start += run;
+        }
+        if codes.iter().all(|&c| c == 0) {
+            // How many of the following pages are also unchanged
+            push_value(&mut data, 0, 1);
+        }
+
+        let comment = escape(&self.queue_comment());
+        // No piece is placed, blocks are coloured and the comment is set
+        let flags = 0b0_1_1_0_0;
+        push_value(&mut data, flags * FIELD_BLOCKS * 4 * 8, 3);
+        push_value(&mut data, comment.len(), 2);
+        for chunk in comment.as_bytes().chunks(4) {
+            let value = chunk
+                .iter()
+                .rev()
+                .fold(0, |value, &c| value * COMMENT_BASE + (c - b' ') as usize);
+            push_value(&mut data, value, 5);
+        }
+
+        let mut fumen = String::from(VERSION_PREFIX);
+        for (i, &c) in data.iter().enumerate() {
+            if i > 0 && i % LINE_LENGTH == 0 {
+                fumen.push(LINE_BREAK);
+            }
+            fumen.push(c as char);
+        }
+        fumen
+    }
+
+    /// Reads the first page of a fumen string. Anything before the version prefix is
+    /// ignored, so fumen URLs can be used as well.
+    pub fn decode(fumen: &str) -> Result<Self> {
+        let start = fumen
+            .find(VERSION_PREFIX)
+            .ok_or_else(|| anyhow!("Not a v115 fumen"))?;
+        let mut data = fumen[start + VERSION_PREFIX.len()..]
+            .chars()
+            .filter(|&c| c != LINE_BREAK);
+
+        let mut codes = Vec::with_capacity(FIELD_BLOCKS);
+        while codes.len() < FIELD_BLOCKS {
+            let value = pop_value(&mut data, 2)?;
+            let code = (value / FIELD_BLOCKS)
+                .checked_sub(8)
+                .ok_or_else(|| anyhow!("Invalid field in fumen"))?;
+            codes.extend(std::iter::repeat_n(code, value % FIELD_BLOCKS + 1));
+            if value == UNCHANGED_FIELD {
+                pop_value(&mut data, 1)?;
+            }
+        }
+        if codes.len() > FIELD_BLOCKS {
+            bail!("Invalid field in fumen");
+        }
+        if codes[..HIDDEN_ROWS * FIELD_WIDTH].iter().any(|&c| c != 0) {
+            bail!("Fumen field is taller than {} rows", GAMEBOARD_HEIGHT);
+        }
+        let field = codes[HIDDEN_ROWS * FIELD_WIDTH..FIELD_ROWS * FIELD_WIDTH]
+            .iter()
+            .map(|&code| Cell::from_code(code))
+            .collect::<Result<_>>()?;
+
+        let mut page = Self {
+            field,
+            hold: None,
+            current: None,
+            next: Vec::new(),
+        };
+        let action = pop_value(&mut data, 3)?;
+        let piece = action % 8;
+        if piece != 0 {
+            // A piece being placed on the page is the best guess for the current piece
+            if let Some(Cell::Piece(kind)) = Cell::from_code(piece)? {
+                page.current = Some(kind);
+            }
+        }
+        let has_comment = action / (FIELD_BLOCKS * 4 * 8 * 8) % 2 == 1;
+        if has_comment {
+            let length = pop_value(&mut data, 2)?;
+            let mut comment = String::with_capacity(length);
+            while comment.len() < length {
+                let mut value = pop_value(&mut data, 5)?;
+                for _ in 0..4.min(length - comment.len()) {
+                    comment.push((b' ' + (value % COMMENT_BASE) as u8) as char);
+                    value /= COMMENT_BASE;
+                }
+            }
+            page.read_queue_comment(&unescape(&comment));
+        }
+        Ok(page)
+    }
+
+    /// Describes the piece queue the way other fumen tools do, e.g. `#Q=[T](I)SZO`.
+    fn queue_comment(&self) -> String {
+        let name = |kind: &TetrominoKind| format!("{:?}", kind);
+        format!(
+            "{}[{}]({}){}",
+            QUEUE_PREFIX,
+            self.hold.as_ref().map(name).unwrap_or_default(),
+            self.current.as_ref().map(name).unwrap_or_default(),
+            self.next.iter().map(name).collect::<String>()
+        )
+    }
+
+    /// Fills in the piece queue from a comment written by `queue_comment`. Comments
+    /// which aren't a queue are ignored.
+    fn read_queue_comment(&mut self, comment: &str) {
+        let queue = match comment.strip_prefix(QUEUE_PREFIX) {
+            Some(queue) => queue,
+            None => return,
+        };
+        let mut hold = false;
+        let mut current = false;
+        for c in queue.chars() {
+            match c {
+                '[' => hold = true,
+                ']' => hold = false,
+                '(' => current = true,
+                ')' => current = false,
+                _ => {
+                    let kind = match TetrominoKind::ALL
+                        .iter()
+                        .find(|kind| format!("{:?}", kind) == c.to_string())
+                    {
+                        Some(kind) => *kind,
+                        // Anything after the queue, e.g. a description
+                        None => return,
+                    };
+                    if hold {
+                        self.hold = Some(kind);
+                    } else if current {
+                        self.current = Some(kind);
+                    } else {
+                        self.next.push(kind);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Writes `value` as `digits` characters of `ENCODE_TABLE`, least significant first.
+fn push_value(data: &mut Vec<u8>, mut value: usize, digits: usize) {
+    for _ in 0..digits {
+        data.push(ENCODE_TABLE[value % 64]);
+        value /= 64;
+    }
+}
+
+/// Reads a value written by `push_value`.
+fn pop_value(data: &mut impl Iterator<Item = char>, digits: usize) -> Result<usize> {
+    let mut value = 0;
+    for digit in 0..digits {
+        let c = data.next().ok_or_else(|| anyhow!("Fumen is cut short"))?;
+        let index = ENCODE_TABLE
+            .iter()
+            .position(|&e| e as char == c)
+            .ok_or_else(|| anyhow!("Invalid character in fumen: {}", c))?;
+        value += index * 64usize.pow(digit as u32);
+    }
+    Ok(value)
+}
+
+/// Escapes a comment the way JavaScript's `escape` does, which fumen comments use.
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() || "@*_+-./".contains(c) {
+            escaped.push(c);
+        } else if (c as u32) < 0x100 {
+            escaped += &format!("%{:02X}", c as u32);
+        } else {
+            escaped += &format!("%u{:04X}", c as u32);
+        }
+    }
+    escaped
+}
+
+/// The reverse of `escape`. Invalid escapes are left as they are.
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let (hex, length) = match rest.strip_prefix("%u") {
+            Some(after) => (after.get(..4), 6),
+            None if c == '%' => (rest.get(1..3), 3),
+            None => (None, 1),
+        };
+        match hex
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .and_then(std::char::from_u32)
+        {
+            Some(decoded) => {
+                unescaped.push(decoded);
+                rest = &rest[length..];
+            }
+            None => {
+                unescaped.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    unescaped
+}
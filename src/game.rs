@@ -1,118 +1,1085 @@
+use crate::action::Action;
+use crate::attack::AttackTable;
+use crate::clock::{Clock, RealClock};
+use crate::fumen::{Cell, Page};
 use crate::gameboard::Gameboard;
-use crate::tetromino::Tetromino;
+use crate::kicks::KickTable;
+use crate::replay::{Input, Replay};
+use crate::rhythm::{Rhythm, ON_BEAT_BONUS};
+use crate::sequence::Sequence;
+use crate::speed::{SpeedCurve, SpeedLevel};
+use crate::tetromino::{Tetromino, TetrominoKind};
 use crate::Vertex;
-use crate::{BLOCK_SIZE, GAMEBOARD_HEIGHT, GAMEBOARD_OFFSET, GAMEBOARD_WIDTH};
-use winit::event::{ElementState, KeyboardInput, VirtualKeyCode};
+use crate::{
+    BLOCK_SIZE, GAMEBOARD_HEIGHT, GAMEBOARD_HIDDEN_ROWS, GAMEBOARD_OFFSET, GAMEBOARD_WIDTH,
+    VERTEX_COUNT,
+};
 
+use anyhow::*;
 use rand::prelude::*;
-use std::time::Instant;
+use rand::rngs::StdRng;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::time::Duration;
+
+/// Number of cleared lines needed to advance a level.
+const LINES_PER_LEVEL: usize = 10;
+/// Gravity acts once a frame, at this rate.
+pub const FRAMES_PER_SECOND: u64 = 60;
+/// Gravity is counted in this many parts of a cell, so fractions of a cell add up
+/// exactly.
+pub const GRAVITY_UNIT: u64 = 65536;
+/// Gravity at level 1, in cells per frame, as a numerator and denominator: a row
+/// every quarter second.
+const START_GRAVITY: (u64, u64) = (1, 15);
+/// How many times stronger gravity gets with each level, as a numerator and
+/// denominator: 1 / 0.85.
+const GRAVITY_PER_LEVEL: (u64, u64) = (20, 17);
+/// Strongest gravity, in cells per frame, at which shapes reach the stack as soon as
+/// they spawn.
+const MAX_GRAVITY: u64 = 20;
+/// Bits below `GRAVITY_UNIT`ths of a cell gravity is worked out to at each level,
+/// before it's rounded up.
+const GRAVITY_FRACTION_BITS: u32 = 32;
+/// Fastest soft drop, as a multiple of gravity. Higher factors are lowered to it, so
+/// no player can drop faster than another online by editing their config.
+pub const MAX_SOFT_DROP_FACTOR: u32 = 40;
+/// Colour of garbage blocks, which were never part of a shape.
+const GARBAGE_COLOR: [f32; 4] = [0.5, 0.5, 0.5, 1.0];
+/// Where the next shape is shown, to the right of the board.
+const NEXT_SHAPE_POS: (i32, i32) = (15, 6);
+/// Where the held shape is shown, to the left of the board.
+const HOLD_SHAPE_POS: (i32, i32) = (-6, 6);
+/// Most shapes `Game::render` can preview, the next shape and the smaller ones after it.
+pub const MAX_PREVIEWS: usize = 6;
+/// Rows each smaller preview after the next shape takes up.
+const PREVIEW_ROWS: i32 = 2;
+/// Size of the previews after the next shape, as a fraction of its size.
+const PREVIEW_SCALE: f32 = 2.0 / 3.0;
+/// How long a shape takes to fade and grow in after spawning or being swapped with the
+/// held shape, in milliseconds of game time. See `Game::animate_spawn`.
+pub const SPAWN_ANIMATION_MS: u64 = 120;
+/// Size shapes start at as they fade in, as a fraction of their full size.
+const SPAWN_START_SCALE: f32 = 0.5;
+/// Opacity of the ghost showing where the current shape would land.
+pub const GHOST_SHAPE_ALPHA: f32 = 0.3;
+/// Vertices `Game::render` fills with the ghost of the current shape, drawn after
+/// everything else.
+pub const GHOST_SHAPE_VERTICES: Range<usize> = 1398..VERTEX_COUNT;
+/// How long a turned shape takes to swing round to its new orientation, in
+/// milliseconds of game time. See `Game::animate_rotation`.
+pub const ROTATION_ANIMATION_MS: u64 = 50;
+
+/// Something that happened during the game which other systems may want to react to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GameEvent {
+    /// A new current shape entered the board.
+    Spawned(TetrominoKind),
+    /// The current shape was moved sideways.
+    Moved,
+    /// The current shape was rotated.
+    Rotated,
+    /// The current shape was locked to the board, either by gravity or a hard drop.
+    PieceLocked { hard_drop: bool },
+    /// One or more rows were completed and removed. `t_spin` is set if the shape was a
+    /// T turned into place, `combo` counts the clears in a row just before this one, and
+    /// `perfect_clear` is set if the board was left empty.
+    LinesCleared {
+        n: usize,
+        kind: ClearKind,
+        t_spin: bool,
+        combo: usize,
+        perfect_clear: bool,
+    },
+    /// The score increased to the given value.
+    ScoreChanged(usize),
+    /// The level increased to the given value.
+    LevelUp(usize),
+    /// The current shape was swapped into the hold slot.
+    Held,
+    /// The height of the stack after a piece was placed, in rows from the bottom.
+    StackHeight(usize),
+    /// A new shape could not be spawned.
+    GameOver,
+}
+
+/// How many rows a line clear removed at once.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ClearKind {
+    Single,
+    Double,
+    Triple,
+    Tetris,
+}
+
+impl ClearKind {
+    /// Returns the kind of clear which removed `n` rows, which must be at least 1.
+    pub fn from_lines(n: usize) -> Self {
+        match n {
+            1 => ClearKind::Single,
+            2 => ClearKind::Double,
+            3 => ClearKind::Triple,
+            _ => ClearKind::Tetris,
+        }
+    }
+}
+
+/// How often the current shape may be swapped into the hold slot.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HoldLimit {
+    /// As often as the player likes, even with the same shape.
+    Unlimited,
+    /// Once, until the shape swapped in is placed.
+    OncePerPiece,
+    /// Never, so every shape has to be placed as it comes.
+    Disabled,
+}
+
+impl HoldLimit {
+    /// Every limit, in the order they are listed.
+    pub const ALL: [HoldLimit; 3] = [
+        HoldLimit::Unlimited,
+        HoldLimit::OncePerPiece,
+        HoldLimit::Disabled,
+    ];
+
+    /// Name shown in menus.
+    pub fn label(self) -> &'static str {
+        match self {
+            HoldLimit::Unlimited => "Unlimited",
+            HoldLimit::OncePerPiece => "Once per Piece",
+            HoldLimit::Disabled => "Off",
+        }
+    }
+
+    /// Name written to replay files.
+    pub fn name(self) -> &'static str {
+        match self {
+            HoldLimit::Unlimited => "unlimited",
+            HoldLimit::OncePerPiece => "once_per_piece",
+            HoldLimit::Disabled => "disabled",
+        }
+    }
+
+    /// Returns the limit written to a replay file as `name`, if there is one.
+    pub fn from_name(name: &str) -> Option<Self> {
+        HoldLimit::ALL
+            .iter()
+            .copied()
+            .find(|limit| limit.name() == name)
+    }
+}
+
+/// The last rotation the current shape attempted.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RotationTest {
+    pub clockwise: bool,
+    /// Cells of the board the rotated shape would cover, which may be off the board.
+    pub locs: [(i32, i32); 4],
+    /// Whether the rotated shape fitted.
+    pub passed: bool,
+}
+
+/// A snapshot of a game's internal state, for debugging collisions and timing.
+#[derive(Debug, Clone)]
+pub struct DebugInfo {
+    /// Which cells of the board hold locked blocks, row by row from the top.
+    pub occupied: [[bool; GAMEBOARD_WIDTH]; GAMEBOARD_HEIGHT],
+    /// Position of the current shape's centre, in blocks.
+    pub position: (i32, i32),
+    /// Blocks of the current shape relative to its centre.
+    pub block_locs: [(i32, i32); 4],
+    /// Cells of the board the current shape covers.
+    pub board_locs: [(i32, i32); 4],
+    /// A copy of the current shape, for trying out moves without changing the game.
+    pub shape: Tetromino,
+    pub rotation: Option<RotationTest>,
+    /// Cells the current shape falls each frame, before soft drop.
+    pub gravity: f64,
+    /// Part of a cell the current shape has fallen towards the next row.
+    pub fall: f64,
+    pub soft_drop_factor: u32,
+    /// Frames the current shape has rested towards the speed curve's lock delay.
+    pub lock_frames: u32,
+    pub pending_seconds: f64,
+    pub time_ms: u64,
+    /// Whether game time only passes by `Game::advance_frame`.
+    pub frame_advance: bool,
+}
+
+/// A system which reacts to game events, e.g. sound effects or statistics, so it
+/// needn't poll the game for changes.
+pub trait EventHandler {
+    /// Reacts to something which happened during the game.
+    ///
+    /// # Parameters
+    ///
+    /// - `event`: The event which just happened.
+    fn handle_event(&mut self, event: &GameEvent);
+}
 
 /// Stores the state of our entire game
 pub struct Game {
     score: usize,
+    lines: usize,
+    level: usize,
     board: Gameboard,
     next_shape: Tetromino,
     current_shape: Tetromino,
-    next_shape_offset: (usize, usize),
-    seconds_per_tick: f64,
-    seconds_since_tick: f64,
+    hold_shape: Option<Tetromino>,
+    hold_used: bool,
+    hold_limit: HoldLimit,
+    /// Offsets the current shape tries when it doesn't fit after turning.
+    kicks: KickTable,
+    /// Where the next and held shapes are shown, on the gameboard's grid but outside
+    /// it.
+    next_shape_offset: (i32, i32),
+    hold_shape_offset: (i32, i32),
+    /// Cells the current shape falls each frame, in `GRAVITY_UNIT`ths of a cell.
+    gravity: u64,
+    /// How far the current shape has fallen towards the next row, in `GRAVITY_UNIT`ths
+    /// of a cell.
+    fall: u64,
+    /// Gravity at every level as a percentage of normal, e.g. as a handicap.
+    gravity_percent: u32,
+    /// Multiplies `gravity` while soft drop is held.
+    soft_drop_factor: u32,
+    /// The beat gravity follows instead of `gravity` in rhythm mode.
+    rhythm: Option<Rhythm>,
+    /// Gravity, lock delay and ARE at each level, or `None` for the classic timings.
+    speed_curve: Option<SpeedCurve>,
+    /// Frames the current shape has rested on the stack since it last fell a row,
+    /// towards the speed curve's lock delay.
+    lock_frames: u32,
     shape_placed: bool,
-    rng: ThreadRng,
-    last_loop_end: Instant,
+    /// Game time the last shape locked, which the next waits the speed curve's ARE
+    /// after to appear.
+    placed_at_ms: u64,
+    /// Shapes to come after `next_shape` before random ones, e.g. from a fumen.
+    queue: VecDeque<TetrominoKind>,
+    /// Shapes `queue` is refilled with whenever it runs out, from a repeating
+    /// `Sequence`.
+    repeat_kinds: Vec<TetrominoKind>,
+    rng: StdRng,
+    /// Game time in milliseconds. Time only passes in whole milliseconds, so a replay
+    /// can simulate the game exactly.
+    time_ms: u64,
+    /// Game time for the real time since `last_loop_end` not yet added to `time_ms`,
+    /// in nanoseconds.
+    pending_ns: u64,
+    /// Game time which passes for each second of real time, in millionths of a
+    /// second.
+    speed_ppm: u64,
+    /// Whether `process_game_loop` lets no time pass, leaving `advance_frame` to step
+    /// the game.
+    frame_advance: bool,
+    /// Time of `clock` at the end of the last loop.
+    last_loop_end: Duration,
+    /// Where `process_game_loop` gets the time from.
+    clock: Box<dyn Clock>,
+    replay: Replay,
+    events: Vec<GameEvent>,
+    /// Hold and rotate actions held down by the player, applied to the next shape as it
+    /// spawns.
+    initial_actions: Vec<Action>,
+    /// Kept for the debug overlay.
+    last_rotation: Option<RotationTest>,
+    /// Whether the current shape's last successful move was a turn, for spotting
+    /// T-spins.
+    rotated_last: bool,
+    /// Clears in a row so far, or `None` if the last shape placed cleared nothing.
+    combo: Option<usize>,
+    /// Game time the current shape appeared, by spawning or from the hold slot.
+    spawned_at_ms: u64,
+    /// Game time the held shape was last swapped into the hold slot.
+    held_at_ms: u64,
+    /// Game time the current shape last turned, and whether it turned clockwise.
+    rotated_at_ms: Option<(u64, bool)>,
+    /// Whether the stack reached the top, rather than the game being ended by setting
+    /// `game_over`.
+    topped_out: bool,
     pub game_over: bool,
 }
 
 impl Game {
     /// Creates a new `Game`
     pub fn new() -> Self {
-        let mut rng = rand::thread_rng();
+        Self::with_seed(rand::random())
+    }
+
+    /// Creates a new `Game` whose shapes are picked by a random number generator
+    /// seeded with `seed`, so the same seed always gives the same shapes.
+    ///
+    /// # Parameters
+    ///
+    /// - `seed`: Seed for the random number generator.
+    pub fn with_seed(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
 
         let gameboard = Gameboard::new();
 
         let mut next_shape = Tetromino::new_random(&mut rng);
-        next_shape.set_pos(30, 7);
+        next_shape.set_pos(NEXT_SHAPE_POS.0, NEXT_SHAPE_POS.1);
 
         let mut current_shape = Tetromino::new_random(&mut rng);
         let spawn_loc = gameboard.get_spawn_loc();
-        current_shape.set_pos(spawn_loc.0 as i32, spawn_loc.1 as i32);
+        current_shape.set_pos(spawn_loc.0, spawn_loc.1);
 
         Self {
             score: 0,
+            lines: 0,
+            level: 1,
             board: gameboard,
             next_shape,
             current_shape,
-            next_shape_offset: (30, 7),
-            seconds_per_tick: 0.25,
-            seconds_since_tick: 0.0,
+            hold_shape: None,
+            hold_used: false,
+            hold_limit: HoldLimit::OncePerPiece,
+            kicks: KickTable::srs().clone(),
+            next_shape_offset: NEXT_SHAPE_POS,
+            hold_shape_offset: HOLD_SHAPE_POS,
+            gravity: classic_gravity(1, 100),
+            fall: 0,
+            gravity_percent: 100,
+            soft_drop_factor: 1,
+            rhythm: None,
+            speed_curve: None,
+            lock_frames: 0,
             shape_placed: false,
+            placed_at_ms: 0,
+            queue: VecDeque::new(),
+            repeat_kinds: Vec::new(),
             rng,
-            last_loop_end: Instant::now(),
+            time_ms: 0,
+            pending_ns: 0,
+            speed_ppm: 1_000_000,
+            frame_advance: false,
+            last_loop_end: Duration::ZERO,
+            clock: Box::new(RealClock::new()),
+            replay: Replay::new(seed),
+            events: vec![GameEvent::Spawned(current_shape.kind())],
+            initial_actions: Vec::new(),
+            last_rotation: None,
+            rotated_last: false,
+            combo: None,
+            spawned_at_ms: 0,
+            held_at_ms: 0,
+            rotated_at_ms: None,
+            topped_out: false,
             game_over: false,
         }
     }
 
-    /// Handles user input
-    pub fn process_input(&mut self, input: KeyboardInput) -> bool {
-        match (input.virtual_keycode, input.state) {
-            (None, _) => {
-                return false;
+    /// Creates a new `Game` starting from a board setup shared as a fumen string.
+    /// Shapes missing from the fumen's piece queue are picked randomly, as in
+    /// `with_seed`.
+    ///
+    /// # Parameters
+    ///
+    /// - `seed`: Seed for the random shapes.
+    /// - `fumen`: The board setup, see `Page::decode`.
+    pub fn from_fumen(seed: u64, fumen: &str) -> Result<Self> {
+        let page = Page::decode(fumen)?;
+        let mut game = Self::with_seed(seed);
+        for (i, cell) in page.field.iter().enumerate() {
+            let color = cell.map(|cell| match cell {
+                Cell::Piece(kind) => Tetromino::new(kind).color(),
+                Cell::Garbage => GARBAGE_COLOR,
+            });
+            let _ = game
+                .board
+                .set_content(i % GAMEBOARD_WIDTH, i / GAMEBOARD_WIDTH, color);
+        }
+        if let Some(kind) = page.current {
+            game.current_shape = Tetromino::new(kind);
+            let spawn_loc = game.board.get_spawn_loc();
+            game.current_shape.set_pos(spawn_loc.0, spawn_loc.1);
+            game.events = vec![GameEvent::Spawned(kind)];
+        }
+        if let Some(kind) = page.hold {
+            let mut held = Tetromino::new(kind);
+            held.set_pos(game.hold_shape_offset.0, game.hold_shape_offset.1);
+            game.hold_shape = Some(held);
+        }
+        game.queue = page.next.into_iter().collect();
+        if !game.queue.is_empty() {
+            game.pick_next_shape();
+        }
+        game.replay.fumen = Some(fumen.to_string());
+        Ok(game)
+    }
+
+    /// Deals shapes in the order of `sequence` instead of randomly, starting with the
+    /// current shape, and records it in the replay. Replaces the current, next and
+    /// queued shapes, e.g. from a fumen, so it's only meant for a game which hasn't
+    /// started yet.
+    ///
+    /// # Parameters
+    ///
+    /// - `sequence`: The shapes to deal.
+    pub fn set_sequence(&mut self, sequence: Sequence) {
+        self.queue = sequence.kinds.iter().copied().collect();
+        self.repeat_kinds = if sequence.repeat {
+            sequence.kinds.clone()
+        } else {
+            Vec::new()
+        };
+        self.pick_next_shape();
+        self.current_shape = Tetromino::new(self.next_shape.kind());
+        let spawn_loc = self.board.get_spawn_loc();
+        self.current_shape.set_pos(spawn_loc.0, spawn_loc.1);
+        self.events = vec![GameEvent::Spawned(self.current_shape.kind())];
+        self.pick_next_shape();
+        self.replay.sequence = Some(sequence);
+    }
+
+    /// Returns the board and the shapes in play as a fumen string, which can be
+    /// opened in the fumen editor and most other Tetris tools.
+    pub fn to_fumen(&self) -> String {
+        let mut field = Vec::with_capacity(GAMEBOARD_WIDTH * GAMEBOARD_HEIGHT);
+        for y in 0..GAMEBOARD_HEIGHT {
+            for x in 0..GAMEBOARD_WIDTH {
+                field.push(self.board.get_content(x, y).map(|color| {
+                    TetrominoKind::ALL
+                        .iter()
+                        .find(|&&kind| Tetromino::new(kind).color() == color)
+                        .map_or(Cell::Garbage, |&kind| Cell::Piece(kind))
+                }));
             }
-            (Some(key), ElementState::Pressed) => match key {
-                VirtualKeyCode::Left => {
-                    self.attempt_move(-1, 0);
-                    true
+        }
+        let mut next = vec![self.next_shape.kind()];
+        next.extend(self.queue.iter().copied());
+        Page {
+            field,
+            hold: self.hold_shape.map(|shape| shape.kind()),
+            current: Some(self.current_shape.kind()),
+            next,
+        }
+        .encode()
+    }
+
+    /// Performs a player action on the current shape.
+    ///
+    /// # Parameters
+    ///
+    /// - `action`: The action to perform.
+    pub fn process_action(&mut self, action: Action) {
+        self.replay
+            .inputs
+            .push((self.time_ms, Input::Action(action)));
+        self.perform_action(action);
+    }
+
+    /// Applies an input recorded in a replay.
+    ///
+    /// # Parameters
+    ///
+    /// - `input`: The input to apply.
+    pub fn apply_input(&mut self, input: &Input) {
+        match input {
+            Input::Action(action) => self.process_action(*action),
+            Input::SoftDropFactor(factor) => self.set_soft_drop_factor(*factor),
+            Input::InitialActions(actions) => self.set_initial_actions(actions),
+            Input::Garbage { rows, hole } => self.add_garbage(*rows, *hole),
+            Input::NextKind(kind) => self.set_next_kind(*kind),
+        }
+    }
+
+    /// Performs an action without recording it, e.g. as part of an initial rotation.
+    fn perform_action(&mut self, action: Action) {
+        if self.shape_placed {
+            // The last shape is already locked, and the next hasn't appeared yet, even
+            // with no ARE until the next step
+            return;
+        }
+        match action {
+            Action::MoveLeft => {
+                if self.attempt_move(-1, 0) {
+                    self.events.push(GameEvent::Moved);
                 }
-                VirtualKeyCode::Right => {
-                    self.attempt_move(1, 0);
-                    true
+            }
+            Action::MoveRight => {
+                if self.attempt_move(1, 0) {
+                    self.events.push(GameEvent::Moved);
                 }
-                VirtualKeyCode::Down => {
-                    self.drop();
-                    self.current_shape.lock_to_gameboard(&mut self.board);
-                    self.shape_placed = true;
-                    true
+            }
+            Action::SoftDrop => {
+                self.attempt_move(0, 1);
+            }
+            Action::HardDrop => {
+                self.drop();
+                self.lock(true);
+                if self
+                    .rhythm
+                    .is_some_and(|rhythm| rhythm.is_on_beat(self.time_ms))
+                {
+                    self.set_score(self.score + ON_BEAT_BONUS);
                 }
-                VirtualKeyCode::Z => {
-                    self.attempt_rotate_ccw();
-                    true
+            }
+            Action::RotateCcw => {
+                if self.attempt_rotate_ccw() {
+                    self.events.push(GameEvent::Rotated);
                 }
-                VirtualKeyCode::X => {
-                    self.attempt_rotate_cw();
-                    true
+            }
+            Action::RotateCw => {
+                if self.attempt_rotate_cw() {
+                    self.events.push(GameEvent::Rotated);
                 }
-                _ => false,
-            },
-            _ => false,
+            }
+            Action::Hold => {
+                self.attempt_hold();
+            }
         }
     }
 
     /// Called once per loop of the game, does all the biz.
     ///
     pub fn process_game_loop(&mut self) {
-        let loop_start = Instant::now();
-        self.seconds_since_tick += (loop_start - self.last_loop_end).as_secs_f64();
-        if self.seconds_since_tick > self.seconds_per_tick {
-            self.tick();
-            self.seconds_since_tick -= self.seconds_per_tick;
+        if self.frame_advance {
+            self.resume();
+            return;
+        }
+        let loop_start = self.clock.now();
+        let elapsed_us = (loop_start - self.last_loop_end).as_micros() as u64;
+        self.pending_ns += elapsed_us * self.speed_ppm / 1000;
+        self.last_loop_end = loop_start;
+        let ms = self.pending_ns / 1_000_000;
+        self.pending_ns %= 1_000_000;
+        self.advance(ms);
+    }
+
+    /// Advances the game time, stopping early on game over.
+    ///
+    /// # Parameters
+    ///
+    /// - `ms`: Number of milliseconds to advance by.
+    pub fn advance(&mut self, ms: u64) {
+        for _ in 0..ms {
+            if self.game_over {
+                break;
+            }
+            self.step();
+        }
+    }
+
+    /// Advances the game time by one millisecond.
+    fn step(&mut self) {
+        self.time_ms += 1;
+        self.replay.length_ms = self.time_ms;
+        if self.shape_placed && self.time_ms < self.placed_at_ms + self.are_ms() {
+            // Nothing moves until the next shape appears
+            return;
+        }
+        // Frames start at the first millisecond of each sixtieth of a second
+        let frame =
+            self.time_ms * FRAMES_PER_SECOND / 1000 > (self.time_ms - 1) * FRAMES_PER_SECOND / 1000;
+        match self.rhythm {
+            Some(rhythm) => self.apply_beat_gravity(rhythm, frame),
+            None if frame => self.apply_gravity(),
+            None => {}
         }
         if self.shape_placed {
-            if !self.spawn_next_shape() {
+            let t_spin = self.is_t_spin();
+            self.hold_used = false;
+            self.shape_placed = false;
+            let cleared = self.board.get_completed_row_indices();
+            let _ = self.board.remove_rows(&cleared);
+            let rows_complete = cleared.len();
+            if rows_complete > 0 {
+                let combo = self.combo.map_or(0, |combo| combo + 1);
+                self.combo = Some(combo);
+                self.events.push(GameEvent::LinesCleared {
+                    n: rows_complete,
+                    kind: ClearKind::from_lines(rows_complete),
+                    t_spin,
+                    combo,
+                    perfect_clear: self.board.stack_height() == 0,
+                });
+                self.set_score(self.score + 400 * rows_complete);
+                self.add_lines(rows_complete);
+            } else {
+                self.combo = None;
+            }
+            // A shape locked wholly in the hidden rows, even after the rows below it
+            // were cleared, tops out like a shape with no room to spawn
+            let top = self.board.top() as i32;
+            let locked_out = self.current_shape.board_locs().iter().all(|&(_, y)| {
+                let below = cleared.iter().filter(|&&row| row as i32 > y).count();
+                y + (below as i32) < top
+            });
+            if locked_out || !self.spawn_next_shape() {
+                self.topped_out = true;
                 self.game_over = true;
+                self.events.push(GameEvent::GameOver);
             } else {
                 self.pick_next_shape();
-                let rows_complete = self.board.remove_completed_rows();
-                self.set_score(self.score + 400 * rows_complete);
+                self.events
+                    .push(GameEvent::StackHeight(self.board.stack_height()));
+                self.apply_initial_actions();
+            }
+        }
+    }
+
+    /// Sets how fast game time passes compared to real time, e.g. to slow a practice
+    /// game down. Everything the game times, such as gravity, scales together, while
+    /// replays are kept in game time so they play back the same at any speed.
+    ///
+    /// # Parameters
+    ///
+    /// - `speed`: Seconds of game time for each second of real time.
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed_ppm = (speed.max(0.0) * 1_000_000.0).round() as u64;
+    }
+
+    /// Returns how fast game time passes compared to real time, see `set_speed`.
+    pub fn speed(&self) -> f64 {
+        self.speed_ppm as f64 / 1_000_000.0
+    }
+
+    /// Returns the game time in milliseconds.
+    pub fn time_ms(&self) -> u64 {
+        self.time_ms
+    }
+
+    /// Returns a hash of everything which decides how the game plays on, for two
+    /// clients simulating the same inputs to check they still agree. It's the same on
+    /// every platform.
+    pub fn state_hash(&self) -> u64 {
+        let mut words = vec![
+            self.time_ms,
+            self.score as u64,
+            self.lines as u64,
+            self.level as u64,
+            self.gravity,
+            self.fall,
+            self.gravity_percent as u64,
+            self.soft_drop_factor as u64,
+            self.rhythm.map_or(0, Rhythm::millibpm),
+            self.board.top() as u64,
+            self.hold_used as u64,
+            self.hold_limit as u64,
+            self.lock_frames as u64,
+            self.placed_at_ms,
+            self.rotated_last as u64,
+            self.combo.map_or(0, |combo| combo as u64 + 1),
+            self.game_over as u64,
+            // The next number the shapes would be picked with stands in for the state
+            // of the random number generator
+            self.rng.clone().gen(),
+        ];
+        for shape in [
+            Some(&self.current_shape),
+            Some(&self.next_shape),
+            self.hold_shape.as_ref(),
+        ] {
+            match shape {
+                Some(shape) => {
+                    words.push(shape.kind() as u64 + 1);
+                    for (x, y) in shape.board_locs().iter() {
+                        words.push(*x as i64 as u64);
+                        words.push(*y as i64 as u64);
+                    }
+                }
+                None => words.push(0),
+            }
+        }
+        words.extend(self.queue.iter().map(|&kind| kind as u64));
+        words.extend(self.repeat_kinds.iter().map(|&kind| kind as u64));
+        words.extend(self.initial_actions.iter().map(|&action| action as u64));
+        for row in self.occupied().iter() {
+            words.push(row.iter().fold(0, |bits, &cell| bits << 1 | cell as u64));
+        }
+        for y in -(GAMEBOARD_HIDDEN_ROWS as i32)..0 {
+            words.push((0..GAMEBOARD_WIDTH as i32).fold(0, |bits, x| {
+                bits << 1 | self.board.get_content_at(x, y).is_some() as u64
+            }));
+        }
+        // 64-bit FNV-1a over the words' bytes, least significant first
+        words
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+            })
+    }
+
+    /// Returns the seed and inputs of the game so far.
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    /// Sets how many times faster than normal gravity the current shape falls.
+    ///
+    /// # Parameters
+    ///
+    /// - `factor`: The soft drop factor while soft drop is held, or 1 otherwise.
+    pub fn set_soft_drop_factor(&mut self, factor: u32) {
+        let factor = factor.clamp(1, MAX_SOFT_DROP_FACTOR);
+        if factor != self.soft_drop_factor {
+            self.replay
+                .inputs
+                .push((self.time_ms, Input::SoftDropFactor(factor)));
+            self.soft_drop_factor = factor;
+        }
+    }
+
+    /// Sets the actions whose keys are currently held, for the initial hold (IHS) and
+    /// initial rotation (IRS) of the next shape to spawn. Anything other than hold and
+    /// rotation is ignored.
+    ///
+    /// # Parameters
+    ///
+    /// - `actions`: Actions held down this frame.
+    pub fn set_initial_actions(&mut self, actions: &[Action]) {
+        let actions: Vec<Action> = actions
+            .iter()
+            .copied()
+            .filter(|action| matches!(action, Action::Hold | Action::RotateCw | Action::RotateCcw))
+            .collect();
+        if actions != self.initial_actions {
+            self.replay
+                .inputs
+                .push((self.time_ms, Input::InitialActions(actions.clone())));
+            self.initial_actions = actions;
+        }
+    }
+
+    /// Pushes the stack up with rows of garbage from the bottom, each with an empty
+    /// cell in the same column. The current shape is pushed up with the stack if it
+    /// would be buried, and the game is over if the stack or the shape is pushed out
+    /// of play.
+    ///
+    /// # Parameters
+    ///
+    /// - `rows`: Number of rows to add.
+    /// - `hole`: Column of the empty cell in each row, wrapped onto the board.
+    pub fn add_garbage(&mut self, rows: usize, hole: usize) {
+        if self.game_over || rows == 0 {
+            return;
+        }
+        self.replay
+            .inputs
+            .push((self.time_ms, Input::Garbage { rows, hole }));
+        let mut kept = true;
+        for _ in 0..rows {
+            kept &= self.board.raise(hole % GAMEBOARD_WIDTH, GARBAGE_COLOR);
+            if self.does_shape_intersect_locked_blocks(&self.current_shape) {
+                self.current_shape.add_pos(0, -1);
+            }
+        }
+        if !kept || !self.is_position_legal(&self.current_shape) {
+            self.topped_out = true;
+            self.game_over = true;
+            self.events.push(GameEvent::GameOver);
+            return;
+        }
+        self.events
+            .push(GameEvent::StackHeight(self.board.stack_height()));
+    }
+
+    /// Shrinks the board to its bottom rows, e.g. as a handicap, and moves the current
+    /// shape to where shapes now spawn. Like `set_sequence`, it's only meant for a game
+    /// which hasn't started yet, and it isn't recorded in the replay.
+    ///
+    /// # Parameters
+    ///
+    /// - `rows`: Rows in play, from 1 to `GAMEBOARD_HEIGHT`.
+    pub fn set_rows(&mut self, rows: usize) {
+        self.board.set_rows(rows);
+        let spawn_loc = self.board.get_spawn_loc();
+        self.current_shape.set_pos(spawn_loc.0, spawn_loc.1);
+    }
+
+    /// Scales gravity at every level, e.g. to slow one side of a versus match down as
+    /// a handicap. It isn't recorded in the replay.
+    ///
+    /// # Parameters
+    ///
+    /// - `percent`: Percentage of normal gravity.
+    pub fn set_gravity_percent(&mut self, percent: u32) {
+        self.gravity_percent = percent;
+        self.gravity = self.level_gravity();
+    }
+
+    /// Makes gravity follow a beat instead of the level's speed, for rhythm mode, and
+    /// records it in the replay. Gravity moves the current shape down on each beat, or
+    /// more often at higher levels, but only locks it on the beat, and hard drops on the
+    /// beat score `ON_BEAT_BONUS`.
+    ///
+    /// # Parameters
+    ///
+    /// - `rhythm`: The beat, counted from the start of the game.
+    pub fn set_rhythm(&mut self, rhythm: Rhythm) {
+        self.rhythm = Some(rhythm);
+        self.replay.bpm = Some(rhythm.bpm());
+    }
+
+    /// Times gravity, lock delay and ARE by a speed curve instead of the classic
+    /// timings, and records it in the replay.
+    ///
+    /// # Parameters
+    ///
+    /// - `curve`: The curve, or `None` for the classic timings.
+    pub fn set_speed_curve(&mut self, curve: Option<SpeedCurve>) {
+        self.speed_curve = curve;
+        self.replay.speed_curve = self.speed_curve.clone();
+        self.gravity = self.level_gravity();
+    }
+
+    /// Sets how often shapes may be held, and records it in the replay.
+    ///
+    /// # Parameters
+    ///
+    /// - `limit`: The new limit, which applies from the current shape on.
+    pub fn set_hold_limit(&mut self, limit: HoldLimit) {
+        self.hold_limit = limit;
+        self.replay.hold_limit = limit;
+    }
+
+    /// Sets the offsets shapes try when they don't fit after turning, and records
+    /// the table in the replay unless it has no kicks. Games start with
+    /// `KickTable::srs`.
+    ///
+    /// # Parameters
+    ///
+    /// - `kicks`: The new table, which applies from the next turn on.
+    pub fn set_kick_table(&mut self, kicks: KickTable) {
+        self.replay.kicks = Some(kicks.clone()).filter(|kicks| *kicks != KickTable::none());
+        self.kicks = kicks;
+    }
+
+    /// Returns how often shapes may be held.
+    pub fn hold_limit(&self) -> HoldLimit {
+        self.hold_limit
+    }
+
+    /// Returns the beat gravity follows in rhythm mode, or `None` in other games.
+    pub fn rhythm(&self) -> Option<Rhythm> {
+        self.rhythm
+    }
+
+    /// Replaces the next shape, e.g. with one picked by a vote.
+    ///
+    /// # Parameters
+    ///
+    /// - `kind`: The shape to come next.
+    pub fn set_next_kind(&mut self, kind: TetrominoKind) {
+        if self.game_over {
+            return;
+        }
+        self.replay
+            .inputs
+            .push((self.time_ms, Input::NextKind(kind)));
+        self.next_shape = Tetromino::new(kind);
+        self.next_shape
+            .set_pos(self.next_shape_offset.0, self.next_shape_offset.1);
+    }
+
+    /// Swaps and then rotates a newly spawned shape according to `initial_actions`.
+    fn apply_initial_actions(&mut self) {
+        let actions = std::mem::take(&mut self.initial_actions);
+        if actions.contains(&Action::Hold) {
+            self.attempt_hold();
+        }
+        if self.game_over {
+            return;
+        }
+        for action in actions {
+            if matches!(action, Action::RotateCw | Action::RotateCcw) {
+                self.perform_action(action);
+            }
+        }
+    }
+
+    /// Stops or restarts game time passing by itself, for stepping through a game a
+    /// frame at a time with `advance_frame` while debugging. Actions still apply at
+    /// once.
+    ///
+    /// # Parameters
+    ///
+    /// - `frame_advance`: `true` to stop time passing by itself.
+    pub fn set_frame_advance(&mut self, frame_advance: bool) {
+        self.frame_advance = frame_advance;
+    }
+
+    /// Returns `true` while game time only passes by `advance_frame`.
+    pub fn frame_advance(&self) -> bool {
+        self.frame_advance
+    }
+
+    /// Advances the game time to the start of the next frame, when gravity and lock
+    /// delay next act.
+    pub fn advance_frame(&mut self) {
+        let frame = self.time_ms * FRAMES_PER_SECOND / 1000 + 1;
+        // The first millisecond in the frame, see `step`
+        let frame_start_ms = (frame * 1000).div_ceil(FRAMES_PER_SECOND);
+        self.advance(frame_start_ms - self.time_ms);
+    }
+
+    /// Forgets the time since the last loop, so gravity doesn't catch up on time spent
+    /// paused.
+    pub fn resume(&mut self) {
+        self.last_loop_end = self.clock.now();
+    }
+
+    /// Replaces the clock `process_game_loop` follows, e.g. with a `MockClock` in tests.
+    /// Time starts counting from the new clock's current time.
+    ///
+    /// # Parameters
+    ///
+    /// - `clock`: The new clock.
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.clock = clock;
+        self.resume();
+    }
+
+    /// Adds a frame of gravity to how far `current_shape` has fallen, and moves it
+    /// down a row for each whole cell, as far as the stack allows.
+    fn apply_gravity(&mut self) {
+        self.fall += self.gravity * self.soft_drop_factor as u64;
+        let rows = self.fall / GRAVITY_UNIT;
+        self.fall %= GRAVITY_UNIT;
+        if let Some(lock_delay) = self.speed_level().and_then(|timings| timings.lock_delay) {
+            if self.shape_placed {
+                return;
+            }
+            for _ in 0..rows {
+                if !self.attempt_move(0, 1) {
+                    break;
+                }
+            }
+            // However strong gravity is, a shape only locks once it has rested for the
+            // lock delay
+            let mut below = self.current_shape;
+            below.add_pos(0, 1);
+            if !self.is_position_legal(&below) {
+                self.lock_frames += 1;
+                if self.lock_frames >= lock_delay {
+                    self.lock(false);
+                }
+            }
+            return;
+        }
+        if self.shape_placed {
+            // A hard drop already locked the shape this millisecond
+            return;
+        }
+        for row in 0..rows {
+            if row == 0 {
+                // Only a shape which was already resting on the stack locks
+                self.tick();
+                if self.shape_placed {
+                    break;
+                }
+            } else if !self.attempt_move(0, 1) {
+                break;
             }
-            self.shape_placed = false;
         }
-        self.last_loop_end = Instant::now();
+    }
+
+    /// Moves `current_shape` down on the rows of a beat in rhythm mode, and locks it if
+    /// it's resting on the stack on the beat itself. Soft drop still moves it down
+    /// every frame, as fast as it would at the same level outside rhythm mode.
+    ///
+    /// # Parameters
+    ///
+    /// - `rhythm`: The beat gravity follows.
+    /// - `frame`: Whether a frame starts this millisecond.
+    fn apply_beat_gravity(&mut self, rhythm: Rhythm, frame: bool) {
+        if frame && self.soft_drop_factor > 1 {
+            self.fall += self.gravity * self.soft_drop_factor as u64;
+            let rows = self.fall / GRAVITY_UNIT;
+            self.fall %= GRAVITY_UNIT;
+            for _ in 0..rows {
+                if !self.attempt_move(0, 1) {
+                    break;
+                }
+            }
+        }
+        if self.shape_placed {
+            return;
+        }
+        match rhythm.pulse(self.time_ms, self.level) {
+            Some(true) => self.tick(),
+            Some(false) => {
+                self.attempt_move(0, 1);
+            }
+            None => {}
+        }
     }
 
     /// Moves `current_shape` down 1 unit and locks to board if it collides.
     pub fn tick(&mut self) {
         if !self.attempt_move(0, 1) {
-            self.current_shape.lock_to_gameboard(&mut self.board);
-            self.shape_placed = true;
+            self.lock(false);
+        }
+    }
+
+    /// Locks `current_shape` to the board where it is, for the next shape to spawn.
+    ///
+    /// # Parameters
+    ///
+    /// - `hard_drop`: `true` if the player dropped it into place.
+    fn lock(&mut self, hard_drop: bool) {
+        self.current_shape.lock_to_gameboard(&mut self.board);
+        self.shape_placed = true;
+        self.placed_at_ms = self.time_ms;
+        self.events.push(GameEvent::PieceLocked { hard_drop });
+    }
+
+    /// Returns the speed curve's timings at the current level, or `None` with the
+    /// classic timings.
+    fn speed_level(&self) -> Option<&SpeedLevel> {
+        self.speed_curve.as_ref().map(|curve| curve.at(self.level))
+    }
+
+    /// Returns how long the next shape takes to appear after one locks, in
+    /// milliseconds.
+    fn are_ms(&self) -> u64 {
+        self.speed_level()
+            .map_or(0, |timings| timings.are as u64 * 1000 / FRAMES_PER_SECOND)
+    }
+
+    /// Adds to the cleared line count, raising the level and speeding up gravity
+    /// every `LINES_PER_LEVEL` lines.
+    ///
+    /// # Parameters
+    ///
+    /// - `lines`: Number of lines just cleared.
+    fn add_lines(&mut self, lines: usize) {
+        self.lines += lines;
+        let level = self.lines / LINES_PER_LEVEL + 1;
+        if level > self.level {
+            self.level = level;
+            self.gravity = self.level_gravity();
+            self.events.push(GameEvent::LevelUp(level));
+        }
+    }
+
+    /// Returns the gravity of the current level, in `GRAVITY_UNIT`ths of a cell.
+    fn level_gravity(&self) -> u64 {
+        match self.speed_level() {
+            Some(timings) => gravity_units(
+                ((timings.gravity as u128 * self.gravity_percent as u128) << GRAVITY_FRACTION_BITS)
+                    / 100,
+            ),
+            None => classic_gravity(self.level, self.gravity_percent),
+        }
+    }
+
+    /// Passes every event which happened since the last call to each handler, oldest
+    /// first.
+    ///
+    /// # Parameters
+    ///
+    /// - `handlers`: Systems reacting to the events, e.g. audio and statistics.
+    pub fn dispatch_events(&mut self, handlers: &mut [&mut dyn EventHandler]) {
+        for event in self.events.drain(..) {
+            for handler in handlers.iter_mut() {
+                handler.handle_event(&event);
+            }
         }
     }
 
@@ -122,6 +1089,9 @@ impl Game {
     ///
     /// - `score`: Score to set.
     pub fn set_score(&mut self, score: usize) {
+        if score != self.score {
+            self.events.push(GameEvent::ScoreChanged(score));
+        }
         self.score = score;
     }
 
@@ -130,27 +1100,100 @@ impl Game {
     /// # Return Value
     ///
     /// Current game score
-    pub fn get_score(&mut self) -> usize {
+    pub fn get_score(&self) -> usize {
         self.score
     }
 
+    /// Getter for `level`
+    ///
+    /// # Return Value
+    ///
+    /// Current level, starting from 1
+    pub fn get_level(&self) -> usize {
+        self.level
+    }
+
+    /// Getter for `lines`
+    ///
+    /// # Return Value
+    ///
+    /// Total number of lines cleared this game
+    pub fn get_lines(&self) -> usize {
+        self.lines
+    }
+
+    /// Swaps `current_shape` with the held shape, or with `next_shape` if nothing is
+    /// held yet. Only allowed as often as the game's `HoldLimit` allows.
+    ///
+    /// # Return Value
+    ///
+    /// `true` if the swap happened
+    pub fn attempt_hold(&mut self) -> bool {
+        if !self.can_hold() {
+            return false;
+        }
+        let mut held = Tetromino::new(self.current_shape.kind());
+        held.set_pos(self.hold_shape_offset.0, self.hold_shape_offset.1);
+        match self.hold_shape.replace(held) {
+            Some(previous) => {
+                self.current_shape = previous;
+                self.rotated_last = false;
+                self.lock_frames = 0;
+                self.spawned_at_ms = self.time_ms;
+                let spawn_loc = self.board.get_spawn_loc();
+                self.current_shape.set_pos(spawn_loc.0, spawn_loc.1);
+                if self.is_position_legal(&self.current_shape) {
+                    self.events
+                        .push(GameEvent::Spawned(self.current_shape.kind()));
+                } else {
+                    self.topped_out = true;
+                    self.game_over = true;
+                    self.events.push(GameEvent::GameOver);
+                }
+            }
+            None => {
+                if !self.spawn_next_shape() {
+                    self.topped_out = true;
+                    self.game_over = true;
+                    self.events.push(GameEvent::GameOver);
+                }
+                self.pick_next_shape();
+            }
+        }
+        self.hold_used = true;
+        self.held_at_ms = self.time_ms;
+        self.events.push(GameEvent::Held);
+        true
+    }
+
     /// Moves the `next_shape` into the `current_shape` and sets position accordingly.
     pub fn spawn_next_shape(&mut self) -> bool {
         self.current_shape = self.next_shape;
+        self.rotated_last = false;
+        self.lock_frames = 0;
+        self.spawned_at_ms = self.time_ms;
         let spawn_loc = self.board.get_spawn_loc();
-        self.current_shape
-            .set_pos(spawn_loc.0 as i32, spawn_loc.1 as i32);
-        self.is_position_legal(&self.current_shape)
+        self.current_shape.set_pos(spawn_loc.0, spawn_loc.1);
+        if !self.is_position_legal(&self.current_shape) {
+            return false;
+        }
+        self.events
+            .push(GameEvent::Spawned(self.current_shape.kind()));
+        true
     }
 
     /// Picks the next Tetromino, sets it's position on the screen to be in the
     /// "Next Shape:" section
     pub fn pick_next_shape(&mut self) {
-        self.next_shape = Tetromino::new_random(&mut self.rng);
-        self.next_shape.set_pos(
-            self.next_shape_offset.0 as i32,
-            self.next_shape_offset.1 as i32,
-        );
+        if self.queue.is_empty() {
+            self.queue.extend(self.repeat_kinds.iter().copied());
+        }
+        self.next_shape = match self.queue.pop_front() {
+            Some(kind) => Tetromino::new(kind),
+            None => Tetromino::new_random(&mut self.rng),
+        };
+        self.next_shape
+            .set_pos(self.next_shape_offset.0, self.next_shape_offset.1);
     }
 
     /// Attempts to add to the `current_shape` position, returns true if successful.
@@ -168,6 +1211,10 @@ impl Game {
         temp.add_pos(x, y);
         if self.is_position_legal(&temp) {
             self.current_shape.add_pos(x, y);
+            self.rotated_last = false;
+            if y > 0 {
+                self.lock_frames = 0;
+            }
             return true;
         }
         false
@@ -179,13 +1226,7 @@ impl Game {
     ///
     /// `true` if successful
     pub fn attempt_rotate_cw(&mut self) -> bool {
-        let mut temp: Tetromino = self.current_shape.clone();
-        temp.rotate_cw();
-        if self.is_position_legal(&temp) {
-            self.current_shape.rotate_cw();
-            return true;
-        }
-        false
+        self.attempt_rotate(true)
     }
 
     /// Attempts to rotate `current_shape` counterclockwise, returns true if successful.
@@ -194,13 +1235,50 @@ impl Game {
     ///
     /// `true` if successful
     pub fn attempt_rotate_ccw(&mut self) -> bool {
-        let mut temp: Tetromino = self.current_shape.clone();
-        temp.rotate_ccw();
-        if self.is_position_legal(&temp) {
-            self.current_shape.rotate_ccw();
-            return true;
+        self.attempt_rotate(false)
+    }
+
+    /// Turns `current_shape` at the first of the kick table's offsets it fits at.
+    ///
+    /// # Parameters
+    ///
+    /// - `clockwise`: Direction to turn.
+    ///
+    /// # Return Value
+    ///
+    /// `true` if successful
+    fn attempt_rotate(&mut self, clockwise: bool) -> bool {
+        let mut turned = self.current_shape;
+        if clockwise {
+            turned.rotate_cw();
+        } else {
+            turned.rotate_ccw();
+        }
+        let kicked = self
+            .kicks
+            .offsets(turned.kind(), self.current_shape.rotation(), clockwise)
+            .iter()
+            .map(|&(x, y)| {
+                let mut kicked = turned;
+                kicked.add_pos(x, y);
+                kicked
+            })
+            .find(|kicked| self.is_position_legal(kicked));
+        // A failed turn shows where the shape would have been without kicks
+        self.last_rotation = Some(RotationTest {
+            clockwise,
+            locs: kicked.unwrap_or(turned).board_locs(),
+            passed: kicked.is_some(),
+        });
+        match kicked {
+            Some(kicked) => {
+                self.current_shape = kicked;
+                self.rotated_last = true;
+                self.rotated_at_ms = Some((self.time_ms, clockwise));
+                true
+            }
+            None => false,
         }
-        false
     }
 
     /// Checks if the position of the given tetromino is within boundaries and does
@@ -218,7 +1296,7 @@ impl Game {
     }
 
     /// Checks if the position of the given tetromino is within boundaries of the
-    /// gameboard
+    /// gameboard, or the hidden rows above it
     ///
     /// # Parameters
     ///
@@ -228,13 +1306,10 @@ impl Game {
     ///
     /// `true` if within boundaries of `board`
     pub fn is_shape_within_borders(&self, shape: &Tetromino) -> bool {
-        let mapped_locs = shape.get_mapped_locs();
-        for p in mapped_locs.iter() {
-            if !(p.0 < GAMEBOARD_WIDTH && p.1 < GAMEBOARD_HEIGHT) {
-                return false;
-            }
-        }
-        true
+        shape
+            .board_locs()
+            .iter()
+            .all(|&(x, y)| self.board.is_in_play(x, y))
     }
 
     /// Checks if the given tetromino's position collides with a block in the gameboard
@@ -247,13 +1322,49 @@ impl Game {
     ///
     /// `true` if shape collides
     pub fn does_shape_intersect_locked_blocks(&self, shape: &Tetromino) -> bool {
-        let mapped_locs = shape.get_mapped_locs();
-        !self.board.are_locs_empty(mapped_locs.to_vec())
+        !self.board.are_locs_empty(&shape.board_locs())
+    }
+
+    /// Returns `true` if the current shape is a T whose last move was a turn, with at
+    /// least three of the four cells diagonal to its centre filled or off the board.
+    fn is_t_spin(&self) -> bool {
+        if self.current_shape.kind() != TetrominoKind::T || !self.rotated_last {
+            return false;
+        }
+        let (x, y) = self.current_shape.pos();
+        let corners = [
+            (x - 1, y - 1),
+            (x + 1, y - 1),
+            (x - 1, y + 1),
+            (x + 1, y + 1),
+        ];
+        corners
+            .iter()
+            .filter(|&&corner| !self.board.are_locs_empty(&[corner]))
+            .count()
+            >= 3
     }
 
     /// Hard drop function
     pub fn drop(&mut self) {
-        while self.attempt_move(0, 1) {}
+        let rows = self.landing_shape().pos().1 - self.current_shape.pos().1;
+        if rows > 0 {
+            self.attempt_move(0, rows);
+        }
+    }
+
+    /// Returns the current shape moved down as far as it can go, where a hard drop
+    /// would put it, without moving it.
+    pub fn landing_shape(&self) -> Tetromino {
+        let mut landed = self.current_shape;
+        loop {
+            let mut below = landed;
+            below.add_pos(0, 1);
+            if !self.is_position_legal(&below) {
+                return landed;
+            }
+            landed = below;
+        }
     }
 
     fn render_background(&self, buf: &mut [Vertex]) {
@@ -317,13 +1428,456 @@ impl Game {
             tex_coords: [0.0, 0.0],
             color: [0.20, 0.20, 0.20, 0.5],
         };
+        // A shrunk board's background starts at its top row in play
+        let top = self.board.top() as f32;
+        for &i in [0, 1, 5].iter() {
+            buf[i].position[1] += BLOCK_SIZE as f32 * top;
+            buf[i].tex_coords[1] += top;
+        }
+    }
+
+    /// Returns the colour of every cell of the board with the current shape in place,
+    /// row by row from the top, for frontends which don't draw with `render`.
+    pub fn field(&self) -> [[Option<[f32; 4]>; GAMEBOARD_WIDTH]; GAMEBOARD_HEIGHT] {
+        let mut field = [[None; GAMEBOARD_WIDTH]; GAMEBOARD_HEIGHT];
+        for (y, row) in field.iter_mut().enumerate() {
+            for (x, cell) in row.iter_mut().enumerate() {
+                *cell = self.board.get_content(x, y);
+            }
+        }
+        if !self.game_over {
+            for &(x, y) in self.current_shape.board_locs().iter() {
+                if self.board.contains(x, y) {
+                    field[y as usize][x as usize] = Some(self.current_shape.color());
+                }
+            }
+        }
+        field
+    }
+
+    /// Returns which cells of the board hold locked blocks, row by row from the top.
+    pub fn occupied(&self) -> [[bool; GAMEBOARD_WIDTH]; GAMEBOARD_HEIGHT] {
+        let mut occupied = [[false; GAMEBOARD_WIDTH]; GAMEBOARD_HEIGHT];
+        for (y, row) in occupied.iter_mut().enumerate() {
+            for (x, cell) in row.iter_mut().enumerate() {
+                *cell = self.board.get_content(x, y).is_some();
+            }
+        }
+        occupied
+    }
+
+    /// Returns a snapshot of the game's internal state for the debug overlay.
+    pub fn debug_info(&self) -> DebugInfo {
+        DebugInfo {
+            occupied: self.occupied(),
+            position: self.current_shape.pos(),
+            block_locs: self.current_shape.block_locs(),
+            board_locs: self.current_shape.board_locs(),
+            shape: self.current_shape,
+            rotation: self.last_rotation,
+            gravity: self.gravity as f64 / GRAVITY_UNIT as f64,
+            fall: self.fall as f64 / GRAVITY_UNIT as f64,
+            soft_drop_factor: self.soft_drop_factor,
+            lock_frames: self.lock_frames,
+            pending_seconds: self.pending_ns as f64 / 1e9,
+            time_ms: self.time_ms,
+            frame_advance: self.frame_advance,
+        }
+    }
+
+    /// Returns the kind of the shape in play.
+    pub fn current_kind(&self) -> TetrominoKind {
+        self.current_shape.kind()
+    }
+
+    /// Returns the kind of the next shape to spawn.
+    pub fn next_kind(&self) -> TetrominoKind {
+        self.next_shape.kind()
+    }
+
+    /// Returns the kinds of the next `n` shapes to spawn, in order, without dealing
+    /// them. Random shapes are foreseen with a copy of the random number generator, so
+    /// previewing them doesn't change what is dealt.
+    ///
+    /// # Parameters
+    ///
+    /// - `n`: Number of shapes, starting with the next shape.
+    pub fn upcoming_kinds(&self, n: usize) -> Vec<TetrominoKind> {
+        let mut rng = self.rng.clone();
+        let mut queue = self.queue.clone();
+        let mut kinds = vec![self.next_kind()];
+        while kinds.len() < n {
+            if queue.is_empty() {
+                queue.extend(self.repeat_kinds.iter().copied());
+            }
+            kinds.push(match queue.pop_front() {
+                Some(kind) => kind,
+                None => Tetromino::new_random(&mut rng).kind(),
+            });
+        }
+        kinds.truncate(n);
+        kinds
+    }
+
+    /// Returns `true` if the current shape may still be swapped into the hold slot.
+    pub fn can_hold(&self) -> bool {
+        match self.hold_limit {
+            HoldLimit::Unlimited => true,
+            HoldLimit::OncePerPiece => !self.hold_used,
+            HoldLimit::Disabled => false,
+        }
+    }
+
+    /// Returns the kind of the held shape, if any.
+    pub fn hold_kind(&self) -> Option<TetrominoKind> {
+        self.hold_shape.map(|shape| shape.kind())
     }
 
     /// Returns renderable vertices to the main graphics api
-    pub fn render(&self, buf: &mut [Vertex]) {
+    ///
+    /// # Parameters
+    ///
+    /// - `buf`: Vertices to fill, `VERTEX_COUNT` long.
+    /// - `shift`: Number of blocks to move everything right by, so several games can be
+    /// drawn side by side.
+    /// - `previews`: Number of upcoming shapes to show, from none up to `MAX_PREVIEWS`.
+    pub fn render(&self, buf: &mut [Vertex], shift: i32, previews: usize) {
+        self.render_board(&self.board, GAMEBOARD_HEIGHT, buf, shift, previews);
+    }
+
+    /// Fades and grows in the current shape, and the held shape after a swap, for
+    /// `SPAWN_ANIMATION_MS` after they appear, so each new shape stands out at high
+    /// speeds. Each block grows from its own centre.
+    ///
+    /// # Parameters
+    ///
+    /// - `buf`: Vertices filled by `render` or `render_topped_out`.
+    pub fn animate_spawn(&self, buf: &mut [Vertex]) {
+        for (range, since_ms) in [
+            (1206..1230, self.spawned_at_ms),
+            (1254..1278, self.held_at_ms),
+        ] {
+            let age_ms = self.time_ms.saturating_sub(since_ms);
+            if age_ms >= SPAWN_ANIMATION_MS {
+                continue;
+            }
+            let progress = age_ms as f32 / SPAWN_ANIMATION_MS as f32;
+            let scale = SPAWN_START_SCALE + (1.0 - SPAWN_START_SCALE) * progress;
+            for quad in buf[range].chunks_mut(6) {
+                // The first and third corners are opposite each other
+                let centre = [
+                    (quad[0].position[0] + quad[2].position[0]) / 2.0,
+                    (quad[0].position[1] + quad[2].position[1]) / 2.0,
+                ];
+                for vertex in quad {
+                    for (position, centre) in vertex.position.iter_mut().zip(centre) {
+                        *position = centre + (*position - centre) * scale;
+                    }
+                    vertex.color[3] *= progress;
+                }
+            }
+        }
+    }
+
+    /// Swings the current shape round from its last orientation to its new one for
+    /// `ROTATION_ANIMATION_MS` after it turns, about the middle of its blocks. Only
+    /// the drawing lags behind: the shape is already in its new orientation.
+    ///
+    /// # Parameters
+    ///
+    /// - `buf`: Vertices filled by `render` or `render_topped_out`.
+    pub fn animate_rotation(&self, buf: &mut [Vertex]) {
+        let (rotated_at_ms, clockwise) = match self.rotated_at_ms {
+            // A turn before the shape appeared belongs to the shape before it
+            Some((at_ms, clockwise)) if at_ms >= self.spawned_at_ms => (at_ms, clockwise),
+            _ => return,
+        };
+        let age_ms = self.time_ms.saturating_sub(rotated_at_ms);
+        if age_ms >= ROTATION_ANIMATION_MS {
+            return;
+        }
+        // Blocks which aren't shown are emptied, with every corner in the same place
+        let shown = |quad: &&mut [Vertex]| quad[0].position != quad[2].position;
+        let quads: Vec<_> = buf[1206..1230].chunks_mut(6).filter(shown).collect();
+        if quads.is_empty() {
+            return;
+        }
+        let corners = quads.len() as f32 * 6.0;
+        let centre = [0, 1].map(|axis| {
+            quads
+                .iter()
+                .flat_map(|quad| quad.iter())
+                .map(|vertex| vertex.position[axis])
+                .sum::<f32>()
+                / corners
+        });
+        // Turned back towards the last orientation, with y down so a positive angle
+        // is clockwise
+        let progress = age_ms as f32 / ROTATION_ANIMATION_MS as f32;
+        let direction = if clockwise { -1.0 } else { 1.0 };
+        let (sin, cos) = (direction * std::f32::consts::FRAC_PI_2 * (1.0 - progress)).sin_cos();
+        for vertex in quads.into_iter().flatten() {
+            let x = vertex.position[0] - centre[0];
+            let y = vertex.position[1] - centre[1];
+            vertex.position[0] = centre[0] + x * cos - y * sin;
+            vertex.position[1] = centre[1] + x * sin + y * cos;
+        }
+    }
+
+    /// Returns `true` if the game ended with the stack reaching the top.
+    pub fn topped_out(&self) -> bool {
+        self.topped_out
+    }
+
+    /// Returns how many rows of the board are in play, which are all filled once
+    /// `render_topped_out` has been given as many.
+    pub fn rows_in_play(&self) -> usize {
+        GAMEBOARD_HEIGHT - self.board.top()
+    }
+
+    /// Returns renderable vertices for a game which has topped out, with its bottom
+    /// rows filled with grey blocks covering the stack and the current shape, as the
+    /// board fills up at the end of a classic game.
+    ///
+    /// # Parameters
+    ///
+    /// - `buf`: Vertices to fill, `VERTEX_COUNT` long.
+    /// - `shift`: Number of blocks to move everything right by, see `render`.
+    /// - `rows`: Number of rows filled so far, counting up from the bottom. Any more
+    ///   than `rows_in_play` are ignored.
+    /// - `previews`: Number of upcoming shapes to show, see `render`.
+    pub fn render_topped_out(&self, buf: &mut [Vertex], shift: i32, rows: usize, previews: usize) {
+        let mut board = self.board.clone();
+        let filled_top = GAMEBOARD_HEIGHT - rows.min(self.rows_in_play());
+        for row in filled_top..GAMEBOARD_HEIGHT {
+            board.fill_row(row, Some(GARBAGE_COLOR)).unwrap();
+        }
+        self.render_board(&board, filled_top, buf, shift, previews);
+    }
+
+    /// Returns renderable vertices with the given board in place of the game's.
+    ///
+    /// # Parameters
+    ///
+    /// - `board`: The board to draw.
+    /// - `filled_top`: First row of the board covering the current shape, or
+    ///   `GAMEBOARD_HEIGHT` to show all of it.
+    /// - `buf`: Vertices to fill, `VERTEX_COUNT` long.
+    /// - `shift`: Number of blocks to move everything right by.
+    /// - `previews`: Number of upcoming shapes to show.
+    fn render_board(
+        &self,
+        board: &Gameboard,
+        filled_top: usize,
+        buf: &mut [Vertex],
+        shift: i32,
+        previews: usize,
+    ) {
         self.render_background(&mut buf[0..6]);
-        self.board.as_vertices(&mut buf[6..1206]);
+        board.as_vertices(&mut buf[6..1206]);
         self.current_shape.as_vertices(&mut buf[1206..1230]);
-        self.next_shape.as_vertices(&mut buf[1230..1254]);
+        let top = board.top() as i32;
+        for (i, &(_, y)) in self.current_shape.board_locs().iter().enumerate() {
+            if y < top || y >= filled_top as i32 {
+                // Blocks in the hidden rows, or under the filled rows, aren't shown
+                use bytemuck::Zeroable;
+                buf[1206 + 6 * i..1212 + 6 * i].copy_from_slice(&[Vertex::zeroed(); 6]);
+            }
+        }
+        if previews > 0 {
+            self.next_shape.as_vertices(&mut buf[1230..1254]);
+        } else {
+            use bytemuck::Zeroable;
+            buf[1230..1254].copy_from_slice(&[Vertex::zeroed(); 24]);
+        }
+        match &self.hold_shape {
+            Some(hold_shape) => {
+                hold_shape.as_vertices(&mut buf[1254..1278]);
+                if !self.can_hold() {
+                    // Greyed out until it can be swapped again
+                    for vertex in &mut buf[1254..1278] {
+                        vertex.color = GARBAGE_COLOR;
+                    }
+                }
+            }
+            None => {
+                use bytemuck::Zeroable;
+                buf[1254..1278].copy_from_slice(&[Vertex::zeroed(); 24]);
+            }
+        }
+        self.render_previews(&mut buf[1278..GHOST_SHAPE_VERTICES.start], previews);
+        self.render_ghost_shape(&mut buf[GHOST_SHAPE_VERTICES], top, filled_top);
+        if shift != 0 {
+            // Empty slots stay degenerate, as all their vertices move together
+            for vertex in buf.iter_mut() {
+                vertex.position[0] += (shift * BLOCK_SIZE as i32) as f32;
+            }
+        }
     }
+
+    /// Fills the ghost slot with a see-through copy of the current shape where it would
+    /// land, leaving out blocks the shape itself covers or which aren't shown. Empty
+    /// once the game is over or while waiting for the next shape.
+    ///
+    /// # Parameters
+    ///
+    /// - `buf`: Vertices to fill, 24 long.
+    /// - `top`: First row of the board shown.
+    /// - `filled_top`: First row of the board covering the current shape.
+    fn render_ghost_shape(&self, buf: &mut [Vertex], top: i32, filled_top: usize) {
+        use bytemuck::Zeroable;
+        if self.game_over || self.shape_placed {
+            buf.copy_from_slice(&[Vertex::zeroed(); 24]);
+            return;
+        }
+        let ghost = self.landing_shape();
+        ghost.as_vertices(buf);
+        let covered = self.current_shape.board_locs();
+        for (i, loc) in ghost.board_locs().iter().enumerate() {
+            let quad = &mut buf[6 * i..6 * i + 6];
+            if loc.1 < top || loc.1 >= filled_top as i32 || covered.contains(loc) {
+                quad.copy_from_slice(&[Vertex::zeroed(); 6]);
+            } else {
+                for vertex in quad {
+                    vertex.color[3] = GHOST_SHAPE_ALPHA;
+                }
+            }
+        }
+    }
+
+    /// Fills the slots after the next shape with smaller previews of the shapes after
+    /// it, each in its own rows below it, and empties the slots of any not shown.
+    ///
+    /// # Parameters
+    ///
+    /// - `buf`: Vertices to fill, 24 for each preview after the next shape.
+    /// - `previews`: Number of upcoming shapes shown, counting the next shape.
+    fn render_previews(&self, buf: &mut [Vertex], previews: usize) {
+        use bytemuck::Zeroable;
+        for vertex in buf.iter_mut() {
+            *vertex = Vertex::zeroed();
+        }
+        let (x, y) = self.next_shape_offset;
+        // Shapes are centred on their position's top left corner, which each preview
+        // shrinks towards before moving down into its rows
+        let anchor = [
+            ((x + GAMEBOARD_OFFSET.0 as i32) * BLOCK_SIZE as i32) as f32,
+            ((y + GAMEBOARD_OFFSET.1 as i32) * BLOCK_SIZE as i32) as f32,
+        ];
+        let kinds = self.upcoming_kinds(previews.min(MAX_PREVIEWS));
+        for (i, (&kind, slot)) in kinds.iter().skip(1).zip(buf.chunks_mut(24)).enumerate() {
+            let mut shape = Tetromino::new(kind);
+            shape.set_pos(x, y);
+            shape.as_vertices(slot);
+            let rows = 1 + PREVIEW_ROWS * i as i32 + PREVIEW_ROWS / 2;
+            let offset = [0.0, (rows * BLOCK_SIZE as i32) as f32];
+            for vertex in slot {
+                for ((position, anchor), offset) in
+                    vertex.position.iter_mut().zip(anchor).zip(offset)
+                {
+                    *position = anchor + offset + (*position - anchor) * PREVIEW_SCALE;
+                }
+            }
+        }
+    }
+}
+
+/// Returns how far down the view, in pixels, the shapes shown by `Game::render` reach,
+/// so text beside the board can go below them.
+///
+/// # Parameters
+///
+/// - `previews`: Number of upcoming shapes shown.
+pub fn previews_bottom(previews: usize) -> f32 {
+    // The next shape takes the two rows just above its position
+    let rows = match previews.min(MAX_PREVIEWS) {
+        0 => 0,
+        n => 2 + PREVIEW_ROWS * (n as i32 - 1),
+    };
+    ((NEXT_SHAPE_POS.1 - 1 + GAMEBOARD_OFFSET.1 as i32 + rows) * BLOCK_SIZE as i32) as f32
+}
+
+/// Describes the rules games are played by, which have to match for two builds to
+/// simulate the same inputs the same way. Online games are played by the rules a new
+/// `Game` starts with, and send garbage by the classic attack table.
+pub fn rules() -> String {
+    let game = Game::with_seed(0);
+    let kicks = serde_json::to_string(&game.kicks).expect("Kick tables convert to JSON");
+    let speed_curve =
+        serde_json::to_string(&game.speed_curve).expect("Speed curves convert to JSON");
+    let attack =
+        serde_json::to_string(&AttackTable::classic()).expect("Attack tables convert to JSON");
+    format!(
+        "board {}x{}+{}, {} lines a level, gravity {}/{} times {}/{} each level up to {} at {} fps, \
+         hold {}, kicks {}, speed curve {}, attack {}",
+        GAMEBOARD_WIDTH,
+        GAMEBOARD_HEIGHT,
+        GAMEBOARD_HIDDEN_ROWS,
+        LINES_PER_LEVEL,
+        START_GRAVITY.0,
+        START_GRAVITY.1,
+        GRAVITY_PER_LEVEL.0,
+        GRAVITY_PER_LEVEL.1,
+        MAX_GRAVITY,
+        FRAMES_PER_SECOND,
+        game.hold_limit.name(),
+        kicks,
+        speed_curve,
+        attack
+    )
+}
+
+/// Describes every shape in each of its rotations, and the first shapes dealt from a
+/// fixed seed, which have to match for two builds to deal and turn shapes the same way.
+pub fn piece_set() -> String {
+    let mut description = String::new();
+    for kind in TetrominoKind::ALL.iter() {
+        let mut shape = Tetromino::new(*kind);
+        for _ in 0..4 {
+            description.push_str(&format!("{:?} {:?}\n", kind, shape.block_locs()));
+            shape.rotate_cw();
+        }
+    }
+    let mut game = Game::with_seed(0);
+    for _ in 0..2 * TetrominoKind::ALL.len() {
+        description.push_str(&format!("{:?}", game.next_kind()));
+        game.pick_next_shape();
+    }
+    description
+}
+
+/// Returns the classic gravity at a level, without a speed curve, in `GRAVITY_UNIT`ths
+/// of a cell per frame.
+///
+/// # Parameters
+///
+/// - `level`: The level, from 1.
+/// - `percent`: Percentage of normal gravity, e.g. as a handicap.
+fn classic_gravity(level: usize, percent: u32) -> u64 {
+    let max = ((MAX_GRAVITY * GRAVITY_UNIT) as u128) << GRAVITY_FRACTION_BITS;
+    let mut gravity = ((GRAVITY_UNIT as u128) << GRAVITY_FRACTION_BITS)
+        * START_GRAVITY.0 as u128
+        * percent as u128
+        / (START_GRAVITY.1 as u128 * 100);
+    // Multiplied out a level at a time, stopping at the cap so high levels can't
+    // overflow
+    for _ in 1..level {
+        if gravity >= max {
+            break;
+        }
+        gravity = gravity * GRAVITY_PER_LEVEL.0 as u128 / GRAVITY_PER_LEVEL.1 as u128;
+    }
+    gravity_units(gravity)
+}
+
+/// Rounds gravity up to whole `GRAVITY_UNIT`ths of a cell per frame, so shapes never
+/// fall slower than asked, and caps it at `MAX_GRAVITY`.
+///
+/// # Parameters
+///
+/// - `gravity`: `GRAVITY_UNIT`ths of a cell to fall each frame, with
+///   `GRAVITY_FRACTION_BITS` bits below the point.
+fn gravity_units(gravity: u128) -> u64 {
+    let units = (gravity + (1 << GRAVITY_FRACTION_BITS) - 1) >> GRAVITY_FRACTION_BITS;
+    units.min((MAX_GRAVITY * GRAVITY_UNIT) as u128) as u64
 }
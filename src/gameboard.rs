@@ -1,15 +1,21 @@
 use crate::Vertex;
 use crate::BLOCK_SIZE;
-use crate::{GAMEBOARD_HEIGHT, GAMEBOARD_OFFSET, GAMEBOARD_WIDTH};
+use crate::{GAMEBOARD_HEIGHT, GAMEBOARD_HIDDEN_ROWS, GAMEBOARD_OFFSET, GAMEBOARD_WIDTH};
 use std::vec::Vec;
 
 /// The playing field of tetris.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Gameboard {
     blocks: [Option<[f32; 4]>; 200],
     width: usize,
     height: usize,
-    block_spawn_loc: (usize, usize),
+    /// First row in play, with the hidden rows above it. 0 unless the board was shrunk
+    /// with `set_rows`.
+    top: usize,
+    /// Blocks locked in the hidden rows above row 0, which come into view when rows
+    /// below are cleared. The last row is the one just above row 0.
+    hidden: [[Option<[f32; 4]>; GAMEBOARD_WIDTH]; GAMEBOARD_HIDDEN_ROWS],
+    block_spawn_loc: (i32, i32),
 }
 
 impl Gameboard {
@@ -19,10 +25,29 @@ impl Gameboard {
             blocks: [None; 200],
             width: GAMEBOARD_WIDTH,
             height: GAMEBOARD_HEIGHT,
-            block_spawn_loc: (GAMEBOARD_WIDTH / 2, 1),
+            top: 0,
+            hidden: [[None; GAMEBOARD_WIDTH]; GAMEBOARD_HIDDEN_ROWS],
+            block_spawn_loc: (GAMEBOARD_WIDTH as i32 / 2 - 1, 0),
         }
     }
 
+    /// Shrinks the part of the board in play to its bottom rows, so the stack tops out
+    /// sooner. Shapes spawn in the new top row, with the hidden rows above it. Only
+    /// meant for an empty board.
+    ///
+    /// # Parameters
+    ///
+    /// - `rows`: Rows in play, from 1 to `GAMEBOARD_HEIGHT`.
+    pub fn set_rows(&mut self, rows: usize) {
+        self.top = self.height - rows.clamp(1, self.height);
+        self.block_spawn_loc.1 = self.top as i32;
+    }
+
+    /// Returns the first row in play, see `set_rows`.
+    pub fn top(&self) -> usize {
+        self.top
+    }
+
     #[inline]
     const fn point_to_index(&self, x: usize, y: usize) -> Option<usize> {
         if x < self.width && y < self.height {
@@ -71,22 +96,84 @@ impl Gameboard {
         Ok(())
     }
 
-    /// Checks if the given block positions are empty.
+    /// Like `get_content`, but for a position which may be past the left edge or in the
+    /// hidden rows above the top edge.
+    ///
+    /// # Parameters
+    ///
+    /// - `x`: Horizontal position, which may be negative.
+    /// - `y`: Vertical position, which may be negative.
+    pub fn get_content_at(&self, x: i32, y: i32) -> Option<[f32; 4]> {
+        let hidden_row = y + GAMEBOARD_HIDDEN_ROWS as i32;
+        if !(0..self.width as i32).contains(&x) || hidden_row < 0 {
+            None
+        } else if y < 0 {
+            self.hidden[hidden_row as usize][x as usize]
+        } else {
+            self.get_content(x as usize, y as usize)
+        }
+    }
+
+    /// Like `set_content`, but for a position which may be past the left edge or in the
+    /// hidden rows above the top edge.
     ///
     /// # Parameters
     ///
-    /// - `locs`: `Vec` of position tuples to check.
+    /// - `x`: Horizontal position within the gameboard
+    /// - `y`: Vertical position within the gameboard
+    /// - `content`: Colour of the block at position (x, y), or None if the position is
+    /// empty.
     ///
     /// # Return Value
     ///
-    /// `true` if all block positions are empty, `false` otherwise.
-    pub fn are_locs_empty(&self, locs: Vec<(usize, usize)>) -> bool {
-        for point in locs {
-            if self.get_content(point.0, point.1).is_some() {
-                return false;
-            }
+    /// Ok(()) if the position is valid, Err(()) otherwise.
+    pub fn set_content_at(&mut self, x: i32, y: i32, content: Option<[f32; 4]>) -> Result<(), ()> {
+        let hidden_row = y + GAMEBOARD_HIDDEN_ROWS as i32;
+        if !(0..self.width as i32).contains(&x) || hidden_row < 0 {
+            return Err(());
         }
-        true
+        if y < 0 {
+            self.hidden[hidden_row as usize][x as usize] = content;
+            return Ok(());
+        }
+        self.set_content(x as usize, y as usize, content)
+    }
+
+    /// Returns `true` if the position is within the gameboard.
+    ///
+    /// # Parameters
+    ///
+    /// - `x`: Horizontal position, which may be negative.
+    /// - `y`: Vertical position, which may be negative.
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        (0..self.width as i32).contains(&x) && (0..self.height as i32).contains(&y)
+    }
+
+    /// Returns `true` if the position is within the gameboard or the hidden rows above
+    /// it, where shapes may move.
+    ///
+    /// # Parameters
+    ///
+    /// - `x`: Horizontal position, which may be negative.
+    /// - `y`: Vertical position, above `top` in the hidden rows.
+    pub fn is_in_play(&self, x: i32, y: i32) -> bool {
+        let top = self.top as i32 - GAMEBOARD_HIDDEN_ROWS as i32;
+        (0..self.width as i32).contains(&x) && (top..self.height as i32).contains(&y)
+    }
+
+    /// Checks if the given block positions are empty. Positions outside the gameboard and
+    /// the hidden rows above it never are.
+    ///
+    /// # Parameters
+    ///
+    /// - `locs`: Positions to check, which may be negative.
+    ///
+    /// # Return Value
+    ///
+    /// `true` if all block positions are empty, `false` otherwise.
+    pub fn are_locs_empty(&self, locs: &[(i32, i32)]) -> bool {
+        locs.iter()
+            .all(|&(x, y)| self.is_in_play(x, y) && self.get_content_at(x, y).is_none())
     }
 
     /// Removes all rows which are full along the horizontal axis.
@@ -101,15 +188,14 @@ impl Gameboard {
     }
 
     /// Returns the position within the `Gameboard` at which new blocks are spawned.
+    /// Shapes spawn flat, centred on columns 4 and 5 with their lowest row in the top
+    /// row in play, so any blocks above it start in the hidden rows.
     ///
     /// # Return Value
     ///
     /// The position within the `Gameboard` at which new blocks are spawned.
-    pub fn get_spawn_loc(&self) -> (usize, usize) {
-        (
-            self.block_spawn_loc.0 + GAMEBOARD_OFFSET.0,
-            self.block_spawn_loc.1 + GAMEBOARD_OFFSET.1,
-        )
+    pub fn get_spawn_loc(&self) -> (i32, i32) {
+        self.block_spawn_loc
     }
 
     /// Returns `true` if the given `row_index` is horizontally full.
@@ -130,6 +216,29 @@ impl Gameboard {
         true
     }
 
+    /// Returns the height of the stack, measured from the bottom of the board to the
+    /// highest occupied row, including the hidden rows.
+    ///
+    /// # Return Value
+    ///
+    /// The number of rows from the bottom up to and including the highest block, or 0
+    /// if the board is empty.
+    pub fn stack_height(&self) -> usize {
+        for (y, row) in self.hidden.iter().enumerate() {
+            if row.iter().any(Option::is_some) {
+                return self.height + GAMEBOARD_HIDDEN_ROWS - y;
+            }
+        }
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if self.get_content(x, y).is_some() {
+                    return self.height - y;
+                }
+            }
+        }
+        0
+    }
+
     /// Returns all horizontally full rows within the Gameboard.
     ///
     /// # Return Value
@@ -145,7 +254,8 @@ impl Gameboard {
         ret
     }
 
-    /// Removes a row and moves down the rows above it to fill.
+    /// Removes a row and moves down the rows above it to fill, including the hidden
+    /// rows.
     ///
     /// # Parameters
     ///
@@ -159,7 +269,11 @@ impl Gameboard {
         for y in (1..=row_index).rev() {
             self.copy_row_into_row(y - 1, y)?;
         }
-        self.fill_row(0, None)?;
+        self.hidden.rotate_right(1);
+        let row = std::mem::replace(&mut self.hidden[0], [None; GAMEBOARD_WIDTH]);
+        for (x, content) in row.iter().enumerate() {
+            self.set_content(x, 0, *content)?;
+        }
         Ok(())
     }
 
@@ -197,6 +311,33 @@ impl Gameboard {
         Ok(())
     }
 
+    /// Moves every row up one, the top row into the hidden rows, and fills the bottom
+    /// row with garbage, except for one empty cell.
+    ///
+    /// # Parameters
+    ///
+    /// - `hole`: Column of the empty cell in the new row.
+    /// - `color`: Colour of the garbage blocks.
+    ///
+    /// # Return Value
+    ///
+    /// `false` if the top row in play held blocks, which were pushed out of play.
+    pub fn raise(&mut self, hole: usize, color: [f32; 4]) -> bool {
+        let overflowed = (0..self.width).any(|x| self.get_content(x, self.top).is_some());
+        self.hidden.rotate_left(1);
+        for x in 0..self.width {
+            self.hidden[GAMEBOARD_HIDDEN_ROWS - 1][x] = self.get_content(x, 0);
+        }
+        for y in 1..self.height {
+            // Both rows are in bounds
+            let _ = self.copy_row_into_row(y, y - 1);
+        }
+        let bottom = self.height - 1;
+        let _ = self.fill_row(bottom, Some(color));
+        let _ = self.set_content(hole, bottom, None);
+        !overflowed
+    }
+
     /// Copies a row into another row.
     /// Used to move rows above down when a row is completed.
     ///
@@ -220,7 +361,8 @@ impl Gameboard {
     }
 
     /// Returns a representation of the Gameboard as vertices which can be drawn.
-    /// See `Sprite::Vertex` and `graphics::draw_vertices`
+    /// See `Sprite::Vertex` and `graphics::draw_vertices`. Rows above `top` are hidden
+    /// rows on a shrunk board, and aren't drawn.
     ///
     /// # Return Value
     ///
@@ -229,8 +371,7 @@ impl Gameboard {
         for (index, block) in self.blocks.iter().enumerate() {
             let (x, y) = self.index_to_point(index);
             let index = index * 6;
-            if block.is_some() {
-                let color = block.unwrap();
+            if let Some(color) = block.filter(|_| y >= self.top) {
                 buf[index] = Vertex {
                     tex_coords: [0.0, 0.0],
                     color,
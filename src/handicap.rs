@@ -0,0 +1,53 @@
+//! Handicaps which even out versus matches between players of different skill.
+
+use crate::game::Game;
+use crate::GAMEBOARD_HEIGHT;
+
+use serde::{Deserialize, Serialize};
+
+/// Most rows of garbage a game can start with.
+pub const MAX_STARTING_GARBAGE: usize = 10;
+/// Fewest rows a board can be shrunk to.
+pub const MIN_ROWS: usize = 12;
+
+/// What one side of a versus match starts with and plays by, to make it harder or
+/// easier than the other. The default is no handicap.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Handicap {
+    /// Rows of garbage on the board when the game starts, up to
+    /// `MAX_STARTING_GARBAGE`.
+    pub starting_garbage: usize,
+    /// Garbage sent to the other side, as a percentage of the usual attack.
+    pub attack_percent: u32,
+    /// Gravity at every level, as a percentage of normal gravity.
+    pub gravity_percent: u32,
+    /// Rows of the board in play, from `MIN_ROWS` to `GAMEBOARD_HEIGHT`.
+    pub rows: usize,
+}
+
+impl Default for Handicap {
+    fn default() -> Self {
+        Self {
+            starting_garbage: 0,
+            attack_percent: 100,
+            gravity_percent: 100,
+            rows: GAMEBOARD_HEIGHT,
+        }
+    }
+}
+
+impl Handicap {
+    /// Sets up a game which hasn't started yet to be played with the handicap. Only the
+    /// starting garbage is recorded in the game's replay.
+    ///
+    /// # Parameters
+    ///
+    /// - `game`: The game of the handicapped side.
+    /// - `hole`: Column of the empty cell in each row of starting garbage.
+    pub fn apply(&self, game: &mut Game, hole: usize) {
+        game.set_rows(self.rows.clamp(MIN_ROWS, GAMEBOARD_HEIGHT));
+        game.set_gravity_percent(self.gravity_percent);
+        game.add_garbage(self.starting_garbage.min(MAX_STARTING_GARBAGE), hole);
+    }
+}
@@ -0,0 +1,322 @@
+use anyhow::*;
+use serde::{Deserialize, Serialize};
+use std::fs::{read_to_string, write};
+use std::path::PathBuf;
+use web_time::{SystemTime, UNIX_EPOCH};
+use wgputris::paths;
+use wgputris::stats::Stats;
+use winit::event::VirtualKeyCode;
+
+const HISTORY_FILE: &str = "history.toml";
+/// Files the history is exported to, without the extension.
+const EXPORT_STEM: &str = "history-export";
+/// Number of games shown at once on the history screen.
+const VISIBLE_ROWS: usize = 9;
+
+/// The kinds of game which can be played.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Mode {
+    /// A single player game.
+    Marathon,
+    /// One player's side of a two player match.
+    Versus,
+    /// A single player game against a replay ghost.
+    Race,
+    /// A single player game started from a fumen setup.
+    Practice,
+    /// A single player game with gravity on the beat of the music.
+    Rhythm,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 5] = [
+        Mode::Marathon,
+        Mode::Versus,
+        Mode::Race,
+        Mode::Practice,
+        Mode::Rhythm,
+    ];
+}
+
+/// Summary of a finished game.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameRecord {
+    pub mode: Mode,
+    pub score: usize,
+    pub lines: usize,
+    pub level: usize,
+    /// How long the game lasted, in milliseconds.
+    pub duration_ms: u64,
+    /// When the game finished, in seconds since the Unix epoch.
+    pub finished_at: u64,
+    /// Number of shapes locked to the board.
+    #[serde(default)]
+    pub pieces: usize,
+    /// Number of singles, doubles, triples and tetrises cleared.
+    #[serde(default)]
+    pub clears: [usize; 4],
+}
+
+impl GameRecord {
+    /// Creates a `GameRecord` of a game which just finished.
+    ///
+    /// # Parameters
+    ///
+    /// - `mode`: The kind of game.
+    /// - `stats`: Totals of the finished game.
+    /// - `duration_ms`: How long the game lasted, in milliseconds.
+    pub fn new(mode: Mode, stats: &Stats, duration_ms: u64) -> Self {
+        Self {
+            mode,
+            score: stats.score,
+            lines: stats.lines,
+            level: stats.level,
+            duration_ms,
+            finished_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            pieces: stats.pieces,
+            clears: stats.clears,
+        }
+    }
+}
+
+/// Exported statistics of a single game, including rates worked out from the record.
+#[derive(Serialize)]
+struct ExportRow {
+    date: String,
+    mode: Mode,
+    score: usize,
+    lines: usize,
+    level: usize,
+    duration_secs: f64,
+    pieces: usize,
+    singles: usize,
+    doubles: usize,
+    triples: usize,
+    tetrises: usize,
+    pieces_per_second: f64,
+    lines_per_minute: f64,
+}
+
+impl From<&GameRecord> for ExportRow {
+    fn from(game: &GameRecord) -> Self {
+        let duration_secs = game.duration_ms as f64 / 1000.0;
+        let per_second = |count: usize| {
+            if duration_secs > 0.0 {
+                count as f64 / duration_secs
+            } else {
+                0.0
+            }
+        };
+        Self {
+            date: date(game.finished_at),
+            mode: game.mode,
+            score: game.score,
+            lines: game.lines,
+            level: game.level,
+            duration_secs,
+            pieces: game.pieces,
+            singles: game.clears[0],
+            doubles: game.clears[1],
+            triples: game.clears[2],
+            tetrises: game.clears[3],
+            pieces_per_second: per_second(game.pieces),
+            lines_per_minute: per_second(game.lines) * 60.0,
+        }
+    }
+}
+
+/// File formats the history can be exported to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+/// Every game played, oldest first.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct History {
+    pub games: Vec<GameRecord>,
+}
+
+impl History {
+    /// Loads the history file, which is empty if it is missing or invalid.
+    pub fn load() -> Self {
+        read_to_string(paths::data_path(HISTORY_FILE))
+            .ok()
+            .and_then(|s| toml::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    /// Adds a finished game to the history file.
+    ///
+    /// # Parameters
+    ///
+    /// - `record`: Summary of the game.
+    pub fn record(record: GameRecord) -> Result<()> {
+        let mut history = Self::load();
+        history.games.push(record);
+        write(paths::data_path(HISTORY_FILE), toml::to_string(&history)?)?;
+        Ok(())
+    }
+
+    /// Writes every game with its statistics to a file for analysis in other tools.
+    ///
+    /// # Parameters
+    ///
+    /// - `format`: The file format to write.
+    ///
+    /// # Return Value
+    ///
+    /// The path of the file written.
+    pub fn export(&self, format: ExportFormat) -> Result<PathBuf> {
+        let rows: Vec<ExportRow> = self.games.iter().map(ExportRow::from).collect();
+        let (path, contents) = match format {
+            ExportFormat::Json => (
+                paths::data_path(&format!("{}.json", EXPORT_STEM)),
+                serde_json::to_string_pretty(&rows)?,
+            ),
+            ExportFormat::Csv => {
+                let mut csv = String::from(
+                    "date,mode,score,lines,level,duration_secs,pieces,singles,doubles,\
+                     triples,tetrises,pieces_per_second,lines_per_minute\n",
+                );
+                for row in &rows {
+                    csv += &format!(
+                        "{},{:?},{},{},{},{:.3},{},{},{},{},{},{:.3},{:.3}\n",
+                        row.date,
+                        row.mode,
+                        row.score,
+                        row.lines,
+                        row.level,
+                        row.duration_secs,
+                        row.pieces,
+                        row.singles,
+                        row.doubles,
+                        row.triples,
+                        row.tetrises,
+                        row.pieces_per_second,
+                        row.lines_per_minute
+                    );
+                }
+                (paths::data_path(&format!("{}.csv", EXPORT_STEM)), csv)
+            }
+        };
+        write(&path, contents)?;
+        Ok(path)
+    }
+}
+
+/// Formats seconds since the Unix epoch as a UTC date, e.g. "2020-11-28".
+fn date(secs: u64) -> String {
+    // Howard Hinnant's days-to-civil algorithm
+    let days = (secs / 86400) as i64 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{}-{:02}-{:02}", year, month, day)
+}
+
+/// Formats milliseconds as minutes and seconds, e.g. "3:07".
+pub fn duration(ms: u64) -> String {
+    let secs = ms / 1000;
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+/// The history screen, listing past games newest first.
+pub struct HistoryMenu {
+    games: Vec<GameRecord>,
+    /// Only games of this mode are listed, or every game if `None`.
+    filter: Option<Mode>,
+    /// Index of the first listed game shown.
+    scroll: usize,
+}
+
+impl HistoryMenu {
+    /// Creates a new `HistoryMenu` listing the games currently recorded.
+    pub fn new() -> Self {
+        let mut games = History::load().games;
+        games.reverse();
+        Self {
+            games,
+            filter: None,
+            scroll: 0,
+        }
+    }
+
+    /// Returns the games which pass the filter.
+    fn filtered(&self) -> Vec<&GameRecord> {
+        self.games
+            .iter()
+            .filter(|game| self.filter.is_none_or(|mode| game.mode == mode))
+            .collect()
+    }
+
+    /// Handles a key press on the history screen.
+    ///
+    /// # Parameters
+    ///
+    /// - `key`: The key which was pressed.
+    pub fn process_input(&mut self, key: VirtualKeyCode) {
+        let max_scroll = self.filtered().len().saturating_sub(VISIBLE_ROWS);
+        match key {
+            VirtualKeyCode::Up => self.scroll = self.scroll.saturating_sub(1),
+            VirtualKeyCode::Down => self.scroll = (self.scroll + 1).min(max_scroll),
+            VirtualKeyCode::PageUp => self.scroll = self.scroll.saturating_sub(VISIBLE_ROWS),
+            VirtualKeyCode::PageDown => self.scroll = (self.scroll + VISIBLE_ROWS).min(max_scroll),
+            VirtualKeyCode::Left | VirtualKeyCode::Right => {
+                // Cycle through every mode, with no filter between the last and first
+                let filters: Vec<Option<Mode>> = std::iter::once(None)
+                    .chain(Mode::ALL.iter().copied().map(Some))
+                    .collect();
+                let current = filters.iter().position(|f| *f == self.filter).unwrap_or(0);
+                let step = if key == VirtualKeyCode::Left {
+                    filters.len() - 1
+                } else {
+                    1
+                };
+                self.filter = filters[(current + step) % filters.len()];
+                self.scroll = 0;
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the mode filter line shown above the list.
+    pub fn filter_line(&self) -> String {
+        match self.filter {
+            Some(mode) => format!("< Mode: {:?} >", mode),
+            None => String::from("< Mode: All >"),
+        }
+    }
+
+    /// Returns the lines of the history screen visible at the current scroll position.
+    pub fn lines(&self) -> Vec<String> {
+        let games = self.filtered();
+        if games.is_empty() {
+            return vec![String::from("No games yet")];
+        }
+        games
+            .iter()
+            .skip(self.scroll)
+            .take(VISIBLE_ROWS)
+            .map(|game| {
+                format!(
+                    "{}  {:<8}  {:>7} pts  {:>3} lines  {}",
+                    date(game.finished_at),
+                    format!("{:?}", game.mode),
+                    game.score,
+                    game.lines,
+                    duration(game.duration_ms)
+                )
+            })
+            .collect()
+    }
+}
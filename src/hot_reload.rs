@@ -0,0 +1,73 @@
+use std::fs::{metadata, read};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use web_time::Instant;
+
+/// How often the watched files are checked for changes.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Watches the files in `assets/` which are built into the game, so changes to them
+/// show up without restarting. Only used in debug builds, where the source tree is
+/// still around.
+pub struct AssetWatcher {
+    /// Each watched file, and when it was last modified.
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+    last_check: Instant,
+}
+
+impl AssetWatcher {
+    /// Creates a new `AssetWatcher` in debug builds, or `None` in release builds.
+    ///
+    /// # Parameters
+    ///
+    /// - `names`: Names of the files in `assets/` to watch, e.g. `block.png`.
+    pub fn new(names: &[&str]) -> Option<Self> {
+        if !cfg!(debug_assertions) {
+            return None;
+        }
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets");
+        let files = names
+            .iter()
+            .map(|name| {
+                let path = dir.join(name);
+                let modified = modified(&path);
+                (path, modified)
+            })
+            .collect();
+        Some(Self {
+            files,
+            last_check: Instant::now(),
+        })
+    }
+
+    /// Returns the files which changed since the last call, checking at most once a
+    /// second.
+    ///
+    /// # Return Value
+    ///
+    /// The name and new contents of each changed file.
+    pub fn changed(&mut self) -> Vec<(String, Vec<u8>)> {
+        if self.last_check.elapsed() < CHECK_INTERVAL {
+            return Vec::new();
+        }
+        self.last_check = Instant::now();
+        let mut changed = Vec::new();
+        for (path, last_modified) in self.files.iter_mut() {
+            let modified = modified(path);
+            if modified == *last_modified {
+                continue;
+            }
+            *last_modified = modified;
+            // Editors may save in several steps, so a failed read is retried next change
+            if let (Ok(bytes), Some(name)) = (read(&path), path.file_name()) {
+                changed.push((name.to_string_lossy().into_owned(), bytes));
+            }
+        }
+        changed
+    }
+}
+
+/// Returns when a file was last modified, or `None` if it doesn't exist.
+fn modified(path: &Path) -> Option<SystemTime> {
+    metadata(path).and_then(|m| m.modified()).ok()
+}
@@ -0,0 +1,537 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+use web_time::Instant;
+use wgputris::action::Action;
+use wgputris::GAMEBOARD_WIDTH;
+use winit::event::{ElementState, VirtualKeyCode};
+
+/// A key event as reported by the platform: the physical key, and the key it produces
+/// in the current keyboard layout.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Key {
+    pub scancode: u32,
+    pub virtual_keycode: Option<VirtualKeyCode>,
+}
+
+impl Key {
+    /// Returns `true` if this key satisfies a binding. Bindings for keys whose position
+    /// differs between layouts (letters, digits and punctuation) are matched by the
+    /// physical key at that position on a US QWERTY keyboard, so e.g. Z and X stay in
+    /// the same place on AZERTY; everything else is matched by virtual key.
+    ///
+    /// # Parameters
+    ///
+    /// - `binding`: The bound key, named as on a US QWERTY keyboard.
+    pub fn matches(&self, binding: VirtualKeyCode) -> bool {
+        match qwerty_scancode(binding) {
+            Some(scancode) if self.scancode != 0 => self.scancode == scancode,
+            _ => self.virtual_keycode == Some(binding),
+        }
+    }
+
+    /// Returns `true` if both events came from the same key.
+    fn same_key(&self, other: &Key) -> bool {
+        if self.scancode != 0 {
+            self.scancode == other.scancode
+        } else {
+            self.virtual_keycode == other.virtual_keycode
+        }
+    }
+}
+
+/// Returns the platform scancode of the key which produces `key` on a US QWERTY
+/// keyboard, or `None` for keys which are the same on every layout.
+#[cfg(not(target_os = "macos"))]
+fn qwerty_scancode(key: VirtualKeyCode) -> Option<u32> {
+    // Linux evdev codes, which match PC set 1 scancodes used by Windows for these keys
+    use VirtualKeyCode::*;
+    let scancode = match key {
+        Key1 => 2,
+        Key2 => 3,
+        Key3 => 4,
+        Key4 => 5,
+        Key5 => 6,
+        Key6 => 7,
+        Key7 => 8,
+        Key8 => 9,
+        Key9 => 10,
+        Key0 => 11,
+        Minus => 12,
+        Equals => 13,
+        Q => 16,
+        W => 17,
+        E => 18,
+        R => 19,
+        T => 20,
+        Y => 21,
+        U => 22,
+        I => 23,
+        O => 24,
+        P => 25,
+        LBracket => 26,
+        RBracket => 27,
+        A => 30,
+        S => 31,
+        D => 32,
+        F => 33,
+        G => 34,
+        H => 35,
+        J => 36,
+        K => 37,
+        L => 38,
+        Semicolon => 39,
+        Apostrophe => 40,
+        Grave => 41,
+        Backslash => 43,
+        Z => 44,
+        X => 45,
+        C => 46,
+        V => 47,
+        B => 48,
+        N => 49,
+        M => 50,
+        Comma => 51,
+        Period => 52,
+        Slash => 53,
+        _ => return None,
+    };
+    Some(scancode)
+}
+
+/// Returns the platform scancode of the key which produces `key` on a US QWERTY
+/// keyboard, or `None` for keys which are the same on every layout.
+#[cfg(target_os = "macos")]
+fn qwerty_scancode(key: VirtualKeyCode) -> Option<u32> {
+    // macOS virtual key codes (kVK_ANSI_*)
+    use VirtualKeyCode::*;
+    let scancode = match key {
+        A => 0,
+        S => 1,
+        D => 2,
+        F => 3,
+        H => 4,
+        G => 5,
+        Z => 6,
+        X => 7,
+        C => 8,
+        V => 9,
+        B => 11,
+        Q => 12,
+        W => 13,
+        E => 14,
+        R => 15,
+        Y => 16,
+        T => 17,
+        Key1 => 18,
+        Key2 => 19,
+        Key3 => 20,
+        Key4 => 21,
+        Key6 => 22,
+        Key5 => 23,
+        Equals => 24,
+        Key9 => 25,
+        Key7 => 26,
+        Minus => 27,
+        Key8 => 28,
+        Key0 => 29,
+        RBracket => 30,
+        O => 31,
+        U => 32,
+        LBracket => 33,
+        I => 34,
+        P => 35,
+        L => 37,
+        J => 38,
+        Apostrophe => 39,
+        K => 40,
+        Semicolon => 41,
+        Backslash => 42,
+        Comma => 43,
+        Slash => 44,
+        N => 45,
+        M => 46,
+        Period => 47,
+        Grave => 50,
+        _ => return None,
+    };
+    Some(scancode)
+}
+
+/// How quickly held movement keys repeat and soft drop falls.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Handling {
+    /// Delayed auto shift: how long a move key is held before it starts repeating, in
+    /// milliseconds.
+    pub das_ms: u64,
+    /// Auto repeat rate: time between repeated moves, in milliseconds. 0 moves straight
+    /// to the wall.
+    pub arr_ms: u64,
+    /// Soft drop factor: how many times faster than gravity the shape falls while soft
+    /// drop is held.
+    pub soft_drop_factor: u32,
+}
+
+impl Default for Handling {
+    fn default() -> Self {
+        Self {
+            das_ms: 167,
+            arr_ms: 33,
+            soft_drop_factor: 20,
+        }
+    }
+}
+
+/// A named set of key bindings, with one or more keys per `Action`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyProfile {
+    pub name: String,
+    pub move_left: Vec<VirtualKeyCode>,
+    pub move_right: Vec<VirtualKeyCode>,
+    pub soft_drop: Vec<VirtualKeyCode>,
+    pub hard_drop: Vec<VirtualKeyCode>,
+    pub rotate_cw: Vec<VirtualKeyCode>,
+    pub rotate_ccw: Vec<VirtualKeyCode>,
+    pub hold: Vec<VirtualKeyCode>,
+    #[serde(default)]
+    pub handling: Handling,
+}
+
+impl KeyProfile {
+    /// The original wgputris controls: arrows to move, Down to hard drop.
+    pub fn arrows() -> Self {
+        use VirtualKeyCode::*;
+        Self {
+            name: String::from("Arrows"),
+            move_left: vec![Left],
+            move_right: vec![Right],
+            soft_drop: vec![],
+            hard_drop: vec![Down],
+            rotate_cw: vec![X],
+            rotate_ccw: vec![Z],
+            hold: vec![C],
+            handling: Handling::default(),
+        }
+    }
+
+    /// Left hand movement on WASD with rotation on Q and E.
+    pub fn wasd() -> Self {
+        use VirtualKeyCode::*;
+        Self {
+            name: String::from("WASD"),
+            move_left: vec![A],
+            move_right: vec![D],
+            soft_drop: vec![S],
+            hard_drop: vec![W],
+            rotate_cw: vec![E],
+            rotate_ccw: vec![Q],
+            hold: vec![LShift],
+            handling: Handling::default(),
+        }
+    }
+
+    /// The default layout from the Tetris Guideline.
+    pub fn guideline() -> Self {
+        use VirtualKeyCode::*;
+        Self {
+            name: String::from("Guideline default"),
+            move_left: vec![Left],
+            move_right: vec![Right],
+            soft_drop: vec![Down],
+            hard_drop: vec![Space],
+            rotate_cw: vec![Up, X],
+            rotate_ccw: vec![LControl, Z],
+            hold: vec![LShift, C],
+            handling: Handling::default(),
+        }
+    }
+
+    /// Accessibility layout for playing with the right hand alone: the arrow keys plus
+    /// the block of six keys above them, so no key is more than a finger's reach away.
+    /// Movement repeats sooner and soft drop is faster, so fewer taps and less holding
+    /// are needed.
+    pub fn one_handed() -> Self {
+        use VirtualKeyCode::*;
+        Self {
+            name: String::from("One-handed"),
+            move_left: vec![Left],
+            move_right: vec![Right],
+            soft_drop: vec![Down],
+            hard_drop: vec![Up],
+            rotate_cw: vec![PageDown],
+            rotate_ccw: vec![Delete],
+            hold: vec![End],
+            handling: Handling {
+                das_ms: 133,
+                arr_ms: 16,
+                soft_drop_factor: 40,
+            },
+        }
+    }
+
+    /// The profiles available out of the box.
+    pub fn defaults() -> Vec<Self> {
+        vec![
+            Self::arrows(),
+            Self::wasd(),
+            Self::guideline(),
+            Self::one_handed(),
+        ]
+    }
+
+    /// Returns the keys bound to an action.
+    ///
+    /// # Parameters
+    ///
+    /// - `action`: The action to look up.
+    pub fn keys(&self, action: Action) -> &[VirtualKeyCode] {
+        match action {
+            Action::MoveLeft => &self.move_left,
+            Action::MoveRight => &self.move_right,
+            Action::SoftDrop => &self.soft_drop,
+            Action::HardDrop => &self.hard_drop,
+            Action::RotateCw => &self.rotate_cw,
+            Action::RotateCcw => &self.rotate_ccw,
+            Action::Hold => &self.hold,
+        }
+    }
+
+    /// Returns the action bound to a key, if any.
+    ///
+    /// # Parameters
+    ///
+    /// - `key`: The key which was pressed.
+    pub fn action(&self, key: Key) -> Option<Action> {
+        Action::ALL
+            .iter()
+            .copied()
+            .find(|action| self.keys(*action).iter().any(|b| key.matches(*b)))
+    }
+
+    /// Returns the keys bound in both this profile and another, e.g. to warn when two
+    /// players sharing a keyboard would fight over a key.
+    ///
+    /// # Parameters
+    ///
+    /// - `other`: The profile to compare against.
+    pub fn conflicts(&self, other: &KeyProfile) -> Vec<VirtualKeyCode> {
+        let mut conflicts = Vec::new();
+        for key in Action::ALL.iter().flat_map(|action| self.keys(*action)) {
+            let shared = Action::ALL
+                .iter()
+                .any(|action| other.keys(*action).contains(key));
+            if shared && !conflicts.contains(key) {
+                conflicts.push(*key);
+            }
+        }
+        conflicts
+    }
+}
+
+/// The order triggered actions are applied in when several happen in the same frame,
+/// so e.g. a rotation pressed together with a hard drop applies before the drop.
+const RESOLUTION_ORDER: [Action; 7] = [
+    Action::Hold,
+    Action::RotateCcw,
+    Action::RotateCw,
+    Action::MoveLeft,
+    Action::MoveRight,
+    Action::SoftDrop,
+    Action::HardDrop,
+];
+
+/// Records which keys are held and which actions were pressed between frames, so that
+/// actions pressed at the same time all register regardless of event order.
+pub struct InputState {
+    held_keys: Vec<Key>,
+    /// The key each scancode has been seen producing, used to label bindings with the
+    /// user's own layout.
+    labels: HashMap<u32, VirtualKeyCode>,
+    pressed: [bool; Action::ALL.len()],
+    /// When each held action was first pressed.
+    held_since: [Option<Instant>; Action::ALL.len()],
+    /// Number of auto repeats already triggered since each action was pressed.
+    repeats: [u32; Action::ALL.len()],
+    /// Whether soft drop is switched on, when it is used as a toggle.
+    soft_drop_toggled: bool,
+}
+
+impl InputState {
+    /// Creates a new `InputState` with nothing held.
+    pub fn new() -> Self {
+        Self {
+            held_keys: Vec::new(),
+            labels: HashMap::new(),
+            pressed: [false; Action::ALL.len()],
+            held_since: [None; Action::ALL.len()],
+            repeats: [0; Action::ALL.len()],
+            soft_drop_toggled: false,
+        }
+    }
+
+    /// Records a key being pressed or released.
+    ///
+    /// # Parameters
+    ///
+    /// - `key`: The key which changed.
+    /// - `state`: Whether it was pressed or released.
+    /// - `profile`: Bindings used to map the key to an action.
+    pub fn key_event(&mut self, key: Key, state: ElementState, profile: &KeyProfile) {
+        if let Some(virtual_keycode) = key.virtual_keycode {
+            if key.scancode != 0 {
+                self.labels.insert(key.scancode, virtual_keycode);
+            }
+        }
+        let action = profile.action(key);
+        match state {
+            ElementState::Pressed => {
+                if self.held_keys.iter().any(|k| k.same_key(&key)) {
+                    return;
+                }
+                if let Some(action) = action {
+                    if !self.is_held(action, profile) {
+                        self.pressed[action as usize] = true;
+                        self.held_since[action as usize] = Some(Instant::now());
+                        self.repeats[action as usize] = 0;
+                    }
+                }
+                self.held_keys.push(key);
+            }
+            ElementState::Released => {
+                self.held_keys.retain(|k| !k.same_key(&key));
+                if let Some(action) = action {
+                    if !self.is_held(action, profile) {
+                        self.held_since[action as usize] = None;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns `true` if any key bound to the action is currently held down.
+    ///
+    /// # Parameters
+    ///
+    /// - `action`: The action to check.
+    /// - `profile`: Bindings used to map keys to the action.
+    pub fn is_held(&self, action: Action, profile: &KeyProfile) -> bool {
+        profile
+            .keys(action)
+            .iter()
+            .any(|binding| self.held_keys.iter().any(|k| k.matches(*binding)))
+    }
+
+    /// Returns the name of a bound key as it appears on the user's keyboard, once a key
+    /// at that position has been pressed; until then the US QWERTY name is used.
+    ///
+    /// # Parameters
+    ///
+    /// - `binding`: The bound key.
+    pub fn label(&self, binding: VirtualKeyCode) -> String {
+        let key = qwerty_scancode(binding)
+            .and_then(|scancode| self.labels.get(&scancode))
+            .unwrap_or(&binding);
+        format!("{:?}", key)
+    }
+
+    /// Returns `true` if the action went from released to held this frame.
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.pressed[action as usize]
+    }
+
+    /// Returns how many actions went from released to held this frame, for counting
+    /// keys pressed.
+    pub fn presses(&self) -> usize {
+        self.pressed.iter().filter(|&&pressed| pressed).count()
+    }
+
+    /// Returns the actions which are held down but weren't pressed this frame, in
+    /// resolution order.
+    ///
+    /// # Parameters
+    ///
+    /// - `profile`: Bindings used to map keys to actions.
+    pub fn held(&self, profile: &KeyProfile) -> Vec<Action> {
+        RESOLUTION_ORDER
+            .iter()
+            .copied()
+            .filter(|action| !self.just_pressed(*action) && self.is_held(*action, profile))
+            .collect()
+    }
+
+    /// Returns the actions to apply this frame, in resolution order. Held movement keys
+    /// also repeat according to the profile's `Handling`, so an action may appear more
+    /// than once.
+    ///
+    /// # Parameters
+    ///
+    /// - `profile`: Bindings and handling of the player.
+    pub fn triggered(&mut self, profile: &KeyProfile) -> Vec<Action> {
+        let now = Instant::now();
+        let mut actions = Vec::new();
+        for action in RESOLUTION_ORDER.iter().copied() {
+            if self.just_pressed(action) {
+                actions.push(action);
+            }
+            if matches!(action, Action::MoveLeft | Action::MoveRight) {
+                for _ in 0..self.auto_repeats(action, &profile.handling, now) {
+                    actions.push(action);
+                }
+            }
+        }
+        actions
+    }
+
+    /// Returns `true` if soft drop is in effect: while its key is held, or in toggle mode
+    /// from one press of the key to the next. Should be called once per frame.
+    ///
+    /// # Parameters
+    ///
+    /// - `profile`: Bindings used to map keys to soft drop.
+    /// - `toggle`: `true` if soft drop is a toggle rather than held.
+    pub fn soft_drop(&mut self, profile: &KeyProfile, toggle: bool) -> bool {
+        if !toggle {
+            return self.is_held(Action::SoftDrop, profile);
+        }
+        if self.just_pressed(Action::SoftDrop) {
+            self.soft_drop_toggled = !self.soft_drop_toggled;
+        }
+        self.soft_drop_toggled
+    }
+
+    /// Returns how many auto repeats of a held action are due, and marks them as done.
+    fn auto_repeats(&mut self, action: Action, handling: &Handling, now: Instant) -> u32 {
+        let held_since = match self.held_since[action as usize] {
+            Some(held_since) => held_since,
+            None => return 0,
+        };
+        let held_for = now - held_since;
+        let das = Duration::from_millis(handling.das_ms);
+        if held_for < das {
+            return 0;
+        }
+        let due = if handling.arr_ms == 0 {
+            GAMEBOARD_WIDTH as u32
+        } else {
+            1 + ((held_for - das).as_millis() / handling.arr_ms as u128) as u32
+        };
+        let repeats = due.saturating_sub(self.repeats[action as usize]);
+        self.repeats[action as usize] = due.max(self.repeats[action as usize]);
+        repeats
+    }
+
+    /// Clears the transitions recorded this frame. Should be called once per frame after
+    /// they have been handled.
+    pub fn end_frame(&mut self) {
+        self.pressed = [false; Action::ALL.len()];
+    }
+
+    /// Forgets every held key, e.g. when the window loses focus.
+    pub fn clear(&mut self) {
+        self.held_keys.clear();
+        self.held_since = [None; Action::ALL.len()];
+        self.soft_drop_toggled = false;
+        self.end_frame();
+    }
+}
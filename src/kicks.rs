@@ -0,0 +1,177 @@
+//! Wall kicks: offsets a turning shape tries in order when it doesn't fit where it is,
+//! so it can turn against the walls and the stack. Tables are read from TOML files, so
+//! other rotation systems can be played without rebuilding.
+
+use crate::tetromino::TetrominoKind;
+
+use anyhow::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fs::read_to_string;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// File the custom kick table is loaded from, in the config directory.
+pub const KICKS_FILE: &str = "kicks.toml";
+/// An example kick table explaining the format, as a file to copy and edit.
+pub const EXAMPLE_TABLE: &str = include_str!("../assets/kicks/example.toml");
+/// The Super Rotation System's kicks, as a file to load.
+pub const SRS_TABLE: &str = include_str!("../assets/kicks/srs.toml");
+/// Names of the four orientations, clockwise from the spawn orientation, as turns are
+/// written in kick table files.
+const ORIENTATIONS: [&str; 4] = ["0", "R", "2", "L"];
+/// Section of a kick table file used for shapes without their own.
+const DEFAULT_SECTION: &str = "default";
+
+/// `SRS_TABLE`, read the first time it's needed, as games and replays are made often,
+/// e.g. by bots.
+static SRS: OnceLock<KickTable> = OnceLock::new();
+
+/// A kick table file: a section for each shape, or `DEFAULT_SECTION`, listing offsets
+/// for each turn, written like `0>R`, as `[x, y]` with y up.
+type KickFile = BTreeMap<String, BTreeMap<String, Vec<[i32; 2]>>>;
+
+/// Which kick table single player games are played with.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KickPreset {
+    /// Shapes only turn where they are.
+    None,
+    /// The Super Rotation System of the Tetris Guideline.
+    Srs,
+    /// The table in `KICKS_FILE`.
+    Custom,
+}
+
+impl KickPreset {
+    /// Every preset, in the order they are listed.
+    pub const ALL: [KickPreset; 3] = [KickPreset::None, KickPreset::Srs, KickPreset::Custom];
+
+    /// Name shown in menus.
+    pub fn label(self) -> &'static str {
+        match self {
+            KickPreset::None => "None",
+            KickPreset::Srs => "SRS",
+            KickPreset::Custom => "Custom",
+        }
+    }
+}
+
+/// Offsets each shape tries for each turn. Saved as the file it was read from, so it
+/// can be kept in replays.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "KickFile", into = "KickFile")]
+pub struct KickTable {
+    file: KickFile,
+    /// Offsets on the board, with y down, for each shape, orientation turned from and
+    /// direction. See `KickTable::index`.
+    offsets: Vec<Vec<(i32, i32)>>,
+}
+
+impl KickTable {
+    /// Returns a table with no kicks, where shapes only turn where they are.
+    pub fn none() -> Self {
+        Self::try_from(KickFile::new()).expect("An empty kick table is valid")
+    }
+
+    /// Returns the Super Rotation System's table.
+    pub fn srs() -> &'static Self {
+        SRS.get_or_init(|| Self::parse(SRS_TABLE).expect("The built-in SRS kick table is valid"))
+    }
+
+    /// Reads a table from the contents of a kick table file.
+    ///
+    /// # Parameters
+    ///
+    /// - `text`: The file's contents, in the format of `EXAMPLE_TABLE`.
+    pub fn parse(text: &str) -> Result<Self> {
+        Ok(toml::from_str(text)?)
+    }
+
+    /// Loads a table from a file, e.g. `KICKS_FILE`.
+    ///
+    /// # Parameters
+    ///
+    /// - `path`: The table file.
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::parse(&read_to_string(path)?)
+    }
+
+    /// Returns the offsets a shape tries for a turn, in the order to try them.
+    ///
+    /// # Parameters
+    ///
+    /// - `kind`: Shape being turned.
+    /// - `from`: Orientation turned from, in quarter turns clockwise from spawn.
+    /// - `clockwise`: Direction of the turn.
+    pub fn offsets(&self, kind: TetrominoKind, from: usize, clockwise: bool) -> &[(i32, i32)] {
+        &self.offsets[Self::index(kind, from, clockwise)]
+    }
+
+    /// Returns where a turn's offsets are kept in `offsets`.
+    fn index(kind: TetrominoKind, from: usize, clockwise: bool) -> usize {
+        (kind as usize * ORIENTATIONS.len() + from % ORIENTATIONS.len()) * 2 + clockwise as usize
+    }
+}
+
+impl TryFrom<KickFile> for KickTable {
+    type Error = Error;
+
+    fn try_from(file: KickFile) -> Result<Self> {
+        let mut offsets = vec![vec![(0, 0)]; TetrominoKind::ALL.len() * ORIENTATIONS.len() * 2];
+        let kinds = |section: &str| -> Result<Vec<TetrominoKind>> {
+            if section == DEFAULT_SECTION {
+                return Ok(TetrominoKind::ALL.to_vec());
+            }
+            TetrominoKind::ALL
+                .iter()
+                .copied()
+                .find(|kind| format!("{:?}", kind).eq_ignore_ascii_case(section))
+                .map(|kind| vec![kind])
+                .ok_or_else(|| anyhow!("Unknown shape in kick table: {}", section))
+        };
+        // Shapes' own sections override the default, whichever order they're in
+        let sections = file
+            .iter()
+            .filter(|(section, _)| *section == DEFAULT_SECTION)
+            .chain(
+                file.iter()
+                    .filter(|(section, _)| *section != DEFAULT_SECTION),
+            );
+        for (section, turns) in sections {
+            let kinds = kinds(section)?;
+            for (turn, kicks) in turns {
+                let orientation = |name: &str| {
+                    ORIENTATIONS
+                        .iter()
+                        .position(|&o| o == name.trim())
+                        .ok_or_else(|| anyhow!("Invalid turn in kick table: {}", turn))
+                };
+                let (from, to) = turn
+                    .split_once('>')
+                    .ok_or_else(|| anyhow!("Invalid turn in kick table: {}", turn))?;
+                let (from, to) = (orientation(from)?, orientation(to)?);
+                let clockwise = match (to + ORIENTATIONS.len() - from) % ORIENTATIONS.len() {
+                    1 => true,
+                    3 => false,
+                    _ => bail!("{} is not a quarter turn", turn),
+                };
+                if kicks.is_empty() {
+                    bail!("The {} turn in [{}] has no offsets", turn, section);
+                }
+                for &kind in &kinds {
+                    offsets[Self::index(kind, from, clockwise)] =
+                        kicks.iter().map(|&[x, y]| (x, -y)).collect();
+                }
+            }
+        }
+        Ok(Self { file, offsets })
+    }
+}
+
+impl From<KickTable> for KickFile {
+    fn from(table: KickTable) -> Self {
+        table.file
+    }
+}
@@ -0,0 +1,50 @@
+//! The rules of wgputris, with no dependency on a window or renderer, so they can be
+//! reused by tests, bots, servers and other frontends.
+
+pub mod achievements;
+pub mod action;
+pub mod ai;
+pub mod arena;
+pub mod attack;
+pub mod celebration;
+pub mod clock;
+pub mod finesse;
+pub mod fumen;
+pub mod game;
+pub mod gameboard;
+pub mod handicap;
+pub mod kicks;
+pub mod lobby;
+pub mod netplay;
+pub mod palette;
+pub mod paths;
+pub mod rating;
+pub mod replay;
+pub mod rhythm;
+pub mod sequence;
+pub mod speed;
+pub mod stats;
+pub mod tbp;
+pub mod tetromino;
+
+pub const BLOCK_SIZE: u32 = 12;
+pub const GAMEBOARD_OFFSET: (usize, usize) = (15, 1);
+pub const GAMEBOARD_WIDTH: usize = 10;
+pub const GAMEBOARD_HEIGHT: usize = 20;
+/// Number of hidden rows above the gameboard, which shapes spawn partly in and may
+/// move through, but not lock in.
+pub const GAMEBOARD_HIDDEN_ROWS: usize = 2;
+/// Number of vertices `Game::render` fills for one game.
+pub const VERTEX_COUNT: usize = 1422;
+
+/// A corner of a coloured quad, as filled in by `Game::render` and drawn by the renderer.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub tex_coords: [f32; 2],
+    pub color: [f32; 4],
+}
+
+unsafe impl bytemuck::Pod for Vertex {}
+unsafe impl bytemuck::Zeroable for Vertex {}
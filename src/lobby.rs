@@ -0,0 +1,766 @@
+//! A server where players find each other for online matches. It lists rooms waiting
+//! for players, pairs players up, starts the match once both in a room are ready, then
+//! relays each player's messages to the other. A player whose connection drops keeps
+//! their place in the match for a while, so they can rejoin. The lobby plays both games
+//! itself from the inputs it relays, so it can decide matches between players who sent
+//! their ids and update their ratings. Anyone else can watch a match in progress,
+//! a few seconds behind, so spectators can't pass on what's about to happen. Once a
+//! match is decided, both players can ask for a rematch, which starts in the same room
+//! with a new seed.
+
+use crate::netplay::{self, Connection, Host, Message};
+use crate::paths;
+use crate::rating::{self, MatchResult, Ratings};
+use crate::replay::{Playback, Replay};
+use anyhow::*;
+use std::collections::{BTreeMap, VecDeque};
+use std::time::Duration;
+use web_time::Instant;
+
+/// How long a player whose connection dropped during a match has to rejoin it.
+pub const REJOIN_TIMEOUT: Duration = Duration::from_secs(30);
+/// Players in a room.
+const ROOM_SIZE: usize = 2;
+/// File in the data directory ratings are saved to.
+const RATINGS_FILE: &str = "ratings.txt";
+/// How far behind the players spectators watch a match.
+pub const SPECTATOR_DELAY: Duration = Duration::from_secs(3);
+
+/// A player's place in a room.
+struct Seat {
+    /// The player's client, or `None` while they're away.
+    client: Option<u64>,
+    /// The id the player's rating is kept under, if they sent one.
+    player: Option<u64>,
+    /// Proves a rejoining client is this player.
+    token: u64,
+    ready: bool,
+    /// Whether the player asked for a rematch once the match was decided.
+    rematch: bool,
+    /// The player's `Input` and `Time` messages in the match so far, with when they
+    /// arrived, to send to the other player again if they rejoin, and to spectators.
+    log: Vec<(Instant, Message)>,
+    /// When the player's connection dropped, if it has.
+    away_since: Option<Instant>,
+}
+
+impl Seat {
+    fn new(client: u64, player: Option<u64>) -> Self {
+        Self {
+            client: Some(client),
+            player,
+            token: rand::random(),
+            ready: false,
+            rematch: false,
+            log: Vec::new(),
+            away_since: None,
+        }
+    }
+}
+
+/// Players waiting for a match together, or playing it.
+struct Room {
+    seats: Vec<Seat>,
+    /// Seed of the match, once it has started.
+    seed: Option<u64>,
+    /// Each seat's game, played up to the latest time its player sent, once the match
+    /// has started.
+    games: Vec<Playback>,
+    /// Whether the match has been decided, by a player topping out or leaving.
+    decided: bool,
+}
+
+impl Room {
+    fn new() -> Self {
+        Self {
+            seats: Vec::new(),
+            seed: None,
+            games: Vec::new(),
+            decided: false,
+        }
+    }
+
+    /// Returns the ids of both players, if the match counts towards their ratings:
+    /// both sent their ids, and they're different players.
+    fn ranked_players(&self) -> Option<(u64, u64)> {
+        match self.seats.as_slice() {
+            [first, second] => match (first.player, second.player) {
+                (Some(first), Some(second)) if first != second => Some((first, second)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// A connected client, and the room it has a place in.
+struct Client {
+    connection: Connection,
+    room: Option<u32>,
+    /// The id the player sent, if they have.
+    player: Option<u64>,
+    /// The match the client is watching, if it is a spectator.
+    watching: Option<Watching>,
+}
+
+/// A spectator's view of a match.
+struct Watching {
+    room: u32,
+    /// Seed of the match, so a rematch in the room isn't mistaken for it.
+    seed: u64,
+    /// Players' messages not sent to the spectator yet, with when they arrived.
+    queue: VecDeque<(Instant, Message)>,
+}
+
+/// The lobby server.
+pub struct Lobby {
+    host: Host,
+    clients: BTreeMap<u64, Client>,
+    /// Rooms by number, which counts up, so the room waiting longest comes first.
+    rooms: BTreeMap<u32, Room>,
+    next_client: u64,
+    next_room: u32,
+    ratings: Ratings,
+    /// How far behind the players spectators watch.
+    spectator_delay: Duration,
+}
+
+impl Lobby {
+    /// Starts listening for clients on every network interface.
+    ///
+    /// # Parameters
+    ///
+    /// - `port`: Port to listen for native clients on, or `0` for any free port.
+    /// - `web_port`: Port to listen for browsers on, or `0` for any free port.
+    pub fn listen(port: u16, web_port: u16) -> Result<Self> {
+        Ok(Self {
+            host: Host::listen(port, web_port)?,
+            clients: BTreeMap::new(),
+            rooms: BTreeMap::new(),
+            next_client: 0,
+            next_room: 1,
+            ratings: Ratings::new(),
+            spectator_delay: SPECTATOR_DELAY,
+        })
+    }
+
+    /// Changes how far behind the players spectators watch, `SPECTATOR_DELAY` unless
+    /// changed.
+    ///
+    /// # Parameters
+    ///
+    /// - `delay`: The delay.
+    pub fn set_spectator_delay(&mut self, delay: Duration) {
+        self.spectator_delay = delay;
+    }
+
+    /// Returns the host clients connect to.
+    pub fn host(&self) -> &Host {
+        &self.host
+    }
+
+    /// Runs the lobby until the process is stopped, keeping ratings in the data
+    /// directory.
+    ///
+    /// # Parameters
+    ///
+    /// - `port`: Port to listen for native clients on.
+    /// - `web_port`: Port to listen for browsers on.
+    pub fn run(port: u16, web_port: u16) -> Result<()> {
+        let mut lobby = Self::listen(port, web_port)?;
+        lobby.ratings = Ratings::load(paths::data_path(RATINGS_FILE))?;
+        println!(
+            "Lobby listening on port {} (browsers: {})",
+            lobby.host.port(),
+            lobby.host.web_port()
+        );
+        loop {
+            lobby.update();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    /// Accepts new clients and handles every message which has arrived, without
+    /// waiting.
+    pub fn update(&mut self) {
+        loop {
+            match self.host.accept() {
+                Ok(Some(mut connection)) => {
+                    connection.send(&Message::Lobby);
+                    let client = Client {
+                        connection,
+                        room: None,
+                        player: None,
+                        watching: None,
+                    };
+                    self.clients.insert(self.next_client, client);
+                    self.next_client += 1;
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    eprintln!("Failed to accept a client: {}", e);
+                    break;
+                }
+            }
+        }
+        let ids: Vec<u64> = self.clients.keys().copied().collect();
+        for id in ids {
+            let received = match self.clients.get_mut(&id) {
+                Some(client) => client.connection.receive(),
+                None => continue,
+            };
+            match received {
+                Ok(messages) => {
+                    for message in messages {
+                        self.handle(id, message);
+                    }
+                }
+                Err(_) => self.disconnect(id),
+            }
+        }
+        self.expire_away_seats();
+        self.update_spectators();
+    }
+
+    /// Queues a message to a client, if it is still connected.
+    fn send(&mut self, id: u64, message: &Message) {
+        if let Some(client) = self.clients.get_mut(&id) {
+            client.connection.send(message);
+        }
+    }
+
+    /// Queues a message to the other player in a client's room, if there is one and
+    /// they're connected.
+    fn send_other(&mut self, id: u64, message: &Message) {
+        let other = self.room_of(id).and_then(|room| {
+            room.seats
+                .iter()
+                .find(|seat| seat.client != Some(id))
+                .and_then(|seat| seat.client)
+        });
+        if let Some(other) = other {
+            self.send(other, message);
+        }
+    }
+
+    fn room_of(&self, id: u64) -> Option<&Room> {
+        let room = self.clients.get(&id)?.room?;
+        self.rooms.get(&room)
+    }
+
+    fn seat_of(&mut self, id: u64) -> Option<&mut Seat> {
+        let room = self.clients.get(&id)?.room?;
+        let room = self.rooms.get_mut(&room)?;
+        room.seats.iter_mut().find(|seat| seat.client == Some(id))
+    }
+
+    /// Handles a message from a client.
+    fn handle(&mut self, id: u64, message: Message) {
+        match message {
+            Message::List => {
+                let rooms: Vec<Message> = self
+                    .rooms
+                    .iter()
+                    .filter(|(_, room)| room.seed.is_none())
+                    .map(|(number, room)| Message::Room {
+                        room: *number,
+                        players: room.seats.len(),
+                        rating: room_rating(&self.ratings, room).round() as u32,
+                    })
+                    .collect();
+                let playing: Vec<Message> = self
+                    .rooms
+                    .iter()
+                    .filter(|(_, room)| room.seed.is_some() && !room.decided)
+                    .map(|(number, _)| Message::Playing(*number))
+                    .collect();
+                for message in rooms.iter().chain(&playing) {
+                    self.send(id, message);
+                }
+                self.send(id, &Message::Listed);
+            }
+            Message::Create => {
+                self.leave_room(id);
+                let room = self.next_room;
+                self.next_room += 1;
+                self.rooms.insert(room, Room::new());
+                self.enter_room(id, room);
+            }
+            Message::Enter(room) => {
+                let open = self
+                    .rooms
+                    .get(&room)
+                    .is_some_and(|room| room.seed.is_none() && room.seats.len() < ROOM_SIZE);
+                if open {
+                    self.leave_room(id);
+                    self.enter_room(id, room);
+                } else {
+                    self.send(id, &Message::Refused(String::from("That room is full")));
+                }
+            }
+            Message::Quick => {
+                let client = self.clients.get(&id);
+                let current = client.and_then(|client| client.room);
+                let bucket = rating::bucket(self.ratings.get(client.and_then(|c| c.player)));
+                // The nearest bucket, then the room waiting longest
+                let waiting = self
+                    .rooms
+                    .iter()
+                    .filter(|(number, room)| {
+                        Some(**number) != current && room.seed.is_none() && room.seats.len() == 1
+                    })
+                    .min_by_key(|(number, room)| {
+                        let room_bucket = rating::bucket(room_rating(&self.ratings, room));
+                        ((room_bucket - bucket).abs(), **number)
+                    })
+                    .map(|(number, _)| *number);
+                match waiting {
+                    Some(room) => self.handle(id, Message::Enter(room)),
+                    None => self.handle(id, Message::Create),
+                }
+            }
+            Message::Ready => {
+                if let Some(seat) = self.seat_of(id) {
+                    seat.ready = true;
+                }
+                self.send_other(id, &Message::Ready);
+                self.start_if_ready(id);
+            }
+            Message::Leave => {
+                if let Some(client) = self.clients.get_mut(&id) {
+                    client.watching = None;
+                }
+                self.leave_room(id);
+            }
+            Message::Input(..) | Message::Time(_) | Message::Hash(..) => {
+                let started = self.room_of(id).is_some_and(|room| room.seed.is_some());
+                if let (true, Some(seat)) = (started, self.seat_of(id)) {
+                    seat.log.push((Instant::now(), message.clone()));
+                    self.send_other(id, &message);
+                    self.show_spectators(id, &message);
+                    self.play(id, message);
+                }
+            }
+            Message::Watch(room) => self.watch(id, room),
+            Message::Profile(player) => {
+                if let Some(client) = self.clients.get_mut(&id) {
+                    client.player = Some(player);
+                }
+                let rating = self.ratings.get(Some(player)).round() as u32;
+                self.send(id, &Message::Rating(rating));
+            }
+            Message::Rejoin { room, token } => self.rejoin(id, room, token),
+            Message::Chat(_) => self.send_other(id, &message),
+            Message::Rematch => {
+                let decided = self.room_of(id).is_some_and(|room| room.decided);
+                if let (true, Some(seat)) = (decided, self.seat_of(id)) {
+                    seat.rematch = true;
+                    self.send_other(id, &Message::Rematch);
+                    self.rematch_if_agreed(id);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Gives a client a place in a room and tells both players in it.
+    fn enter_room(&mut self, id: u64, number: u32) {
+        let room = match self.rooms.get_mut(&number) {
+            Some(room) => room,
+            None => return,
+        };
+        let player = self.clients.get(&id).and_then(|client| client.player);
+        let seat = Seat::new(id, player);
+        let token = seat.token;
+        let other_ready = room.seats.first().map(|other| other.ready);
+        room.seats.push(seat);
+        if let Some(client) = self.clients.get_mut(&id) {
+            client.room = Some(number);
+        }
+        self.send(
+            id,
+            &Message::Entered {
+                room: number,
+                token,
+            },
+        );
+        if let Some(other_ready) = other_ready {
+            self.send(id, &Message::Joined);
+            if other_ready {
+                self.send(id, &Message::Ready);
+            }
+            self.send_other(id, &Message::Joined);
+        }
+    }
+
+    /// Starts the match in a client's room if both players are ready.
+    fn start_if_ready(&mut self, id: u64) {
+        let number = match self.clients.get(&id).and_then(|client| client.room) {
+            Some(number) => number,
+            None => return,
+        };
+        let room = match self.rooms.get_mut(&number) {
+            Some(room) => room,
+            None => return,
+        };
+        if room.seed.is_some()
+            || room.seats.len() < ROOM_SIZE
+            || !room.seats.iter().all(|seat| seat.ready)
+        {
+            return;
+        }
+        self.start_match(number);
+    }
+
+    /// Starts a rematch in a client's room once the match is decided and both players
+    /// are there and have asked for one. Spectators of the last match see the rest of
+    /// it, then are told it's over.
+    fn rematch_if_agreed(&mut self, id: u64) {
+        let number = match self.clients.get(&id).and_then(|client| client.room) {
+            Some(number) => number,
+            None => return,
+        };
+        let room = match self.rooms.get_mut(&number) {
+            Some(room) => room,
+            None => return,
+        };
+        if !room.decided
+            || room.seats.len() < ROOM_SIZE
+            || !room
+                .seats
+                .iter()
+                .all(|seat| seat.rematch && seat.client.is_some())
+        {
+            return;
+        }
+        room.decided = false;
+        for seat in &mut room.seats {
+            seat.rematch = false;
+            seat.log.clear();
+        }
+        self.start_match(number);
+    }
+
+    /// Deals a new seed for the match in a room and tells both players to start.
+    fn start_match(&mut self, number: u32) {
+        let room = match self.rooms.get_mut(&number) {
+            Some(room) => room,
+            None => return,
+        };
+        let seed = rand::random();
+        room.seed = Some(seed);
+        // A new game without a board set up can't fail to start
+        room.games = room
+            .seats
+            .iter()
+            .filter_map(|_| Playback::new(Replay::new(seed)).ok())
+            .collect();
+        let clients: Vec<u64> = room.seats.iter().filter_map(|seat| seat.client).collect();
+        for client in clients {
+            self.send(client, &Message::Start { seed });
+        }
+    }
+
+    /// Takes a client out of its room, telling the other player, and closes the room
+    /// once nobody is left in it.
+    fn leave_room(&mut self, id: u64) {
+        self.forfeit(id);
+        self.send_other(id, &Message::Leave);
+        let number = match self
+            .clients
+            .get_mut(&id)
+            .and_then(|client| client.room.take())
+        {
+            Some(number) => number,
+            None => return,
+        };
+        if let Some(room) = self.rooms.get_mut(&number) {
+            room.seats.retain(|seat| seat.client != Some(id));
+            for seat in &mut room.seats {
+                seat.ready = false;
+            }
+            if room.seats.is_empty() {
+                self.rooms.remove(&number);
+            }
+        }
+    }
+
+    /// Forgets a client whose connection dropped. During a match its player keeps
+    /// their place for `REJOIN_TIMEOUT`.
+    fn disconnect(&mut self, id: u64) {
+        let started = self.room_of(id).is_some_and(|room| room.seed.is_some());
+        if started {
+            self.send_other(id, &Message::Away);
+            if let Some(seat) = self.seat_of(id) {
+                seat.client = None;
+                seat.away_since = Some(Instant::now());
+            }
+        } else {
+            self.leave_room(id);
+        }
+        self.clients.remove(&id);
+    }
+
+    /// Gives a player whose connection dropped their place in the match back, and
+    /// sends them everything the other player has sent so far.
+    fn rejoin(&mut self, id: u64, number: u32, token: u64) {
+        let seat = self.rooms.get_mut(&number).and_then(|room| {
+            room.seats
+                .iter_mut()
+                .find(|seat| seat.token == token && seat.client.is_none())
+        });
+        let seat = match seat {
+            Some(seat) => seat,
+            None => {
+                let reason = String::from("The match is over");
+                self.send(id, &Message::Refused(reason));
+                return;
+            }
+        };
+        seat.client = Some(id);
+        seat.away_since = None;
+        let inputs = seat
+            .log
+            .iter()
+            .filter(|(_, message)| matches!(message, Message::Input(..)))
+            .count();
+        let other_log = self.rooms[&number]
+            .seats
+            .iter()
+            .find(|seat| seat.token != token)
+            .map(|seat| seat.log.clone())
+            .unwrap_or_default();
+        if let Some(client) = self.clients.get_mut(&id) {
+            client.room = Some(number);
+        }
+        self.send(id, &Message::Rejoined(inputs));
+        for (_, message) in &other_log {
+            self.send(id, message);
+        }
+        self.send_other(id, &Message::Back);
+    }
+
+    /// Gives up on players who haven't rejoined in time, telling the other player
+    /// they left, and closes rooms nobody is left in.
+    fn expire_away_seats(&mut self) {
+        let mut expired = Vec::new();
+        for (number, room) in &self.rooms {
+            for (index, seat) in room.seats.iter().enumerate() {
+                let away_since = seat.away_since;
+                if away_since.is_some_and(|since| since.elapsed() >= REJOIN_TIMEOUT) {
+                    expired.push((*number, index));
+                }
+            }
+        }
+        for (number, seat) in expired {
+            self.forfeit_seat(number, seat);
+        }
+        let mut left = Vec::new();
+        for room in self.rooms.values_mut() {
+            let before = room.seats.len();
+            room.seats.retain(|seat| {
+                seat.away_since
+                    .map_or(true, |since| since.elapsed() < REJOIN_TIMEOUT)
+            });
+            if room.seats.len() < before {
+                left.extend(room.seats.iter().filter_map(|seat| seat.client));
+            }
+        }
+        for id in left {
+            self.send(id, &Message::Leave);
+        }
+        self.rooms.retain(|_, room| !room.seats.is_empty());
+    }
+
+    /// Makes a client a spectator of the match in a room, and queues everything its
+    /// players have sent so far.
+    fn watch(&mut self, id: u64, number: u32) {
+        self.leave_room(id);
+        let room = match self.rooms.get(&number) {
+            Some(room) => room,
+            None => {
+                let reason = String::from("That match is over");
+                self.send(id, &Message::Refused(reason));
+                return;
+            }
+        };
+        let seed = match room.seed {
+            Some(seed) => seed,
+            None => {
+                let reason = String::from("That match hasn't started");
+                self.send(id, &Message::Refused(reason));
+                return;
+            }
+        };
+        let mut queue: Vec<(Instant, Message)> = room
+            .seats
+            .iter()
+            .enumerate()
+            .flat_map(|(seat, player)| {
+                player.log.iter().map(move |(at, message)| {
+                    (*at, Message::Player(seat, Box::new(message.clone())))
+                })
+            })
+            .collect();
+        // Stable, so each player's messages stay in order
+        queue.sort_by_key(|(at, _)| *at);
+        self.send(id, &Message::Watching { room: number, seed });
+        if let Some(client) = self.clients.get_mut(&id) {
+            client.watching = Some(Watching {
+                room: number,
+                seed,
+                queue: queue.into(),
+            });
+        }
+    }
+
+    /// Queues a message from a player to every spectator of their match.
+    fn show_spectators(&mut self, id: u64, message: &Message) {
+        let number = match self.clients.get(&id).and_then(|client| client.room) {
+            Some(number) => number,
+            None => return,
+        };
+        let room = self.rooms.get(&number);
+        let seed = room.and_then(|room| room.seed);
+        let seat = room.and_then(|room| room.seats.iter().position(|seat| seat.client == Some(id)));
+        let seat = match seat {
+            Some(seat) => seat,
+            None => return,
+        };
+        let now = Instant::now();
+        for client in self.clients.values_mut() {
+            if let Some(watching) = &mut client.watching {
+                if watching.room == number && Some(watching.seed) == seed {
+                    let message = Message::Player(seat, Box::new(message.clone()));
+                    watching.queue.push_back((now, message));
+                }
+            }
+        }
+    }
+
+    /// Sends spectators the players' messages which are `spectator_delay` old, and
+    /// tells them once the match they're watching has closed or been followed by a
+    /// rematch, and they've seen all of it.
+    fn update_spectators(&mut self) {
+        let delay = self.spectator_delay;
+        for client in self.clients.values_mut() {
+            let watching = match &mut client.watching {
+                Some(watching) => watching,
+                None => continue,
+            };
+            while let Some((at, _)) = watching.queue.front() {
+                if at.elapsed() < delay {
+                    break;
+                }
+                if let Some((_, message)) = watching.queue.pop_front() {
+                    client.connection.send(&message);
+                }
+            }
+            let seed = self.rooms.get(&watching.room).and_then(|room| room.seed);
+            if watching.queue.is_empty() && seed != Some(watching.seed) {
+                client.connection.send(&Message::Leave);
+                client.watching = None;
+            }
+        }
+    }
+
+    /// Plays a player's game on with a message they sent, and decides the match if
+    /// it's over.
+    fn play(&mut self, id: u64, message: Message) {
+        let number = match self.clients.get(&id).and_then(|client| client.room) {
+            Some(number) => number,
+            None => return,
+        };
+        let room = match self.rooms.get_mut(&number) {
+            Some(room) => room,
+            None => return,
+        };
+        if room.decided {
+            return;
+        }
+        let seat = room.seats.iter().position(|seat| seat.client == Some(id));
+        let game = match seat.and_then(|seat| room.games.get_mut(seat)) {
+            Some(game) => game,
+            None => return,
+        };
+        match message {
+            Message::Input(time_ms, input) => game.push_input(time_ms, input),
+            Message::Time(time_ms) => game.extend_to(time_ms),
+            _ => {}
+        }
+        if let [first, second] = room.games.as_slice() {
+            if let Some(result) = netplay::decide(first.game(), second.game()) {
+                self.finish_match(number, result);
+            }
+        }
+    }
+
+    /// Ends the match in a client's room as a loss for them, if it's undecided, as they
+    /// left part way through.
+    fn forfeit(&mut self, id: u64) {
+        let number = match self.clients.get(&id).and_then(|client| client.room) {
+            Some(number) => number,
+            None => return,
+        };
+        let seat = self
+            .rooms
+            .get(&number)
+            .and_then(|room| room.seats.iter().position(|seat| seat.client == Some(id)));
+        if let Some(seat) = seat {
+            self.forfeit_seat(number, seat);
+        }
+    }
+
+    /// Ends a match as a loss for the player in a seat, if it has started and is
+    /// undecided.
+    fn forfeit_seat(&mut self, number: u32, seat: usize) {
+        let undecided = self
+            .rooms
+            .get(&number)
+            .is_some_and(|room| room.seed.is_some() && !room.decided);
+        if undecided {
+            let result = if seat == 0 {
+                MatchResult::Lost
+            } else {
+                MatchResult::Won
+            };
+            self.finish_match(number, result);
+        }
+    }
+
+    /// Marks a match decided and, if it's ranked, updates both players' ratings and
+    /// tells them their new ratings.
+    ///
+    /// # Parameters
+    ///
+    /// - `number`: The match's room.
+    /// - `result`: How the match ended, from the first seat's side.
+    fn finish_match(&mut self, number: u32, result: MatchResult) {
+        let room = match self.rooms.get_mut(&number) {
+            Some(room) => room,
+            None => return,
+        };
+        room.decided = true;
+        let (first, second) = match room.ranked_players() {
+            Some(players) => players,
+            None => return,
+        };
+        self.ratings.record(first, second, result);
+        let seats: Vec<(Option<u64>, Option<u64>)> = room
+            .seats
+            .iter()
+            .map(|seat| (seat.client, seat.player))
+            .collect();
+        for (client, player) in seats {
+            if let Some(client) = client {
+                let rating = self.ratings.get(player).round() as u32;
+                self.send(client, &Message::Rating(rating));
+            }
+        }
+    }
+}
+
+/// Returns the rating of the player who has waited longest in a room.
+fn room_rating(ratings: &Ratings, room: &Room) -> f64 {
+    ratings.get(room.seats.first().and_then(|seat| seat.player))
+}
@@ -1,64 +1,122 @@
-use std::io::Cursor;
-
 use wgpu::util::DeviceExt;
-use wgpu_glyph::{ab_glyph, GlyphBrushBuilder, Section, Text};
+use wgpu_glyph::{
+    ab_glyph, GlyphBrushBuilder, HorizontalAlign, Layout, Section, Text, VerticalAlign,
+};
 
 use winit::{
     event::*,
     event_loop::{ControlFlow, EventLoop},
-    window::{Window, WindowBuilder},
+    window::{Fullscreen, Window, WindowBuilder},
 };
 
+use anyhow::*;
 use bytemuck::Zeroable;
+use clap::Parser;
+use screen::{GameOver, Playing, Screen};
+use std::collections::VecDeque;
+use std::path::Path;
+use std::time::Duration;
+use web_time::Instant;
 
-mod game;
-mod gameboard;
-mod tetromino;
-mod texture;
+use wgputris::sequence::Sequence;
+use wgputris::stats::Stats;
+use wgputris::{
+    achievements, ai, attack, celebration, finesse, game, netplay, paths, replay, rhythm, Vertex,
+    BLOCK_SIZE, GAMEBOARD_OFFSET, GAMEBOARD_WIDTH, VERTEX_COUNT,
+};
 
-const BLOCK_SIZE: u32 = 12;
-const GAMEBOARD_OFFSET: (usize, usize) = (15, 1);
-const GAMEBOARD_WIDTH: usize = 10;
-const GAMEBOARD_HEIGHT: usize = 20;
-const TETRIS_SONG: [u8; 410354] = *include_bytes!("../assets/tetris.ogg");
+#[macro_use]
+mod profiler;
 
-#[repr(C)]
-#[derive(Copy, Clone, Debug)]
-pub struct Vertex {
-    pub position: [f32; 3],
-    pub tex_coords: [f32; 2],
-    pub color: [f32; 4],
-}
+mod attract;
+mod audio;
+mod bench;
+mod capture;
+mod chat;
+mod cli;
+mod config;
+mod crash;
+mod downscale;
+mod exhibition;
+mod export;
+mod history;
+mod hot_reload;
+mod input;
+mod mods;
+mod online;
+mod presence;
+mod replay_menu;
+mod round_over;
+mod save_states;
+mod screen;
+mod settings;
+mod speech;
+mod telemetry;
+mod texture;
+mod train;
+mod trophies;
+mod twitch;
+mod versus_setup;
 
-impl Vertex {
-    fn desc<'a>() -> wgpu::VertexBufferDescriptor<'a> {
-        wgpu::VertexBufferDescriptor {
-            stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
-            step_mode: wgpu::InputStepMode::Vertex,
-            attributes: &[
-                wgpu::VertexAttributeDescriptor {
-                    offset: 0,
-                    shader_location: 0,
-                    format: wgpu::VertexFormat::Float3,
-                },
-                wgpu::VertexAttributeDescriptor {
-                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
-                    shader_location: 1,
-                    format: wgpu::VertexFormat::Float2,
-                },
-                wgpu::VertexAttributeDescriptor {
-                    offset: std::mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
-                    shader_location: 2,
-                    format: wgpu::VertexFormat::Float4,
-                },
-            ],
-        }
+/// Size of the screen layout in pixels. Bigger or smaller windows scale it to fit,
+/// keeping its aspect ratio.
+const LAYOUT_WIDTH: u32 = 960;
+const LAYOUT_HEIGHT: u32 = 544;
+/// Width of the view in world units for a single player.
+const VIEW_WIDTH: f32 = 480.0;
+/// Width of the view in world units when two boards are shown side by side.
+const VERSUS_VIEW_WIDTH: f32 = 660.0;
+/// Horizontal offsets, in blocks, of each player's board in versus.
+const VERSUS_SHIFTS: [i32; 2] = [-7, 19];
+/// Vertices drawn at most each frame: two boards, each with its celebration.
+const MAX_VERTICES: usize = 2 * (VERTEX_COUNT + celebration::CELEBRATION_VERTICES);
+/// Opacity of a replay ghost's board.
+const GHOST_ALPHA: f32 = 0.35;
+/// Opacity of a replay ghost's board with high contrast.
+const HIGH_CONTRAST_GHOST_ALPHA: f32 = 0.7;
+const VOLUME_STEP: f32 = 0.1;
+/// How long audio messages such as the volume stay on screen.
+const AUDIO_MESSAGE_SECS: f64 = 1.5;
+/// How long each row of the board takes to fill with grey blocks after topping out.
+const TOP_OUT_ROW_SECS: f64 = 0.05;
+/// File the board is saved to as a fumen string.
+const FUMEN_FILE: &str = "fumen.txt";
+/// Speeds a practice game can be stepped through, as multiples of normal speed.
+const PRACTICE_SPEEDS: [f64; 6] = [0.25, 0.5, 0.75, 1.0, 1.5, 2.0];
+/// Files in `assets/` which are reloaded when they change, in debug builds.
+const RELOADABLE_ASSETS: [&str; 2] = ["block.png", "RedOctober.ttf"];
+/// Size of text which isn't given one, in layout pixels at a UI scale of 1.
+const TEXT_SCALE: f32 = 16.0;
+/// Where the lines of help at the bottom of the screen end, in layout pixels.
+const HELP_BOTTOM: f32 = 520.0;
+/// Time between frames while the window is idle, see `State::is_idle`.
+const IDLE_FRAME_MS: u64 = 100;
+
+/// Describes the layout of `Vertex` in the vertex buffer.
+fn vertex_desc<'a>() -> wgpu::VertexBufferDescriptor<'a> {
+    wgpu::VertexBufferDescriptor {
+        stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+        step_mode: wgpu::InputStepMode::Vertex,
+        attributes: &[
+            wgpu::VertexAttributeDescriptor {
+                offset: 0,
+                shader_location: 0,
+                format: wgpu::VertexFormat::Float3,
+            },
+            wgpu::VertexAttributeDescriptor {
+                offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                shader_location: 1,
+                format: wgpu::VertexFormat::Float2,
+            },
+            wgpu::VertexAttributeDescriptor {
+                offset: std::mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
+                shader_location: 2,
+                format: wgpu::VertexFormat::Float4,
+            },
+        ],
     }
 }
 
-unsafe impl bytemuck::Pod for Vertex {}
-unsafe impl bytemuck::Zeroable for Vertex {}
-
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 struct Uniforms {
@@ -66,9 +124,11 @@ struct Uniforms {
 }
 
 impl Uniforms {
-    fn new() -> Self {
+    /// Creates a projection showing `width` units horizontally, at the screen's aspect
+    /// ratio.
+    fn new(width: f32) -> Self {
         use cgmath::SquareMatrix;
-        let proj = cgmath::ortho(0.0, 480.0, 272.0, 0.0, -1.0, 1.0);
+        let proj = cgmath::ortho(0.0, width, width * 272.0 / 480.0, 0.0, -1.0, 1.0);
         let view = cgmath::Matrix4::identity();
         Self {
             view_proj: OPENGL_TO_WGPU_MATRIX * proj * view,
@@ -84,33 +144,535 @@ pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
 );
 
 struct State {
+    surface: wgpu::Surface,
     device: wgpu::Device,
     queue: wgpu::Queue,
+    sc_desc: wgpu::SwapChainDescriptor,
     swap_chain: wgpu::SwapChain,
+    /// Draws at a lower resolution than the window, if `render_scale` asks for it.
+    downscaler: Option<downscale::Downscaler>,
     render_pipeline: wgpu::RenderPipeline,
     vertex_buffer: wgpu::Buffer,
-    vertices: [Vertex; 1254],
+    vertices: [Vertex; MAX_VERTICES],
+    uniform_buffer: wgpu::Buffer,
     uniform_bind_group: wgpu::BindGroup,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    diffuse_texture: texture::Texture,
     diffuse_bind_group: wgpu::BindGroup,
     glyph_brush: wgpu_glyph::GlyphBrush<()>,
     staging_belt: wgpu::util::StagingBelt,
     game: game::Game,
-    menu: bool,
+    /// Totals of `game`, shown in the HUD and recorded in the history.
+    stats: Stats,
+    audio: audio::Audio,
+    config: config::Config,
+    audio_message: Option<(String, Instant)>,
+    recorder: capture::Recorder,
+    /// Whether the last single player game set a new personal best.
+    new_best: bool,
+    input_state: input::InputState,
+    versus: Option<Versus>,
+    /// An online match against another player, whose game is drawn alongside.
+    online: Option<online::Match>,
+    /// Chat with the other player online, in a lobby room and during the match.
+    chat: chat::Chat,
+    /// A replay raced against by the single player game, kept in time with it.
+    ghost: Option<replay::Playback>,
+    /// The finesse training session the single player game is part of, which replaces
+    /// the game with an empty board for each target.
+    finesse: Option<finesse::Trainer>,
+    screen: Screen,
+    asset_watcher: Option<hot_reload::AssetWatcher>,
+    mods: mods::Mods,
+    /// Seed every game's shapes are dealt from, if set on the command line.
+    seed: Option<u64>,
+    /// Index in `PRACTICE_SPEEDS` of the speed practice games are played at, kept from
+    /// one practice game to the next.
+    practice_speed: usize,
+    /// Whether the internal state of each game is drawn over its board.
+    debug_overlay: bool,
+    profiler: profiler::Profiler,
+    presence: presence::Presence,
+    /// Speaks game events and the selected menu line, if turned on.
+    announcer: speech::Announcer,
+    /// Achievements of every key binding profile.
+    achievements: achievements::Achievements,
+    /// Watches the single player's game, or player 1's in versus, for achievements.
+    tracker: achievements::Tracker,
+    /// Confetti and glows for tetrises and perfect clears on each board: the single
+    /// player's game, a replay or player 1's, then player 2's in versus.
+    celebrations: [celebration::Celebration; 2],
+    /// Achievements just unlocked, announced one at a time at the top of the screen.
+    toasts: VecDeque<achievements::Achievement>,
+    /// When the first of `toasts` started being shown.
+    toast_shown_at: Instant,
+    /// When a key was last pressed, to start a demo once the title screen has been left
+    /// alone long enough.
+    idle_since: Instant,
+    /// `false` while another window has focus.
+    focused: bool,
+    /// Votes from a Twitch channel's chat on chaos in single player games.
+    chaos: twitch::Chaos,
+    /// Quick save states of practice games.
+    save_states: save_states::SaveStates,
+    /// Anonymous usage data, if the player shares it.
+    telemetry: telemetry::Telemetry,
+}
+
+/// Graphics backends tried in turn: Vulkan, Metal and DX12, then DX11 for GPUs too old
+/// for them. wgpu 0.6 has no OpenGL backend to fall back to after that, though
+/// `BackendBit::SECONDARY` names one.
+const BACKENDS: [wgpu::BackendBit; 2] = [wgpu::BackendBit::PRIMARY, wgpu::BackendBit::DX11];
+
+/// The second player in a local versus match.
+struct Versus {
+    game: game::Game,
+    stats: Stats,
+    input_state: input::InputState,
+    /// Plays the second player's game instead of the keyboard, against the computer.
+    cpu: Option<ai::Cpu>,
+    /// Garbage sent by player 1 and player 2, scaled by their handicaps.
+    attacks: [attack::Attack; 2],
+}
+
+impl Versus {
+    /// Returns the names of both players, shown over their boards.
+    fn names(&self) -> [String; 2] {
+        match &self.cpu {
+            Some(cpu) if cpu.name() == ai::HEURISTIC_BOT => [
+                String::from("You"),
+                format!("Computer ({})", cpu.difficulty().label()),
+            ],
+            Some(cpu) => [
+                String::from("You"),
+                format!("{} ({})", cpu.name(), cpu.difficulty().label()),
+            ],
+            None => [String::from("Player 1"), String::from("Player 2")],
+        }
+    }
+}
+
+/// Applies a player's input to their game and advances it by a frame.
+///
+/// # Parameters
+///
+/// - `game`: The player's game.
+/// - `input_state`: Keys held and pressed by the player.
+/// - `stats`: Totals of the player's game, which counts the keys pressed.
+/// - `profile`: The player's key bindings and handling.
+/// - `toggle_soft_drop`: `true` if soft drop is toggled by each press instead of held.
+/// - `delay`: Holds the input back for a few frames, online.
+fn update_player(
+    game: &mut game::Game,
+    input_state: &mut input::InputState,
+    stats: &mut Stats,
+    profile: &input::KeyProfile,
+    toggle_soft_drop: bool,
+    delay: Option<&mut netplay::InputDelay>,
+) {
+    stats.keys += input_state.presses();
+    let mut inputs: Vec<replay::Input> = input_state
+        .triggered(profile)
+        .into_iter()
+        .map(replay::Input::Action)
+        .collect();
+    let soft_drop_factor = if input_state.soft_drop(profile, toggle_soft_drop) {
+        profile.handling.soft_drop_factor
+    } else {
+        1
+    };
+    inputs.push(replay::Input::SoftDropFactor(soft_drop_factor));
+    inputs.push(replay::Input::InitialActions(input_state.held(profile)));
+    match delay {
+        Some(delay) => {
+            for input in inputs {
+                delay.push(game, input);
+            }
+            delay.apply(game);
+        }
+        None => {
+            for input in &inputs {
+                game.apply_input(input);
+            }
+        }
+    }
+    game.process_game_loop();
+}
+
+/// Adds a finished game to the game history.
+///
+/// # Parameters
+///
+/// - `mode`: The kind of game.
+/// - `game`: The finished game.
+/// - `stats`: Totals of the finished game.
+fn record_game(mode: history::Mode, game: &game::Game, stats: &Stats) {
+    let record = history::GameRecord::new(mode, stats, game.time_ms());
+    if let Err(e) = history::History::record(record) {
+        eprintln!("Failed to record game history: {}", e);
+    }
+}
+
+/// Queues the score, level and shape labels for one player's board.
+///
+/// # Parameters
+///
+/// - `glyph_brush`: Brush to queue the text on.
+/// - `game`: The player's game, for the actions per minute and whether it can hold.
+/// - `stats`: Totals of the player's game.
+/// - `shift`: Horizontal offset of the board in blocks, as passed to `Game::render`.
+/// - `px_per_unit`: Screen pixels per world unit of the current view.
+/// - `mod_lines`: Lines added below the previews by mods, and the practice speed.
+/// - `previews`: Number of upcoming shapes shown, as passed to `Game::render`.
+/// - `ui_scale`: Size of the text, as a multiple of its usual size.
+#[allow(clippy::too_many_arguments)]
+fn queue_hud(
+    glyph_brush: &mut wgpu_glyph::GlyphBrush<()>,
+    game: &game::Game,
+    stats: &Stats,
+    shift: i32,
+    px_per_unit: f32,
+    mod_lines: &[String],
+    previews: usize,
+    ui_scale: f32,
+) {
+    let position = |x: f32, y: f32| {
+        (
+            (x + (shift * BLOCK_SIZE as i32) as f32) * px_per_unit,
+            y * px_per_unit,
+        )
+    };
+    // The labels end just above the previews whatever their size, and the lines above
+    // them stack upwards, so bigger text grows away from the board
+    let label_bottom = 60.0 + TEXT_SCALE / px_per_unit;
+    let line_bottom = |line: f32| label_bottom - 10.0 * ui_scale * line;
+    let layout = Layout::default().v_align(VerticalAlign::Bottom);
+    let scale = TEXT_SCALE * ui_scale;
+
+    let score_string = format!("Score: {}", stats.score);
+    let score_text = Section {
+        screen_position: position(340.0, line_bottom(2.0)),
+        text: vec![Text::new(&score_string)
+            .with_scale(scale)
+            .with_color([1.0, 1.0, 1.0, 1.0])],
+        layout,
+        ..Section::default()
+    };
+
+    glyph_brush.queue(score_text);
+
+    // Only players at the keyboard have keys counted
+    if stats.keys > 0 {
+        let input_string = format!(
+            "KPP: {:.2}  APM: {:.0}",
+            stats.keys_per_piece(),
+            stats.actions_per_minute(game.time_ms())
+        );
+        glyph_brush.queue(Section {
+            screen_position: position(340.0, line_bottom(3.0)),
+            text: vec![Text::new(&input_string)
+                .with_scale(scale)
+                .with_color([1.0, 1.0, 1.0, 1.0])],
+            layout,
+            ..Section::default()
+        });
+    }
+
+    let level_string = format!("Level: {}  Lines: {}", stats.level, stats.lines);
+    let level_text = Section {
+        screen_position: position(340.0, line_bottom(1.0)),
+        text: vec![Text::new(&level_string)
+            .with_scale(scale)
+            .with_color([1.0, 1.0, 1.0, 1.0])],
+        layout,
+        ..Section::default()
+    };
+
+    glyph_brush.queue(level_text);
+
+    if previews > 0 {
+        let next_shape_text = Section {
+            screen_position: position(340.0, line_bottom(0.0)),
+
+            text: vec![Text::new("Next Shape:")
+                .with_scale(scale)
+                .with_color([1.0, 1.0, 1.0, 1.0])],
+            layout,
+            ..Section::default()
+        };
+
+        glyph_brush.queue(next_shape_text);
+    }
+
+    // Greyed out along with the held shape while it can't be swapped
+    let hold_color = if game.can_hold() {
+        [1.0, 1.0, 1.0, 1.0]
+    } else {
+        [0.5, 0.5, 0.5, 1.0]
+    };
+    let hold_shape_text = Section {
+        screen_position: position(90.0, line_bottom(0.0)),
+        text: vec![Text::new("Hold:").with_scale(scale).with_color(hold_color)],
+        layout,
+        ..Section::default()
+    };
+
+    glyph_brush.queue(hold_shape_text);
+
+    // Mod lines move down to make room for a longer queue of previews
+    let mods_top = game::previews_bottom(previews).max(144.0) + 6.0;
+    for (i, line) in mod_lines.iter().enumerate() {
+        let mod_text = Section {
+            screen_position: position(340.0, mods_top + 10.0 * ui_scale * i as f32),
+            text: vec![Text::new(line)
+                .with_scale(scale)
+                .with_color([1.0, 1.0, 1.0, 1.0])],
+            ..Section::default()
+        };
+        glyph_brush.queue(mod_text);
+    }
+}
+
+/// Queues the debug overlay for one board: a marker on every cell showing whether it
+/// is occupied, the cells of the current shape and of its last rotation attempt, and
+/// the shape's position and tick timing beside the board.
+///
+/// # Parameters
+///
+/// - `glyph_brush`: Brush to queue the text on.
+/// - `info`: Internal state of the game.
+/// - `shift`: Horizontal offset of the board in blocks, as passed to `Game::render`.
+/// - `px_per_unit`: Screen pixels per world unit of the current view.
+fn queue_debug_overlay(
+    glyph_brush: &mut wgpu_glyph::GlyphBrush<()>,
+    info: &game::DebugInfo,
+    shift: i32,
+    px_per_unit: f32,
+) {
+    let block_px = BLOCK_SIZE as f32 * px_per_unit;
+    let cell_position = |x: i32, y: i32| {
+        (
+            (GAMEBOARD_OFFSET.0 as i32 + shift + x) as f32 * block_px,
+            (GAMEBOARD_OFFSET.1 as i32 + y) as f32 * block_px,
+        )
+    };
+    let mut queue_marker = |(x, y): (i32, i32), marker: &str, color: [f32; 4], corner: f32| {
+        let (left, top) = cell_position(x, y);
+        glyph_brush.queue(Section {
+            screen_position: (left + corner * block_px, top + corner * block_px),
+            text: vec![Text::new(marker)
+                .with_scale(block_px / 2.0)
+                .with_color(color)],
+            ..Section::default()
+        });
+    };
+
+    for (y, row) in info.occupied.iter().enumerate() {
+        for (x, occupied) in row.iter().enumerate() {
+            let (marker, color) = if *occupied {
+                ("1", [1.0, 1.0, 0.0, 1.0])
+            } else {
+                ("0", [0.5, 0.5, 0.5, 1.0])
+            };
+            queue_marker((x as i32, y as i32), marker, color, 0.0);
+        }
+    }
+    for loc in info.board_locs.iter() {
+        queue_marker(*loc, "P", [0.0, 1.0, 1.0, 1.0], 0.5);
+    }
+    if let Some(rotation) = &info.rotation {
+        let color = if rotation.passed {
+            [0.0, 1.0, 0.0, 1.0]
+        } else {
+            [1.0, 0.0, 0.0, 1.0]
+        };
+        for loc in rotation.locs.iter() {
+            queue_marker(*loc, "R", color, 0.25);
+        }
+    }
+
+    let rotation_string = match &info.rotation {
+        Some(rotation) => format!(
+            "{} {}",
+            if rotation.clockwise { "CW" } else { "CCW" },
+            if rotation.passed { "passed" } else { "blocked" }
+        ),
+        None => String::from("none"),
+    };
+    let mut debug_string = format!(
+        "Position: {:?}\nBlocks: {:?}\nCells: {:?}\nLast rotation: {}\n\
+         Gravity: {:.3} G\nFall: {:.2} cells\nSoft drop factor: {}\nLock: {} frames\n\
+         Pending: {:.2} ms\nTime: {} ms",
+        info.position,
+        info.block_locs,
+        info.board_locs,
+        rotation_string,
+        info.gravity,
+        info.fall,
+        info.soft_drop_factor,
+        info.lock_frames,
+        info.pending_seconds * 1000.0,
+        info.time_ms,
+    );
+    if info.frame_advance {
+        debug_string += "\nFrame advance: PageDown steps";
+    }
+    glyph_brush.queue(Section {
+        screen_position: (
+            (shift * BLOCK_SIZE as i32) as f32 * px_per_unit,
+            120.0 * px_per_unit,
+        ),
+        text: vec![Text::new(&debug_string)
+            .with_scale(block_px * 0.6)
+            .with_color([1.0, 1.0, 1.0, 1.0])],
+        ..Section::default()
+    });
+}
+
+/// Creates the bind group which samples the block texture.
+///
+/// # Parameters
+///
+/// - `device`: The graphics device.
+/// - `layout`: Layout of the texture bind group.
+/// - `texture`: The block texture.
+fn create_diffuse_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    texture: &texture::Texture,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&texture.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&texture.sampler),
+            },
+        ],
+        label: Some("diffuse_bind_group"),
+    })
+}
+
+/// Queues a screen made of a title, a list of lines and a line of help text. Lists
+/// too long to fit above the help are scrolled to keep the last highlighted line in
+/// view.
+///
+/// # Parameters
+///
+/// - `glyph_brush`: Brush to queue the text on.
+/// - `title`: Heading of the screen.
+/// - `lines`: Each line of the list, and whether it is highlighted.
+/// - `help`: Keys used on the screen, shown at the bottom.
+/// - `ui_scale`: Size of the text, as a multiple of its usual size.
+fn queue_list_screen(
+    glyph_brush: &mut wgpu_glyph::GlyphBrush<()>,
+    title: &str,
+    lines: &[(String, bool)],
+    help: &str,
+    ui_scale: f32,
+) {
+    let title_text = Section {
+        screen_position: (100.0, 60.0),
+        text: vec![Text::new(title)
+            .with_scale(40.0 * ui_scale)
+            .with_color([1.0, 1.0, 1.0, 1.0])],
+        ..Section::default()
+    };
+    glyph_brush.queue(title_text);
+
+    let (top, spacing) = (60.0 + 70.0 * ui_scale, 35.0 * ui_scale);
+    let help_top = HELP_BOTTOM - 20.0 * ui_scale;
+    let shown = (((help_top - top) / spacing) as usize).max(1);
+    let last_highlighted = lines.iter().rposition(|(_, selected)| *selected);
+    let first = last_highlighted.map_or(0, |i| (i + 1).saturating_sub(shown));
+    for (i, (line, selected)) in lines.iter().skip(first).take(shown).enumerate() {
+        let color = if *selected {
+            [1.0, 1.0, 0.0, 1.0]
+        } else {
+            [1.0, 1.0, 1.0, 1.0]
+        };
+        let line_text = Section {
+            screen_position: (100.0, top + spacing * i as f32),
+            text: vec![Text::new(line)
+                .with_scale(30.0 * ui_scale)
+                .with_color(color)],
+            ..Section::default()
+        };
+        glyph_brush.queue(line_text);
+    }
+
+    let help_text = Section {
+        screen_position: (100.0, HELP_BOTTOM),
+        text: vec![Text::new(help)
+            .with_scale(20.0 * ui_scale)
+            .with_color([1.0, 1.0, 1.0, 1.0])],
+        layout: Layout::default().v_align(VerticalAlign::Bottom),
+        ..Section::default()
+    };
+    glyph_brush.queue(help_text);
+}
+
+/// Queues the chat in the bottom left corner, latest line at the bottom.
+///
+/// # Parameters
+///
+/// - `glyph_brush`: Brush to queue the text on.
+/// - `lines`: The chat lines to show.
+/// - `ui_scale`: Size of the text, as a multiple of its usual size.
+fn queue_chat(glyph_brush: &mut wgpu_glyph::GlyphBrush<()>, lines: &[String], ui_scale: f32) {
+    for (i, line) in lines.iter().rev().enumerate() {
+        let line_text = Section {
+            screen_position: (20.0, 450.0 - 22.0 * ui_scale * i as f32),
+            text: vec![Text::new(line)
+                .with_scale(20.0 * ui_scale)
+                .with_color([0.8, 1.0, 0.8, 1.0])],
+            ..Section::default()
+        };
+        glyph_brush.queue(line_text);
+    }
 }
 
 impl State {
-    async fn new(window: &Window) -> Self {
+    /// Creates the renderer and audio output for the window.
+    ///
+    /// # Parameters
+    ///
+    /// - `window`: The game window.
+    /// - `config`: Settings loaded at startup.
+    /// - `args`: The command line options.
+    async fn new(window: &Window, config: config::Config, args: &cli::Args) -> Result<Self> {
         let size = window.inner_size();
 
-        let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
-        let surface = unsafe { instance.create_surface(window) };
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::Default,
-                compatible_surface: Some(&surface),
-            })
-            .await
-            .unwrap();
+        let power_preference = if config.graphics.low_power {
+            wgpu::PowerPreference::LowPower
+        } else {
+            wgpu::PowerPreference::Default
+        };
+        let backends = match args.backend {
+            Some(backend) => vec![backend.bit()],
+            None => BACKENDS.to_vec(),
+        };
+        let mut found = None;
+        for backends in backends.iter() {
+            let instance = wgpu::Instance::new(*backends);
+            let surface = unsafe { instance.create_surface(window) };
+            let adapter = instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference,
+                    compatible_surface: Some(&surface),
+                })
+                .await;
+            if let Some(adapter) = adapter {
+                found = Some((surface, adapter));
+                break;
+            }
+        }
+        let (surface, adapter) =
+            found.ok_or_else(|| anyhow!("No compatible graphics adapter was found"))?;
 
         let (device, queue) = adapter
             .request_device(
@@ -122,7 +684,11 @@ impl State {
                 None,
             )
             .await
-            .unwrap();
+            .context("Failed to open the graphics device")?;
+        let telemetry = telemetry::Telemetry::start(
+            &config.telemetry,
+            &format!("{:?}", adapter.get_info().backend),
+        );
 
         let sc_desc = wgpu::SwapChainDescriptor {
             usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
@@ -132,6 +698,14 @@ impl State {
             present_mode: wgpu::PresentMode::Immediate,
         };
         let swap_chain = device.create_swap_chain(&surface, &sc_desc);
+        let downscaler = downscale::Downscaler::new(
+            &device,
+            sc_desc.format,
+            config.graphics.render_scale,
+            config.graphics.resolution.size(),
+            size.width,
+            size.height,
+        );
 
         let vs_module =
             device.create_shader_module(wgpu::include_spirv!("../shaders/shader.vert.spv"));
@@ -139,8 +713,12 @@ impl State {
             device.create_shader_module(wgpu::include_spirv!("../shaders/shader.frag.spv"));
 
         let diffuse_bytes = include_bytes!("../assets/block.png");
-        let diffuse_texture =
-            texture::Texture::from_png_bytes(&device, &queue, diffuse_bytes, "block").unwrap();
+        let diffuse_texture = texture::Texture::block_atlas_or_white(
+            &device,
+            &queue,
+            diffuse_bytes,
+            config.graphics.texture_filter,
+        );
 
         let texture_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -165,28 +743,16 @@ impl State {
                 label: Some("texture_bind_group_layout"),
             });
 
-        let diffuse_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &texture_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                },
-            ],
-            label: Some("diffuse_bind_group"),
-        });
+        let diffuse_bind_group =
+            create_diffuse_bind_group(&device, &texture_bind_group_layout, &diffuse_texture);
 
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Vertex Buffer"),
-            contents: bytemuck::cast_slice(&[Vertex::zeroed(); 1254]),
+            contents: bytemuck::cast_slice(&[Vertex::zeroed(); MAX_VERTICES]),
             usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
         });
 
-        let uniforms = Uniforms::new();
+        let uniforms = Uniforms::new(VIEW_WIDTH);
 
         let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Uniform Buffer"),
@@ -262,7 +828,7 @@ impl State {
             depth_stencil_state: None,
             vertex_state: wgpu::VertexStateDescriptor {
                 index_format: wgpu::IndexFormat::Uint16,
-                vertex_buffers: &[Vertex::desc()],
+                vertex_buffers: &[vertex_desc()],
             },
             sample_count: 1,
             sample_mask: !0,
@@ -271,191 +837,1640 @@ impl State {
 
         let staging_belt = wgpu::util::StagingBelt::new(1024);
         let font = ab_glyph::FontArc::try_from_slice(include_bytes!("../assets/RedOctober.ttf"))
-            .expect("Load font");
+            .context("Failed to load the font")?;
 
         let glyph_brush = GlyphBrushBuilder::using_font(font).build(&device, sc_desc.format);
 
-        let vertices: [Vertex; 1254] = [Vertex::zeroed(); 1254];
+        let vertices: [Vertex; MAX_VERTICES] = [Vertex::zeroed(); MAX_VERTICES];
 
         // This is kind of pointless because we reinitialize when we exit the menu
         // TODO should it be Option<Game> maybe?
         let game = game::Game::new();
 
-        Self {
+        let audio = audio::Audio::new(&config).context("Failed to open the audio device")?;
+        let announcer = speech::Announcer::new(config.screen_reader);
+
+        Ok(Self {
+            surface,
             device,
             queue,
+            sc_desc,
             swap_chain,
+            downscaler,
             render_pipeline,
             vertex_buffer,
             vertices,
+            uniform_buffer,
             uniform_bind_group,
+            texture_bind_group_layout,
+            diffuse_texture,
             diffuse_bind_group,
             glyph_brush,
             staging_belt,
             game,
-            menu: true,
+            stats: Stats::new(),
+            audio,
+            config,
+            audio_message: None,
+            recorder: capture::Recorder::new(),
+            new_best: false,
+            input_state: input::InputState::new(),
+            versus: None,
+            online: None,
+            chat: chat::Chat::new(),
+            ghost: None,
+            finesse: None,
+            screen: Screen::Boot(screen::Boot {
+                launch: args.launch(),
+            }),
+            seed: args.seed,
+            practice_speed: 3,
+            asset_watcher: hot_reload::AssetWatcher::new(&RELOADABLE_ASSETS),
+            mods: mods::Mods::load(),
+            debug_overlay: false,
+            profiler: profiler::Profiler::new(),
+            presence: presence::Presence::new(),
+            announcer,
+            achievements: achievements::Achievements::load(),
+            tracker: achievements::Tracker::new(),
+            celebrations: Default::default(),
+            toasts: VecDeque::new(),
+            toast_shown_at: Instant::now(),
+            idle_since: Instant::now(),
+            focused: true,
+            chaos: twitch::Chaos::new(),
+            save_states: save_states::SaveStates::new(),
+            telemetry,
+        })
+    }
+
+    /// Recreates the swap chain for a new window size.
+    ///
+    /// # Parameters
+    ///
+    /// - `size`: New inner size of the window.
+    fn resize(&mut self, size: winit::dpi::PhysicalSize<u32>) {
+        // Minimized windows have no size, and there's nothing to draw anyway
+        if size.width == 0 || size.height == 0 {
+            return;
+        }
+        self.sc_desc.width = size.width;
+        self.sc_desc.height = size.height;
+        self.swap_chain = self.device.create_swap_chain(&self.surface, &self.sc_desc);
+        if let Some(downscaler) = &mut self.downscaler {
+            downscaler.resize(&self.device, size.width, size.height);
+        }
+    }
+
+    /// Returns the width and height in pixels of what the game is drawn into, which is
+    /// smaller than the window when downscaling.
+    fn target_size(&self) -> (f32, f32) {
+        let (width, height) = match &self.downscaler {
+            Some(downscaler) => downscaler.size(),
+            None => (self.sc_desc.width, self.sc_desc.height),
+        };
+        (width as f32, height as f32)
+    }
+
+    /// Returns the part of the window the layout is drawn in, as x, y, width and height
+    /// in pixels. The layout is scaled as large as fits, with bars on either side if the
+    /// window is a different shape.
+    fn viewport(&self) -> (f32, f32, f32, f32) {
+        let (window_width, window_height) = self.target_size();
+        let scale = (window_width / LAYOUT_WIDTH as f32).min(window_height / LAYOUT_HEIGHT as f32);
+        let (width, height) = (LAYOUT_WIDTH as f32 * scale, LAYOUT_HEIGHT as f32 * scale);
+        (
+            (window_width - width) / 2.0,
+            (window_height - height) / 2.0,
+            width,
+            height,
+        )
+    }
+
+    /// Returns the transform which draws text positioned in layout pixels into the
+    /// viewport.
+    fn text_transform(&self) -> [f32; 16] {
+        let (x, y, width, height) = self.viewport();
+        let (window_width, window_height) = self.target_size();
+        let scale_x = width / window_width;
+        let scale_y = height / window_height;
+        // Position of the viewport's centre in normalized device coordinates
+        let center_x = (2.0 * x + width) / window_width - 1.0;
+        let center_y = 1.0 - (2.0 * y + height) / window_height;
+        [
+            2.0 * scale_x / LAYOUT_WIDTH as f32,
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+            -2.0 * scale_y / LAYOUT_HEIGHT as f32,
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+            1.0,
+            0.0,
+            center_x - scale_x,
+            center_y + scale_y,
+            0.0,
+            1.0,
+        ]
+    }
+
+    /// Returns the key bindings used by a player.
+    ///
+    /// # Parameters
+    ///
+    /// - `player`: 0 for player 1, 1 for player 2.
+    fn key_profile(&self, player: usize) -> &input::KeyProfile {
+        if self.is_local_versus() {
+            self.config.versus_profile(player)
+        } else {
+            self.config.key_profile()
+        }
+    }
+
+    /// Returns `true` during a versus match between two players on one keyboard.
+    fn is_local_versus(&self) -> bool {
+        self.versus.as_ref().is_some_and(|v| v.cpu.is_none())
+    }
+
+    /// Starts a new game, replacing any game in progress.
+    ///
+    /// # Parameters
+    ///
+    /// - `versus`: `true` for a two player match on one keyboard.
+    fn start_game(&mut self, versus: bool) {
+        self.game = self.new_game(versus);
+        self.stats = Stats::new();
+        self.tracker = achievements::Tracker::new();
+        self.new_best = false;
+        self.ghost = None;
+        self.online = None;
+        self.finesse = None;
+        self.recorder.clear();
+        self.versus = if versus {
+            let handicaps = self.config.versus_handicaps;
+            let table = self.config.attack_table();
+            let mut game = self.new_game(true);
+            handicaps[0].apply(&mut self.game, rand::random());
+            handicaps[1].apply(&mut game, rand::random());
+            Some(Versus {
+                game,
+                stats: Stats::new(),
+                input_state: input::InputState::new(),
+                cpu: None,
+                attacks: handicaps
+                    .map(|handicap| attack::Attack::new(table.clone(), handicap.attack_percent)),
+            })
+        } else {
+            None
+        };
+        self.set_view_width(if versus {
+            VERSUS_VIEW_WIDTH
+        } else {
+            VIEW_WIDTH
+        });
+        self.input_state.clear();
+        self.audio.stop_rhythm();
+        self.audio.reset_music();
+        self.mods.start_game();
+        self.screen = Screen::Playing(Playing);
+    }
+
+    /// Starts a versus match against the computer.
+    ///
+    /// # Parameters
+    ///
+    /// - `cpu`: The computer player, e.g. as picked in the settings.
+    fn start_cpu_game(&mut self, cpu: ai::Cpu) {
+        self.start_game(true);
+        if let Some(versus) = &mut self.versus {
+            versus.cpu = Some(cpu);
+        }
+    }
+
+    /// Starts a rematch of the last versus match, against the same opponent.
+    fn rematch(&mut self) {
+        let cpu = self
+            .versus
+            .as_ref()
+            .and_then(|v| v.cpu.as_ref())
+            .map(|cpu| ai::Cpu::named(cpu.name(), cpu.difficulty()));
+        match cpu {
+            Some(cpu) => self.start_cpu_game(cpu),
+            None => self.start_game(true),
+        }
+    }
+
+    /// Starts an online match which has just been set up on the online screen.
+    ///
+    /// # Parameters
+    ///
+    /// - `online`: The match, with both games at their start.
+    fn start_online(&mut self, online: online::Match) {
+        self.start_game(false);
+        self.game = game::Game::with_seed(online.remote.game().replay().seed);
+        self.online = Some(online);
+        self.set_view_width(VERSUS_VIEW_WIDTH);
+    }
+
+    /// Starts watching a lobby match which has just been set up on the online screen.
+    ///
+    /// # Parameters
+    ///
+    /// - `spectate`: The match, with both games at their start.
+    fn start_spectating(&mut self, spectate: Box<online::Spectate>) {
+        self.screen = Screen::Spectating(spectate);
+        self.versus = None;
+        self.ghost = None;
+        self.online = None;
+        self.set_view_width(VERSUS_VIEW_WIDTH);
+        self.audio.reset_music();
+    }
+
+    /// Starts a rhythm mode game, where gravity moves shapes on the beat of the music.
+    fn start_rhythm(&mut self) {
+        self.start_game(false);
+        let bpm = self.audio.start_rhythm();
+        self.game.set_rhythm(rhythm::Rhythm::new(bpm));
+        self.audio_message = Some((format!("Rhythm mode: {:.0} BPM", bpm), Instant::now()));
+    }
+
+    /// Starts a new single player game, in rhythm mode if the last game was.
+    fn start_again(&mut self) {
+        if self.game.rhythm().is_some() && self.versus.is_none() && self.online.is_none() {
+            self.start_rhythm();
+        } else {
+            self.start_game(false);
+        }
+    }
+
+    /// Starts a finesse training session, one target at a time on an empty board.
+    fn start_finesse(&mut self) {
+        self.start_game(false);
+        let mut trainer = finesse::Trainer::new(self.seed.unwrap_or_else(rand::random));
+        self.game = trainer.game();
+        self.game.set_speed(PRACTICE_SPEEDS[self.practice_speed]);
+        self.finesse = Some(trainer);
+    }
+
+    /// Starts an exhibition match between the computer players picked in the settings.
+    fn start_exhibition(&mut self) {
+        let exhibition =
+            exhibition::Exhibition::new(self.config.exhibition_cpus(), self.config.attack_table());
+        self.screen = Screen::Exhibition(Box::new(exhibition));
+        self.versus = None;
+        self.ghost = None;
+        self.online = None;
+        self.set_view_width(VERSUS_VIEW_WIDTH);
+        self.audio.reset_music();
+    }
+
+    /// Returns a new game, dealing shapes from the seed given on the command line if any,
+    /// with the rules picked in the settings.
+    ///
+    /// # Parameters
+    ///
+    /// - `versus`: `true` for one side of a local versus match.
+    fn new_game(&self, versus: bool) -> game::Game {
+        let mut game = match self.seed {
+            Some(seed) => game::Game::with_seed(seed),
+            None => game::Game::new(),
+        };
+        self.apply_rules(&mut game, versus);
+        game
+    }
+
+    /// Applies the hold limit, kick table and speed curve picked in the settings to a
+    /// new game.
+    ///
+    /// # Parameters
+    ///
+    /// - `game`: The game.
+    /// - `versus`: `true` for one side of a local versus match.
+    fn apply_rules(&self, game: &mut game::Game, versus: bool) {
+        game.set_hold_limit(self.config.hold_limit);
+        game.set_kick_table(self.config.kick_table());
+        game.set_speed_curve(self.config.speed_curve(versus));
+    }
+
+    /// Starts a new single player practice game from a board setup shared as a fumen
+    /// string, a sequence of shapes, or both.
+    ///
+    /// # Parameters
+    ///
+    /// - `seed`: Seed for any shapes not set by the fumen or sequence.
+    /// - `fumen`: The board setup, see `wgputris::fumen::Page::decode`.
+    /// - `sequence`: The order to deal shapes in, see `game::Game::set_sequence`.
+    fn start_practice(&mut self, seed: u64, fumen: Option<&str>, sequence: Option<&Sequence>) {
+        let game = match fumen {
+            Some(fumen) => game::Game::from_fumen(seed, fumen),
+            None => Ok(game::Game::with_seed(seed)),
+        };
+        match game {
+            Ok(mut game) => {
+                if let Some(sequence) = sequence {
+                    game.set_sequence(sequence.clone());
+                }
+                self.apply_rules(&mut game, false);
+                self.start_game(false);
+                self.game = game;
+                self.game.set_speed(PRACTICE_SPEEDS[self.practice_speed]);
+            }
+            Err(e) => eprintln!("Failed to load fumen: {}", e),
+        }
+    }
+
+    /// Starts the practice game over from its board setup and the start of its
+    /// sequence, so a setup can be drilled again and again.
+    fn restart_practice(&mut self) {
+        if self.finesse.is_some() {
+            return self.start_finesse();
+        }
+        let replay = self.game.replay();
+        let (seed, fumen, sequence) = (replay.seed, replay.fumen.clone(), replay.sequence.clone());
+        self.start_practice(seed, fumen.as_deref(), sequence.as_ref());
+    }
+
+    /// Returns `true` while a single player practice game is being played.
+    fn is_practice(&self) -> bool {
+        self.versus.is_none() && self.ghost.is_none() && self.game.replay().is_practice()
+    }
+
+    /// Handles the frame advance hotkeys of local games while the debug overlay is
+    /// showing: `Pause` stops and restarts time passing by itself, and `PageDown` steps
+    /// every board to the next frame. Drawing carries on meanwhile.
+    ///
+    /// # Return Value
+    ///
+    /// `true` if the key was a frame advance hotkey
+    fn frame_advance_input(&mut self, key: VirtualKeyCode) -> bool {
+        if !self.debug_overlay
+            || self.online.is_some()
+            || !matches!(self.screen, Screen::Playing(_))
+        {
+            return false;
         }
+        let frame_advance = self.game.frame_advance();
+        let step = match key {
+            VirtualKeyCode::Pause => false,
+            VirtualKeyCode::PageDown if frame_advance => true,
+            _ => return false,
+        };
+        let versus = self.versus.as_mut().map(|versus| &mut versus.game);
+        for game in std::iter::once(&mut self.game).chain(versus) {
+            if step {
+                game.advance_frame();
+            } else {
+                game.set_frame_advance(!frame_advance);
+            }
+        }
+        true
+    }
+
+    /// Handles the save state hotkeys of practice games: `F5` saves, `F9` loads and
+    /// `F12` switches to the next slot. The finesse trainer keeps its own targets, so
+    /// has no save states.
+    ///
+    /// # Return Value
+    ///
+    /// `true` if the key was a save state hotkey
+    fn save_state_input(&mut self, key: VirtualKeyCode) -> bool {
+        if self.finesse.is_some() {
+            return false;
+        }
+        let slot = self.save_states.slot_number();
+        let message = match key {
+            VirtualKeyCode::F5 => {
+                match self
+                    .save_states
+                    .save(&self.game, self.config.save_states_to_disk)
+                {
+                    Ok(()) => format!("Saved state {}", slot),
+                    Err(e) => format!("Failed to save state {}: {}", slot, e),
+                }
+            }
+            VirtualKeyCode::F9 => match self.save_states.load(self.config.save_states_to_disk) {
+                Ok((game, stats)) => {
+                    self.game = game;
+                    self.stats = stats;
+                    self.game.set_speed(PRACTICE_SPEEDS[self.practice_speed]);
+                    // Gravity shouldn't catch up on the time since the state was saved
+                    self.game.resume();
+                    self.screen = Screen::Playing(Playing);
+                    format!("Loaded state {}", slot)
+                }
+                Err(e) => format!("Failed to load state {}: {}", slot, e),
+            },
+            VirtualKeyCode::F12 => {
+                self.save_states.next_slot();
+                format!("Save state slot {}", self.save_states.slot_number())
+            }
+            _ => return false,
+        };
+        self.audio_message = Some((message, Instant::now()));
+        true
+    }
+
+    /// Returns `true` while a single player game which chat can vote on is being
+    /// played: not practice, versus or racing a replay.
+    fn is_chaos_game(&self) -> bool {
+        self.versus.is_none()
+            && self.online.is_none()
+            && self.ghost.is_none()
+            && self.game.rhythm().is_none()
+            && !self.game.replay().is_practice()
+    }
+
+    /// Does what chat voted for to the single player game.
+    ///
+    /// # Parameters
+    ///
+    /// - `effect`: The winning vote.
+    fn apply_chaos(&mut self, effect: twitch::Effect) {
+        match effect {
+            twitch::Effect::Garbage => self.game.add_garbage(
+                twitch::GARBAGE_ROWS,
+                rand::random::<usize>() % GAMEBOARD_WIDTH,
+            ),
+            // `update` keeps the game's speed in step with the burst
+            twitch::Effect::Speed => {}
+            twitch::Effect::Piece(kind) => self.game.set_next_kind(kind),
+        }
+        self.audio_message = Some((format!("Chat voted for {}", effect.label()), Instant::now()));
+    }
+
+    /// Steps the speed of the practice game up or down.
+    ///
+    /// # Parameters
+    ///
+    /// - `direction`: `1` to speed up, `-1` to slow down.
+    fn change_practice_speed(&mut self, direction: i32) {
+        self.practice_speed = (self.practice_speed as i32 + direction)
+            .clamp(0, PRACTICE_SPEEDS.len() as i32 - 1) as usize;
+        let speed = PRACTICE_SPEEDS[self.practice_speed];
+        self.game.set_speed(speed);
+        self.audio_message = Some((format!("Speed: {}x", speed), Instant::now()));
+    }
+
+    /// Sets how many world units the view shows horizontally.
+    fn set_view_width(&self, width: f32) {
+        self.queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[Uniforms::new(width)]),
+        );
+    }
+
+    /// Saves the replay of the single player game.
+    fn save_replay(&self) {
+        if let Err(e) = self.game.replay().save() {
+            eprintln!("Failed to save replay: {}", e);
+        }
+    }
+
+    /// Keeps the replay of a single player game left unfinished for a new one.
+    fn abandon_game(&self) {
+        if self.versus.is_none() && self.finesse.is_none() {
+            self.save_replay();
+        }
+    }
+
+    /// Saves the replay of a finished single player game, and records it as the
+    /// personal best if the score beat the previous best.
+    fn finish_game(&mut self) {
+        self.save_replay();
+        let mode = if self.ghost.is_some() {
+            history::Mode::Race
+        } else if self.game.replay().is_practice() {
+            history::Mode::Practice
+        } else if self.game.rhythm().is_some() {
+            history::Mode::Rhythm
+        } else {
+            history::Mode::Marathon
+        };
+        record_game(mode, &self.game, &self.stats);
+        self.telemetry.game_finished(mode);
+        let score = self.stats.score;
+        if score > self.config.best_score {
+            self.config.best_score = score;
+            self.config_changed();
+            self.new_best = true;
+            if let Err(e) = self.game.replay().save_personal_best(score) {
+                eprintln!("Failed to save personal best replay: {}", e);
+            }
+        }
+    }
+
+    /// Starts playing back a saved replay.
+    ///
+    /// # Parameters
+    ///
+    /// - `path`: Path of the replay file.
+    fn watch_replay(&mut self, path: &Path) {
+        match replay::Replay::load(path).and_then(replay::Playback::new) {
+            Ok(playback) => {
+                self.screen = Screen::Playback(Box::new(playback));
+                self.versus = None;
+                self.ghost = None;
+                self.online = None;
+                self.set_view_width(VIEW_WIDTH);
+                self.audio.reset_music();
+            }
+            Err(e) => eprintln!("Failed to load replay {}: {}", path.display(), e),
+        }
+    }
+
+    /// Starts a new single player game with a saved replay playing alongside it.
+    ///
+    /// # Parameters
+    ///
+    /// - `path`: Path of the replay file.
+    fn race_replay(&mut self, path: &Path) {
+        match replay::Replay::load(path).and_then(replay::Playback::new) {
+            Ok(ghost) => {
+                self.start_game(false);
+                self.ghost = Some(ghost);
+                self.set_view_width(VERSUS_VIEW_WIDTH);
+            }
+            Err(e) => eprintln!("Failed to load replay {}: {}", path.display(), e),
+        }
+    }
+
+    /// Returns how many rows of the boards which topped out have filled with grey
+    /// blocks since the game or versus round ended, or `None` if it's still going or
+    /// ended some other way. With reduced motion, they all fill at once.
+    fn top_out_rows(&self) -> Option<usize> {
+        match &self.screen {
+            Screen::GameOver(GameOver {
+                topped_out_at: Some(at),
+            }) => Some(self.top_out_rows_since(*at)),
+            Screen::RoundOver(round_over) => Some(self.top_out_rows_since(round_over.ended_at())),
+            _ => None,
+        }
+    }
+
+    /// Returns how many rows of a board which topped out have filled with grey blocks
+    /// since a time, see `top_out_rows`.
+    ///
+    /// # Parameters
+    ///
+    /// - `at`: When the board started filling.
+    fn top_out_rows_since(&self, at: Instant) -> usize {
+        if self.config.graphics.reduced_motion {
+            return usize::MAX;
+        }
+        (at.elapsed().as_secs_f64() / TOP_OUT_ROW_SECS) as usize
+    }
+
+    /// Returns `true` once any player has topped out, or online, once the match is
+    /// decided.
+    fn is_game_over(&self) -> bool {
+        if let Some(online) = &self.online {
+            return online.outcome.is_some();
+        }
+        self.game.game_over || self.versus.as_ref().is_some_and(|v| v.game.game_over)
+    }
+
+    /// Handles the mute, volume and track skipping hotkeys, which work on every screen.
+    ///
+    /// # Return Value
+    ///
+    /// `true` if the key was an audio hotkey
+    fn audio_input(&mut self, key: VirtualKeyCode) -> bool {
+        match key {
+            VirtualKeyCode::M => self.config.muted = !self.config.muted,
+            VirtualKeyCode::Equals | VirtualKeyCode::Add => {
+                self.config.volume = (self.config.volume + VOLUME_STEP).min(1.0)
+            }
+            VirtualKeyCode::Minus | VirtualKeyCode::Subtract => {
+                self.config.volume = (self.config.volume - VOLUME_STEP).max(0.0)
+            }
+            VirtualKeyCode::LBracket | VirtualKeyCode::RBracket => {
+                let offset = if key == VirtualKeyCode::RBracket {
+                    1
+                } else {
+                    -1
+                };
+                if let Some(track) = self.audio.skip_track(offset) {
+                    self.audio_message = Some((format!("Now playing: {}", track), Instant::now()));
+                }
+                return true;
+            }
+            _ => return false,
+        }
+        self.config_changed();
+        let volume_string = if self.config.muted {
+            String::from("Muted")
+        } else {
+            format!("Volume: {:.0}%", self.config.volume * 100.0)
+        };
+        self.audio_message = Some((volume_string, Instant::now()));
+        true
+    }
+
+    /// Handles the hotkeys which save the board, a replay clip or a GIF, which work on
+    /// every screen showing a game.
+    ///
+    /// # Return Value
+    ///
+    /// `true` if the key was a capture hotkey
+    fn capture_input(&mut self, key: VirtualKeyCode) -> bool {
+        let message = match key {
+            VirtualKeyCode::F7 => {
+                let game = match &self.screen {
+                    Screen::Playback(playback) => playback.game(),
+                    Screen::Spectating(spectate) => spectate.games[0].game(),
+                    Screen::Exhibition(exhibition) => exhibition.games()[0],
+                    _ => &self.game,
+                };
+                let fumen = game.to_fumen();
+                let path = paths::data_path(FUMEN_FILE);
+                match std::fs::write(&path, fumen) {
+                    Ok(()) => format!("Saved {}", path.display()),
+                    Err(e) => format!("Failed to save fumen: {}", e),
+                }
+            }
+            VirtualKeyCode::F6 => match self.recorder.export() {
+                Ok(path) => format!("Saved {}", path.display()),
+                Err(e) => format!("Failed to save clip: {}", e),
+            },
+            VirtualKeyCode::F8
+                if self.versus.is_none()
+                    && !matches!(
+                        self.screen,
+                        Screen::Playback(_) | Screen::Spectating(_) | Screen::Exhibition(_)
+                    ) =>
+            {
+                match self.game.replay().save_clip() {
+                    Ok(path) => format!("Saved {}", path.display()),
+                    Err(e) => format!("Failed to save replay clip: {}", e),
+                }
+            }
+            _ => return false,
+        };
+        self.audio_message = Some((message, Instant::now()));
+        true
+    }
+
+    /// Applies and saves the config after it has been modified.
+    fn config_changed(&mut self) {
+        self.audio.apply_config(&self.config);
+        self.announcer.set_enabled(self.config.screen_reader);
+        self.telemetry.apply_config(&self.config.telemetry);
+        let filter = self.config.graphics.texture_filter;
+        if filter != self.diffuse_texture.filter {
+            self.diffuse_texture.set_filter(&self.device, filter);
+            self.diffuse_bind_group = create_diffuse_bind_group(
+                &self.device,
+                &self.texture_bind_group_layout,
+                &self.diffuse_texture,
+            );
+        }
+        let resolution = self.config.graphics.resolution.size();
+        if resolution != self.downscaler.as_ref().and_then(|d| d.fixed()) {
+            self.downscaler = downscale::Downscaler::new(
+                &self.device,
+                self.sc_desc.format,
+                self.config.graphics.render_scale,
+                resolution,
+                self.sc_desc.width,
+                self.sc_desc.height,
+            );
+        }
+        if let Err(e) = self.config.save() {
+            eprintln!("Failed to save config: {}", e);
+        }
+    }
+
+    /// Returns `true` where players can chat: in a lobby room, and during an online
+    /// match and after it until leaving the results.
+    fn chat_allowed(&self) -> bool {
+        match &self.screen {
+            Screen::Online(online_menu) => online_menu.in_room(),
+            Screen::Playing(_)
+            | Screen::GameOver(_)
+            | Screen::RoundOver(_)
+            | Screen::Results(_) => self.online.is_some(),
+            _ => false,
+        }
+    }
+
+    /// Handles a key press while typing a chat message, sending it on enter.
+    fn chat_input(&mut self, key: VirtualKeyCode) {
+        let text = match self.chat.process_input(key) {
+            Some(text) => text,
+            None => return,
+        };
+        match (&mut self.screen, &mut self.online) {
+            (Screen::Online(online_menu), _) => online_menu.send_chat(&text),
+            (_, Some(online)) => online.send_chat(&text),
+            _ => return,
+        }
+        self.chat.push("You", &text);
+    }
+
+    /// Returns `true` while the window isn't focused and nothing is being played or
+    /// watched, so frames can be drawn about ten times a second to save power.
+    fn is_idle(&self) -> bool {
+        // Online matches play on while paused
+        let playing_online = self.online.is_some() && matches!(self.screen, Screen::Paused(_));
+        !self.focused && self.screen.is_waiting() && !playing_online
+    }
+
+    /// Calls the current screen with the rest of the state, then puts the screen back
+    /// unless it moved to another.
+    ///
+    /// # Parameters
+    ///
+    /// - `f`: Calls the screen, see `screen::ScreenState`.
+    fn dispatch<R>(&mut self, f: impl FnOnce(&mut dyn screen::ScreenState, &mut Self) -> R) -> R {
+        let mut screen = std::mem::replace(&mut self.screen, Screen::Dispatching);
+        let result = f(screen.as_state(), self);
+        if let Screen::Dispatching = self.screen {
+            self.screen = screen;
+        }
+        result
+    }
+
+    /// Handles the hotkeys which work on every screen but the paused game and the demo.
+    ///
+    /// # Return Value
+    ///
+    /// `true` if the key was one of them
+    fn hotkey_input(&mut self, key: VirtualKeyCode) -> bool {
+        if self.audio_input(key) {
+            return true;
+        }
+        if self.screen.shows_boards() && key == VirtualKeyCode::F3 {
+            self.debug_overlay = !self.debug_overlay;
+            if !self.debug_overlay {
+                // Frame advance is only shown on the overlay, so it ends with it
+                self.game.set_frame_advance(false);
+                if let Some(versus) = &mut self.versus {
+                    versus.game.set_frame_advance(false);
+                }
+            }
+            return true;
+        }
+        if self.frame_advance_input(key) {
+            return true;
+        }
+        if key == VirtualKeyCode::F10 {
+            self.profiler.toggle();
+            return true;
+        }
+        if key == VirtualKeyCode::Tab && self.chat_allowed() {
+            // Keys held when typing starts would otherwise stay held
+            self.input_state.clear();
+            self.chat.start_typing();
+            return true;
+        }
+        (self.screen.shows_boards() || matches!(self.screen, Screen::Results(_)))
+            && self.capture_input(key)
     }
 
     fn input(&mut self, event: &WindowEvent) -> bool {
+        if let WindowEvent::CloseRequested = event {
+            if self.dispatch(|screen, state| screen.close_requested(state)) {
+                return true;
+            }
+        }
+        if let WindowEvent::Focused(focused) = event {
+            self.focused = *focused;
+            if !focused {
+                self.input_state.clear();
+                if let Some(versus) = &mut self.versus {
+                    versus.input_state.clear();
+                }
+            }
+        }
+        if self.chat.is_typing() {
+            match event {
+                WindowEvent::ReceivedCharacter(c) => self.chat.type_char(*c),
+                WindowEvent::KeyboardInput { input, .. } => {
+                    if let (Some(key), ElementState::Pressed) = (input.virtual_keycode, input.state)
+                    {
+                        self.chat_input(key);
+                    }
+                }
+                _ => return false,
+            }
+            return true;
+        }
+        if let WindowEvent::ReceivedCharacter(c) = event {
+            return self.dispatch(|screen, state| screen.type_char(state, *c));
+        }
         if let WindowEvent::KeyboardInput { input, .. } = event {
-            if (*input).virtual_keycode == Some(VirtualKeyCode::Space)
-                && (*input).state == ElementState::Pressed
-            {
-                self.game = game::Game::new();
-                self.menu = false;
+            let physical_key = input::Key {
+                scancode: input.scancode,
+                virtual_keycode: input.virtual_keycode,
+            };
+            let profile = if self.is_local_versus() {
+                self.config.versus_profile(0)
             } else {
-                return self.game.process_input(*input);
+                self.config.key_profile()
+            };
+            self.input_state
+                .key_event(physical_key, input.state, profile);
+            if let Some(versus) = self.versus.as_mut().filter(|v| v.cpu.is_none()) {
+                versus.input_state.key_event(
+                    physical_key,
+                    input.state,
+                    self.config.versus_profile(1),
+                );
+            }
+            if let (Some(key), ElementState::Pressed) = (input.virtual_keycode, input.state) {
+                self.idle_since = Instant::now();
+                if !self.screen.takes_every_key() && self.hotkey_input(key) {
+                    return true;
+                }
+                return self
+                    .dispatch(|screen, state| screen.handle_input(state, key, physical_key));
             }
         }
         false
     }
 
+    /// Rebuilds the block texture or font if its file in `assets/` changed, so art can
+    /// be tried out without restarting.
+    fn reload_assets(&mut self) {
+        let changed = match &mut self.asset_watcher {
+            Some(asset_watcher) => asset_watcher.changed(),
+            None => return,
+        };
+        for (name, bytes) in changed {
+            let message = match self.reload_asset(&name, bytes) {
+                Ok(()) => format!("Reloaded {}", name),
+                Err(e) => format!("Failed to reload {}: {}", name, e),
+            };
+            self.audio_message = Some((message, Instant::now()));
+        }
+    }
+
+    /// Replaces a built in asset with new contents.
+    ///
+    /// # Parameters
+    ///
+    /// - `name`: Name of the file in `assets/`.
+    /// - `bytes`: New contents of the file.
+    fn reload_asset(&mut self, name: &str, bytes: Vec<u8>) -> Result<()> {
+        match name {
+            "block.png" => {
+                self.diffuse_texture = texture::Texture::block_atlas(
+                    &self.device,
+                    &self.queue,
+                    &bytes,
+                    self.config.graphics.texture_filter,
+                )?;
+                self.diffuse_bind_group = create_diffuse_bind_group(
+                    &self.device,
+                    &self.texture_bind_group_layout,
+                    &self.diffuse_texture,
+                );
+            }
+            "RedOctober.ttf" => {
+                let font = ab_glyph::FontArc::try_from_vec(bytes)?;
+                self.glyph_brush =
+                    GlyphBrushBuilder::using_font(font).build(&self.device, self.sc_desc.format);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
     fn update(&mut self) {
-        if !self.menu {
-            self.game.process_game_loop();
+        profile_scope!("update");
+        self.reload_assets();
+        self.telemetry.frame(self.is_idle());
+        // Only single player games can be resumed after a crash
+        let resumable = matches!(self.screen, Screen::Playing(_) | Screen::Paused(_))
+            && self.versus.is_none()
+            && self.online.is_none();
+        crash::track(if resumable {
+            Some(self.game.replay())
+        } else {
+            None
+        });
+        self.dispatch(|screen, state| screen.update(state));
+        self.update_chat();
+        let chaos_game = matches!(self.screen, Screen::Playing(_)) && self.is_chaos_game();
+        if let Some(effect) = self.chaos.update(&self.config.twitch, chaos_game) {
+            self.apply_chaos(effect);
+        }
+        if chaos_game {
+            self.game.set_speed(self.chaos.speed());
+        }
+        if let (Screen::Playing(_), Some(rhythm)) = (&self.screen, self.game.rhythm()) {
+            self.game.set_speed(
+                rhythm.sync_speed(self.game.time_ms() as f64, self.audio.music_position_ms()),
+            );
+        }
+        self.presence
+            .update(self.config.discord_status, self.presence_status());
+        let line = self.dispatch(|screen, state| screen.focused_line(state));
+        self.announcer.focus(line);
+        self.input_state.end_frame();
+        if let Some(versus) = &mut self.versus {
+            versus.input_state.end_frame();
+        }
+        self.audio.update();
+    }
+
+    /// Adds chat which has arrived to the scrollback, unless chat is turned off, and
+    /// stops typing once chat is no longer possible.
+    fn update_chat(&mut self) {
+        let received = match (&mut self.screen, &mut self.online) {
+            (Screen::Online(online_menu), _) => online_menu.take_chat(),
+            (_, Some(online)) => online.take_chat(),
+            _ => Vec::new(),
+        };
+        if self.config.chat {
+            for text in &received {
+                self.chat.push("Opponent", text);
+            }
+        }
+        if self.chat.is_typing() && !self.chat_allowed() {
+            self.chat.stop_typing();
+        }
+    }
+
+    /// Returns what the player is doing, for their Discord status.
+    fn presence_status(&self) -> presence::Status {
+        let details = match &self.screen {
+            Screen::Playing(_)
+            | Screen::Paused(_)
+            | Screen::GameOver(_)
+            | Screen::RoundOver(_)
+            | Screen::Results(_) => {
+                if self.is_local_versus() {
+                    "Local versus"
+                } else if self.versus.is_some() {
+                    "Versus the computer"
+                } else if self.online.is_some() {
+                    "Online versus"
+                } else if self.ghost.is_some() {
+                    "Racing a replay"
+                } else if self.finesse.is_some() {
+                    "Finesse training"
+                } else if self.game.rhythm().is_some() {
+                    "Rhythm mode"
+                } else if self.game.replay().is_practice() {
+                    "Practice"
+                } else {
+                    "Marathon"
+                }
+            }
+            Screen::Online(_) => "Looking for an online match",
+            Screen::Playback(_) => "Watching a replay",
+            Screen::Spectating(_) => "Watching an online match",
+            Screen::Exhibition(_) => "Watching the computer play itself",
+            _ => "In the menus",
+        };
+        let state = match &self.screen {
+            Screen::Playing(_)
+            | Screen::Paused(_)
+            | Screen::GameOver(_)
+            | Screen::RoundOver(_)
+            | Screen::Results(_) => Some(format!(
+                "Score {}, {} lines",
+                self.stats.score, self.stats.lines
+            )),
+            _ => None,
+        };
+        presence::Status {
+            details: details.to_string(),
+            state,
+        }
+    }
+
+    /// Unlocks an achievement earned in the single player's game, or player 1's in
+    /// versus, for their key binding profile. Practice games can't unlock any, as they
+    /// can start from any board.
+    ///
+    /// # Parameters
+    ///
+    /// - `achievement`: The achievement earned.
+    fn unlock(&mut self, achievement: achievements::Achievement) {
+        if self.game.replay().is_practice() {
+            return;
+        }
+        let profile = self.key_profile(0).name.clone();
+        if self.achievements.unlock(&profile, achievement) {
+            self.toast(achievement);
+            if let Err(e) = self.achievements.save() {
+                eprintln!("Failed to save achievements: {}", e);
+            }
+        }
+    }
+
+    /// Queues an achievement to be announced as unlocked.
+    ///
+    /// # Parameters
+    ///
+    /// - `achievement`: The achievement just unlocked.
+    fn toast(&mut self, achievement: achievements::Achievement) {
+        if self.toasts.is_empty() {
+            self.toast_shown_at = Instant::now();
+        }
+        self.toasts.push_back(achievement);
+        self.announcer
+            .say(&format!("Achievement unlocked: {}", achievement.name()));
+    }
+
+    /// Returns the overlay shown once a versus round ends, or `None` after a single
+    /// player game.
+    fn round_over(&self) -> Option<round_over::RoundOver> {
+        let side = |name: &str, sent, received, game: &game::Game| round_over::Side {
+            name: name.to_string(),
+            sent,
+            received,
+            time_ms: game.time_ms(),
+        };
+        if let Some(versus) = &self.versus {
+            let names = versus.names();
+            let winner = match (self.game.game_over, versus.game.game_over) {
+                (true, true) => String::from("Draw!"),
+                (false, _) if versus.cpu.is_some() => String::from("You win!"),
+                (false, _) => format!("{} wins!", names[0]),
+                (true, false) => format!("{} wins!", names[1]),
+            };
+            let [sent1, sent2] = [versus.attacks[0].sent(), versus.attacks[1].sent()];
+            let sides = [
+                side(&names[0], sent1, sent2, &self.game),
+                side(&names[1], sent2, sent1, &versus.game),
+            ];
+            return Some(round_over::RoundOver::new(winner, sides, false));
+        }
+        let online = self.online.as_ref()?;
+        let winner = match online.outcome.as_ref()? {
+            online::Outcome::Won => String::from("You win!"),
+            online::Outcome::Lost => String::from("You lose."),
+            online::Outcome::Draw => String::from("Draw!"),
+            online::Outcome::Disconnected(reason) => format!("Disconnected: {}", reason),
+        };
+        let (sent, received) = (online.attack.sent(), online.received());
+        let sides = [
+            side("You", sent, received, &self.game),
+            side("Opponent", received, sent, online.remote.game()),
+        ];
+        Some(round_over::RoundOver::new(winner, sides, true))
+    }
+
+    /// Passes the single player game's events to mods as well as the usual handlers,
+    /// adds any points they award, and ends the game if a mod's mode is over.
+    fn update_mods(&mut self) {
+        self.game.dispatch_events(&mut [
+            &mut self.audio,
+            &mut self.stats,
+            &mut self.announcer,
+            &mut self.tracker,
+            &mut self.celebrations[0],
+            &mut self.mods,
+        ]);
+        let bonus = self.mods.take_bonus();
+        if bonus != 0 {
+            let score = (self.game.get_score() as i64 + bonus).max(0) as usize;
+            self.game.set_score(score);
+            self.game.dispatch_events(&mut [
+                &mut self.audio,
+                &mut self.stats,
+                &mut self.announcer,
+                &mut self.tracker,
+                &mut self.celebrations[0],
+                &mut self.mods,
+            ]);
+        }
+        if !self.game.game_over && self.mods.finished(&self.stats) {
+            self.game.game_over = true;
+        }
+    }
+
+    /// Fills the vertex buffer with every board on screen and draws them, along with
+    /// each board's score and labels.
+    fn draw_boards<'a>(&'a mut self, render_pass: &mut wgpu::RenderPass<'a>) {
+        let (x, y, width, height) = self.viewport();
+        render_pass.set_viewport(x, y, width, height, 0.0, 1.0);
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+
+        let mod_lines = if self.versus.is_none()
+            && self.online.is_none()
+            && !matches!(
+                self.screen,
+                Screen::Playback(_)
+                    | Screen::Spectating(_)
+                    | Screen::Demo(_)
+                    | Screen::Exhibition(_)
+            ) {
+            let mut lines = self.mods.hud(&self.stats);
+            if self.is_chaos_game() {
+                lines.extend(self.chaos.hud(&self.config.twitch));
+            }
+            if let Some(trainer) = &self.finesse {
+                lines.extend(trainer.hud());
+            }
+            if self.is_practice() && self.game.speed() != 1.0 {
+                lines.insert(0, format!("Speed: {}x", self.game.speed()));
+            }
+            lines
+        } else {
+            Vec::new()
+        };
+        let top_out_rows = self.top_out_rows();
+        let previews = self.config.graphics.previews();
+        let names = self.versus.as_ref().map(Versus::names).unwrap_or_default();
+        let boards: Vec<(&game::Game, &Stats, &str)> = if let Some(versus) = &self.versus {
+            vec![
+                (&self.game, &self.stats, names[0].as_str()),
+                (&versus.game, &versus.stats, names[1].as_str()),
+            ]
+        } else if let Some(online) = &self.online {
+            let (you, opponent) = if online.is_rejoining() {
+                ("You (reconnecting...)", "Opponent")
+            } else if online.opponent_away {
+                ("You", "Opponent (reconnecting...)")
+            } else {
+                ("You", "Opponent")
+            };
+            vec![
+                (&self.game, &self.stats, you),
+                (online.remote.game(), online.remote.stats(), opponent),
+            ]
+        } else if let Some(ghost) = &self.ghost {
+            vec![
+                (&self.game, &self.stats, "You"),
+                (ghost.game(), ghost.stats(), "Replay ghost"),
+            ]
+        } else if let Screen::Playback(playback) = &self.screen {
+            vec![(playback.game(), playback.stats(), "")]
+        } else if let Screen::Demo(demo) = &self.screen {
+            vec![(demo.game(), demo.stats(), "Demo")]
+        } else if let Screen::Exhibition(exhibition) = &self.screen {
+            let (games, stats) = (exhibition.games(), exhibition.stats());
+            vec![
+                (games[0], stats[0], names[0].as_str()),
+                (games[1], stats[1], names[1].as_str()),
+            ]
+        } else if let Screen::Spectating(spectate) = &self.screen {
+            spectate
+                .games
+                .iter()
+                .zip(["Player 1", "Player 2"])
+                .map(|(game, label)| (game.game(), game.stats(), label))
+                .collect()
+        } else {
+            vec![(&self.game, &self.stats, "")]
+        };
+
+        // Exhibitions are drawn as their arena extracts them, with its particles and
+        // widgets
+        let extracted = match &self.screen {
+            Screen::Exhibition(exhibition) => exhibition.frame(),
+            _ => &[],
+        };
+        let board_vertices = boards.len() * VERTEX_COUNT;
+        let vertex_count = board_vertices + boards.len() * celebration::CELEBRATION_VERTICES;
+        {
+            profile_scope!("vertex generation");
+            let skin = self.config.graphics.skin();
+            let animate = !self.config.graphics.reduced_motion;
+            // Boards which topped out fill with grey blocks once the game is over
+            let render = |game: &game::Game, buf: &mut [Vertex], shift| {
+                let rows = top_out_rows.filter(|_| game.topped_out());
+                match rows {
+                    Some(rows) => game.render_topped_out(buf, shift, rows, previews),
+                    None => game.render(buf, shift, previews),
+                }
+            };
+            if let [(game, _, _)] = boards[..] {
+                render(game, &mut self.vertices[..VERTEX_COUNT], 0);
+                if animate {
+                    game.animate_spawn(&mut self.vertices[..VERTEX_COUNT]);
+                    game.animate_rotation(&mut self.vertices[..VERTEX_COUNT]);
+                }
+                skin.apply(&mut self.vertices[..VERTEX_COUNT]);
+            } else {
+                let (p1, rest) = self.vertices.split_at_mut(VERTEX_COUNT);
+                let p2 = &mut rest[..VERTEX_COUNT];
+                if let [left, right] = extracted {
+                    p1.copy_from_slice(&left.vertices);
+                    p2.copy_from_slice(&right.vertices);
+                } else {
+                    render(boards[0].0, p1, VERSUS_SHIFTS[0]);
+                    render(boards[1].0, p2, VERSUS_SHIFTS[1]);
+                    if animate {
+                        boards[0].0.animate_spawn(p1);
+                        boards[1].0.animate_spawn(p2);
+                        boards[0].0.animate_rotation(p1);
+                        boards[1].0.animate_rotation(p2);
+                    }
+                }
+                skin.apply(p1);
+                skin.apply(p2);
+                if self.ghost.is_some() {
+                    let alpha = if skin.high_contrast {
+                        HIGH_CONTRAST_GHOST_ALPHA
+                    } else {
+                        GHOST_ALPHA
+                    };
+                    for vertex in p2.iter_mut() {
+                        vertex.color[3] *= alpha;
+                    }
+                }
+            }
+            // Celebrations go over the boards, and only follow games being played or
+            // replayed
+            let celebrating = matches!(
+                self.screen,
+                Screen::Playing(_) | Screen::Paused(_) | Screen::Playback(_)
+            );
+            for (i, (game, _, _)) in boards.iter().enumerate() {
+                let start = board_vertices + i * celebration::CELEBRATION_VERTICES;
+                let buf = &mut self.vertices[start..start + celebration::CELEBRATION_VERTICES];
+                let shift = if boards.len() == 1 {
+                    0
+                } else {
+                    VERSUS_SHIFTS[i]
+                };
+                if let Some(board) = extracted.get(i) {
+                    buf.copy_from_slice(&board.effects);
+                } else if celebrating && !game.game_over && (i == 0 || self.versus.is_some()) {
+                    self.celebrations[i].render(buf, shift, animate);
+                } else {
+                    buf.copy_from_slice(&[Vertex::zeroed(); celebration::CELEBRATION_VERTICES]);
+                }
+            }
+        }
+        {
+            profile_scope!("buffer upload");
+            self.queue.write_buffer(
+                &self.vertex_buffer,
+                0,
+                bytemuck::cast_slice(&self.vertices[..vertex_count]),
+            );
+        }
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.draw(0..vertex_count as u32, 0..1);
+        let view_width = if boards.len() == 1 {
+            VIEW_WIDTH
+        } else {
+            VERSUS_VIEW_WIDTH
+        };
+        self.recorder
+            .capture(&self.vertices[..vertex_count], view_width);
+
+        let ui_scale = self.config.graphics.ui_scale();
+        if let [(game, stats, _)] = boards[..] {
+            queue_hud(
+                &mut self.glyph_brush,
+                game,
+                stats,
+                0,
+                LAYOUT_WIDTH as f32 / VIEW_WIDTH,
+                &mod_lines,
+                previews,
+                ui_scale,
+            );
+        } else {
+            let px_per_unit = LAYOUT_WIDTH as f32 / VERSUS_VIEW_WIDTH;
+            for (player, (game, stats, label)) in boards.iter().enumerate() {
+                let shift = VERSUS_SHIFTS[player];
+                let lines: &[String] = match extracted.get(player) {
+                    Some(board) => &board.hud,
+                    None if player == 0 => &mod_lines,
+                    None => &[],
+                };
+                queue_hud(
+                    &mut self.glyph_brush,
+                    game,
+                    stats,
+                    shift,
+                    px_per_unit,
+                    lines,
+                    previews,
+                    ui_scale,
+                );
+                let label_text = Section {
+                    screen_position: (
+                        ((GAMEBOARD_OFFSET.0 as i32 + shift) * BLOCK_SIZE as i32) as f32
+                            * px_per_unit,
+                        0.0,
+                    ),
+                    text: vec![Text::new(label)
+                        .with_scale(TEXT_SCALE * ui_scale)
+                        .with_color([1.0, 1.0, 1.0, 1.0])],
+                    ..Section::default()
+                };
+                self.glyph_brush.queue(label_text);
+            }
+        }
+
+        if self.debug_overlay {
+            let (shifts, px_per_unit) = if boards.len() == 1 {
+                (&[0][..], LAYOUT_WIDTH as f32 / VIEW_WIDTH)
+            } else {
+                (&VERSUS_SHIFTS[..], LAYOUT_WIDTH as f32 / VERSUS_VIEW_WIDTH)
+            };
+            for ((game, _, _), shift) in boards.iter().zip(shifts) {
+                queue_debug_overlay(
+                    &mut self.glyph_brush,
+                    &game.debug_info(),
+                    *shift,
+                    px_per_unit,
+                );
+            }
         }
     }
 
     fn render(&mut self) {
-        let frame = self
-            .swap_chain
-            .get_current_frame()
-            .expect("Timeout getting texture")
-            .output;
+        profile_scope!("render");
+        let frame = match self.swap_chain.get_current_frame() {
+            Ok(frame) => frame.output,
+            Err(wgpu::SwapChainError::Lost) | Err(wgpu::SwapChainError::Outdated) => {
+                // e.g. the window moved to another monitor, so try again next frame
+                self.swap_chain = self.device.create_swap_chain(&self.surface, &self.sc_desc);
+                return;
+            }
+            Err(e) => {
+                eprintln!("Skipped a frame: {}", e);
+                return;
+            }
+        };
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Render Encoder"),
             });
+        // Taken for the frame, as drawing the boards borrows all of `self`
+        let downscaler = self.downscaler.take();
+        let target = match &downscaler {
+            Some(downscaler) => downscaler.view(),
+            None => &frame.view,
+        };
 
+        let background = if self.config.graphics.high_contrast {
+            wgpu::Color::BLACK
+        } else {
+            wgpu::Color {
+                r: 0.2,
+                g: 0.267,
+                b: 0.333,
+                a: 1.0,
+            }
+        };
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
-                    attachment: &frame.view,
+                    attachment: target,
                     resolve_target: None,
                     ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.2,
-                            g: 0.267,
-                            b: 0.333,
-                            a: 1.0,
-                        }),
+                        load: wgpu::LoadOp::Clear(background),
                         store: true,
                     },
                 }],
                 depth_stencil_attachment: None,
             });
 
-            if self.menu {
-                let menu_string = String::from(
-                    "Controls
-Left Arrow: Move tetromino left
-Right Arrow: Move tetromino right
-Down Arrow: Hard drop
-Z: Rotate tetromino counterclockwise
-X: Rotate tetromino clockwise
-Space: Start new game
-
-Press space to start
-",
-                );
-                let menu_text = Section {
-                    screen_position: (100.0, 100.0),
-                    text: vec![Text::new(&menu_string)
-                        .with_scale(30.0)
-                        .with_color([1.0, 1.0, 1.0, 1.0])],
-                    ..Section::default()
-                };
-
-                self.glyph_brush.queue(menu_text);
-            } else {
-                render_pass.set_pipeline(&self.render_pipeline);
-                render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
-                render_pass.set_bind_group(1, &self.uniform_bind_group, &[]);
-
-                self.game.render(&mut self.vertices);
-                self.queue.write_buffer(
-                    &self.vertex_buffer,
-                    0,
-                    bytemuck::cast_slice(&self.vertices),
-                );
-                render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-                render_pass.draw(0..self.vertices.len() as u32, 0..1);
-
-                let score_string = format!("Score: {}", self.game.get_score());
-                let score_text = Section {
-                    screen_position: (680.0, 80.0),
-                    text: vec![Text::new(&score_string).with_color([1.0, 1.0, 1.0, 1.0])],
-                    ..Section::default()
-                };
-
-                self.glyph_brush.queue(score_text);
-
-                let next_shape_text = Section {
-                    screen_position: (680.0, 120.0),
-
-                    text: vec![Text::new("Next Shape:").with_color([1.0, 1.0, 1.0, 1.0])],
-                    ..Section::default()
-                };
+            if self.screen.shows_boards() {
+                self.draw_boards(&mut render_pass);
+            }
+        }
 
-                self.glyph_brush.queue(next_shape_text);
+        self.dispatch(|screen, state| screen.render(state));
+        if self.chat_allowed() {
+            let ui_scale = self.config.graphics.ui_scale();
+            queue_chat(&mut self.glyph_brush, &self.chat.visible_lines(), ui_scale);
+        }
+        if let Some((_, shown_at)) = &self.audio_message {
+            if shown_at.elapsed().as_secs_f64() >= AUDIO_MESSAGE_SECS {
+                self.audio_message = None;
             }
+        }
+        if let Some((message, _)) = &self.audio_message {
+            let message_text = Section {
+                screen_position: (20.0, 20.0),
+                text: vec![Text::new(message)
+                    .with_scale(TEXT_SCALE * self.config.graphics.ui_scale())
+                    .with_color([1.0, 1.0, 1.0, 1.0])],
+                ..Section::default()
+            };
+            self.glyph_brush.queue(message_text);
+        }
 
-            if self.game.game_over {
-                let game_over_text = Section {
-                    screen_position: (350.0, 250.0),
-                    text: vec![Text::new(
-                        "Game Over.
-Press space to play again.",
-                    )
-                    .with_scale(20.0)
+        if self.toast_shown_at.elapsed().as_secs_f64() >= trophies::TOAST_SECS
+            && self.toasts.pop_front().is_some()
+        {
+            self.toast_shown_at = Instant::now();
+        }
+        if let Some(achievement) = self.toasts.front() {
+            let toast_text = Section {
+                screen_position: (LAYOUT_WIDTH as f32 / 2.0, 20.0),
+                text: vec![
+                    Text::new("Achievement unlocked: ")
+                        .with_scale(TEXT_SCALE * self.config.graphics.ui_scale())
+                        .with_color([1.0, 1.0, 1.0, 1.0]),
+                    Text::new(achievement.name())
+                        .with_scale(TEXT_SCALE * self.config.graphics.ui_scale())
+                        .with_color([1.0, 1.0, 0.0, 1.0]),
+                ],
+                layout: Layout::default().h_align(HorizontalAlign::Center),
+                ..Section::default()
+            };
+            self.glyph_brush.queue(toast_text);
+        }
+
+        if self.profiler.shown() {
+            let profile_string = self.profiler.lines().join("\n");
+            let profile_text = Section {
+                screen_position: (20.0, 40.0),
+                text: vec![Text::new(&profile_string)
+                    .with_scale(12.0)
                     .with_color([1.0, 1.0, 1.0, 1.0])],
-                    ..Section::default()
-                };
-                self.glyph_brush.queue(game_over_text);
-            }
+                ..Section::default()
+            };
+            self.glyph_brush.queue(profile_text);
         }
-        self.glyph_brush
-            .draw_queued(
+
+        let transform = self.text_transform();
+        {
+            profile_scope!("glyph drawing");
+            if let Err(e) = self.glyph_brush.draw_queued_with_transform(
                 &self.device,
                 &mut self.staging_belt,
                 &mut encoder,
-                &frame.view,
-                960,
-                544,
-            )
-            .expect("Draw queued");
+                target,
+                transform,
+            ) {
+                eprintln!("Failed to draw text: {}", e);
+            }
+        }
+        if let Some(downscaler) = &downscaler {
+            downscaler.blit(&mut encoder, &frame.view);
+        }
+        self.downscaler = downscaler;
 
+        profile_scope!("present");
         self.staging_belt.finish();
         self.queue.submit(std::iter::once(encoder.finish()));
+        // The frame is shown when it's dropped
+        drop(frame);
     }
 }
 
+/// Returns borderless fullscreen on the named monitor, or on the window's current
+/// monitor if it isn't connected.
+///
+/// # Parameters
+///
+/// - `window`: The game window.
+/// - `monitor`: Name of the monitor to prefer.
+fn fullscreen_on(window: &Window, monitor: Option<&String>) -> Fullscreen {
+    let named = monitor.and_then(|name| {
+        window
+            .available_monitors()
+            .find(|m| m.name().as_ref() == Some(name))
+    });
+    Fullscreen::Borderless(named.unwrap_or_else(|| window.current_monitor()))
+}
+
 fn main() {
+    if let Err(e) = run() {
+        // "{:#}" includes the cause, e.g. "Failed to open the audio device: NoDevice"
+        let message = format!("{:#}", e);
+        eprintln!("wgputris failed to start: {}", message);
+        // Quotes can't be shown by every dialog backend
+        tinyfiledialogs::message_box_ok(
+            "wgputris failed to start",
+            &message.replace(['"', '\''], "`"),
+            tinyfiledialogs::MessageBoxIcon::Error,
+        );
+        std::process::exit(1);
+    }
+}
+
+/// Opens the window and runs the game until it is closed.
+///
+/// # Return Value
+///
+/// Only returns if the game couldn't start.
+fn run() -> Result<()> {
+    let args = cli::Args::parse();
+    paths::init(args.portable);
+    match args.command {
+        Some(cli::Command::Bench { games, max_pieces }) => {
+            return bench::run(games, max_pieces, args.seed.unwrap_or(0))
+        }
+        Some(cli::Command::Train {
+            population,
+            generations,
+            games,
+            max_pieces,
+        }) => {
+            return train::run(
+                population,
+                generations,
+                games,
+                max_pieces,
+                args.seed.unwrap_or(0),
+                &paths::config_file(ai::WEIGHTS_FILE),
+            )
+        }
+        Some(cli::Command::Verify { replay, score }) => return cli::run_verify(&replay, score),
+        Some(cli::Command::Export {
+            replay,
+            output,
+            fps,
+            height,
+        }) => return export::run(&replay, output, fps, height),
+        Some(cli::Command::Server { port, web_port }) => {
+            return wgputris::lobby::Lobby::run(port, web_port)
+        }
+        None => {}
+    }
+    if args.headless {
+        return cli::run_headless(&args);
+    }
+    if let Some(bot) = &args.bot {
+        wgputris::tbp::register(bot);
+    }
+    // Weights saved by `wgputris train`
+    let weights_path = paths::config_file(ai::WEIGHTS_FILE);
+    if weights_path.exists() {
+        match ai::Weights::load(&weights_path) {
+            Ok(weights) => weights.use_trained(),
+            Err(e) => eprintln!("Failed to load {}: {:#}", weights_path.display(), e),
+        }
+    }
+    crash::install_hook();
+    let config = config::Config::load();
+    let (width, height) = args
+        .size
+        .unwrap_or((config.window.width, config.window.height));
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new()
         .with_title("wgputris")
-        .with_inner_size(winit::dpi::PhysicalSize::new(960, 544))
-        .with_resizable(false)
+        .with_inner_size(winit::dpi::PhysicalSize::new(width, height))
         .build(&event_loop)
-        .unwrap();
+        .context("Failed to open the window")?;
+    if let (Some(x), Some(y)) = (config.window.x, config.window.y) {
+        window.set_outer_position(winit::dpi::PhysicalPosition::new(x, y));
+    }
+    if args.fullscreen(config.window.fullscreen) {
+        window.set_fullscreen(Some(fullscreen_on(&window, config.window.monitor.as_ref())));
+    }
 
     use futures::executor::block_on;
-    let mut state = block_on(State::new(&window));
-
-    let (_stream, stream_handle) = rodio::OutputStream::try_default().unwrap();
-    let source = rodio::Decoder::new_looped(Cursor::new(&TETRIS_SONG)).unwrap();
-    let sink = rodio::Sink::try_new(&stream_handle).unwrap();
-    sink.append(source);
-    sink.play();
-    sink.detach();
+    let mut state = block_on(State::new(&window, config, &args))?;
+    let mut last_frame = Instant::now();
 
     event_loop.run(move |event, _, control_flow| match event {
         Event::WindowEvent {
             ref event,
             window_id,
         } if window_id == window.id() => {
+            match event {
+                WindowEvent::Resized(size) => {
+                    state.resize(*size);
+                    if window.fullscreen().is_none() {
+                        state.config.window.width = size.width;
+                        state.config.window.height = size.height;
+                    }
+                }
+                WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                    state.resize(**new_inner_size)
+                }
+                WindowEvent::Moved(position) => {
+                    if window.fullscreen().is_none() {
+                        state.config.window.x = Some(position.x);
+                        state.config.window.y = Some(position.y);
+                    }
+                    state.config.window.monitor = window.current_monitor().name();
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::F11),
+                            ..
+                        },
+                    ..
+                } => {
+                    let fullscreen = window.fullscreen().is_none();
+                    let monitor = window.current_monitor().name();
+                    window.set_fullscreen(if fullscreen {
+                        Some(fullscreen_on(&window, monitor.as_ref()))
+                    } else {
+                        None
+                    });
+                    state.config.window.fullscreen = fullscreen;
+                    state.config.window.monitor = monitor;
+                    return;
+                }
+                _ => {}
+            }
             if !state.input(event) {
                 match event {
                     WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
@@ -472,11 +2487,26 @@ fn main() {
             }
         }
         Event::RedrawRequested(_) => {
+            last_frame = Instant::now();
             state.update();
             state.render();
+            state.profiler.end_frame();
         }
         Event::MainEventsCleared => {
-            window.request_redraw();
+            let next_frame = last_frame + Duration::from_millis(IDLE_FRAME_MS);
+            if state.is_idle() && Instant::now() < next_frame {
+                // Focusing the window wakes the loop, so full speed returns at once
+                *control_flow = ControlFlow::WaitUntil(next_frame);
+            } else {
+                *control_flow = ControlFlow::Poll;
+                window.request_redraw();
+            }
+        }
+        Event::LoopDestroyed => {
+            state.telemetry.finish();
+            if let Err(e) = state.config.save() {
+                eprintln!("Failed to save config: {}", e);
+            }
         }
         _ => {}
     });
@@ -0,0 +1,253 @@
+use anyhow::*;
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{CallFnOptions, Dynamic, Engine, FuncArgs, Map, Scope, AST};
+use std::fs::{read_dir, read_to_string};
+use std::path::Path;
+use wgputris::game::{EventHandler, GameEvent};
+use wgputris::stats::Stats;
+
+/// Directory scanned for `.rhai` scripts which change the game.
+const MODS_DIR: &str = "mods";
+/// Most operations a script may run in one call, so a runaway loop can't hang the game.
+const MAX_OPERATIONS: u64 = 100_000;
+/// Deepest a script's function calls may nest.
+const MAX_CALL_LEVELS: usize = 32;
+/// Largest string, array or map a script may build.
+const MAX_SIZE: usize = 10_000;
+
+/// A script loaded from `MODS_DIR`.
+struct Mod {
+    /// File name of the script, without the extension.
+    name: String,
+    ast: AST,
+    /// The script's `this`, which keeps its state between hooks. Reset for each game.
+    state: Dynamic,
+    /// Set when a hook fails, after which the script is left alone so the error isn't
+    /// repeated every frame.
+    failed: bool,
+}
+
+impl Mod {
+    /// Calls a function defined by the script, if it defines one.
+    ///
+    /// # Parameters
+    ///
+    /// - `engine`: The engine the script was compiled by.
+    /// - `hook`: Name of the function.
+    /// - `args`: Arguments to call the function with.
+    ///
+    /// # Return Value
+    ///
+    /// What the function returned, or `None` if the script doesn't define it, has
+    /// failed before, or fails now.
+    fn call<T: Clone + 'static>(
+        &mut self,
+        engine: &Engine,
+        hook: &str,
+        args: impl FuncArgs,
+    ) -> Option<T> {
+        if self.failed || !self.ast.iter_functions().any(|f| f.name == hook) {
+            return None;
+        }
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .bind_this_ptr(&mut self.state);
+        let result = engine.call_fn_with_options::<Dynamic>(
+            options,
+            &mut Scope::new(),
+            &self.ast,
+            hook,
+            args,
+        );
+        match result.map(|value| value.try_cast::<T>()) {
+            Ok(Some(value)) => Some(value),
+            Ok(None) => {
+                eprintln!("Mod {}: {} returned the wrong type", self.name, hook);
+                self.failed = true;
+                None
+            }
+            Err(e) => {
+                eprintln!("Mod {}: {} failed: {}", self.name, hook, e);
+                self.failed = true;
+                None
+            }
+        }
+    }
+}
+
+/// User scripts from `MODS_DIR`, which can react to game events, award extra points,
+/// end the game early to make a new mode, and add lines to the HUD.
+///
+/// Scripts are run by a rhai engine which can't touch the filesystem or import other
+/// scripts, and has limits on how long and how much memory each hook may use.
+pub struct Mods {
+    engine: Engine,
+    mods: Vec<Mod>,
+    /// Level of the current game, passed to the `bonus` hook.
+    level: usize,
+    /// Points awarded by `bonus` hooks and not yet added to the score.
+    bonus: i64,
+}
+
+impl Mods {
+    /// Loads every script in `MODS_DIR`. Scripts which fail to compile are skipped.
+    pub fn load() -> Self {
+        let mut engine = Engine::new();
+        engine.set_module_resolver(DummyModuleResolver::new());
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.set_max_call_levels(MAX_CALL_LEVELS);
+        engine.set_max_string_size(MAX_SIZE);
+        engine.set_max_array_size(MAX_SIZE);
+        engine.set_max_map_size(MAX_SIZE);
+        engine.disable_symbol("eval");
+
+        let mut mods = Vec::new();
+        if let Ok(entries) = read_dir(MODS_DIR) {
+            let mut paths: Vec<_> = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "rhai"))
+                .collect();
+            paths.sort();
+            for path in paths {
+                match Self::compile(&engine, &path) {
+                    Ok(ast) => mods.push(Mod {
+                        name: path
+                            .file_stem()
+                            .map(|stem| stem.to_string_lossy().into_owned())
+                            .unwrap_or_default(),
+                        ast,
+                        state: Dynamic::from_map(Map::new()),
+                        failed: false,
+                    }),
+                    Err(e) => eprintln!("Failed to load mod {}: {}", path.display(), e),
+                }
+            }
+        }
+        Self {
+            engine,
+            mods,
+            level: 1,
+            bonus: 0,
+        }
+    }
+
+    /// Reads and compiles one script.
+    ///
+    /// # Parameters
+    ///
+    /// - `engine`: The engine to compile the script with.
+    /// - `path`: Path to the script.
+    fn compile(engine: &Engine, path: &Path) -> Result<AST> {
+        let source = read_to_string(path)?;
+        engine.compile(&source).map_err(|e| anyhow!("{}", e))
+    }
+
+    /// Returns the names of the loaded scripts.
+    pub fn names(&self) -> Vec<&str> {
+        self.mods.iter().map(|m| m.name.as_str()).collect()
+    }
+
+    /// Resets every script's state for a new game and calls its `on_start` hook.
+    pub fn start_game(&mut self) {
+        self.level = 1;
+        self.bonus = 0;
+        for m in self.mods.iter_mut() {
+            m.state = Dynamic::from_map(Map::new());
+            m.call::<Dynamic>(&self.engine, "on_start", ());
+        }
+    }
+
+    /// Returns the points awarded by `bonus` hooks since the last call, which may be
+    /// negative.
+    pub fn take_bonus(&mut self) -> i64 {
+        std::mem::take(&mut self.bonus)
+    }
+
+    /// Returns whether any script's `finished` hook ended the game.
+    ///
+    /// # Parameters
+    ///
+    /// - `stats`: Totals of the game so far.
+    pub fn finished(&mut self, stats: &Stats) -> bool {
+        let stats = stats_map(stats);
+        let mut finished = false;
+        for m in self.mods.iter_mut() {
+            finished |= m
+                .call::<bool>(&self.engine, "finished", (stats.clone(),))
+                .unwrap_or(false);
+        }
+        finished
+    }
+
+    /// Returns the lines every script's `hud` hook adds below the next shape.
+    ///
+    /// # Parameters
+    ///
+    /// - `stats`: Totals of the game so far.
+    pub fn hud(&mut self, stats: &Stats) -> Vec<String> {
+        let stats = stats_map(stats);
+        let mut lines = Vec::new();
+        for m in self.mods.iter_mut() {
+            if let Some(value) = m.call::<Dynamic>(&self.engine, "hud", (stats.clone(),)) {
+                if value.is_array() {
+                    lines.extend(value.cast::<rhai::Array>().iter().map(|v| v.to_string()));
+                } else if !value.is_unit() {
+                    lines.push(value.to_string());
+                }
+            }
+        }
+        lines
+    }
+}
+
+impl EventHandler for Mods {
+    fn handle_event(&mut self, event: &GameEvent) {
+        if let GameEvent::LevelUp(level) = *event {
+            self.level = level;
+        }
+        let (name, value) = event_args(event);
+        for m in self.mods.iter_mut() {
+            m.call::<Dynamic>(
+                &self.engine,
+                "on_event",
+                (Dynamic::from(name), value.clone()),
+            );
+            if let GameEvent::LinesCleared { n, .. } = *event {
+                self.bonus += m
+                    .call::<i64>(&self.engine, "bonus", (n as i64, self.level as i64))
+                    .unwrap_or(0);
+            }
+        }
+    }
+}
+
+/// Returns the name and value a game event is passed to `on_event` hooks as.
+///
+/// # Parameters
+///
+/// - `event`: The event which happened.
+fn event_args(event: &GameEvent) -> (&'static str, Dynamic) {
+    match *event {
+        GameEvent::Spawned(kind) => ("spawned", format!("{:?}", kind).into()),
+        GameEvent::Moved => ("moved", Dynamic::UNIT),
+        GameEvent::Rotated => ("rotated", Dynamic::UNIT),
+        GameEvent::PieceLocked { hard_drop } => ("piece_locked", hard_drop.into()),
+        GameEvent::LinesCleared { n, .. } => ("lines_cleared", (n as i64).into()),
+        GameEvent::ScoreChanged(score) => ("score_changed", (score as i64).into()),
+        GameEvent::LevelUp(level) => ("level_up", (level as i64).into()),
+        GameEvent::Held => ("held", Dynamic::UNIT),
+        GameEvent::StackHeight(height) => ("stack_height", (height as i64).into()),
+        GameEvent::GameOver => ("game_over", Dynamic::UNIT),
+    }
+}
+
+/// Returns the totals of a game as the map passed to `finished` and `hud` hooks.
+fn stats_map(stats: &Stats) -> Dynamic {
+    let mut map = Map::new();
+    map.insert("score".into(), (stats.score as i64).into());
+    map.insert("lines".into(), (stats.lines as i64).into());
+    map.insert("level".into(), (stats.level as i64).into());
+    map.insert("pieces".into(), (stats.pieces as i64).into());
+    map.insert("keys".into(), (stats.keys as i64).into());
+    Dynamic::from_map(map)
+}
@@ -0,0 +1,728 @@
+//! Messages two players' clients exchange in an online match, directly or through a
+//! lobby server, and the TCP or WebSocket connection they travel over. Each client
+//! simulates both games, so only the seed and each player's inputs are sent. Both ends
+//! start with a `Handshake`, so clients which would simulate games differently refuse
+//! each other up front instead of drifting apart mid-match.
+
+use crate::game::{self, Game, FRAMES_PER_SECOND, MAX_SOFT_DROP_FACTOR};
+use crate::rating::MatchResult;
+use crate::replay::{format_input, parse_input, Input};
+use anyhow::*;
+use std::collections::VecDeque;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use tungstenite::http::Uri;
+use tungstenite::{Error as WebSocketError, Message as WebSocketMessage, WebSocket};
+
+/// Port games are hosted on, and joined on if the address doesn't give one.
+pub const DEFAULT_PORT: u16 = 7788;
+/// Port games are hosted on for browsers, which can only connect over WebSockets.
+pub const DEFAULT_WEB_PORT: u16 = 7789;
+/// Version of the messages below, raised whenever they change.
+pub const PROTOCOL_VERSION: u32 = 5;
+/// How often each side sends a hash of its game's state, for the other to check its
+/// copy of the game against, in frames.
+pub const HASH_INTERVAL_FRAMES: u64 = 60;
+/// Shortest input delay players can pick, in frames, other than none.
+pub const MIN_INPUT_DELAY: u32 = 2;
+/// Longest input delay players can pick, in frames.
+pub const MAX_INPUT_DELAY: u32 = 8;
+/// How long joining waits for the host to answer, and hosting for a browser to finish
+/// its WebSocket handshake.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// What each end of a connection sends first: everything which has to match for two
+/// clients to play each other.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Handshake {
+    /// `PROTOCOL_VERSION` of the sender.
+    pub version: u32,
+    /// Hash of the sender's `game::rules`.
+    pub rules: u64,
+    /// Hash of the sender's `game::piece_set`.
+    pub pieces: u64,
+    /// Highest soft drop factor the sender's games apply.
+    pub max_soft_drop_factor: u32,
+}
+
+impl Handshake {
+    /// Returns the handshake of this build.
+    pub fn local() -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            rules: hash(&game::rules()),
+            pieces: hash(&game::piece_set()),
+            max_soft_drop_factor: MAX_SOFT_DROP_FACTOR,
+        }
+    }
+
+    /// Checks whether a client can play against this one.
+    ///
+    /// # Parameters
+    ///
+    /// - `other`: The other client's handshake.
+    ///
+    /// # Return Value
+    ///
+    /// Fails with why not, worded for the player, if the clients don't match.
+    pub fn check(&self, other: &Handshake) -> Result<()> {
+        const UPDATE: &str = "Both players need the same version of wgputris.";
+        if other.version != self.version {
+            bail!(
+                "The other game speaks online protocol version {}, but this one speaks \
+                 version {}. {}",
+                other.version,
+                self.version,
+                UPDATE
+            );
+        }
+        if other.rules != self.rules {
+            bail!("The other game plays by different rules. {}", UPDATE);
+        }
+        if other.pieces != self.pieces {
+            bail!(
+                "The other game deals or turns shapes differently. {}",
+                UPDATE
+            );
+        }
+        if other.max_soft_drop_factor != self.max_soft_drop_factor {
+            bail!(
+                "The other game allows soft drop up to {}x, but this one up to {}x. {}",
+                other.max_soft_drop_factor,
+                self.max_soft_drop_factor,
+                UPDATE
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Hashes text with 64-bit FNV-1a, which unlike the standard library's hasher gives
+/// the same hash in every build.
+fn hash(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// A message between a player's client and the other player's client or a lobby
+/// server, sent as one line of text.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    /// The sender's handshake, always the first message on a connection.
+    Hello(Handshake),
+    /// Sent by the host once a player joins, or by a lobby once both players in a room
+    /// are ready: the seed both games deal shapes from.
+    Start { seed: u64 },
+    /// An input to the sender's game, with its game time in milliseconds.
+    Input(u64, Input),
+    /// The sender's game has reached this game time, in milliseconds, with every
+    /// input up to it already sent.
+    Time(u64),
+    /// `Game::state_hash` of the sender's game at a game time in milliseconds, before
+    /// any inputs at that time.
+    Hash(u64, u64),
+    /// Sent by a lobby server as soon as a client connects, so it can tell a lobby
+    /// from a player hosting a game.
+    Lobby,
+    /// Asks the lobby for the rooms waiting for players.
+    List,
+    /// A room waiting for players, with how many it has and the rating of the player
+    /// waiting longest, in answer to `List`.
+    Room {
+        room: u32,
+        players: usize,
+        rating: u32,
+    },
+    /// A room whose match is being played, which can be watched, in answer to `List`.
+    Playing(u32),
+    /// Ends the rooms listed in answer to `List`.
+    Listed,
+    /// Asks the lobby for a new room.
+    Create,
+    /// Asks the lobby for a place in a room.
+    Enter(u32),
+    /// Asks the lobby for a place in the room which has waited longest for a second
+    /// player rated about the same as the sender, or a new room if none has.
+    Quick,
+    /// The sender's player id, which the lobby keeps their rating under.
+    Profile(u64),
+    /// The sender's rating, from the lobby once they send their id and after each
+    /// ranked match.
+    Rating(u32),
+    /// The sender has a place in a room, along with the token to rejoin it with if the
+    /// connection drops during the match.
+    Entered { room: u32, token: u64 },
+    /// The lobby turned a request down, for this reason.
+    Refused(String),
+    /// The other player has come into the room.
+    Joined,
+    /// The sender is ready to start, or from the lobby, the other player is.
+    Ready,
+    /// The sender is leaving their room, or from the lobby, the other player left.
+    Leave,
+    /// The other player's connection dropped during the match, so they may rejoin.
+    Away,
+    /// The other player rejoined the match.
+    Back,
+    /// Asks for a place in a match back after the connection dropped.
+    Rejoin { room: u32, token: u64 },
+    /// The sender has its place in the match back. The lobby had this many of its
+    /// inputs, and sends the other player's whole game again after this.
+    Rejoined(usize),
+    /// A line of chat, from the sender or through the lobby from the other player.
+    Chat(String),
+    /// Asks the lobby to watch the match in a room.
+    Watch(u32),
+    /// The sender is watching the match in a room, dealt from this seed. The players'
+    /// messages follow, a few seconds behind.
+    Watching { room: u32, seed: u64 },
+    /// A message the player in a seat sent, passed on to spectators.
+    Player(usize, Box<Message>),
+    /// After a match, the sender wants to play the same opponent again, or from the
+    /// lobby, the other player does. Once both have asked, the host or lobby sends
+    /// `Start` with a new seed.
+    Rematch,
+}
+
+impl Message {
+    /// Writes the message as a line of text, without the line break.
+    pub fn to_line(&self) -> String {
+        match self {
+            Message::Hello(handshake) => format!(
+                "hello {} {:x} {:x} {}",
+                handshake.version,
+                handshake.rules,
+                handshake.pieces,
+                handshake.max_soft_drop_factor
+            ),
+            Message::Start { seed } => format!("start {}", seed),
+            Message::Input(time_ms, input) => format!("input {}", format_input(*time_ms, input)),
+            Message::Time(time_ms) => format!("time {}", time_ms),
+            Message::Hash(time_ms, hash) => format!("hash {} {:x}", time_ms, hash),
+            Message::Lobby => String::from("lobby"),
+            Message::List => String::from("list"),
+            Message::Room {
+                room,
+                players,
+                rating,
+            } => format!("room {} {} {}", room, players, rating),
+            Message::Playing(room) => format!("playing {}", room),
+            Message::Listed => String::from("listed"),
+            Message::Create => String::from("create"),
+            Message::Enter(room) => format!("enter {}", room),
+            Message::Quick => String::from("quick"),
+            Message::Profile(player) => format!("profile {}", player),
+            Message::Rating(rating) => format!("rating {}", rating),
+            Message::Entered { room, token } => format!("entered {} {}", room, token),
+            Message::Refused(reason) => format!("refused {}", reason),
+            Message::Joined => String::from("joined"),
+            Message::Ready => String::from("ready"),
+            Message::Leave => String::from("leave"),
+            Message::Away => String::from("away"),
+            Message::Back => String::from("back"),
+            Message::Rejoin { room, token } => format!("rejoin {} {}", room, token),
+            Message::Rejoined(inputs) => format!("rejoined {}", inputs),
+            // Lines can't hold line breaks
+            Message::Chat(text) => format!("chat {}", text.replace(['\r', '\n'], " ")),
+            Message::Watch(room) => format!("watch {}", room),
+            Message::Watching { room, seed } => format!("watching {} {}", room, seed),
+            Message::Player(seat, message) => format!("player {} {}", seat, message.to_line()),
+            Message::Rematch => String::from("rematch"),
+        }
+    }
+
+    /// Parses a line written by `Message::to_line`.
+    ///
+    /// # Parameters
+    ///
+    /// - `line`: The line, without the line break.
+    pub fn parse(line: &str) -> Result<Self> {
+        let (kind, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let (first, second) = rest.split_once(' ').unwrap_or((rest, ""));
+        Ok(match kind {
+            "hello" => {
+                // Only the version has to be readable, as other versions may lay the
+                // rest out differently
+                let mut fields = rest.split(' ');
+                let version = fields.next().unwrap_or_default().parse()?;
+                let mut field = || fields.next().unwrap_or_default();
+                Message::Hello(Handshake {
+                    version,
+                    rules: u64::from_str_radix(field(), 16).unwrap_or_default(),
+                    pieces: u64::from_str_radix(field(), 16).unwrap_or_default(),
+                    max_soft_drop_factor: field().parse().unwrap_or_default(),
+                })
+            }
+            "start" => Message::Start {
+                seed: rest.parse()?,
+            },
+            "input" => {
+                let (time_ms, input) = parse_input(rest)?;
+                Message::Input(time_ms, input)
+            }
+            "time" => Message::Time(rest.parse()?),
+            "hash" => Message::Hash(first.parse()?, u64::from_str_radix(second, 16)?),
+            "lobby" => Message::Lobby,
+            "list" => Message::List,
+            "room" => {
+                let (players, rating) = second.split_once(' ').unwrap_or((second, ""));
+                Message::Room {
+                    room: first.parse()?,
+                    players: players.parse()?,
+                    rating: rating.parse()?,
+                }
+            }
+            "playing" => Message::Playing(rest.parse()?),
+            "listed" => Message::Listed,
+            "create" => Message::Create,
+            "enter" => Message::Enter(rest.parse()?),
+            "quick" => Message::Quick,
+            "profile" => Message::Profile(rest.parse()?),
+            "rating" => Message::Rating(rest.parse()?),
+            "entered" => Message::Entered {
+                room: first.parse()?,
+                token: second.parse()?,
+            },
+            "refused" => Message::Refused(rest.to_string()),
+            "joined" => Message::Joined,
+            "ready" => Message::Ready,
+            "leave" => Message::Leave,
+            "away" => Message::Away,
+            "back" => Message::Back,
+            "rejoin" => Message::Rejoin {
+                room: first.parse()?,
+                token: second.parse()?,
+            },
+            "rejoined" => Message::Rejoined(rest.parse()?),
+            "chat" => Message::Chat(rest.to_string()),
+            "watch" => Message::Watch(rest.parse()?),
+            "watching" => Message::Watching {
+                room: first.parse()?,
+                seed: second.parse()?,
+            },
+            "player" => Message::Player(first.parse()?, Box::new(Message::parse(second)?)),
+            "rematch" => Message::Rematch,
+            _ => bail!("Invalid message: {}", line),
+        })
+    }
+}
+
+/// Works out who won a match, once it is certain. Each player's clock runs
+/// separately, so whoever topped out earlier in game time loses, even if the news
+/// arrives later.
+///
+/// # Parameters
+///
+/// - `first`: The first player's game, as far as it is known.
+/// - `second`: The second player's game, as far as it is known.
+///
+/// # Return Value
+///
+/// How the match ended from the first player's side, or `None` while it's undecided.
+pub fn decide(first: &Game, second: &Game) -> Option<MatchResult> {
+    match (first.game_over, second.game_over) {
+        (true, true) => Some(match first.time_ms().cmp(&second.time_ms()) {
+            std::cmp::Ordering::Less => MatchResult::Lost,
+            std::cmp::Ordering::Greater => MatchResult::Won,
+            std::cmp::Ordering::Equal => MatchResult::Draw,
+        }),
+        (false, true) if second.time_ms() <= first.time_ms() => Some(MatchResult::Won),
+        (true, false) if second.time_ms() >= first.time_ms() => Some(MatchResult::Lost),
+        _ => None,
+    }
+}
+
+/// Holds a player's inputs back a fixed number of frames before they reach their game,
+/// for players who would rather see both boards move steadily than have their own
+/// react at once. The inputs are recorded when they reach the game, so the other
+/// player simulates it the same way with or without a delay.
+///
+/// The other player's game is never predicted or rolled back: it is only played as far
+/// as their inputs have arrived. Without a delay it is shown as soon as they do, so it
+/// stalls and jumps as the connection's latency varies. With one, it is shown the delay
+/// behind this player's game instead, see `shown_ms`, which hides any variation up to
+/// the delay. Holding this player's own inputs back by the same amount keeps both
+/// boards on the same clock, so garbage lands when each player sees it sent.
+#[derive(Debug, Clone)]
+pub struct InputDelay {
+    frames: u32,
+    /// Inputs held back, with the game time each is due at, soonest first.
+    queue: VecDeque<(u64, Input)>,
+}
+
+impl InputDelay {
+    /// Creates a new `InputDelay` holding nothing back yet.
+    ///
+    /// # Parameters
+    ///
+    /// - `frames`: Frames to hold each input back for, `0` for none, or from
+    ///   `MIN_INPUT_DELAY` to `MAX_INPUT_DELAY`.
+    pub fn new(frames: u32) -> Self {
+        Self {
+            frames: frames.min(MAX_INPUT_DELAY),
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Returns the frames each input is held back for.
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    /// Returns how long each input is held back for, in milliseconds of game time.
+    pub fn ms(&self) -> u64 {
+        self.frames as u64 * 1000 / FRAMES_PER_SECOND
+    }
+
+    /// Holds an input back until it is due.
+    ///
+    /// # Parameters
+    ///
+    /// - `game`: The game the input is for.
+    /// - `input`: The input, as the player gave it.
+    pub fn push(&mut self, game: &Game, input: Input) {
+        self.queue.push_back((game.time_ms() + self.ms(), input));
+    }
+
+    /// Returns how far the other player's game can be shown, in milliseconds of game
+    /// time. With a delay, it moves on steadily with this player's game as long as the
+    /// other player's inputs arrive within the delay.
+    ///
+    /// # Parameters
+    ///
+    /// - `local_ms`: Game time of this player's game.
+    /// - `remote_ms`: Game time the other player's game has reached, with every input
+    ///   up to it received.
+    pub fn shown_ms(&self, local_ms: u64, remote_ms: u64) -> u64 {
+        if self.frames == 0 {
+            remote_ms
+        } else {
+            remote_ms.min(local_ms.saturating_sub(self.ms()))
+        }
+    }
+
+    /// Applies every input which has been held back long enough to the game.
+    ///
+    /// # Parameters
+    ///
+    /// - `game`: The game the inputs are for.
+    pub fn apply(&mut self, game: &mut Game) {
+        while let Some((due_ms, _)) = self.queue.front() {
+            if *due_ms > game.time_ms() {
+                break;
+            }
+            if let Some((_, input)) = self.queue.pop_front() {
+                game.apply_input(&input);
+            }
+        }
+    }
+}
+
+/// Returns the input delay after stepping it up or down: off, then each number of
+/// frames from `MIN_INPUT_DELAY` to `MAX_INPUT_DELAY`.
+///
+/// # Parameters
+///
+/// - `frames`: The current delay, `0` for none.
+/// - `direction`: `1` to lengthen, `-1` to shorten.
+pub fn step_input_delay(frames: u32, direction: i32) -> u32 {
+    match (frames, direction.signum()) {
+        (0, 1) => MIN_INPUT_DELAY,
+        (frames, -1) if frames <= MIN_INPUT_DELAY => 0,
+        (frames, direction) => (frames as i32 + direction).clamp(0, MAX_INPUT_DELAY as i32) as u32,
+    }
+}
+
+/// Waits for another player to join a hosted game, over TCP from a native client or
+/// over a WebSocket from a browser.
+pub struct Host {
+    listener: TcpListener,
+    web_listener: TcpListener,
+}
+
+impl Host {
+    /// Starts listening for a player on every network interface.
+    ///
+    /// # Parameters
+    ///
+    /// - `port`: Port to listen for native clients on, or `0` for any free port.
+    /// - `web_port`: Port to listen for browsers on, or `0` for any free port.
+    pub fn listen(port: u16, web_port: u16) -> Result<Self> {
+        let bind = |port| -> Result<TcpListener> {
+            let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, port))
+                .with_context(|| format!("Failed to listen on port {}", port))?;
+            listener.set_nonblocking(true)?;
+            Ok(listener)
+        };
+        Ok(Self {
+            listener: bind(port)?,
+            web_listener: bind(web_port)?,
+        })
+    }
+
+    /// Returns the port native clients join on.
+    pub fn port(&self) -> u16 {
+        self.listener.local_addr().map_or(0, |addr| addr.port())
+    }
+
+    /// Returns the port browsers join on.
+    pub fn web_port(&self) -> u16 {
+        self.web_listener.local_addr().map_or(0, |addr| addr.port())
+    }
+
+    /// Checks whether a player has joined, without waiting, except briefly for a
+    /// browser's WebSocket handshake.
+    ///
+    /// # Return Value
+    ///
+    /// The connection to the player, if one has joined.
+    pub fn accept(&self) -> Result<Option<Connection>> {
+        if let Some(stream) = accept(&self.listener)? {
+            return Ok(Some(Connection::new(Transport::Tcp(stream))?));
+        }
+        if let Some(stream) = accept(&self.web_listener)? {
+            stream.set_nonblocking(false)?;
+            stream.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+            let socket = tungstenite::accept(stream)
+                .map_err(|e| anyhow!("WebSocket handshake failed: {}", e))?;
+            return Ok(Some(Connection::new(Transport::WebSocket(Box::new(
+                socket,
+            )))?));
+        }
+        Ok(None)
+    }
+}
+
+/// Checks whether a listener has a connection waiting, without waiting.
+fn accept(listener: &TcpListener) -> Result<Option<TcpStream>> {
+    match listener.accept() {
+        Ok((stream, _)) => Ok(Some(stream)),
+        Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// What messages travel over: lines of text on a plain TCP stream, or text
+/// WebSocket messages each holding one or more lines.
+enum Transport {
+    Tcp(TcpStream),
+    /// Boxed, as it holds its own buffers.
+    WebSocket(Box<WebSocket<TcpStream>>),
+}
+
+impl Transport {
+    fn stream(&self) -> &TcpStream {
+        match self {
+            Transport::Tcp(stream) => stream,
+            Transport::WebSocket(socket) => socket.get_ref(),
+        }
+    }
+}
+
+/// A connection to the other player's client, which never blocks once made.
+pub struct Connection {
+    transport: Transport,
+    /// Bytes received which don't make up a whole line yet.
+    incoming: Vec<u8>,
+    /// Bytes queued to send which haven't been handed to the transport yet.
+    outgoing: Vec<u8>,
+    /// Whether the other end's handshake has arrived and matches this build.
+    greeted: bool,
+}
+
+impl Connection {
+    /// Joins a game hosted at an address, waiting briefly for the host to answer.
+    ///
+    /// # Parameters
+    ///
+    /// - `address`: Host name or IP address, optionally followed by `:port`, or a
+    ///   `ws://` URL to join over a WebSocket.
+    pub fn join(address: &str) -> Result<Self> {
+        let address = address.trim();
+        if address.starts_with("ws://") {
+            let uri: Uri = address.parse()?;
+            let host = uri
+                .host()
+                .ok_or_else(|| anyhow!("No host in {}", address))?;
+            let stream = connect(host, uri.port_u16().unwrap_or(DEFAULT_WEB_PORT))?;
+            stream.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+            let (socket, _) = tungstenite::client(uri, stream)
+                .map_err(|e| anyhow!("WebSocket handshake failed: {}", e))?;
+            return Self::new(Transport::WebSocket(Box::new(socket)));
+        }
+        let stream = match address.to_socket_addrs() {
+            Ok(_) => connect_to(address)?,
+            // No port given
+            Err(_) => connect(address, DEFAULT_PORT)?,
+        };
+        Self::new(Transport::Tcp(stream))
+    }
+
+    fn new(transport: Transport) -> Result<Self> {
+        let stream = transport.stream();
+        stream.set_read_timeout(None)?;
+        stream.set_nonblocking(true)?;
+        // Inputs are tiny and need to arrive as soon as possible
+        stream.set_nodelay(true)?;
+        let mut connection = Self {
+            transport,
+            incoming: Vec::new(),
+            outgoing: Vec::new(),
+            greeted: false,
+        };
+        connection.send(&Message::Hello(Handshake::local()));
+        Ok(connection)
+    }
+
+    /// Returns `true` once the other end's handshake has arrived and matches this
+    /// build, so the match can be set up.
+    pub fn is_greeted(&self) -> bool {
+        self.greeted
+    }
+
+    /// Returns the address of the other player.
+    pub fn peer(&self) -> String {
+        self.transport
+            .stream()
+            .peer_addr()
+            .map_or_else(|_| String::from("unknown"), |addr| addr.to_string())
+    }
+
+    /// Queues a message to send on the next `receive`.
+    ///
+    /// # Parameters
+    ///
+    /// - `message`: The message.
+    pub fn send(&mut self, message: &Message) {
+        self.outgoing.extend(message.to_line().bytes());
+        self.outgoing.push(b'\n');
+    }
+
+    /// Sends every queued message the transport will take, then reads every message
+    /// which has arrived, without waiting. The other end's handshake is checked rather
+    /// than returned.
+    ///
+    /// # Return Value
+    ///
+    /// The messages received, oldest first. Fails if the other player has left, or
+    /// their game doesn't match this one.
+    pub fn receive(&mut self) -> Result<Vec<Message>> {
+        self.flush()?;
+        match &mut self.transport {
+            Transport::Tcp(stream) => {
+                let mut buffer = [0; 4096];
+                loop {
+                    match stream.read(&mut buffer) {
+                        Ok(0) => bail!("The other player left"),
+                        Ok(read) => self.incoming.extend(&buffer[..read]),
+                        Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                        Err(e) => return Err(left(e)),
+                    }
+                }
+            }
+            Transport::WebSocket(socket) => {
+                loop {
+                    match socket.read() {
+                        Ok(WebSocketMessage::Text(text)) => self.incoming.extend(text.bytes()),
+                        Ok(WebSocketMessage::Close(_)) => bail!("The other player left"),
+                        // Pings are answered on the next flush
+                        Ok(_) => {}
+                        Err(WebSocketError::Io(e)) if e.kind() == ErrorKind::WouldBlock => break,
+                        Err(WebSocketError::ConnectionClosed)
+                        | Err(WebSocketError::AlreadyClosed) => bail!("The other player left"),
+                        Err(WebSocketError::Io(e)) => return Err(left(e)),
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+                allow_would_block(socket.flush())?;
+            }
+        }
+        let mut messages = Vec::new();
+        while let Some(end) = self.incoming.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.incoming.drain(..=end).collect();
+            let message = Message::parse(std::str::from_utf8(&line)?.trim_end())?;
+            if self.greeted {
+                messages.push(message);
+                continue;
+            }
+            match message {
+                Message::Hello(handshake) => {
+                    if let Err(e) = Handshake::local().check(&handshake) {
+                        // Tells clients which don't check for themselves why
+                        self.send(&Message::Refused(e.to_string()));
+                        let _ = self.flush();
+                        return Err(e);
+                    }
+                    self.greeted = true;
+                }
+                Message::Refused(reason) => bail!(reason),
+                _ => bail!("The other game is too old to play online with this one"),
+            }
+        }
+        Ok(messages)
+    }
+
+    /// Hands every queued message the transport will take to it, without waiting.
+    fn flush(&mut self) -> Result<()> {
+        match &mut self.transport {
+            Transport::Tcp(stream) => {
+                while !self.outgoing.is_empty() {
+                    match stream.write(&self.outgoing) {
+                        Ok(0) => bail!("The other player left"),
+                        Ok(sent) => drop(self.outgoing.drain(..sent)),
+                        Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                        Err(e) => return Err(left(e)),
+                    }
+                }
+            }
+            Transport::WebSocket(socket) => {
+                if !self.outgoing.is_empty() {
+                    // Only whole lines are queued, so this is always valid UTF-8
+                    let text = String::from_utf8(std::mem::take(&mut self.outgoing))?;
+                    // Kept to send on a later flush if the socket is full
+                    allow_would_block(socket.write(WebSocketMessage::Text(text)))?;
+                    allow_would_block(socket.flush())?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reports a connection the other end closed abruptly as them leaving, as it would be
+/// had it closed cleanly.
+fn left(e: std::io::Error) -> Error {
+    match e.kind() {
+        ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted | ErrorKind::BrokenPipe => {
+            anyhow!("The other player left")
+        }
+        _ => e.into(),
+    }
+}
+
+/// Treats a WebSocket write which couldn't finish without waiting as a success, as the
+/// rest is sent on the next flush.
+fn allow_would_block(result: std::result::Result<(), WebSocketError>) -> Result<()> {
+    match result {
+        Err(WebSocketError::Io(e)) if e.kind() == ErrorKind::WouldBlock => Ok(()),
+        result => Ok(result?),
+    }
+}
+
+/// Connects to a host and port, giving up after `CONNECT_TIMEOUT`.
+fn connect(host: &str, port: u16) -> Result<TcpStream> {
+    connect_to((host, port))
+}
+
+/// Connects to the first address an address resolves to, giving up after
+/// `CONNECT_TIMEOUT`.
+fn connect_to(address: impl ToSocketAddrs) -> Result<TcpStream> {
+    let addr = address
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| anyhow!("Unknown address"))?;
+    TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)
+        .with_context(|| format!("Failed to connect to {}", addr))
+}
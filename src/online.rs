@@ -0,0 +1,997 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+use web_time::Instant;
+use wgputris::attack::{Attack, AttackTable};
+use wgputris::game::{Game, FRAMES_PER_SECOND};
+use wgputris::lobby::REJOIN_TIMEOUT;
+use wgputris::netplay::{
+    self, Connection, Host, InputDelay, Message, DEFAULT_PORT, DEFAULT_WEB_PORT,
+    HASH_INTERVAL_FRAMES,
+};
+use wgputris::rating::MatchResult;
+use wgputris::replay::{Playback, Replay};
+use winit::event::VirtualKeyCode;
+
+/// How often the list of rooms in a lobby is refreshed.
+const LIST_INTERVAL: Duration = Duration::from_secs(2);
+/// How long to wait between attempts to rejoin a match after the connection drops.
+const REJOIN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What the online screen is doing.
+enum Status {
+    /// Waiting for an address to be typed.
+    Idle,
+    /// Waiting for another player to join.
+    Hosting(Host),
+    /// A player joined this game, waiting for their handshake before starting.
+    Accepted,
+    /// Connected, waiting to hear whether it's a player's game or a lobby.
+    Joining,
+    /// Browsing the rooms of a lobby server.
+    Lobby {
+        /// Rooms waiting for players, with how many each has and the rating of the
+        /// player waiting longest.
+        rooms: Vec<(u32, usize, u32)>,
+        /// Rooms received so far in answer to the latest `List`.
+        listing: Vec<(u32, usize, u32)>,
+        /// Rooms whose matches can be watched.
+        matches: Vec<u32>,
+        /// Matches received so far in answer to the latest `List`.
+        listing_matches: Vec<u32>,
+        /// Index of the selected room, counting on into the matches after the rooms.
+        selected: usize,
+        listed_at: Instant,
+    },
+    /// In a lobby room, waiting for both players to be ready.
+    Room {
+        room: u32,
+        token: u64,
+        ready: bool,
+        /// Whether the other player is ready, if there is one.
+        opponent: Option<bool>,
+    },
+    /// The last attempt to host or join failed, with the reason.
+    Failed(String),
+}
+
+/// The "Play Online" screen, for hosting a game, joining one by address, or finding
+/// one in a lobby.
+pub struct OnlineMenu {
+    address: String,
+    /// Id this player's rating is kept under, sent to lobbies.
+    player_id: u64,
+    /// Frames this player's inputs are held back for in matches, `0` for none.
+    input_delay: u32,
+    /// This player's rating in the lobby, once it has said.
+    rating: Option<u32>,
+    /// Connection to a host or lobby, once joined.
+    connection: Option<Connection>,
+    status: Status,
+    /// The lobby's reason for turning the last request down.
+    notice: Option<String>,
+    /// Chat received from the other player in the room, not yet shown.
+    chat: Vec<String>,
+}
+
+impl OnlineMenu {
+    /// Creates a new `OnlineMenu` with no address typed.
+    ///
+    /// # Parameters
+    ///
+    /// - `player_id`: Id this player's rating is kept under.
+    /// - `input_delay`: Frames this player's inputs are held back for in matches.
+    pub fn new(player_id: u64, input_delay: u32) -> Self {
+        Self {
+            address: String::new(),
+            player_id,
+            input_delay,
+            rating: None,
+            connection: None,
+            status: Status::Idle,
+            notice: None,
+            chat: Vec::new(),
+        }
+    }
+
+    /// Adds a typed character to the address, while one is being typed.
+    ///
+    /// # Parameters
+    ///
+    /// - `c`: The character.
+    pub fn type_char(&mut self, c: char) {
+        if matches!(self.status, Status::Idle | Status::Failed(_))
+            && !c.is_control()
+            && !c.is_whitespace()
+        {
+            self.address.push(c);
+        }
+    }
+
+    /// Handles a key press on the online screen.
+    ///
+    /// # Parameters
+    ///
+    /// - `key`: The key which was pressed.
+    ///
+    /// # Return Value
+    ///
+    /// `true` if the player chose to leave the screen.
+    pub fn process_input(&mut self, key: VirtualKeyCode) -> bool {
+        self.notice = None;
+        let direction = match key {
+            VirtualKeyCode::Left => -1,
+            VirtualKeyCode::Right => 1,
+            _ => 0,
+        };
+        if direction != 0 && !self.is_connecting() {
+            self.input_delay = netplay::step_input_delay(self.input_delay, direction);
+            return false;
+        }
+        match (&mut self.status, key) {
+            (Status::Idle | Status::Failed(_), VirtualKeyCode::Escape) => return true,
+            (Status::Idle | Status::Failed(_), VirtualKeyCode::Back) => {
+                self.address.pop();
+            }
+            (Status::Idle | Status::Failed(_), VirtualKeyCode::Tab) => {
+                self.status = match Host::listen(DEFAULT_PORT, DEFAULT_WEB_PORT) {
+                    Ok(host) => Status::Hosting(host),
+                    Err(e) => Status::Failed(format!("{:#}", e)),
+                };
+            }
+            (Status::Idle | Status::Failed(_), VirtualKeyCode::Return)
+                if !self.address.is_empty() =>
+            {
+                match Connection::join(&self.address) {
+                    Ok(connection) => {
+                        self.connection = Some(connection);
+                        self.status = Status::Joining;
+                    }
+                    Err(e) => self.status = Status::Failed(format!("{:#}", e)),
+                }
+            }
+            (
+                Status::Lobby {
+                    rooms,
+                    matches,
+                    selected,
+                    ..
+                },
+                _,
+            ) => {
+                let count = (rooms.len() + matches.len()).max(1);
+                let request = match rooms.get(*selected) {
+                    Some((room, ..)) => Some(Message::Enter(*room)),
+                    None => matches
+                        .get(*selected - rooms.len())
+                        .map(|room| Message::Watch(*room)),
+                };
+                match key {
+                    VirtualKeyCode::Up => *selected = (*selected + count - 1) % count,
+                    VirtualKeyCode::Down => *selected = (*selected + 1) % count,
+                    VirtualKeyCode::Return => {
+                        if let Some(request) = request {
+                            self.send(&request);
+                        }
+                    }
+                    VirtualKeyCode::Q => self.send(&Message::Quick),
+                    VirtualKeyCode::C => self.send(&Message::Create),
+                    VirtualKeyCode::Escape => self.disconnect(),
+                    _ => {}
+                }
+            }
+            (Status::Room { ready, .. }, VirtualKeyCode::Return) if !*ready => {
+                *ready = true;
+                self.send(&Message::Ready);
+            }
+            (Status::Room { .. }, VirtualKeyCode::Escape) => {
+                self.send(&Message::Leave);
+                self.enter_lobby();
+            }
+            (Status::Hosting(_) | Status::Accepted | Status::Joining, VirtualKeyCode::Escape) => {
+                self.disconnect()
+            }
+            _ => {}
+        }
+        false
+    }
+
+    /// Returns the frames this player's inputs are held back for in matches.
+    pub fn input_delay(&self) -> u32 {
+        self.input_delay
+    }
+
+    /// Returns `true` while waiting for a player to join or for a host or lobby to
+    /// answer.
+    fn is_connecting(&self) -> bool {
+        matches!(
+            self.status,
+            Status::Hosting(_) | Status::Accepted | Status::Joining
+        )
+    }
+
+    /// Returns `true` while in a lobby room, where players can chat.
+    pub fn in_room(&self) -> bool {
+        matches!(self.status, Status::Room { .. })
+    }
+
+    /// Sends a line of chat to the other player in the room.
+    ///
+    /// # Parameters
+    ///
+    /// - `text`: The line.
+    pub fn send_chat(&mut self, text: &str) {
+        self.send(&Message::Chat(text.to_string()));
+    }
+
+    /// Takes the chat received from the other player since the last call.
+    pub fn take_chat(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.chat)
+    }
+
+    /// Queues a message to the host or lobby, if connected.
+    fn send(&mut self, message: &Message) {
+        if let Some(connection) = &mut self.connection {
+            connection.send(message);
+        }
+    }
+
+    /// Stops hosting or drops the connection, going back to typing an address.
+    fn disconnect(&mut self) {
+        self.connection = None;
+        self.status = Status::Idle;
+    }
+
+    /// Shows the lobby's rooms, asking for them straight away.
+    fn enter_lobby(&mut self) {
+        self.status = Status::Lobby {
+            rooms: Vec::new(),
+            listing: Vec::new(),
+            matches: Vec::new(),
+            listing_matches: Vec::new(),
+            selected: 0,
+            listed_at: Instant::now(),
+        };
+        self.send(&Message::List);
+    }
+
+    /// Checks whether a match can start, without waiting. A host picks the seed and
+    /// sends it as soon as a player's handshake arrives, and a lobby once both players
+    /// in a room are ready, or straight away for a spectator.
+    ///
+    /// # Return Value
+    ///
+    /// The match against the other player or being watched, once it has started.
+    pub fn poll(&mut self) -> Option<Started> {
+        if let Status::Hosting(host) = &self.status {
+            match host.accept() {
+                Ok(Some(connection)) => {
+                    self.connection = Some(connection);
+                    self.status = Status::Accepted;
+                }
+                Ok(None) => return None,
+                Err(e) => {
+                    self.status = Status::Failed(format!("{:#}", e));
+                    return None;
+                }
+            }
+        }
+        if let Status::Lobby { listed_at, .. } = &mut self.status {
+            if listed_at.elapsed() >= LIST_INTERVAL {
+                *listed_at = Instant::now();
+                self.send(&Message::List);
+            }
+        }
+        let messages = match self.connection.as_mut()?.receive() {
+            Ok(messages) => messages,
+            Err(e) => {
+                self.connection = None;
+                self.status = Status::Failed(format!("{:#}", e));
+                return None;
+            }
+        };
+        let greeted = self.connection.as_ref().is_some_and(Connection::is_greeted);
+        if let (Status::Accepted, true) = (&self.status, greeted) {
+            let seed = rand::random();
+            self.send(&Message::Start { seed });
+            return self.start(seed, messages).map(Started::Match);
+        }
+        let mut messages = messages.into_iter();
+        while let Some(message) = messages.next() {
+            match message {
+                Message::Start { seed } => {
+                    // The other player may have started playing before the seed was read
+                    return self.start(seed, messages.collect()).map(Started::Match);
+                }
+                Message::Watching { room, seed } => {
+                    let connection = self.connection.take()?;
+                    self.status = Status::Idle;
+                    let mut spectate = Spectate::new(connection, room, seed);
+                    spectate.receive(messages.collect());
+                    return Some(Started::Spectate(Box::new(spectate)));
+                }
+                message => self.handle(message),
+            }
+        }
+        None
+    }
+
+    /// Handles a message from the host or lobby before the match starts.
+    fn handle(&mut self, message: Message) {
+        match (&mut self.status, message) {
+            (_, Message::Lobby) => {
+                self.send(&Message::Profile(self.player_id));
+                self.enter_lobby();
+            }
+            (
+                Status::Lobby { listing, .. },
+                Message::Room {
+                    room,
+                    players,
+                    rating,
+                },
+            ) => listing.push((room, players, rating)),
+            (
+                Status::Lobby {
+                    listing_matches, ..
+                },
+                Message::Playing(room),
+            ) => listing_matches.push(room),
+            (_, Message::Rating(rating)) => self.rating = Some(rating),
+            (
+                Status::Lobby {
+                    rooms,
+                    listing,
+                    matches,
+                    listing_matches,
+                    selected,
+                    ..
+                },
+                Message::Listed,
+            ) => {
+                *rooms = std::mem::take(listing);
+                *matches = std::mem::take(listing_matches);
+                let count = rooms.len() + matches.len();
+                *selected = (*selected).min(count.saturating_sub(1));
+            }
+            (_, Message::Entered { room, token }) => {
+                self.status = Status::Room {
+                    room,
+                    token,
+                    ready: false,
+                    opponent: None,
+                }
+            }
+            (Status::Room { opponent, .. }, Message::Joined) => *opponent = Some(false),
+            (Status::Room { opponent, .. }, Message::Ready) => *opponent = Some(true),
+            (
+                Status::Room {
+                    opponent, ready, ..
+                },
+                Message::Leave,
+            ) => {
+                *opponent = None;
+                *ready = false;
+            }
+            (_, Message::Refused(reason)) => self.notice = Some(reason),
+            (_, Message::Chat(text)) => self.chat.push(text),
+            _ => {}
+        }
+    }
+
+    /// Starts the match, leaving the screen ready to set up another.
+    ///
+    /// # Parameters
+    ///
+    /// - `seed`: Seed both games deal shapes from.
+    /// - `later`: Messages received after the seed, already part of the match.
+    fn start(&mut self, seed: u64, later: Vec<Message>) -> Option<Match> {
+        let connection = self.connection.take()?;
+        let status = std::mem::replace(&mut self.status, Status::Idle);
+        let host = matches!(status, Status::Accepted);
+        let rejoin = match status {
+            Status::Room { room, token, .. } => Some(Rejoin {
+                address: self.address.clone(),
+                room,
+                token,
+            }),
+            _ => None,
+        };
+        match Match::new(connection, seed, host, rejoin, self.input_delay) {
+            Ok(mut online) => {
+                online.chat = self.take_chat();
+                online.receive(later);
+                Some(online)
+            }
+            Err(e) => {
+                self.status = Status::Failed(format!("{:#}", e));
+                None
+            }
+        }
+    }
+
+    /// Returns the title of the online screen.
+    pub fn title(&self) -> &str {
+        match self.status {
+            Status::Lobby { .. } => "Lobby",
+            Status::Room { .. } => "Room",
+            _ => "Play Online",
+        }
+    }
+
+    /// Returns the lines of the online screen, with headings and the selected room
+    /// highlighted.
+    pub fn lines(&self) -> Vec<(String, bool)> {
+        let mut lines = match &self.status {
+            Status::Lobby {
+                rooms,
+                matches,
+                selected,
+                ..
+            } => {
+                let mut lines = match self.rating {
+                    Some(rating) => vec![(format!("Your rating: {}", rating), false)],
+                    None => Vec::new(),
+                };
+                if rooms.is_empty() {
+                    lines.push((String::from("No rooms are waiting for players"), false));
+                }
+                for (i, (room, players, rating)) in rooms.iter().enumerate() {
+                    let plural = if *players == 1 { "" } else { "s" };
+                    let line = format!(
+                        "Room {}: {} player{}, rated {}",
+                        room, players, plural, rating
+                    );
+                    lines.push((line, i == *selected));
+                }
+                for (i, room) in matches.iter().enumerate() {
+                    let line = format!("Room {}: match in progress, watch it", room);
+                    lines.push((line, rooms.len() + i == *selected));
+                }
+                lines
+            }
+            Status::Room {
+                room,
+                ready,
+                opponent,
+                ..
+            } => {
+                let you = if *ready { "Ready" } else { "Not ready" };
+                let opponent = match opponent {
+                    None => "Waiting for a player to join...",
+                    Some(false) => "Not ready",
+                    Some(true) => "Ready",
+                };
+                vec![
+                    (format!("Room {}", room), true),
+                    (format!("You: {}", you), false),
+                    (format!("Opponent: {}", opponent), false),
+                ]
+            }
+            status => {
+                let status = match status {
+                    Status::Hosting(host) => format!(
+                        "Waiting for a player to join on port {} (browsers: {})...",
+                        host.port(),
+                        host.web_port()
+                    ),
+                    Status::Accepted => String::from("A player joined, checking their game..."),
+                    Status::Joining => String::from("Connected, waiting for the game..."),
+                    Status::Failed(reason) => reason.clone(),
+                    _ => String::from("Type the address of a player hosting, or of a lobby"),
+                };
+                vec![
+                    (format!("Address: {}_", self.address), true),
+                    (status, false),
+                ]
+            }
+        };
+        if !self.is_connecting() {
+            let delay = match self.input_delay {
+                0 => String::from("Off"),
+                1 => String::from("1 frame"),
+                frames => format!("{} frames", frames),
+            };
+            lines.push((format!("Input delay: < {} >", delay), false));
+        }
+        if let Some(notice) = &self.notice {
+            lines.push((notice.clone(), false));
+        }
+        lines
+    }
+
+    /// Returns the keys which work on the online screen right now.
+    pub fn help(&self) -> &str {
+        match self.status {
+            Status::Lobby { .. } => {
+                "Up/Down: Select  Enter: Join/Watch  Q: Quick match  C: Create room  Left/Right: Input delay  Escape: Back"
+            }
+            Status::Room { .. } => "Enter: Ready  Tab: Chat  Left/Right: Input delay  Escape: Leave the room",
+            Status::Hosting(_) | Status::Accepted | Status::Joining => "Escape: Cancel",
+            _ => "Enter: Join  Tab: Host  Left/Right: Input delay  Escape: Back",
+        }
+    }
+}
+
+/// What the online screen has set up.
+pub enum Started {
+    /// A match against another player.
+    Match(Match),
+    /// A lobby match being watched. Boxed, as it holds two whole games.
+    Spectate(Box<Spectate>),
+}
+
+/// How an online match ended, from this player's side.
+pub enum Outcome {
+    Won,
+    Lost,
+    /// Both players topped out at the same moment.
+    Draw,
+    /// The connection failed before the match was decided, with the reason.
+    Disconnected(String),
+}
+
+/// What a player needs to get their place in a lobby match back after their
+/// connection drops.
+pub struct Rejoin {
+    address: String,
+    room: u32,
+    token: u64,
+}
+
+/// An online match in progress, with the other player's game simulated from the
+/// inputs they send.
+///
+/// Each side adds the garbage the other sends to its own game, which records it as an
+/// input, so both players' games stay the same on both ends.
+///
+/// Once it's decided, both players can ask for a rematch over the same connection.
+/// When both have, the host of a direct match or the lobby deals a new seed, and the
+/// match starts again from it.
+pub struct Match {
+    connection: Connection,
+    /// Whether this player hosted a direct match, so deals the seed of rematches.
+    host: bool,
+    /// The other player's game, played up to the latest time they sent.
+    pub remote: Playback,
+    /// Garbage this player sends, counted from their game's events. Online matches
+    /// always use the classic table, as both ends have to count the same garbage.
+    pub attack: Attack,
+    /// Garbage the other player sends, counted from `remote`'s events.
+    remote_attack: Attack,
+    /// Game time the other player's game has reached, with every input up to it
+    /// received.
+    remote_time: u64,
+    /// Holds this player's inputs back, if they picked an input delay.
+    pub input_delay: InputDelay,
+    /// Game time this player's game was last hashed at for the other player.
+    hashed_ms: u64,
+    /// Hashes of the other player's game and the times they were taken at, oldest
+    /// first, until `remote` reaches them.
+    checks: VecDeque<(u64, u64)>,
+    /// Rows of the other player's garbage added to this player's game so far.
+    received: usize,
+    /// Number of this player's inputs sent so far.
+    sent_inputs: usize,
+    /// How the match ended, once it is decided.
+    pub outcome: Option<Outcome>,
+    /// How to rejoin, for matches found in a lobby.
+    rejoin: Option<Rejoin>,
+    /// When the connection dropped and when rejoining was last tried, while
+    /// rejoining.
+    lost: Option<(Instant, Instant)>,
+    /// Whether the other player's connection dropped and they may rejoin.
+    pub opponent_away: bool,
+    /// Why the other player can't carry on, if they left or the lobby wouldn't let this
+    /// player rejoin.
+    ended: Option<String>,
+    /// Chat received from the other player, not yet shown.
+    chat: Vec<String>,
+    /// This player's rating after the match, once a lobby has decided it.
+    pub rating: Option<u32>,
+    /// Whether this player asked for a rematch.
+    asked_rematch: bool,
+    /// Whether the other player asked for a rematch.
+    opponent_rematch: bool,
+    /// Whether a rematch started, until this player's game is started again.
+    rematch_started: bool,
+}
+
+impl Match {
+    /// Creates a new `Match` at the start of both games.
+    ///
+    /// # Parameters
+    ///
+    /// - `connection`: The connection to the other player or lobby.
+    /// - `seed`: Seed both games deal shapes from.
+    /// - `host`: `true` if this player hosted a direct match.
+    /// - `rejoin`: How to rejoin if the connection drops, for matches found in a lobby.
+    /// - `input_delay`: Frames this player's inputs are held back for, `0` for none.
+    pub fn new(
+        connection: Connection,
+        seed: u64,
+        host: bool,
+        rejoin: Option<Rejoin>,
+        input_delay: u32,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            connection,
+            host,
+            remote: Playback::new(Replay::new(seed))?,
+            attack: Attack::new(AttackTable::classic(), 100),
+            remote_attack: Attack::new(AttackTable::classic(), 100),
+            remote_time: 0,
+            input_delay: InputDelay::new(input_delay),
+            hashed_ms: 0,
+            checks: VecDeque::new(),
+            received: 0,
+            sent_inputs: 0,
+            outcome: None,
+            rejoin,
+            lost: None,
+            opponent_away: false,
+            ended: None,
+            chat: Vec::new(),
+            rating: None,
+            asked_rematch: false,
+            opponent_rematch: false,
+            rematch_started: false,
+        })
+    }
+
+    /// Returns `true` while trying to rejoin the match after the connection dropped.
+    pub fn is_rejoining(&self) -> bool {
+        self.lost.is_some()
+    }
+
+    /// Sends a line of chat to the other player.
+    ///
+    /// # Parameters
+    ///
+    /// - `text`: The line.
+    pub fn send_chat(&mut self, text: &str) {
+        self.connection.send(&Message::Chat(text.to_string()));
+    }
+
+    /// Takes the chat received from the other player since the last call.
+    pub fn take_chat(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.chat)
+    }
+
+    /// Passes the other player's new events to their totals, and returns the rows of
+    /// garbage they sent since the last call, for this player's game to receive.
+    pub fn take_garbage(&mut self) -> usize {
+        self.remote.dispatch_events(&mut [&mut self.remote_attack]);
+        self.remote_attack.take();
+        // After rejoining, their game is played again from the start, so garbage
+        // already received isn't sent twice
+        let rows = self.remote_attack.sent().saturating_sub(self.received);
+        self.received += rows;
+        rows
+    }
+
+    /// Returns the rows of garbage the other player has sent.
+    pub fn received(&self) -> usize {
+        self.received
+    }
+
+    /// Asks the other player for a rematch, once the match is decided and they're
+    /// still there.
+    pub fn ask_rematch(&mut self) {
+        let decided = matches!(
+            self.outcome,
+            Some(Outcome::Won) | Some(Outcome::Lost) | Some(Outcome::Draw)
+        );
+        if !decided || self.ended.is_some() || self.asked_rematch {
+            return;
+        }
+        self.asked_rematch = true;
+        self.connection.send(&Message::Rematch);
+        self.deal_rematch();
+    }
+
+    /// Returns how asking for a rematch is going, to show after the match.
+    pub fn rematch_status(&self) -> Option<&str> {
+        if let Some(reason) = &self.ended {
+            return Some(reason);
+        }
+        match (self.asked_rematch, self.opponent_rematch) {
+            (true, _) => Some("Waiting for the other player to accept the rematch"),
+            (false, true) => Some("The other player wants a rematch"),
+            (false, false) => None,
+        }
+    }
+
+    /// Returns `true` once after a rematch has started, so this player's game can be
+    /// started again from the new seed.
+    pub fn take_rematch(&mut self) -> bool {
+        std::mem::take(&mut self.rematch_started)
+    }
+
+    /// Starts a rematch with a new seed, if this player hosts and both players have
+    /// asked for one.
+    fn deal_rematch(&mut self) {
+        if self.host && self.asked_rematch && self.opponent_rematch {
+            let seed = rand::random();
+            self.connection.send(&Message::Start { seed });
+            self.start_rematch(seed);
+        }
+    }
+
+    /// Starts the other player's game again from a new seed.
+    ///
+    /// # Parameters
+    ///
+    /// - `seed`: Seed both games deal shapes from in the rematch.
+    fn start_rematch(&mut self, seed: u64) {
+        // A new game without a board set up can't fail to start
+        if let Ok(remote) = Playback::new(Replay::new(seed)) {
+            self.remote = remote;
+        }
+        self.attack = Attack::new(AttackTable::classic(), 100);
+        self.remote_attack = Attack::new(AttackTable::classic(), 100);
+        self.remote_time = 0;
+        self.input_delay = InputDelay::new(self.input_delay.frames());
+        self.hashed_ms = 0;
+        self.checks.clear();
+        self.received = 0;
+        self.sent_inputs = 0;
+        self.outcome = None;
+        self.rating = None;
+        self.asked_rematch = false;
+        self.opponent_rematch = false;
+        self.rematch_started = true;
+    }
+
+    /// Sends this player's new inputs, plays the other player's game up to the latest
+    /// inputs received, checking it against the hashes they send, and decides the
+    /// match once either player has topped out. Once the match is decided, only chat
+    /// and rematch requests are sent and received.
+    ///
+    /// # Parameters
+    ///
+    /// - `game`: This player's game.
+    pub fn update(&mut self, game: &Game) {
+        if self.outcome.is_some() {
+            if let Ok(messages) = self.connection.receive() {
+                self.receive(messages);
+            }
+            return;
+        }
+        let inputs = &game.replay().inputs;
+        for (time_ms, input) in &inputs[self.sent_inputs..] {
+            self.connection
+                .send(&Message::Input(*time_ms, input.clone()));
+        }
+        self.sent_inputs = inputs.len();
+        if game.time_ms() >= self.hashed_ms + HASH_INTERVAL_FRAMES * 1000 / FRAMES_PER_SECOND {
+            self.hashed_ms = game.time_ms();
+            self.connection
+                .send(&Message::Hash(game.time_ms(), game.state_hash()));
+        }
+        self.connection.send(&Message::Time(game.time_ms()));
+        match self.connection.receive() {
+            Ok(messages) => self.receive(messages),
+            Err(e) if !self.try_rejoin() => {
+                self.outcome = Some(Outcome::Disconnected(format!("{:#}", e)));
+                return;
+            }
+            Err(_) => {}
+        }
+        // Once this player has topped out, the rest of the other player's game decides
+        // the match, so it's no longer held back
+        let shown_ms = if game.game_over {
+            self.remote_time
+        } else {
+            self.input_delay.shown_ms(game.time_ms(), self.remote_time)
+        };
+        while let Some(&(time_ms, hash)) = self.checks.front() {
+            if time_ms > shown_ms {
+                break;
+            }
+            self.checks.pop_front();
+            self.remote.extend_to(time_ms);
+            let remote = self.remote.game();
+            if remote.time_ms() == time_ms && remote.state_hash() != hash {
+                eprintln!(
+                    "The other player's game differs from theirs at {} ms",
+                    time_ms
+                );
+                self.outcome = Some(Outcome::Disconnected(String::from(
+                    "The two games went out of step, so the match can't be decided",
+                )));
+                return;
+            }
+        }
+        self.remote.extend_to(shown_ms);
+        self.outcome = self.decide(game);
+        if self.outcome.is_none() && !self.remote.game().game_over {
+            if let Some(reason) = &self.ended {
+                self.outcome = Some(Outcome::Disconnected(reason.clone()));
+            }
+        }
+    }
+
+    /// Tries to get this player's place in the match back after the connection
+    /// dropped, at most once per `REJOIN_INTERVAL`.
+    ///
+    /// # Return Value
+    ///
+    /// `false` once it's time to give up, or if the match can't be rejoined.
+    fn try_rejoin(&mut self) -> bool {
+        let rejoin = match &self.rejoin {
+            Some(rejoin) => rejoin,
+            None => return false,
+        };
+        let now = Instant::now();
+        let (since, attempted) = self.lost.get_or_insert((now, now - REJOIN_INTERVAL));
+        if since.elapsed() >= REJOIN_TIMEOUT {
+            return false;
+        }
+        if attempted.elapsed() < REJOIN_INTERVAL {
+            return true;
+        }
+        *attempted = now;
+        if let Ok(mut connection) = Connection::join(&rejoin.address) {
+            connection.send(&Message::Rejoin {
+                room: rejoin.room,
+                token: rejoin.token,
+            });
+            self.connection = connection;
+        }
+        true
+    }
+
+    /// Plays the other player's game on with messages they sent, and handles news
+    /// from the lobby.
+    ///
+    /// # Parameters
+    ///
+    /// - `messages`: The messages, oldest first.
+    fn receive(&mut self, messages: Vec<Message>) {
+        for message in messages {
+            match message {
+                Message::Input(time_ms, input) => self.remote.push_input(time_ms, input),
+                Message::Time(time_ms) => self.remote_time = self.remote_time.max(time_ms),
+                Message::Hash(time_ms, hash) => self.checks.push_back((time_ms, hash)),
+                Message::Rejoined(inputs) => {
+                    // The other player's whole game is sent again after this
+                    let seed = self.remote.game().replay().seed;
+                    if let Ok(remote) = Playback::new(Replay::new(seed)) {
+                        self.remote = remote;
+                        self.remote_attack = Attack::new(AttackTable::classic(), 100);
+                        self.remote_time = 0;
+                        self.checks.clear();
+                    }
+                    self.sent_inputs = inputs;
+                    self.lost = None;
+                }
+                Message::Away => self.opponent_away = true,
+                Message::Back => self.opponent_away = false,
+                Message::Leave => self.ended = Some(String::from("The other player left")),
+                Message::Refused(reason) => self.ended = Some(reason),
+                Message::Chat(text) => self.chat.push(text),
+                Message::Rating(rating) => self.rating = Some(rating),
+                Message::Rematch => {
+                    self.opponent_rematch = true;
+                    self.deal_rematch();
+                }
+                Message::Start { seed } if self.asked_rematch => self.start_rematch(seed),
+                _ => {}
+            }
+        }
+    }
+
+    /// Works out who won, once it is certain, see `netplay::decide`.
+    fn decide(&self, game: &Game) -> Option<Outcome> {
+        Some(match netplay::decide(game, self.remote.game())? {
+            MatchResult::Won => Outcome::Won,
+            MatchResult::Lost => Outcome::Lost,
+            MatchResult::Draw => Outcome::Draw,
+        })
+    }
+}
+
+impl Drop for Match {
+    /// Tells the other player or lobby this player is leaving, so a lobby can close
+    /// the room straight away instead of waiting for them to rejoin.
+    fn drop(&mut self) {
+        self.connection.send(&Message::Leave);
+        let _ = self.connection.receive();
+    }
+}
+
+/// A lobby match being watched, a few seconds behind its players, with both games
+/// simulated from the inputs the lobby passes on.
+pub struct Spectate {
+    connection: Connection,
+    room: u32,
+    /// Each seat's game, played up to the latest time its player sent.
+    pub games: Vec<Playback>,
+    /// Why the match can't be watched any further, once it can't.
+    ended: Option<String>,
+}
+
+impl Spectate {
+    /// Creates a new `Spectate` at the start of both games.
+    ///
+    /// # Parameters
+    ///
+    /// - `connection`: The connection to the lobby.
+    /// - `room`: The room of the match.
+    /// - `seed`: Seed both games deal shapes from.
+    pub fn new(connection: Connection, room: u32, seed: u64) -> Self {
+        Self {
+            connection,
+            room,
+            // A new game without a board set up can't fail to start
+            games: (0..2)
+                .filter_map(|_| Playback::new(Replay::new(seed)).ok())
+                .collect(),
+            ended: None,
+        }
+    }
+
+    /// Plays both games up to the latest inputs received.
+    pub fn update(&mut self) {
+        if self.ended.is_some() {
+            return;
+        }
+        match self.connection.receive() {
+            Ok(messages) => self.receive(messages),
+            Err(e) => self.ended = Some(format!("{:#}", e)),
+        }
+        for game in &mut self.games {
+            game.dispatch_events(&mut []);
+        }
+    }
+
+    /// Plays the games on with messages the lobby passed on.
+    ///
+    /// # Parameters
+    ///
+    /// - `messages`: The messages, oldest first.
+    fn receive(&mut self, messages: Vec<Message>) {
+        for message in messages {
+            match message {
+                Message::Player(seat, message) => {
+                    let game = match self.games.get_mut(seat) {
+                        Some(game) => game,
+                        None => continue,
+                    };
+                    match *message {
+                        Message::Input(time_ms, input) => game.push_input(time_ms, input),
+                        Message::Time(time_ms) => game.extend_to(time_ms),
+                        _ => {}
+                    }
+                }
+                Message::Leave => self.ended = Some(String::from("The match is over")),
+                Message::Refused(reason) => self.ended = Some(reason),
+                _ => {}
+            }
+        }
+    }
+
+    /// Returns a line saying who won, once it is certain, or how watching is going.
+    pub fn status(&self) -> String {
+        if let [first, second] = self.games.as_slice() {
+            match netplay::decide(first.game(), second.game()) {
+                Some(MatchResult::Won) => return String::from("Player 1 wins!"),
+                Some(MatchResult::Lost) => return String::from("Player 2 wins!"),
+                Some(MatchResult::Draw) => return String::from("Draw!"),
+                None => {}
+            }
+        }
+        match &self.ended {
+            Some(reason) => reason.clone(),
+            None => format!("Watching room {}, a few seconds behind", self.room),
+        }
+    }
+}
+
+impl Drop for Spectate {
+    /// Tells the lobby to stop sending the match.
+    fn drop(&mut self) {
+        self.connection.send(&Message::Leave);
+        let _ = self.connection.receive();
+    }
+}
@@ -0,0 +1,270 @@
+//! Alternative block colours, and symbols stamped on each kind of block, for players
+//! who find the classic colours hard to tell apart, e.g. the red Z and green S.
+//!
+//! Blocks are drawn from an atlas built out of the block image: a board's worth of
+//! plain blocks for the background to tile, then a column with one stamped block for
+//! each kind of shape, and a column of the same blocks with thick outlines for high
+//! contrast. `Skin::apply` points the vertices of `Game::render` into it.
+
+use crate::game::GHOST_SHAPE_VERTICES;
+use crate::tetromino::{Tetromino, TetrominoKind};
+use crate::{Vertex, GAMEBOARD_HEIGHT, GAMEBOARD_WIDTH};
+
+use serde::{Deserialize, Serialize};
+
+/// Width of the block atlas in blocks: the background, then the stamped blocks, then
+/// the outlined blocks.
+pub const ATLAS_COLUMNS: usize = GAMEBOARD_WIDTH + 2;
+/// Height of the block atlas in blocks.
+pub const ATLAS_ROWS: usize = GAMEBOARD_HEIGHT;
+/// Column of the atlas with a block stamped with each kind's symbol, in the order of
+/// `TetrominoKind::ALL`, then a block of solid white.
+pub const SYMBOL_COLUMN: usize = GAMEBOARD_WIDTH;
+/// Column of the atlas with the blocks of `SYMBOL_COLUMN` outlined, then a plain
+/// outlined block.
+pub const OUTLINE_COLUMN: usize = GAMEBOARD_WIDTH + 1;
+/// Row of the atlas after the symbols, with a solid block and a plain outlined block.
+pub const PLAIN_ROW: usize = TetrominoKind::ALL.len();
+/// How much a symbol darkens the pixels of the block it is stamped on.
+const STAMP_SHADE: f32 = 0.4;
+/// Width of the outline of high contrast blocks, as a fraction of a block.
+const OUTLINE_WIDTH: f32 = 0.125;
+/// Opacity of the ghost of the current shape with high contrast, solid enough for its
+/// outlines to stand out on the black background.
+const HIGH_CONTRAST_GHOST_SHAPE_ALPHA: f32 = 0.6;
+
+/// Which colours shapes are drawn in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Palette {
+    /// The colours each shape is created with.
+    Classic,
+    /// The Okabe-Ito colours, which stay distinct with every common kind of colour
+    /// blindness.
+    Colorblind,
+    /// Shades of grey from light to dark, best used with symbols.
+    Monochrome,
+}
+
+impl Palette {
+    /// Every palette, in the order they are listed.
+    pub const ALL: [Palette; 3] = [Palette::Classic, Palette::Colorblind, Palette::Monochrome];
+
+    /// Name shown in menus.
+    pub fn label(self) -> &'static str {
+        match self {
+            Palette::Classic => "Classic",
+            Palette::Colorblind => "Colorblind Safe",
+            Palette::Monochrome => "Monochrome",
+        }
+    }
+
+    /// Returns the colour a kind of shape is drawn in.
+    ///
+    /// # Parameters
+    ///
+    /// - `kind`: The kind of shape.
+    pub fn color(self, kind: TetrominoKind) -> [f32; 4] {
+        use TetrominoKind::*;
+        let rgb = |r: u8, g: u8, b: u8| [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, 1.0];
+        match self {
+            Palette::Classic => Tetromino::new(kind).color(),
+            Palette::Colorblind => match kind {
+                O => rgb(240, 228, 66),
+                I => rgb(86, 180, 233),
+                S => rgb(0, 158, 115),
+                Z => rgb(213, 94, 0),
+                L => rgb(230, 159, 0),
+                J => rgb(0, 114, 178),
+                T => rgb(204, 121, 167),
+            },
+            Palette::Monochrome => {
+                let shade = match kind {
+                    O => 1.0,
+                    I => 0.94,
+                    S => 0.88,
+                    Z => 0.82,
+                    L => 0.76,
+                    J => 0.7,
+                    T => 0.64,
+                };
+                [shade, shade, shade, 1.0]
+            }
+        }
+    }
+}
+
+/// Returns the colour a kind of shape is drawn in with high contrast, each bright
+/// against the black background and far from the others.
+///
+/// # Parameters
+///
+/// - `kind`: The kind of shape.
+fn high_contrast_color(kind: TetrominoKind) -> [f32; 4] {
+    use TetrominoKind::*;
+    match kind {
+        O => [1.0, 1.0, 0.0, 1.0],
+        I => [0.0, 1.0, 1.0, 1.0],
+        S => [0.2, 1.0, 0.2, 1.0],
+        Z => [1.0, 0.2, 0.2, 1.0],
+        L => [1.0, 0.6, 0.0, 1.0],
+        J => [0.5, 0.6, 1.0, 1.0],
+        T => [1.0, 0.4, 1.0, 1.0],
+    }
+}
+
+/// How blocks look: their colours, and whether each kind has its own symbol.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Skin {
+    pub palette: Palette,
+    /// Stamp a symbol on each block showing which kind of shape it came from.
+    pub patterns: bool,
+    /// Draw bright blocks with thick outlines on a solid black background, whatever
+    /// the palette.
+    pub high_contrast: bool,
+}
+
+impl Skin {
+    /// Recolours the blocks of one game, and points every vertex at its block in the
+    /// atlas. Garbage and other blocks not of a shape's colour keep their colour and
+    /// have no symbol. High contrast also makes the ghost of the current shape more
+    /// solid.
+    ///
+    /// # Parameters
+    ///
+    /// - `buf`: One game's vertices, as filled by `Game::render`.
+    pub fn apply(&self, buf: &mut [Vertex]) {
+        if self.high_contrast {
+            for vertex in &mut buf[GHOST_SHAPE_VERTICES] {
+                vertex.color[3] = HIGH_CONTRAST_GHOST_SHAPE_ALPHA;
+            }
+        }
+        let (background, blocks) = buf.split_at_mut(6);
+        for vertex in background {
+            if self.high_contrast {
+                vertex.color = [0.0, 0.0, 0.0, 1.0];
+                vertex.tex_coords = solid_tex_coords();
+            } else {
+                vertex.tex_coords = atlas_coords(vertex.tex_coords, 0, 0);
+            }
+        }
+        let classic = TetrominoKind::ALL.map(|kind| Palette::Classic.color(kind));
+        for quad in blocks.chunks_mut(6) {
+            let kind = classic
+                .iter()
+                .position(|color| color[..3] == quad[0].color[..3]);
+            let (column, row) = match (kind, self.high_contrast) {
+                (Some(i), true) if self.patterns => (OUTLINE_COLUMN, i),
+                (_, true) => (OUTLINE_COLUMN, PLAIN_ROW),
+                (Some(i), false) if self.patterns => (SYMBOL_COLUMN, i),
+                (_, false) => (0, 0),
+            };
+            for vertex in quad {
+                if let Some(i) = kind {
+                    let kind = TetrominoKind::ALL[i];
+                    let color = if self.high_contrast {
+                        high_contrast_color(kind)
+                    } else {
+                        self.palette.color(kind)
+                    };
+                    vertex.color = [color[0], color[1], color[2], vertex.color[3]];
+                }
+                vertex.tex_coords = atlas_coords(vertex.tex_coords, column, row);
+            }
+        }
+    }
+}
+
+/// Returns texture coordinates in the middle of the atlas's solid white block, for
+/// every corner of a quad drawn in a flat colour.
+pub fn solid_tex_coords() -> [f32; 2] {
+    atlas_coords([0.5, 0.5], SYMBOL_COLUMN, PLAIN_ROW)
+}
+
+/// Returns texture coordinates in the atlas, from coordinates in blocks.
+///
+/// # Parameters
+///
+/// - `tex_coords`: Coordinates in blocks from the corner of the first block.
+/// - `column`, `row`: The first block, in blocks from the top left of the atlas.
+fn atlas_coords(tex_coords: [f32; 2], column: usize, row: usize) -> [f32; 2] {
+    [
+        (column as f32 + tex_coords[0]) / ATLAS_COLUMNS as f32,
+        (row as f32 + tex_coords[1]) / ATLAS_ROWS as f32,
+    ]
+}
+
+/// Returns `true` where the symbol of a kind of shape covers a block. Each symbol is
+/// the shape's letter, apart from S and Z, which get stripes slanted the way they are.
+///
+/// # Parameters
+///
+/// - `kind`: The kind of shape.
+/// - `x`, `y`: Position on the block, from `-1.0` at the top left to `1.0` at the
+///   bottom right.
+pub fn symbol_covers(kind: TetrominoKind, x: f32, y: f32) -> bool {
+    use TetrominoKind::*;
+    let inside = x.abs() < 0.6 && y.abs() < 0.6;
+    let stroke = 0.15;
+    match kind {
+        O => (0.3..0.55).contains(&(x * x + y * y).sqrt()),
+        I => x.abs() < stroke && y.abs() < 0.55,
+        S => inside && ((x + y) * 1.5).rem_euclid(1.0) < 0.4,
+        Z => inside && ((x - y) * 1.5).rem_euclid(1.0) < 0.4,
+        L => {
+            ((x + 0.3).abs() < stroke && y.abs() < 0.55)
+                || ((0.25..0.55).contains(&y) && (-0.45..0.45).contains(&x))
+        }
+        J => {
+            ((x - 0.3).abs() < stroke && y.abs() < 0.55)
+                || ((0.25..0.55).contains(&y) && (-0.45..0.45).contains(&x))
+        }
+        T => ((-0.55..-0.25).contains(&y) && x.abs() < 0.5) || (x.abs() < stroke && y.abs() < 0.55),
+    }
+}
+
+/// Lays out the block atlas from the image of one block.
+///
+/// # Parameters
+///
+/// - `block`: Pixels of the block image in 8-bit RGBA, row by row with no padding.
+/// - `width`, `height`: Size of the block image in pixels.
+///
+/// # Return Value
+///
+/// The atlas in the same format, along with its width and height in pixels.
+pub fn block_atlas(block: &[u8], width: u32, height: u32) -> (Vec<u8>, u32, u32) {
+    let (atlas_width, atlas_height) = (width * ATLAS_COLUMNS as u32, height * ATLAS_ROWS as u32);
+    let mut atlas = Vec::with_capacity(4 * atlas_width as usize * atlas_height as usize);
+    for y in 0..atlas_height {
+        for x in 0..atlas_width {
+            let (column, row) = ((x / width) as usize, (y / height) as usize);
+            let (bx, by) = (x % width, y % height);
+            let pixel = 4 * (by * width + bx) as usize;
+            let mut rgba = [
+                block[pixel],
+                block[pixel + 1],
+                block[pixel + 2],
+                block[pixel + 3],
+            ];
+            // Sample the symbol and outline at the middle of each pixel
+            let sx = (2 * bx + 1) as f32 / width as f32 - 1.0;
+            let sy = (2 * by + 1) as f32 / height as f32 - 1.0;
+            let stamped = column >= SYMBOL_COLUMN && row < PLAIN_ROW;
+            if stamped && symbol_covers(TetrominoKind::ALL[row], sx, sy) {
+                for channel in &mut rgba[..3] {
+                    *channel = (*channel as f32 * STAMP_SHADE).round() as u8;
+                }
+            }
+            if column == SYMBOL_COLUMN && row == PLAIN_ROW {
+                rgba = [255; 4];
+            }
+            let edge = 1.0 - 2.0 * OUTLINE_WIDTH;
+            if column == OUTLINE_COLUMN && (sx.abs() > edge || sy.abs() > edge) {
+                rgba = [0, 0, 0, 255];
+            }
+            atlas.extend_from_slice(&rgba);
+        }
+    }
+    (atlas, atlas_width, atlas_height)
+}
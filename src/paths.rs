@@ -0,0 +1,72 @@
+use directories::ProjectDirs;
+use std::fs::create_dir_all;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Directories files are read from and written to, chosen once at startup.
+struct Dirs {
+    /// Settings, e.g. `config.toml`.
+    config: PathBuf,
+    /// Everything the game records, e.g. replays and game history.
+    data: PathBuf,
+}
+
+static DIRS: OnceLock<Dirs> = OnceLock::new();
+
+/// Chooses where files are kept for the rest of the run. Must be called before any
+/// other function here, or the platform directories are used.
+///
+/// # Parameters
+///
+/// - `portable`: `true` to keep everything beside the executable instead of in the
+/// platform's config and data directories.
+pub fn init(portable: bool) {
+    let _ = DIRS.set(Dirs::new(portable));
+}
+
+impl Dirs {
+    fn new(portable: bool) -> Self {
+        if !portable {
+            // XDG directories on Linux, AppData on Windows and Library on macOS
+            if let Some(dirs) = ProjectDirs::from("", "", "wgputris") {
+                return Self {
+                    config: dirs.config_dir().to_path_buf(),
+                    data: dirs.data_dir().to_path_buf(),
+                };
+            }
+        }
+        let beside_exe = std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(Path::to_path_buf))
+            .unwrap_or_default();
+        Self {
+            config: beside_exe.clone(),
+            data: beside_exe,
+        }
+    }
+}
+
+fn dirs() -> &'static Dirs {
+    DIRS.get_or_init(|| Dirs::new(false))
+}
+
+/// Returns the path of a settings file, creating its directory if needed.
+///
+/// # Parameters
+///
+/// - `name`: File name, e.g. `config.toml`.
+pub fn config_file(name: &str) -> PathBuf {
+    let _ = create_dir_all(&dirs().config);
+    dirs().config.join(name)
+}
+
+/// Returns the path of a file or directory of recorded data, creating its parent
+/// directory if needed.
+///
+/// # Parameters
+///
+/// - `name`: File or directory name, e.g. `replays`.
+pub fn data_path(name: &str) -> PathBuf {
+    let _ = create_dir_all(&dirs().data);
+    dirs().data.join(name)
+}
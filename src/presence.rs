@@ -0,0 +1,128 @@
+#[cfg(feature = "discord")]
+use discord_rich_presence::{activity, DiscordIpc, DiscordIpcClient};
+#[cfg(feature = "discord")]
+use web_time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Discord application the status is shown under, set when the game is built. Without
+/// one there is nothing to show the status under, so it is never sent.
+#[cfg(feature = "discord")]
+const APPLICATION_ID: Option<&str> = option_env!("WGPUTRIS_DISCORD_APP_ID");
+/// Shortest time between two status updates for the same activity. Discord only
+/// takes one every 15 seconds, so the score shown lags behind a little.
+#[cfg(feature = "discord")]
+const UPDATE_SECS: f64 = 15.0;
+/// How long to wait before trying to reach Discord again after failing to.
+#[cfg(feature = "discord")]
+const RETRY_SECS: f64 = 30.0;
+
+/// What the player is doing, as shown in their Discord status.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Status {
+    /// The mode being played, or the screen the player is on.
+    pub details: String,
+    /// The score and lines of the game being played, if any.
+    pub state: Option<String>,
+}
+
+/// Shows what the player is doing in their Discord status. Does nothing unless the
+/// game is built with the `discord` feature.
+pub struct Presence {
+    #[cfg(feature = "discord")]
+    client: Option<DiscordIpcClient>,
+    /// The status last sent, when it was sent, and when its activity started, in
+    /// milliseconds since the Unix epoch.
+    #[cfg(feature = "discord")]
+    sent: Option<(Status, Instant, i64)>,
+    /// When connecting to Discord last failed.
+    #[cfg(feature = "discord")]
+    failed_at: Option<Instant>,
+}
+
+impl Presence {
+    /// Creates a new `Presence`, which connects to Discord once it has a status to show.
+    pub fn new() -> Self {
+        Self {
+            #[cfg(feature = "discord")]
+            client: None,
+            #[cfg(feature = "discord")]
+            sent: None,
+            #[cfg(feature = "discord")]
+            failed_at: None,
+        }
+    }
+
+    /// Shows a status, or clears it if the player turned their Discord status off.
+    ///
+    /// # Parameters
+    ///
+    /// - `enabled`: Whether the player allows their status to be shown.
+    /// - `status`: What the player is doing.
+    #[cfg(feature = "discord")]
+    pub fn update(&mut self, enabled: bool, status: Status) {
+        if !enabled {
+            if let Some(mut client) = self.client.take() {
+                let _ = client.clear_activity();
+                let _ = client.close();
+            }
+            self.sent = None;
+            return;
+        }
+        let started = match &self.sent {
+            Some((sent, _, _)) if *sent == status => return,
+            Some((sent, sent_at, started)) if sent.details == status.details => {
+                if sent_at.elapsed().as_secs_f64() < UPDATE_SECS {
+                    return;
+                }
+                *started
+            }
+            _ => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_millis() as i64),
+        };
+        let client = match self.connect() {
+            Some(client) => client,
+            None => return,
+        };
+        let mut activity = activity::Activity::new()
+            .details(status.details.as_str())
+            .timestamps(activity::Timestamps::new().start(started));
+        if let Some(state) = &status.state {
+            activity = activity.state(state.as_str());
+        }
+        if let Err(e) = client.set_activity(activity) {
+            eprintln!("Failed to update Discord status: {}", e);
+            self.client = None;
+            self.failed_at = Some(Instant::now());
+            return;
+        }
+        self.sent = Some((status, Instant::now(), started));
+    }
+
+    /// Does nothing, as the game was built without the `discord` feature.
+    #[cfg(not(feature = "discord"))]
+    pub fn update(&mut self, _enabled: bool, _status: Status) {}
+
+    /// Returns the connection to Discord, connecting if there is none and it hasn't
+    /// failed recently.
+    #[cfg(feature = "discord")]
+    fn connect(&mut self) -> Option<&mut DiscordIpcClient> {
+        if self.client.is_none() {
+            let application_id = APPLICATION_ID?;
+            if let Some(failed_at) = self.failed_at {
+                if failed_at.elapsed().as_secs_f64() < RETRY_SECS {
+                    return None;
+                }
+            }
+            let mut client = DiscordIpcClient::new(application_id);
+            // Discord not running is normal, so this isn't reported
+            if client.connect().is_err() {
+                self.failed_at = Some(Instant::now());
+                return None;
+            }
+            self.failed_at = None;
+            self.sent = None;
+            self.client = Some(client);
+        }
+        self.client.as_mut()
+    }
+}
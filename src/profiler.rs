@@ -0,0 +1,124 @@
+/// Times the rest of the enclosing block as a scope of the frame's profile. Does
+/// nothing unless the game is built with the `profile` feature.
+macro_rules! profile_scope {
+    ($name:expr) => {
+        #[cfg(feature = "profile")]
+        puffin::profile_scope!($name);
+    };
+}
+
+/// Width in characters of a scope's bar for each millisecond it took.
+#[cfg(feature = "profile")]
+const BAR_CHARS_PER_MS: f64 = 4.0;
+/// Longest bar drawn, so one slow scope doesn't push the rest off the screen.
+#[cfg(feature = "profile")]
+const MAX_BAR_CHARS: usize = 60;
+
+/// Collects how long each part of a frame took, and describes the last frame for the
+/// in-game overlay. Scopes are only recorded while the overlay is shown.
+pub struct Profiler {
+    shown: bool,
+    #[cfg(feature = "profile")]
+    view: puffin::GlobalFrameView,
+}
+
+impl Profiler {
+    /// Creates a new `Profiler`, with the overlay hidden.
+    pub fn new() -> Self {
+        Self {
+            shown: false,
+            #[cfg(feature = "profile")]
+            view: puffin::GlobalFrameView::default(),
+        }
+    }
+
+    /// Returns whether the overlay is shown.
+    pub fn shown(&self) -> bool {
+        self.shown
+    }
+
+    /// Shows or hides the overlay, starting or stopping the recording of scopes.
+    pub fn toggle(&mut self) {
+        self.shown = !self.shown;
+        #[cfg(feature = "profile")]
+        puffin::set_scopes_on(self.shown);
+    }
+
+    /// Ends the current frame, so the scopes recorded since the last call are shown.
+    pub fn end_frame(&self) {
+        #[cfg(feature = "profile")]
+        if self.shown {
+            puffin::GlobalProfiler::lock().new_frame();
+        }
+    }
+
+    /// Returns the overlay's text: one line for each scope of the last frame, indented
+    /// under the scope it ran in, with how long it took and a bar of that length.
+    #[cfg(feature = "profile")]
+    pub fn lines(&self) -> Vec<String> {
+        let view = self.view.lock();
+        let frame = match view.latest_frame().and_then(|frame| frame.unpacked().ok()) {
+            Some(frame) => frame,
+            None => return vec!["Waiting for a frame...".to_string()],
+        };
+        let mut lines = vec![format!(
+            "Frame {}: {:.2} ms",
+            frame.meta.frame_index,
+            frame.meta.range_ns.1.saturating_sub(frame.meta.range_ns.0) as f64 / 1e6
+        )];
+        for stream_info in frame.thread_streams.values() {
+            if let Ok(scopes) = puffin::Reader::from_start(&stream_info.stream).read_top_scopes() {
+                for scope in scopes {
+                    scope_lines(&view, &stream_info.stream, &scope, 0, &mut lines);
+                }
+            }
+        }
+        lines
+    }
+
+    /// Returns the overlay's text, which says how to build the game with profiling.
+    #[cfg(not(feature = "profile"))]
+    pub fn lines(&self) -> Vec<String> {
+        vec!["Profiling is off. Build with --features profile to turn it on".to_string()]
+    }
+}
+
+/// Adds a line for a scope and each of the scopes it contains to the overlay's text.
+///
+/// # Parameters
+///
+/// - `view`: The recorded frames, which know each scope's name.
+/// - `stream`: The recorded scopes of one thread.
+/// - `scope`: The scope to describe.
+/// - `depth`: How many scopes this scope ran in.
+/// - `lines`: The overlay's text, added to.
+#[cfg(feature = "profile")]
+fn scope_lines(
+    view: &puffin::FrameView,
+    stream: &puffin::Stream,
+    scope: &puffin::Scope,
+    depth: usize,
+    lines: &mut Vec<String>,
+) {
+    let name = view
+        .scope_collection()
+        .fetch_by_id(&scope.id)
+        .map(|details| details.name().to_string())
+        .unwrap_or_else(|| "?".to_string());
+    let ms = scope.record.duration_ns as f64 / 1e6;
+    let bar = ((ms * BAR_CHARS_PER_MS).ceil() as usize).min(MAX_BAR_CHARS);
+    lines.push(format!(
+        "{}{} {:.2} ms {}",
+        "  ".repeat(depth),
+        name,
+        ms,
+        "|".repeat(bar)
+    ));
+    let children = puffin::Reader::with_offset(stream, scope.child_begin_position)
+        .and_then(|reader| reader.read_top_scopes());
+    if let Ok(children) = children {
+        for child in children {
+            scope_lines(view, stream, &child, depth + 1, lines);
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! Elo ratings of players in ranked online matches, kept by a lobby server under the
+//! id each player's client sends.
+
+use anyhow::*;
+use std::collections::BTreeMap;
+use std::fs::{read_to_string, write};
+use std::path::PathBuf;
+
+/// Rating of a player who hasn't finished a ranked match yet.
+pub const DEFAULT_RATING: f64 = 1500.0;
+/// Most a rating moves after one match.
+const K_FACTOR: f64 = 32.0;
+/// Width of each matchmaking bucket, in rating points.
+const BUCKET_WIDTH: f64 = 200.0;
+
+/// How a match ended, from the first player's side.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MatchResult {
+    Won,
+    Lost,
+    Draw,
+}
+
+/// Returns the matchmaking bucket a rating falls in. Quick matches pair players from
+/// the same bucket, or failing that the nearest.
+///
+/// # Parameters
+///
+/// - `rating`: The player's rating.
+pub fn bucket(rating: f64) -> i64 {
+    (rating / BUCKET_WIDTH).floor() as i64
+}
+
+/// Returns two players' new ratings after a match between them.
+///
+/// # Parameters
+///
+/// - `first`: The first player's rating.
+/// - `second`: The second player's rating.
+/// - `result`: How the match ended, from the first player's side.
+pub fn update(first: f64, second: f64, result: MatchResult) -> (f64, f64) {
+    let expected = 1.0 / (1.0 + 10f64.powf((second - first) / 400.0));
+    let scored = match result {
+        MatchResult::Won => 1.0,
+        MatchResult::Lost => 0.0,
+        MatchResult::Draw => 0.5,
+    };
+    let change = K_FACTOR * (scored - expected);
+    (first + change, second - change)
+}
+
+/// Every player's rating, by player id, saved to a file after each change.
+pub struct Ratings {
+    /// The file, or `None` to keep ratings in memory only.
+    path: Option<PathBuf>,
+    ratings: BTreeMap<u64, f64>,
+}
+
+impl Ratings {
+    /// Creates a new, empty `Ratings` which isn't saved.
+    pub fn new() -> Self {
+        Self {
+            path: None,
+            ratings: BTreeMap::new(),
+        }
+    }
+
+    /// Reads the ratings saved in a file, which is created on the first change if it
+    /// doesn't exist yet.
+    ///
+    /// # Parameters
+    ///
+    /// - `path`: The file, holding a player id and rating on each line.
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let mut ratings = BTreeMap::new();
+        if path.exists() {
+            for line in read_to_string(&path)?.lines() {
+                let (id, rating) = line
+                    .split_once(' ')
+                    .ok_or_else(|| anyhow!("Invalid rating line: {}", line))?;
+                ratings.insert(id.parse()?, rating.parse()?);
+            }
+        }
+        Ok(Self {
+            path: Some(path),
+            ratings,
+        })
+    }
+
+    /// Returns a player's rating.
+    ///
+    /// # Parameters
+    ///
+    /// - `player`: The player's id, or `None` for a player who didn't send one.
+    pub fn get(&self, player: Option<u64>) -> f64 {
+        player
+            .and_then(|player| self.ratings.get(&player).copied())
+            .unwrap_or(DEFAULT_RATING)
+    }
+
+    /// Updates two players' ratings after a ranked match between them, and saves them.
+    ///
+    /// # Parameters
+    ///
+    /// - `first`: The first player's id.
+    /// - `second`: The second player's id.
+    /// - `result`: How the match ended, from the first player's side.
+    pub fn record(&mut self, first: u64, second: u64, result: MatchResult) {
+        let (first_rating, second_rating) =
+            update(self.get(Some(first)), self.get(Some(second)), result);
+        self.ratings.insert(first, first_rating);
+        self.ratings.insert(second, second_rating);
+        if let Err(e) = self.save() {
+            eprintln!("Failed to save ratings: {}", e);
+        }
+    }
+
+    fn save(&self) -> Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let contents: String = self
+            .ratings
+            .iter()
+            .map(|(id, rating)| format!("{} {}\n", id, rating))
+            .collect();
+        write(path, contents)?;
+        Ok(())
+    }
+}
+
+impl Default for Ratings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,577 @@
+use crate::action::Action;
+use crate::clock::{Clock, RealClock};
+use crate::game::{EventHandler, Game, HoldLimit, MAX_SOFT_DROP_FACTOR};
+use crate::kicks::KickTable;
+use crate::paths;
+use crate::rhythm::Rhythm;
+use crate::sequence::Sequence;
+use crate::speed::SpeedCurve;
+use crate::stats::Stats;
+use crate::tetromino::TetrominoKind;
+use anyhow::*;
+use std::cmp::Reverse;
+use std::fs::{create_dir_all, read_dir, read_to_string, write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use web_time::{SystemTime, UNIX_EPOCH};
+
+/// Directory replays are saved to.
+const REPLAY_DIR: &str = "replays";
+/// File name prefix of instant replay clips, followed by the time they were saved.
+const CLIP_PREFIX: &str = "clip-";
+/// How much of the game an instant replay clip keeps, in milliseconds.
+const CLIP_MS: u64 = 30_000;
+/// File name prefix of replays of personal best scores, followed by the score.
+const PERSONAL_BEST_PREFIX: &str = "personal-best-score-";
+/// First line of every replay file, so the format can change later.
+const REPLAY_HEADER: &str = "wgputris replay 1";
+/// Playback speeds which can be stepped through, as multiples of real time.
+const SPEEDS: [f64; 5] = [0.25, 0.5, 1.0, 2.0, 4.0];
+
+/// An input to a `Game`, from the player or from outside the game, as recorded in a
+/// replay.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Input {
+    /// See `Game::process_action`.
+    Action(Action),
+    /// See `Game::set_soft_drop_factor`.
+    SoftDropFactor(u32),
+    /// See `Game::set_initial_actions`.
+    InitialActions(Vec<Action>),
+    /// See `Game::add_garbage`.
+    Garbage { rows: usize, hole: usize },
+    /// See `Game::set_next_kind`.
+    NextKind(TetrominoKind),
+}
+
+/// The seed and timestamped inputs of a game, which are enough to simulate it again
+/// exactly.
+#[derive(Debug, Clone)]
+pub struct Replay {
+    pub seed: u64,
+    /// How long the game lasted, in milliseconds.
+    pub length_ms: u64,
+    /// Game time playback starts from, in milliseconds. Everything before it is still
+    /// simulated, but not shown.
+    pub start_ms: u64,
+    /// Board setup the game started from, see `Game::from_fumen`.
+    pub fumen: Option<String>,
+    /// Order the game's shapes were dealt in, see `Game::set_sequence`.
+    pub sequence: Option<Sequence>,
+    /// Tempo of the beat gravity followed in rhythm mode, see `Game::set_rhythm`.
+    pub bpm: Option<f64>,
+    /// How often shapes could be held, see `Game::set_hold_limit`.
+    pub hold_limit: HoldLimit,
+    /// Kicks shapes turned with, or `None` for no kicks, see `Game::set_kick_table`.
+    pub kicks: Option<KickTable>,
+    /// Timings the game was played at, if not the classic ones, see
+    /// `Game::set_speed_curve`.
+    pub speed_curve: Option<SpeedCurve>,
+    /// Inputs in the order they happened, each with the game time in milliseconds.
+    pub inputs: Vec<(u64, Input)>,
+}
+
+impl Replay {
+    /// Creates an empty `Replay` of a game started with `seed`, with the rules a new
+    /// `Game` starts with.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            length_ms: 0,
+            start_ms: 0,
+            fumen: None,
+            sequence: None,
+            bpm: None,
+            hold_limit: HoldLimit::OncePerPiece,
+            kicks: Some(KickTable::srs().clone()),
+            speed_curve: None,
+            inputs: Vec::new(),
+        }
+    }
+
+    /// Returns `true` if the game was set up for practice, from a fumen or a sequence.
+    pub fn is_practice(&self) -> bool {
+        self.fumen.is_some() || self.sequence.is_some()
+    }
+
+    /// Simulates the game again to check a score claimed for it, e.g. before accepting
+    /// the score onto a leaderboard. Practice games can't be checked, as they start
+    /// from a board or shapes of the player's choosing.
+    ///
+    /// # Parameters
+    ///
+    /// - `score`: The score claimed.
+    ///
+    /// # Return Value
+    ///
+    /// The game's totals. Fails with the reason if the replay couldn't have come from
+    /// a real game, or doesn't reach exactly the score claimed.
+    pub fn verify(&self, score: usize) -> Result<Stats> {
+        if self.is_practice() {
+            bail!("Practice games can't be verified");
+        }
+        if self
+            .kicks
+            .as_ref()
+            .is_some_and(|kicks| kicks != KickTable::srs())
+        {
+            bail!("Games with custom kicks can't be verified");
+        }
+        if self.speed_curve.is_some() {
+            bail!("Games with a speed curve can't be verified");
+        }
+        let mut last_ms = 0;
+        for (time_ms, input) in &self.inputs {
+            if *time_ms < last_ms || *time_ms > self.length_ms {
+                bail!("The input at {} ms is out of order", time_ms);
+            }
+            last_ms = *time_ms;
+            match input {
+                Input::SoftDropFactor(factor) if !(1..=MAX_SOFT_DROP_FACTOR).contains(factor) => {
+                    bail!("A soft drop factor of {} is impossible", factor);
+                }
+                Input::Garbage { .. } | Input::NextKind(_) => {
+                    bail!("Games changed by chat votes can't be verified");
+                }
+                _ => {}
+            }
+        }
+        let mut playback = Playback::new(Self {
+            start_ms: 0,
+            ..self.clone()
+        })?;
+        playback.advance_to(self.length_ms);
+        playback.dispatch_events(&mut []);
+        let end_ms = playback.game().time_ms();
+        if self.inputs.iter().any(|(time_ms, _)| *time_ms > end_ms) {
+            bail!("The game ended at {} ms, before its last inputs", end_ms);
+        }
+        let stats = playback.stats().clone();
+        if stats.score != score {
+            bail!(
+                "The replay scores {} points, not the {} claimed",
+                stats.score,
+                score
+            );
+        }
+        Ok(stats)
+    }
+
+    /// Reads a replay file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = read_to_string(path)?;
+        let mut lines = contents.lines();
+        if lines.next() != Some(REPLAY_HEADER) {
+            bail!("{} is not a replay", path.display());
+        }
+        let mut lines = lines.peekable();
+        let mut replay = Self::new(0);
+        // Replays without a kicks line were played without kicks
+        replay.kicks = None;
+        // Named fields, until the first input line which starts with its time
+        while let Some(line) = lines.next_if(|line| !line.starts_with(|c: char| c.is_ascii_digit()))
+        {
+            match line.split_once(' ') {
+                Some(("seed", seed)) => replay.seed = seed.parse()?,
+                Some(("length", length_ms)) => replay.length_ms = length_ms.parse()?,
+                Some(("start", start_ms)) => replay.start_ms = start_ms.parse()?,
+                Some(("fumen", fumen)) => replay.fumen = Some(fumen.to_string()),
+                Some(("sequence", sequence)) => replay.sequence = Some(Sequence::parse(sequence)?),
+                Some(("bpm", bpm)) => replay.bpm = Some(bpm.parse()?),
+                Some(("hold", hold)) => {
+                    replay.hold_limit = HoldLimit::from_name(hold)
+                        .ok_or_else(|| anyhow!("Invalid hold limit: {}", hold))?
+                }
+                Some(("kicks", kicks)) => replay.kicks = Some(serde_json::from_str(kicks)?),
+                Some(("speed", curve)) => replay.speed_curve = Some(serde_json::from_str(curve)?),
+                _ => bail!("Invalid replay line: {}", line),
+            }
+        }
+        for line in lines {
+            replay.inputs.push(parse_input(line)?);
+        }
+        Ok(replay)
+    }
+
+    /// Writes the replay to a new file in the replay directory, named after the current
+    /// time.
+    ///
+    /// # Return Value
+    ///
+    /// The path of the new file.
+    pub fn save(&self) -> Result<PathBuf> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        self.save_as(&timestamp.to_string())
+    }
+
+    /// Writes the last `CLIP_MS` of the replay to the replay directory as an instant
+    /// replay clip.
+    ///
+    /// # Return Value
+    ///
+    /// The path of the new file.
+    pub fn save_clip(&self) -> Result<PathBuf> {
+        let mut clip = self.clone();
+        clip.start_ms = self.length_ms.saturating_sub(CLIP_MS);
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        clip.save_as(&format!("{}{}", CLIP_PREFIX, timestamp))
+    }
+
+    /// Writes the replay to the replay directory as the personal best score.
+    ///
+    /// # Parameters
+    ///
+    /// - `score`: The score reached in the replay.
+    ///
+    /// # Return Value
+    ///
+    /// The path of the new file.
+    pub fn save_personal_best(&self, score: usize) -> Result<PathBuf> {
+        self.save_as(&format!("{}{}", PERSONAL_BEST_PREFIX, score))
+    }
+
+    /// Writes the replay to the replay directory.
+    ///
+    /// # Parameters
+    ///
+    /// - `file_stem`: File name without the extension.
+    ///
+    /// # Return Value
+    ///
+    /// The path of the new file.
+    fn save_as(&self, file_stem: &str) -> Result<PathBuf> {
+        let replay_dir = paths::data_path(REPLAY_DIR);
+        create_dir_all(&replay_dir)?;
+        let path = replay_dir.join(format!("{}.replay", file_stem));
+        self.save_to(&path)?;
+        Ok(path)
+    }
+
+    /// Writes the replay to a file, which `Replay::load` can read back.
+    ///
+    /// # Parameters
+    ///
+    /// - `path`: Path of the file, which is replaced if it exists.
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        let mut contents = format!(
+            "{}\nseed {}\nlength {}\nstart {}\n",
+            REPLAY_HEADER, self.seed, self.length_ms, self.start_ms
+        );
+        if let Some(fumen) = &self.fumen {
+            contents += &format!("fumen {}\n", fumen);
+        }
+        if let Some(sequence) = &self.sequence {
+            contents += &format!("sequence {}\n", sequence);
+        }
+        if let Some(bpm) = self.bpm {
+            contents += &format!("bpm {}\n", bpm);
+        }
+        if self.hold_limit != HoldLimit::OncePerPiece {
+            contents += &format!("hold {}\n", self.hold_limit.name());
+        }
+        if let Some(kicks) = &self.kicks {
+            contents += &format!("kicks {}\n", serde_json::to_string(kicks)?);
+        }
+        if let Some(curve) = &self.speed_curve {
+            contents += &format!("speed {}\n", serde_json::to_string(curve)?);
+        }
+        for (time_ms, input) in &self.inputs {
+            contents += &format_input(*time_ms, input);
+            contents += "\n";
+        }
+        write(path, contents)?;
+        Ok(())
+    }
+
+    /// Returns the most recently saved replay file named after its time, if there are
+    /// any.
+    pub fn latest() -> Option<PathBuf> {
+        replay_files()
+            .into_iter()
+            .filter_map(|(stem, path)| Some((stem.parse::<u64>().ok()?, path)))
+            .max()
+            .map(|(_, path)| path)
+    }
+
+    /// Returns the instant replay clip files along with the time they were saved, in
+    /// seconds since the Unix epoch, newest first.
+    pub fn clips() -> Vec<(u64, PathBuf)> {
+        let mut clips: Vec<(u64, PathBuf)> = replay_files()
+            .into_iter()
+            .filter_map(|(stem, path)| {
+                let timestamp = stem.strip_prefix(CLIP_PREFIX)?.parse().ok()?;
+                Some((timestamp, path))
+            })
+            .collect();
+        clips.sort_by_key(|(timestamp, _)| Reverse(*timestamp));
+        clips
+    }
+
+    /// Returns the replay files of personal best scores along with their scores,
+    /// highest first.
+    pub fn personal_bests() -> Vec<(usize, PathBuf)> {
+        let mut bests: Vec<(usize, PathBuf)> = replay_files()
+            .into_iter()
+            .filter_map(|(stem, path)| {
+                let score = stem.strip_prefix(PERSONAL_BEST_PREFIX)?.parse().ok()?;
+                Some((score, path))
+            })
+            .collect();
+        bests.sort_by_key(|(score, _)| Reverse(*score));
+        bests
+    }
+}
+
+/// Returns every file in the replay directory with the replay extension, along with
+/// its name without the extension.
+fn replay_files() -> Vec<(String, PathBuf)> {
+    let entries = match read_dir(paths::data_path(REPLAY_DIR)) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "replay"))
+        .filter_map(|path| Some((path.file_stem()?.to_str()?.to_string(), path)))
+        .collect()
+}
+
+/// Writes a timestamped input as one line of a replay file, without the line break.
+///
+/// # Parameters
+///
+/// - `time_ms`: Game time of the input, in milliseconds.
+/// - `input`: The input.
+pub(crate) fn format_input(time_ms: u64, input: &Input) -> String {
+    match input {
+        Input::Action(action) => format!("{} a {:?}", time_ms, action),
+        Input::SoftDropFactor(factor) => format!("{} s {}", time_ms, factor),
+        Input::InitialActions(actions) => {
+            let names: Vec<String> = actions.iter().map(|a| format!("{:?}", a)).collect();
+            format!("{} i {}", time_ms, names.join(","))
+        }
+        Input::Garbage { rows, hole } => format!("{} g {} {}", time_ms, rows, hole),
+        Input::NextKind(kind) => format!("{} n {:?}", time_ms, kind),
+    }
+}
+
+/// Parses a line written by `format_input`.
+///
+/// # Return Value
+///
+/// The game time of the input in milliseconds, and the input.
+pub(crate) fn parse_input(line: &str) -> Result<(u64, Input)> {
+    let mut fields = line.split(' ');
+    let time_ms = fields.next().unwrap_or_default().parse()?;
+    let input = match (fields.next(), fields.next()) {
+        (Some("a"), Some(action)) => Input::Action(parse_action(action)?),
+        (Some("s"), Some(factor)) => Input::SoftDropFactor(factor.parse()?),
+        (Some("i"), actions) => Input::InitialActions(
+            actions
+                .unwrap_or_default()
+                .split(',')
+                .filter(|action| !action.is_empty())
+                .map(parse_action)
+                .collect::<Result<_>>()?,
+        ),
+        (Some("g"), Some(rows)) => Input::Garbage {
+            rows: rows.parse()?,
+            hole: fields.next().unwrap_or_default().parse()?,
+        },
+        (Some("n"), Some(kind)) => Input::NextKind(
+            TetrominoKind::ALL
+                .iter()
+                .copied()
+                .find(|k| format!("{:?}", k) == kind)
+                .ok_or_else(|| anyhow!("Unknown shape: {}", kind))?,
+        ),
+        _ => bail!("Invalid replay line: {}", line),
+    };
+    Ok((time_ms, input))
+}
+
+/// Parses an action from its name as written by `Replay::save`.
+fn parse_action(name: &str) -> Result<Action> {
+    Action::ALL
+        .iter()
+        .copied()
+        .find(|action| format!("{:?}", action) == name)
+        .ok_or_else(|| anyhow!("Unknown action: {}", name))
+}
+
+/// Plays a `Replay` back by simulating a new game with the same seed and inputs.
+pub struct Playback {
+    replay: Replay,
+    game: Game,
+    /// Totals shown for the game, kept up to date as its events are dispatched.
+    stats: Stats,
+    /// Index of the next input to apply.
+    next_input: usize,
+    /// How far into the game playback has reached, in milliseconds.
+    position_ms: f64,
+    speed: usize,
+    pub paused: bool,
+    /// Time of `clock` at the last update.
+    last_update: Duration,
+    /// Where `update` gets the time from.
+    clock: Box<dyn Clock>,
+}
+
+impl Playback {
+    /// Creates a new `Playback` at the start of the replay.
+    pub fn new(replay: Replay) -> Result<Self> {
+        let start_ms = replay.start_ms;
+        let mut game = match &replay.fumen {
+            Some(fumen) => Game::from_fumen(replay.seed, fumen)?,
+            None => Game::with_seed(replay.seed),
+        };
+        if let Some(sequence) = &replay.sequence {
+            game.set_sequence(sequence.clone());
+        }
+        if let Some(bpm) = replay.bpm {
+            game.set_rhythm(Rhythm::new(bpm));
+        }
+        game.set_hold_limit(replay.hold_limit);
+        game.set_kick_table(replay.kicks.clone().unwrap_or_else(KickTable::none));
+        game.set_speed_curve(replay.speed_curve.clone());
+        let mut playback = Self {
+            game,
+            replay,
+            stats: Stats::new(),
+            next_input: 0,
+            position_ms: start_ms as f64,
+            speed: 2,
+            paused: false,
+            last_update: Duration::ZERO,
+            clock: Box::new(RealClock::new()),
+        };
+        playback.advance_to(start_ms);
+        // Clips start part way through, so catch the totals up without the sounds
+        playback.dispatch_events(&mut []);
+        Ok(playback)
+    }
+
+    /// Returns the game being played back.
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    /// Returns the totals of the game being played back.
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Passes the events of the game being played back to its totals and to each
+    /// handler, see `Game::dispatch_events`.
+    ///
+    /// # Parameters
+    ///
+    /// - `handlers`: Other systems reacting to the events, e.g. audio.
+    pub fn dispatch_events(&mut self, handlers: &mut [&mut dyn EventHandler]) {
+        let mut all: Vec<&mut dyn EventHandler> = vec![&mut self.stats];
+        for handler in handlers.iter_mut() {
+            all.push(&mut **handler);
+        }
+        self.game.dispatch_events(&mut all);
+    }
+
+    /// Ends playback, handing over the game and its totals, e.g. to carry on playing it.
+    pub fn into_parts(self) -> (Game, Stats) {
+        (self.game, self.stats)
+    }
+
+    /// Returns the playback speed as a multiple of real time.
+    pub fn speed(&self) -> f64 {
+        SPEEDS[self.speed]
+    }
+
+    /// Steps the playback speed up or down.
+    ///
+    /// # Parameters
+    ///
+    /// - `direction`: `1` to speed up, `-1` to slow down.
+    pub fn change_speed(&mut self, direction: i32) {
+        self.speed = (self.speed as i32 + direction).clamp(0, SPEEDS.len() as i32 - 1) as usize;
+    }
+
+    /// Starts playing from the beginning again.
+    pub fn restart(&mut self) {
+        let paused = self.paused;
+        let speed = self.speed;
+        // The replay already started once, so it can't fail to start again
+        if let Ok(playback) = Self::new(self.replay.clone()) {
+            let clock = std::mem::replace(&mut self.clock, playback.clock);
+            *self = Self { clock, ..playback };
+            self.last_update = self.clock.now();
+        }
+        self.paused = paused;
+        self.speed = speed;
+    }
+
+    /// Replaces the clock `update` follows, e.g. with a `MockClock` in tests. Time
+    /// starts counting from the new clock's current time.
+    ///
+    /// # Parameters
+    ///
+    /// - `clock`: The new clock.
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.clock = clock;
+        self.last_update = self.clock.now();
+    }
+
+    /// Adds an input which happened after every input so far, e.g. as it arrives from a
+    /// remote player.
+    ///
+    /// # Parameters
+    ///
+    /// - `time_ms`: Game time of the input, in milliseconds.
+    /// - `input`: The input.
+    pub fn push_input(&mut self, time_ms: u64, input: Input) {
+        self.replay.inputs.push((time_ms, input));
+    }
+
+    /// Lengthens the replay to a later time and plays it up to there, e.g. as a remote
+    /// player's game reaches it.
+    ///
+    /// # Parameters
+    ///
+    /// - `length_ms`: Game time the replay now lasts until, in milliseconds.
+    pub fn extend_to(&mut self, length_ms: u64) {
+        self.replay.length_ms = self.replay.length_ms.max(length_ms);
+        self.advance_to(length_ms);
+    }
+
+    /// Returns `true` once the whole game has been played back.
+    pub fn is_finished(&self) -> bool {
+        self.game.game_over || self.game.time_ms() >= self.replay.length_ms
+    }
+
+    /// Advances the game by the real time since the last call, scaled by the speed.
+    pub fn update(&mut self) {
+        let now = self.clock.now();
+        if !self.paused {
+            self.position_ms += (now - self.last_update).as_secs_f64() * 1000.0 * self.speed();
+        }
+        self.last_update = now;
+        self.advance_to(self.position_ms as u64);
+    }
+
+    /// Advances the game to a point in time, e.g. to keep pace with a live game.
+    ///
+    /// # Parameters
+    ///
+    /// - `time_ms`: Game time to advance to, in milliseconds.
+    pub fn advance_to(&mut self, time_ms: u64) {
+        while self.game.time_ms() < time_ms && !self.is_finished() {
+            // Inputs are applied before advancing, as in a live game
+            while let Some((time_ms, input)) = self.replay.inputs.get(self.next_input) {
+                if *time_ms > self.game.time_ms() {
+                    break;
+                }
+                self.game.apply_input(input);
+                self.next_input += 1;
+            }
+            self.game.advance(1);
+        }
+    }
+}
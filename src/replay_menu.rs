@@ -0,0 +1,93 @@
+use std::path::PathBuf;
+use web_time::{SystemTime, UNIX_EPOCH};
+use wgputris::replay::Replay;
+use winit::event::VirtualKeyCode;
+
+/// Describes how long ago something happened, e.g. "5 minutes ago".
+fn ago(secs: u64) -> String {
+    let (count, unit) = match secs {
+        0..=59 => return String::from("just now"),
+        60..=3599 => (secs / 60, "minute"),
+        3600..=86399 => (secs / 3600, "hour"),
+        _ => (secs / 86400, "day"),
+    };
+    let plural = if count == 1 { "" } else { "s" };
+    format!("{} {}{} ago", count, unit, plural)
+}
+
+/// What to do with a replay chosen on the `ReplayMenu`.
+pub enum MenuChoice {
+    /// Play the replay back.
+    Watch(PathBuf),
+    /// Start a new game with the replay playing alongside as a ghost.
+    Race(PathBuf),
+}
+
+/// The "My Replays" screen, listing the last game and every personal best.
+pub struct ReplayMenu {
+    entries: Vec<(String, PathBuf)>,
+    selected: usize,
+}
+
+impl ReplayMenu {
+    /// Creates a new `ReplayMenu` listing the replays currently saved.
+    pub fn new() -> Self {
+        let mut entries = Vec::new();
+        if let Some(path) = Replay::latest() {
+            entries.push((String::from("Last game"), path));
+        }
+        for (score, path) in Replay::personal_bests() {
+            entries.push((format!("Personal best: {} points", score), path));
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        for (timestamp, path) in Replay::clips() {
+            entries.push((
+                format!("Clip saved {}", ago(now.saturating_sub(timestamp))),
+                path,
+            ));
+        }
+        Self {
+            entries,
+            selected: 0,
+        }
+    }
+
+    /// Handles a key press on the replay screen.
+    ///
+    /// # Parameters
+    ///
+    /// - `key`: The key which was pressed.
+    ///
+    /// # Return Value
+    ///
+    /// The replay file chosen and what to do with it, if any.
+    pub fn process_input(&mut self, key: VirtualKeyCode) -> Option<MenuChoice> {
+        let count = self.entries.len().max(1);
+        let path = self
+            .entries
+            .get(self.selected)
+            .map(|(_, path)| path.clone());
+        match key {
+            VirtualKeyCode::Up => self.selected = (self.selected + count - 1) % count,
+            VirtualKeyCode::Down => self.selected = (self.selected + 1) % count,
+            VirtualKeyCode::Return => return path.map(MenuChoice::Watch),
+            VirtualKeyCode::G => return path.map(MenuChoice::Race),
+            _ => {}
+        }
+        None
+    }
+
+    /// Returns each line of the replay screen along with whether it is selected.
+    pub fn lines(&self) -> Vec<(String, bool)> {
+        if self.entries.is_empty() {
+            return vec![(String::from("No replays yet"), false)];
+        }
+        self.entries
+            .iter()
+            .enumerate()
+            .map(|(i, (label, _))| (label.clone(), i == self.selected))
+            .collect()
+    }
+}
@@ -0,0 +1,175 @@
+//! Rhythm mode, where gravity moves and locks shapes on the beat of the music instead
+//! of every few frames, and shapes hard dropped on the beat score a bonus.
+//!
+//! Beats are counted in game time from the start of the game, so a replay plays back
+//! the same without the music. While playing, the game is kept in time with the music
+//! by speeding it up or slowing it down slightly, see `Rhythm::sync_speed`.
+
+/// Slowest tempo a track can set, in beats per minute.
+pub const MIN_BPM: f64 = 40.0;
+/// Fastest tempo a track can set, in beats per minute.
+pub const MAX_BPM: f64 = 300.0;
+/// Furthest a hard drop can be from a beat and still count as on it, in milliseconds.
+pub const ON_BEAT_WINDOW_MS: u64 = 60;
+/// Points scored for a hard drop on the beat.
+pub const ON_BEAT_BONUS: usize = 100;
+/// Levels gained for gravity to move shapes twice as many rows each beat.
+const LEVELS_PER_DOUBLE: usize = 4;
+/// Most times the rows moved each beat double, so shapes move at most 8 rows a beat.
+const MAX_DOUBLINGS: usize = 3;
+/// Milliseconds in a minute.
+const MINUTE_MS: u64 = 60_000;
+/// How long the game takes to catch up with the music, in milliseconds of music.
+const SYNC_MS: f64 = 1000.0;
+/// Most the game is sped up or slowed down to keep in time with the music, as a
+/// fraction of its speed.
+const MAX_SYNC_ADJUST: f64 = 0.05;
+
+/// The beat gravity follows in rhythm mode.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Rhythm {
+    /// Tempo in thousandths of a beat per minute, so beats fall on the same
+    /// milliseconds on every platform.
+    millibpm: u64,
+}
+
+impl Rhythm {
+    /// Creates a new `Rhythm`.
+    ///
+    /// # Parameters
+    ///
+    /// - `bpm`: Tempo in beats per minute, clamped between `MIN_BPM` and `MAX_BPM`.
+    pub fn new(bpm: f64) -> Self {
+        Self {
+            millibpm: (bpm.clamp(MIN_BPM, MAX_BPM) * 1000.0).round() as u64,
+        }
+    }
+
+    /// Returns the tempo in beats per minute.
+    pub fn bpm(self) -> f64 {
+        self.millibpm as f64 / 1000.0
+    }
+
+    /// Returns the tempo in thousandths of a beat per minute.
+    pub fn millibpm(self) -> u64 {
+        self.millibpm
+    }
+
+    /// Returns the time between beats in milliseconds.
+    pub fn beat_ms(self) -> f64 {
+        (MINUTE_MS * 1000) as f64 / self.millibpm as f64
+    }
+
+    /// Returns how many rows gravity moves a shape each beat at a level. Every row
+    /// but the one on the beat only moves the shape, so shapes only lock on the beat.
+    ///
+    /// # Parameters
+    ///
+    /// - `level`: The level, starting from 1.
+    pub fn rows_per_beat(self, level: usize) -> usize {
+        let doublings = (level.max(1) - 1) / LEVELS_PER_DOUBLE;
+        1 << doublings.min(MAX_DOUBLINGS)
+    }
+
+    /// Returns whether gravity moves the current shape at a millisecond of game time.
+    ///
+    /// # Parameters
+    ///
+    /// - `time_ms`: Game time in milliseconds, from 1.
+    /// - `level`: The level, starting from 1.
+    ///
+    /// # Return Value
+    ///
+    /// `Some(true)` if a beat starts during the millisecond, `Some(false)` if a row
+    /// between beats does, or `None` if gravity waits.
+    pub fn pulse(self, time_ms: u64, level: usize) -> Option<bool> {
+        let rows = self.rows_per_beat(level) as u64;
+        let row = |time_ms: u64| time_ms * self.millibpm * rows / (MINUTE_MS * 1000);
+        let now = row(time_ms);
+        if now > row(time_ms.saturating_sub(1)) {
+            Some(now % rows == 0)
+        } else {
+            None
+        }
+    }
+
+    /// Returns `true` if a time is within `ON_BEAT_WINDOW_MS` of a beat.
+    ///
+    /// # Parameters
+    ///
+    /// - `time_ms`: Game time in milliseconds.
+    pub fn is_on_beat(self, time_ms: u64) -> bool {
+        // In thousandths of a millisecond times beats per minute, where a beat is
+        // exactly a minute of milliseconds long
+        let beat = MINUTE_MS * 1000;
+        let offset = time_ms * self.millibpm % beat;
+        offset.min(beat - offset) <= ON_BEAT_WINDOW_MS * self.millibpm
+    }
+
+    /// Returns the speed to run the game at to bring its beats in time with the
+    /// music's, see `Game::set_speed`. Only where each is within its beat matters, so
+    /// the music can loop or be paused without the game jumping to catch up.
+    ///
+    /// # Parameters
+    ///
+    /// - `game_ms`: Game time in milliseconds.
+    /// - `music_ms`: How far the music has played, in milliseconds from the first beat.
+    pub fn sync_speed(self, game_ms: f64, music_ms: f64) -> f64 {
+        let beat_ms = self.beat_ms();
+        let mut drift = (music_ms - game_ms).rem_euclid(beat_ms);
+        if drift > beat_ms / 2.0 {
+            drift -= beat_ms;
+        }
+        1.0 + (drift / SYNC_MS).clamp(-MAX_SYNC_ADJUST, MAX_SYNC_ADJUST)
+    }
+}
+
+/// Reads the tempo from the `BPM` or `TEMPO` tag of an OGG Vorbis file, which most
+/// music players and taggers can set.
+///
+/// # Parameters
+///
+/// - `bytes`: The start of the file, which must include its comment header.
+///
+/// # Return Value
+///
+/// The tempo in beats per minute, or `None` if the file has no tag with one.
+pub fn vorbis_bpm(bytes: &[u8]) -> Option<f64> {
+    const COMMENT_HEADER: &[u8] = b"\x03vorbis";
+    let start = bytes
+        .windows(COMMENT_HEADER.len())
+        .position(|window| window == COMMENT_HEADER)?;
+    let mut rest = &bytes[start + COMMENT_HEADER.len()..];
+    let vendor_len = read_u32(&mut rest)?;
+    take(&mut rest, vendor_len)?;
+    for _ in 0..read_u32(&mut rest)? {
+        let len = read_u32(&mut rest)?;
+        let comment = String::from_utf8_lossy(take(&mut rest, len)?);
+        if let Some((tag, value)) = comment.split_once('=') {
+            if tag.eq_ignore_ascii_case("BPM") || tag.eq_ignore_ascii_case("TEMPO") {
+                if let Ok(bpm) = value.trim().parse::<f64>() {
+                    return Some(bpm).filter(|bpm| (MIN_BPM..=MAX_BPM).contains(bpm));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Reads a number stored least significant byte first from the front of `bytes`, as
+/// Vorbis headers store lengths and counts.
+fn read_u32(bytes: &mut &[u8]) -> Option<u32> {
+    let word = take(bytes, 4)?;
+    Some(u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+}
+
+/// Removes `len` bytes from the front of `bytes` and returns them.
+fn take<'a>(bytes: &mut &'a [u8], len: u32) -> Option<&'a [u8]> {
+    let len = len as usize;
+    if bytes.len() < len {
+        return None;
+    }
+    let (front, back) = bytes.split_at(len);
+    *bytes = back;
+    Some(front)
+}
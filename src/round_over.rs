@@ -0,0 +1,140 @@
+use web_time::Instant;
+use winit::event::VirtualKeyCode;
+
+/// What to do once a versus round has ended.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RoundChoice {
+    /// Play the same opponent again, online once they ask for a rematch too.
+    Rematch,
+    /// Change the handicaps, or online, the settings, before playing again.
+    ChangeSettings,
+    /// Go back to the title screen.
+    Quit,
+}
+
+impl RoundChoice {
+    /// Every choice, in the order they are listed.
+    const ALL: [RoundChoice; 3] = [
+        RoundChoice::Rematch,
+        RoundChoice::ChangeSettings,
+        RoundChoice::Quit,
+    ];
+
+    /// Name shown on the overlay.
+    ///
+    /// # Parameters
+    ///
+    /// - `online`: `true` after an online round, which has settings instead of
+    ///   handicaps.
+    fn label(self, online: bool) -> &'static str {
+        match (self, online) {
+            (RoundChoice::Rematch, _) => "Rematch",
+            (RoundChoice::ChangeSettings, false) => "Change Handicaps",
+            (RoundChoice::ChangeSettings, true) => "Change Settings",
+            (RoundChoice::Quit, _) => "Quit",
+        }
+    }
+}
+
+/// How one player did in a versus round.
+pub struct Side {
+    pub name: String,
+    /// Rows of garbage sent to the other player.
+    pub sent: usize,
+    /// Rows of garbage received from the other player.
+    pub received: usize,
+    /// Game time the player lasted, in milliseconds.
+    pub time_ms: u64,
+}
+
+impl Side {
+    /// Returns the rows of garbage sent per minute.
+    fn attack_per_minute(&self) -> f64 {
+        if self.time_ms == 0 {
+            return 0.0;
+        }
+        self.sent as f64 * 60_000.0 / self.time_ms as f64
+    }
+}
+
+/// The overlay shown over both boards once a versus round ends, with the winner, how
+/// hard each player attacked, and what to do next.
+pub struct RoundOver {
+    /// Who won, e.g. "Player 1 wins!".
+    winner: String,
+    /// Player 1 or this player, then the other player.
+    sides: [Side; 2],
+    online: bool,
+    selected: usize,
+    /// When the round ended, which starts the boards which topped out filling with grey
+    /// blocks.
+    ended_at: Instant,
+}
+
+impl RoundOver {
+    /// Creates a new `RoundOver` with the rematch selected.
+    ///
+    /// # Parameters
+    ///
+    /// - `winner`: Who won, as shown at the top.
+    /// - `sides`: How each player did.
+    /// - `online`: `true` after an online round.
+    pub fn new(winner: String, sides: [Side; 2], online: bool) -> Self {
+        Self {
+            winner,
+            sides,
+            online,
+            selected: 0,
+            ended_at: Instant::now(),
+        }
+    }
+
+    /// Returns when the round ended.
+    pub fn ended_at(&self) -> Instant {
+        self.ended_at
+    }
+
+    /// Handles a key press on the overlay.
+    ///
+    /// # Parameters
+    ///
+    /// - `key`: The key which was pressed.
+    ///
+    /// # Return Value
+    ///
+    /// The choice made, once enter or escape is pressed.
+    pub fn process_input(&mut self, key: VirtualKeyCode) -> Option<RoundChoice> {
+        let count = RoundChoice::ALL.len();
+        match key {
+            VirtualKeyCode::Up => self.selected = (self.selected + count - 1) % count,
+            VirtualKeyCode::Down => self.selected = (self.selected + 1) % count,
+            VirtualKeyCode::Return => return Some(RoundChoice::ALL[self.selected]),
+            VirtualKeyCode::Escape => return Some(RoundChoice::Quit),
+            _ => {}
+        }
+        None
+    }
+
+    /// Returns each line of the overlay along with whether it is highlighted: the
+    /// winner and the selected choice.
+    pub fn lines(&self) -> Vec<(String, bool)> {
+        let mut lines = vec![(self.winner.clone(), true)];
+        for side in self.sides.iter() {
+            lines.push((
+                format!(
+                    "{}: {:.1} APM, sent {}, received {}",
+                    side.name,
+                    side.attack_per_minute(),
+                    side.sent,
+                    side.received
+                ),
+                false,
+            ));
+        }
+        lines.push((String::new(), false));
+        for (i, choice) in RoundChoice::ALL.iter().enumerate() {
+            lines.push((choice.label(self.online).to_string(), i == self.selected));
+        }
+        lines
+    }
+}
@@ -0,0 +1,101 @@
+//! Quick save states for practice games, for trying a setup over and over from the same
+//! point. A state is kept as the game's replay up to the moment it was saved, which
+//! plays back to exactly the same game, so states are small and can be kept on disk.
+
+use anyhow::*;
+use std::fs::create_dir_all;
+use std::path::PathBuf;
+use wgputris::game::Game;
+use wgputris::paths;
+use wgputris::replay::{Playback, Replay};
+use wgputris::stats::Stats;
+
+/// Directory save states are written to, in the data directory.
+const STATE_DIR: &str = "states";
+/// Number of save state slots.
+pub const SLOTS: usize = 4;
+
+/// The save state slots, and which one the hotkeys use.
+pub struct SaveStates {
+    slots: [Option<Replay>; SLOTS],
+    /// Index of the slot saved to and loaded from.
+    slot: usize,
+}
+
+impl SaveStates {
+    /// Creates a new `SaveStates`, with every slot empty in memory.
+    pub fn new() -> Self {
+        Self {
+            slots: Default::default(),
+            slot: 0,
+        }
+    }
+
+    /// Returns the number of the slot in use, from 1.
+    pub fn slot_number(&self) -> usize {
+        self.slot + 1
+    }
+
+    /// Moves on to the next slot, back to the first after the last.
+    pub fn next_slot(&mut self) {
+        self.slot = (self.slot + 1) % SLOTS;
+    }
+
+    /// Saves a game to the slot in use, replacing what was saved there.
+    ///
+    /// # Parameters
+    ///
+    /// - `game`: The game to save.
+    /// - `to_disk`: `true` to write the state to the data directory as well, where it
+    ///   outlasts the session.
+    pub fn save(&mut self, game: &Game, to_disk: bool) -> Result<()> {
+        let replay = game.replay().clone();
+        if to_disk {
+            let path = state_path(self.slot);
+            create_dir_all(path.parent().unwrap())?;
+            replay.save_to(&path)?;
+        }
+        self.slots[self.slot] = Some(replay);
+        Ok(())
+    }
+
+    /// Loads the game saved in the slot in use, simulated back to where it was saved.
+    ///
+    /// # Parameters
+    ///
+    /// - `from_disk`: `true` to read the state from the data directory if the slot
+    ///   hasn't been saved to this session.
+    ///
+    /// # Return Value
+    ///
+    /// The saved game and its totals, to be played on.
+    pub fn load(&self, from_disk: bool) -> Result<(Game, Stats)> {
+        let replay = match &self.slots[self.slot] {
+            Some(replay) => replay.clone(),
+            None if from_disk && state_path(self.slot).exists() => {
+                Replay::load(&state_path(self.slot))?
+            }
+            None => bail!("Slot {} is empty", self.slot_number()),
+        };
+        let length_ms = replay.length_ms;
+        let mut playback = Playback::new(replay)?;
+        playback.advance_to(length_ms);
+        playback.dispatch_events(&mut []);
+        Ok(playback.into_parts())
+    }
+}
+
+impl Default for SaveStates {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the file a slot is saved to on disk.
+///
+/// # Parameters
+///
+/// - `slot`: Index of the slot.
+fn state_path(slot: usize) -> PathBuf {
+    paths::data_path(STATE_DIR).join(format!("slot-{}.replay", slot + 1))
+}
@@ -0,0 +1,1205 @@
+use crate::attract::{self, Demo};
+use crate::cli::Launch;
+use crate::crash::Crash;
+use crate::exhibition::Exhibition;
+use crate::history::{self, HistoryMenu};
+use crate::online::{self, OnlineMenu, Spectate};
+use crate::replay_menu::{MenuChoice, ReplayMenu};
+use crate::round_over::{RoundChoice, RoundOver};
+use crate::settings::SettingsMenu;
+use crate::trophies::TrophyMenu;
+use crate::twitch::TwitchPanel;
+use crate::versus_setup::VersusSetup;
+use crate::{
+    input, queue_list_screen, record_game, update_player, State, HELP_BOTTOM, PRACTICE_SPEEDS,
+    VIEW_WIDTH,
+};
+use web_time::Instant;
+use wgpu_glyph::{Layout, Section, Text, VerticalAlign};
+use wgputris::action::Action;
+use wgputris::ai::Cpu;
+use wgputris::replay::Playback;
+use winit::event::VirtualKeyCode;
+
+/// What the game is showing, which decides how input is handled and what is updated
+/// and drawn each frame.
+pub enum Screen {
+    Boot(Boot),
+    Menu(Menu),
+    /// A game played by the computer after the title screen was left alone. Boxed, as
+    /// it holds a whole game.
+    Demo(Box<Demo>),
+    Settings(SettingsMenu),
+    Replays(ReplayMenu),
+    History(HistoryMenu),
+    /// Every achievement, and which the active key binding profile has unlocked.
+    Trophies(TrophyMenu),
+    Online(OnlineMenu),
+    /// The streamer's control panel for chat votes.
+    Twitch(TwitchPanel),
+    /// Setting each player's handicap before a versus match.
+    VersusSetup(VersusSetup),
+    Playing(Playing),
+    Paused(Paused),
+    GameOver(GameOver),
+    /// A finished versus round, with the winner, attacks and what to do next shown
+    /// over the boards.
+    RoundOver(RoundOver),
+    Results(Results),
+    /// A saved replay playing back. Boxed, as it holds a whole game.
+    Playback(Box<Playback>),
+    /// A lobby match being watched.
+    Spectating(Box<Spectate>),
+    /// Two computer players playing each other, for watching. Boxed, as it holds two
+    /// whole games.
+    Exhibition(Box<Exhibition>),
+    /// Offering to carry on the game which was in progress when the game last crashed.
+    Recovery(Crash),
+    /// Stands in for the screen while it's being dispatched to, see `State::dispatch`.
+    Dispatching,
+}
+
+impl Screen {
+    /// Returns the screen's behaviour, to dispatch to.
+    pub fn as_state(&mut self) -> &mut dyn ScreenState {
+        match self {
+            Screen::Boot(boot) => boot,
+            Screen::Menu(menu) => menu,
+            Screen::Demo(demo) => demo.as_mut(),
+            Screen::Settings(settings_menu) => settings_menu,
+            Screen::Replays(replay_menu) => replay_menu,
+            Screen::History(history_menu) => history_menu,
+            Screen::Trophies(trophy_menu) => trophy_menu,
+            Screen::Online(online_menu) => online_menu,
+            Screen::Twitch(panel) => panel,
+            Screen::VersusSetup(setup) => setup,
+            Screen::Playing(playing) => playing,
+            Screen::Paused(paused) => paused,
+            Screen::GameOver(game_over) => game_over,
+            Screen::RoundOver(round_over) => round_over,
+            Screen::Results(results) => results,
+            Screen::Playback(playback) => playback.as_mut(),
+            Screen::Spectating(spectate) => spectate.as_mut(),
+            Screen::Exhibition(exhibition) => exhibition.as_mut(),
+            Screen::Recovery(crash) => crash,
+            Screen::Dispatching => unreachable!("The screen is already being dispatched to"),
+        }
+    }
+
+    /// Returns `true` on screens which draw the game boards.
+    pub fn shows_boards(&self) -> bool {
+        matches!(
+            self,
+            Screen::Demo(_)
+                | Screen::Exhibition(_)
+                | Screen::Playing(_)
+                | Screen::Paused(_)
+                | Screen::GameOver(_)
+                | Screen::RoundOver(_)
+                | Screen::Playback(_)
+                | Screen::Spectating(_)
+        )
+    }
+
+    /// Returns `true` on screens which only wait for the player: menus, the paused game
+    /// and the title screen's demo.
+    pub fn is_waiting(&self) -> bool {
+        matches!(
+            self,
+            Screen::Menu(_)
+                | Screen::Demo(_)
+                | Screen::Settings(_)
+                | Screen::Replays(_)
+                | Screen::History(_)
+                | Screen::Trophies(_)
+                | Screen::Online(_)
+                | Screen::Twitch(_)
+                | Screen::VersusSetup(_)
+                | Screen::Paused(_)
+                | Screen::Results(_)
+                | Screen::Recovery(_)
+        )
+    }
+
+    /// Returns `true` on screens which get every key press, without the hotkeys which
+    /// work on other screens: the paused game, where any key but escape plays on, and
+    /// the demo, which any key ends.
+    pub fn takes_every_key(&self) -> bool {
+        matches!(self, Screen::Paused(_) | Screen::Demo(_))
+    }
+}
+
+/// What a screen does with key presses, each frame and when drawn. Each is given the
+/// rest of the `State`, as the game, config and audio are shared by every screen, and
+/// moves to another screen by setting `State::screen`.
+pub trait ScreenState {
+    /// Handles a key press the hotkeys which work on every screen left alone.
+    ///
+    /// # Parameters
+    ///
+    /// - `state`: Everything but the screen.
+    /// - `key`: The key which was pressed.
+    /// - `physical_key`: The key which was pressed, as matched against key bindings.
+    ///
+    /// # Return Value
+    ///
+    /// `false` if the key was left unhandled, e.g. so escape closes the window.
+    fn handle_input(
+        &mut self,
+        state: &mut State,
+        key: VirtualKeyCode,
+        physical_key: input::Key,
+    ) -> bool;
+
+    /// Handles a character typed on screens with text to type.
+    ///
+    /// # Return Value
+    ///
+    /// `true` if the screen took the character.
+    fn type_char(&mut self, _state: &mut State, _c: char) -> bool {
+        false
+    }
+
+    /// Handles the window being asked to close.
+    ///
+    /// # Return Value
+    ///
+    /// `true` if the screen kept the window open.
+    fn close_requested(&mut self, _state: &mut State) -> bool {
+        false
+    }
+
+    /// Advances the screen once a frame.
+    fn update(&mut self, _state: &mut State) {}
+
+    /// Queues the text drawn over the boards, or making up the whole screen if it
+    /// doesn't show them.
+    fn render(&self, state: &mut State);
+
+    /// Returns what the screen reader reads out: the selected line of a menu, or what
+    /// to do on screens without one.
+    fn focused_line(&self, _state: &State) -> Option<String> {
+        None
+    }
+}
+
+/// Returns the last highlighted of a menu's lines, for the screen reader.
+fn highlighted_line(lines: Vec<(String, bool)>) -> Option<String> {
+    lines
+        .into_iter()
+        .rev()
+        .find(|(_, highlighted)| *highlighted)
+        .map(|(line, _)| line)
+}
+
+/// Queues a block of white text.
+///
+/// # Parameters
+///
+/// - `state`: The state whose glyph brush to queue it on.
+/// - `text`: The text.
+/// - `position`: Top left corner, in layout pixels.
+/// - `scale`: Size of the text, before the UI scale.
+fn queue_text(state: &mut State, text: &str, position: (f32, f32), scale: f32) {
+    let section = Section {
+        screen_position: position,
+        text: vec![Text::new(text)
+            .with_scale(scale * state.config.graphics.ui_scale())
+            .with_color([1.0, 1.0, 1.0, 1.0])],
+        ..Section::default()
+    };
+    state.glyph_brush.queue(section);
+}
+
+/// Queues the status and keys of a screen for watching, at the bottom left.
+///
+/// # Parameters
+///
+/// - `state`: The state whose glyph brush to queue it on.
+/// - `text`: The status and keys.
+fn queue_help(state: &mut State, text: &str) {
+    let section = Section {
+        screen_position: (20.0, HELP_BOTTOM),
+        text: vec![Text::new(text)
+            .with_scale(20.0 * state.config.graphics.ui_scale())
+            .with_color([1.0, 1.0, 1.0, 1.0])],
+        layout: Layout::default().v_align(VerticalAlign::Bottom),
+        ..Section::default()
+    };
+    state.glyph_brush.queue(section);
+}
+
+/// Starting up, with what the command line asked to show still to start.
+pub struct Boot {
+    pub launch: Launch,
+}
+
+impl ScreenState for Boot {
+    fn handle_input(&mut self, _: &mut State, _: VirtualKeyCode, _: input::Key) -> bool {
+        false
+    }
+
+    fn update(&mut self, state: &mut State) {
+        let launch = std::mem::replace(&mut self.launch, Launch::Menu);
+        state.screen = Screen::Menu(Menu);
+        match launch {
+            Launch::Menu => {
+                if let Some(crash) = Crash::load() {
+                    state.screen = Screen::Recovery(crash);
+                }
+            }
+            Launch::Game { versus } => state.start_game(versus),
+            Launch::Practice { fumen, sequence } => state.start_practice(
+                state.seed.unwrap_or_else(rand::random),
+                fumen.as_deref(),
+                sequence.as_ref(),
+            ),
+            Launch::Replay(path) => state.watch_replay(&path),
+            Launch::Cpu(name) => {
+                state.start_cpu_game(Cpu::named(&name, state.config.cpu_difficulty))
+            }
+        }
+    }
+
+    fn render(&self, _: &mut State) {}
+}
+
+/// The title screen listing the controls.
+pub struct Menu;
+
+impl ScreenState for Menu {
+    fn handle_input(&mut self, state: &mut State, key: VirtualKeyCode, _: input::Key) -> bool {
+        match key {
+            // Left unhandled so the window closes
+            VirtualKeyCode::Escape => return false,
+            VirtualKeyCode::S => state.screen = Screen::Settings(SettingsMenu::new()),
+            VirtualKeyCode::R => state.screen = Screen::Replays(ReplayMenu::new()),
+            VirtualKeyCode::H => state.screen = Screen::History(HistoryMenu::new()),
+            VirtualKeyCode::A => {
+                let profile = &state.config.key_profile().name;
+                state.screen = Screen::Trophies(TrophyMenu::new(
+                    profile,
+                    state.achievements.progress(profile),
+                ))
+            }
+            VirtualKeyCode::T => state.screen = Screen::Twitch(TwitchPanel::new()),
+            VirtualKeyCode::O => {
+                state.chat.clear();
+                state.screen = Screen::Online(OnlineMenu::new(
+                    state.config.player_id,
+                    state.config.input_delay,
+                ));
+            }
+            VirtualKeyCode::V => state.screen = Screen::VersusSetup(VersusSetup::new(false)),
+            VirtualKeyCode::C => state.screen = Screen::VersusSetup(VersusSetup::new(true)),
+            VirtualKeyCode::E => state.start_exhibition(),
+            VirtualKeyCode::F => state.start_finesse(),
+            VirtualKeyCode::B => state.start_rhythm(),
+            VirtualKeyCode::Space => state.start_game(false),
+            _ => {}
+        }
+        true
+    }
+
+    fn update(&mut self, state: &mut State) {
+        if state.idle_since.elapsed().as_secs_f64() >= attract::IDLE_SECS {
+            state.screen = Screen::Demo(Box::new(Demo::new()));
+            // A match left for the title screen would otherwise be drawn instead
+            state.versus = None;
+            state.ghost = None;
+            state.online = None;
+            state.set_view_width(VIEW_WIDTH);
+        }
+    }
+
+    fn render(&self, state: &mut State) {
+        let profile = state.config.key_profile();
+        let mut menu_string = format!("Controls ({})\n", profile.name);
+        for action in Action::ALL.iter() {
+            let keys = profile.keys(*action);
+            if keys.is_empty() {
+                continue;
+            }
+            let key_names: Vec<String> = keys.iter().map(|k| state.input_state.label(*k)).collect();
+            menu_string += &format!("{}: {}\n", key_names.join("/"), action.label());
+        }
+        menu_string += "M: Mute, +/-: Volume, [/]: Previous/next track
+F3: Show the debug overlay
+F10: Show how long each part of a frame takes
+F7: Save the board as a fumen
+F4: Start a practice game over
+F1/F2: Slow down/speed up a practice game
+F5/F9: Save/load a practice game's state, F12: Next slot
+F8: Save the last 30 seconds as a replay
+F6: Save a GIF of the last 5 seconds
+Space: Start new game
+
+Press space to start
+Press V for two player versus
+Press C to play against the computer
+Press E to watch the computer play itself
+Press F to train finesse
+Press B for rhythm mode
+Press O to play online
+Press T to let Twitch chat vote on your game
+Press R for my replays, H for history
+Press A for trophies
+Press S for settings
+";
+        let mod_names = state.mods.names();
+        if !mod_names.is_empty() {
+            menu_string += &format!("\nMods: {}\n", mod_names.join(", "));
+        }
+        queue_text(state, &menu_string, (100.0, 100.0), 25.0);
+    }
+
+    fn focused_line(&self, _: &State) -> Option<String> {
+        Some(String::from("Title screen. Press space to start"))
+    }
+}
+
+impl ScreenState for Demo {
+    fn handle_input(&mut self, state: &mut State, _: VirtualKeyCode, _: input::Key) -> bool {
+        // Any key at all ends the demo, and does nothing else
+        state.screen = Screen::Menu(Menu);
+        true
+    }
+
+    fn update(&mut self, state: &mut State) {
+        Demo::update(self);
+        if self.is_finished() {
+            state.screen = Screen::Menu(Menu);
+            state.idle_since = Instant::now();
+        }
+    }
+
+    fn render(&self, state: &mut State) {
+        queue_text(state, "Press any key", (380.0, 250.0), 30.0);
+    }
+}
+
+impl ScreenState for SettingsMenu {
+    fn handle_input(&mut self, state: &mut State, key: VirtualKeyCode, _: input::Key) -> bool {
+        if let VirtualKeyCode::Escape | VirtualKeyCode::Return = key {
+            state.screen = Screen::Menu(Menu);
+        } else if self.process_input(key, &mut state.config) {
+            state.config_changed();
+        }
+        true
+    }
+
+    fn render(&self, state: &mut State) {
+        let lines = self.lines(&state.config);
+        queue_list_screen(
+            &mut state.glyph_brush,
+            "Settings",
+            &lines,
+            "Up/Down: Select  Left/Right: Adjust  Enter/Escape: Back",
+            state.config.graphics.ui_scale(),
+        );
+    }
+
+    fn focused_line(&self, state: &State) -> Option<String> {
+        highlighted_line(self.lines(&state.config))
+    }
+}
+
+impl ScreenState for ReplayMenu {
+    fn handle_input(&mut self, state: &mut State, key: VirtualKeyCode, _: input::Key) -> bool {
+        if key == VirtualKeyCode::Escape {
+            state.screen = Screen::Menu(Menu);
+            return true;
+        }
+        match self.process_input(key) {
+            Some(MenuChoice::Watch(path)) => state.watch_replay(&path),
+            Some(MenuChoice::Race(path)) => state.race_replay(&path),
+            None => {}
+        }
+        true
+    }
+
+    fn render(&self, state: &mut State) {
+        queue_list_screen(
+            &mut state.glyph_brush,
+            "My Replays",
+            &self.lines(),
+            "Up/Down: Select  Enter: Watch  G: Race  Escape: Back",
+            state.config.graphics.ui_scale(),
+        );
+    }
+
+    fn focused_line(&self, _: &State) -> Option<String> {
+        highlighted_line(self.lines())
+    }
+}
+
+impl ScreenState for HistoryMenu {
+    fn handle_input(&mut self, state: &mut State, key: VirtualKeyCode, _: input::Key) -> bool {
+        let format = match key {
+            VirtualKeyCode::Escape => {
+                state.screen = Screen::Menu(Menu);
+                return true;
+            }
+            VirtualKeyCode::C => history::ExportFormat::Csv,
+            VirtualKeyCode::J => history::ExportFormat::Json,
+            _ => {
+                self.process_input(key);
+                return true;
+            }
+        };
+        let message = match history::History::load().export(format) {
+            Ok(path) => format!("Saved {}", path.display()),
+            Err(e) => format!("Failed to export history: {}", e),
+        };
+        state.audio_message = Some((message, Instant::now()));
+        true
+    }
+
+    fn render(&self, state: &mut State) {
+        let mut lines = vec![(self.filter_line(), true)];
+        lines.extend(self.lines().into_iter().map(|line| (line, false)));
+        queue_list_screen(
+            &mut state.glyph_brush,
+            "History",
+            &lines,
+            "Up/Down: Scroll  Left/Right: Filter  C/J: Export CSV/JSON  Escape: Back",
+            state.config.graphics.ui_scale(),
+        );
+    }
+
+    fn focused_line(&self, _: &State) -> Option<String> {
+        Some(self.filter_line())
+    }
+}
+
+impl ScreenState for TrophyMenu {
+    fn handle_input(&mut self, state: &mut State, key: VirtualKeyCode, _: input::Key) -> bool {
+        match key {
+            VirtualKeyCode::Escape | VirtualKeyCode::Return => state.screen = Screen::Menu(Menu),
+            _ => self.process_input(key),
+        }
+        true
+    }
+
+    fn render(&self, state: &mut State) {
+        queue_list_screen(
+            &mut state.glyph_brush,
+            "Trophies",
+            &self.lines(),
+            "Up/Down: Select  Escape: Back",
+            state.config.graphics.ui_scale(),
+        );
+    }
+
+    fn focused_line(&self, _: &State) -> Option<String> {
+        highlighted_line(self.lines())
+    }
+}
+
+impl ScreenState for OnlineMenu {
+    fn handle_input(&mut self, state: &mut State, key: VirtualKeyCode, _: input::Key) -> bool {
+        if self.process_input(key) {
+            state.screen = Screen::Menu(Menu);
+        }
+        let input_delay = self.input_delay();
+        if input_delay != state.config.input_delay {
+            state.config.input_delay = input_delay;
+            state.config_changed();
+        }
+        true
+    }
+
+    fn type_char(&mut self, _: &mut State, c: char) -> bool {
+        OnlineMenu::type_char(self, c);
+        true
+    }
+
+    fn update(&mut self, state: &mut State) {
+        match self.poll() {
+            Some(online::Started::Match(online)) => state.start_online(online),
+            Some(online::Started::Spectate(spectate)) => state.start_spectating(spectate),
+            None => {}
+        }
+    }
+
+    fn render(&self, state: &mut State) {
+        queue_list_screen(
+            &mut state.glyph_brush,
+            self.title(),
+            &self.lines(),
+            self.help(),
+            state.config.graphics.ui_scale(),
+        );
+    }
+
+    fn focused_line(&self, _: &State) -> Option<String> {
+        highlighted_line(self.lines())
+    }
+}
+
+impl ScreenState for TwitchPanel {
+    fn handle_input(&mut self, state: &mut State, key: VirtualKeyCode, _: input::Key) -> bool {
+        if key == VirtualKeyCode::Escape {
+            state.screen = Screen::Menu(Menu);
+        } else if self.process_input(key, &mut state.config.twitch, &mut state.chaos) {
+            state.config_changed();
+        }
+        true
+    }
+
+    fn type_char(&mut self, state: &mut State, c: char) -> bool {
+        if TwitchPanel::type_char(self, c, &mut state.config.twitch) {
+            state.config_changed();
+        }
+        true
+    }
+
+    fn render(&self, state: &mut State) {
+        let lines = self.lines(&state.config.twitch, &state.chaos);
+        queue_list_screen(
+            &mut state.glyph_brush,
+            "Twitch Chaos",
+            &lines,
+            "Type: Channel  Enter: Connect/Disconnect  Left/Right: Adjust  Escape: Back",
+            state.config.graphics.ui_scale(),
+        );
+    }
+
+    fn focused_line(&self, state: &State) -> Option<String> {
+        highlighted_line(self.lines(&state.config.twitch, &state.chaos))
+    }
+}
+
+impl ScreenState for VersusSetup {
+    fn handle_input(&mut self, state: &mut State, key: VirtualKeyCode, _: input::Key) -> bool {
+        if self.process_input(key, &mut state.config.versus_handicaps) {
+            state.config_changed();
+        }
+        match key {
+            VirtualKeyCode::Escape => state.screen = Screen::Menu(Menu),
+            VirtualKeyCode::Return if self.is_cpu() => state.start_cpu_game(state.config.cpu()),
+            VirtualKeyCode::Return => state.start_game(true),
+            _ => {}
+        }
+        true
+    }
+
+    fn render(&self, state: &mut State) {
+        let lines = self.lines(&state.config.versus_handicaps);
+        queue_list_screen(
+            &mut state.glyph_brush,
+            "Versus Handicaps",
+            &lines,
+            "Up/Down: Select  Left/Right: Adjust  Backspace: No handicap  Enter: Start  Escape: Back",
+            state.config.graphics.ui_scale(),
+        );
+    }
+
+    fn focused_line(&self, state: &State) -> Option<String> {
+        highlighted_line(self.lines(&state.config.versus_handicaps))
+    }
+}
+
+/// A game in progress.
+pub struct Playing;
+
+impl ScreenState for Playing {
+    fn handle_input(
+        &mut self,
+        state: &mut State,
+        key: VirtualKeyCode,
+        physical_key: input::Key,
+    ) -> bool {
+        let players = if state.is_local_versus() { 2 } else { 1 };
+        if key == VirtualKeyCode::Escape {
+            state.screen = Screen::Paused(Paused);
+        } else if state.is_practice() && key == VirtualKeyCode::F4 {
+            state.abandon_game();
+            state.restart_practice();
+        } else if state.is_practice() && matches!(key, VirtualKeyCode::F1 | VirtualKeyCode::F2) {
+            state.change_practice_speed(if key == VirtualKeyCode::F2 { 1 } else { -1 });
+        } else if state.is_practice() && state.save_state_input(key) {
+            // Handled by `save_state_input`
+        } else if (0..players).any(|p| state.key_profile(p).action(physical_key).is_some()) {
+            // Handled by the input states in `update`
+        } else if key == VirtualKeyCode::Space && state.online.is_none() {
+            state.abandon_game();
+            state.start_again();
+        }
+        true
+    }
+
+    fn close_requested(&mut self, state: &mut State) -> bool {
+        state.screen = Screen::Paused(Paused);
+        true
+    }
+
+    /// Advances every game in play and checks whether the game has finished.
+    fn update(&mut self, state: &mut State) {
+        let profile = if state.is_local_versus() {
+            state.config.versus_profile(0)
+        } else {
+            state.config.key_profile()
+        };
+        let toggle_soft_drop = state.config.toggle_soft_drop;
+        // Online, a topped out game waits for the other player's result
+        if !(state.online.is_some() && state.game.game_over) {
+            update_player(
+                &mut state.game,
+                &mut state.input_state,
+                &mut state.stats,
+                profile,
+                toggle_soft_drop,
+                state.online.as_mut().map(|online| &mut online.input_delay),
+            );
+        }
+        if let Some(ghost) = &mut state.ghost {
+            ghost.advance_to(state.game.time_ms());
+            ghost.dispatch_events(&mut []);
+        }
+        if let Some(versus) = &mut state.versus {
+            match &mut versus.cpu {
+                Some(cpu) => {
+                    cpu.update(&mut versus.game);
+                    versus.game.process_game_loop();
+                }
+                None => update_player(
+                    &mut versus.game,
+                    &mut versus.input_state,
+                    &mut versus.stats,
+                    state.config.versus_profile(1),
+                    toggle_soft_drop,
+                    None,
+                ),
+            }
+        }
+        if let Some(versus) = &mut state.versus {
+            let [attack1, attack2] = &mut versus.attacks;
+            state.game.dispatch_events(&mut [
+                &mut state.audio,
+                &mut state.stats,
+                &mut state.announcer,
+                &mut state.tracker,
+                &mut state.celebrations[0],
+                attack1,
+            ]);
+            match &mut versus.cpu {
+                Some(cpu) => versus.game.dispatch_events(&mut [
+                    &mut state.audio,
+                    &mut versus.stats,
+                    &mut state.celebrations[1],
+                    cpu,
+                    attack2,
+                ]),
+                None => versus.game.dispatch_events(&mut [
+                    &mut state.audio,
+                    &mut versus.stats,
+                    &mut state.celebrations[1],
+                    attack2,
+                ]),
+            }
+            state.celebrations[1].update(versus.game.time_ms());
+            // Each batch of garbage has its hole in a random column
+            versus.game.add_garbage(attack1.take(), rand::random());
+            state.game.add_garbage(attack2.take(), rand::random());
+        } else if let Some(online) = &mut state.online {
+            online.update(&state.game);
+            state.game.dispatch_events(&mut [
+                &mut state.audio,
+                &mut state.stats,
+                &mut state.announcer,
+                &mut state.tracker,
+                &mut state.celebrations[0],
+                &mut online.attack,
+            ]);
+            online.attack.take();
+            state
+                .game
+                .add_garbage(online.take_garbage(), rand::random());
+        } else if let Some(trainer) = &mut state.finesse {
+            for &action in Action::ALL.iter() {
+                if state.input_state.just_pressed(action) {
+                    trainer.press(action);
+                }
+            }
+            state.game.dispatch_events(&mut [
+                &mut state.audio,
+                &mut state.stats,
+                &mut state.announcer,
+                &mut state.tracker,
+                &mut state.celebrations[0],
+                trainer,
+            ]);
+            if trainer.finish(&state.game).is_some() {
+                state.game = trainer.game();
+                state.game.set_speed(PRACTICE_SPEEDS[state.practice_speed]);
+            }
+        } else {
+            state.update_mods();
+        }
+        state.celebrations[0].update(state.game.time_ms());
+        state.tracker.update(&state.game);
+        for achievement in state.tracker.take() {
+            state.unlock(achievement);
+        }
+        if !state.is_game_over() {
+            return;
+        }
+        match &state.versus {
+            Some(versus) => {
+                record_game(history::Mode::Versus, &state.game, &state.stats);
+                record_game(history::Mode::Versus, &versus.game, &versus.stats);
+                state.telemetry.game_finished(history::Mode::Versus);
+            }
+            None if state.online.is_some() => {
+                record_game(history::Mode::Versus, &state.game, &state.stats);
+                state.telemetry.game_finished(history::Mode::Versus);
+            }
+            None => state.finish_game(),
+        }
+        if !state.game.replay().is_practice() {
+            let profile = state.key_profile(0).name.clone();
+            if let Some(achievement) = state.achievements.record_game(&profile) {
+                state.toast(achievement);
+            }
+            if let Err(e) = state.achievements.save() {
+                eprintln!("Failed to save achievements: {}", e);
+            }
+        }
+        state.screen = match state.round_over() {
+            Some(round_over) => Screen::RoundOver(round_over),
+            None => Screen::GameOver(GameOver {
+                topped_out_at: Some(Instant::now()).filter(|_| state.game.topped_out()),
+            }),
+        };
+    }
+
+    fn render(&self, _: &mut State) {}
+}
+
+/// A game in progress, stopped behind the quit confirmation. An online match can't be
+/// stopped, so it plays on behind the confirmation instead.
+pub struct Paused;
+
+impl ScreenState for Paused {
+    fn handle_input(&mut self, state: &mut State, key: VirtualKeyCode, _: input::Key) -> bool {
+        if key == VirtualKeyCode::Escape {
+            // Left unhandled so the window closes
+            return false;
+        }
+        state.screen = Screen::Playing(Playing);
+        if state.online.is_some() {
+            return true;
+        }
+        state.game.resume();
+        if let Some(versus) = &mut state.versus {
+            versus.game.resume();
+        }
+        true
+    }
+
+    fn update(&mut self, state: &mut State) {
+        // The other player's game, inputs and chat keep arriving
+        if state.online.is_some() {
+            Playing.update(state);
+        }
+    }
+
+    fn render(&self, state: &mut State) {
+        queue_text(
+            state,
+            "Quit? Your run will be lost.\nPress escape to quit, or any other key to keep playing.",
+            (350.0, 250.0),
+            20.0,
+        );
+    }
+
+    fn focused_line(&self, _: &State) -> Option<String> {
+        Some(String::from(
+            "Paused. Press escape to quit, or any other key to keep playing",
+        ))
+    }
+}
+
+/// Handles input once the game has finished, both over the boards and on the results
+/// screen.
+///
+/// # Parameters
+///
+/// - `state`: Everything but the screen.
+/// - `key`: The key which was pressed.
+/// - `results`: `true` on the results screen.
+fn finished_input(state: &mut State, key: VirtualKeyCode, results: bool) {
+    match key {
+        VirtualKeyCode::F4 if state.is_practice() => state.restart_practice(),
+        VirtualKeyCode::F9 if state.is_practice() => {
+            state.save_state_input(key);
+        }
+        VirtualKeyCode::V if state.versus.is_some() => state.rematch(),
+        VirtualKeyCode::Space => state.start_again(),
+        VirtualKeyCode::Return if !results => state.screen = Screen::Results(Results),
+        VirtualKeyCode::Return | VirtualKeyCode::Escape => {
+            // Dropping an online match tells the other player this one left
+            state.online = None;
+            state.screen = Screen::Menu(Menu);
+        }
+        _ => {}
+    }
+}
+
+/// Keeps chat and rematch requests arriving between online rounds, and starts the
+/// rematch once both players have asked for it.
+fn update_finished(state: &mut State) {
+    let mut rematch = false;
+    if let Some(online) = &mut state.online {
+        online.update(&state.game);
+        rematch = online.take_rematch();
+    }
+    if rematch {
+        if let Some(online) = state.online.take() {
+            state.start_online(online);
+        }
+    }
+}
+
+/// A finished game, with the outcome shown over the boards once the board has filled
+/// up.
+pub struct GameOver {
+    /// When the stack reached the top, which starts the board filling with grey blocks
+    /// from the bottom, or `None` if the game ended some other way.
+    pub topped_out_at: Option<Instant>,
+}
+
+impl ScreenState for GameOver {
+    fn handle_input(&mut self, state: &mut State, key: VirtualKeyCode, _: input::Key) -> bool {
+        finished_input(state, key, false);
+        true
+    }
+
+    fn update(&mut self, state: &mut State) {
+        update_finished(state);
+    }
+
+    fn render(&self, state: &mut State) {
+        // The outcome waits for the board to fill up
+        if let Some(at) = self.topped_out_at {
+            if state.top_out_rows_since(at) < state.game.rows_in_play() {
+                return;
+            }
+        }
+        let game_over_string = if state.new_best {
+            "Game Over.\nNew personal best!\nPress enter for results or space to play again."
+        } else {
+            "Game Over.\nPress enter for results or space to play again."
+        };
+        queue_text(state, game_over_string, (350.0, 250.0), 20.0);
+    }
+}
+
+impl ScreenState for RoundOver {
+    fn handle_input(&mut self, state: &mut State, key: VirtualKeyCode, _: input::Key) -> bool {
+        let online = state.online.is_some();
+        match self.process_input(key) {
+            Some(RoundChoice::Rematch) if online => {
+                if let Some(online) = &mut state.online {
+                    online.ask_rematch();
+                }
+            }
+            Some(RoundChoice::Rematch) => state.rematch(),
+            Some(RoundChoice::ChangeSettings) if online => {
+                state.online = None;
+                state.screen = Screen::Settings(SettingsMenu::new());
+            }
+            Some(RoundChoice::ChangeSettings) => {
+                let cpu = state.versus.as_ref().is_some_and(|v| v.cpu.is_some());
+                state.screen = Screen::VersusSetup(VersusSetup::new(cpu));
+            }
+            Some(RoundChoice::Quit) => {
+                state.online = None;
+                state.screen = Screen::Menu(Menu);
+            }
+            None if key == VirtualKeyCode::D => state.screen = Screen::Results(Results),
+            None => {}
+        }
+        true
+    }
+
+    fn update(&mut self, state: &mut State) {
+        update_finished(state);
+    }
+
+    fn render(&self, state: &mut State) {
+        let mut lines = self.lines();
+        if let Some(status) = state.online.as_ref().and_then(|o| o.rematch_status()) {
+            lines.push((status.to_string(), false));
+        }
+        lines.push((String::new(), false));
+        lines.push((
+            String::from(if state.online.is_some() {
+                "Up/Down: Select  Enter: Choose  D: Details  Tab: Chat"
+            } else {
+                "Up/Down: Select  Enter: Choose  D: Details"
+            }),
+            false,
+        ));
+        let lines: Vec<(String, bool)> = lines
+            .into_iter()
+            .map(|(line, highlighted)| (line + "\n", highlighted))
+            .collect();
+        let ui_scale = state.config.graphics.ui_scale();
+        let text = lines
+            .iter()
+            .map(|(line, highlighted)| {
+                let color = if *highlighted {
+                    [1.0, 1.0, 0.0, 1.0]
+                } else {
+                    [1.0, 1.0, 1.0, 1.0]
+                };
+                Text::new(line)
+                    .with_scale(20.0 * ui_scale)
+                    .with_color(color)
+            })
+            .collect();
+        state.glyph_brush.queue(Section {
+            screen_position: (150.0, 180.0),
+            text,
+            ..Section::default()
+        });
+    }
+
+    fn focused_line(&self, _: &State) -> Option<String> {
+        highlighted_line(self.lines())
+    }
+}
+
+/// Statistics of the finished game.
+pub struct Results;
+
+impl Results {
+    /// Returns the lines of the results screen, with headings highlighted.
+    fn lines(&self, state: &State) -> Vec<(String, bool)> {
+        let mut lines = Vec::new();
+        let names = state
+            .versus
+            .as_ref()
+            .map(|versus| versus.names())
+            .unwrap_or_default();
+        let games = match (&state.versus, &state.online) {
+            (Some(versus), _) => vec![
+                (&state.game, &state.stats, names[0].as_str()),
+                (&versus.game, &versus.stats, names[1].as_str()),
+            ],
+            (None, Some(online)) => vec![
+                (&state.game, &state.stats, "You"),
+                (online.remote.game(), online.remote.stats(), "Opponent"),
+            ],
+            (None, None) => vec![(&state.game, &state.stats, "")],
+        };
+        if state.new_best {
+            lines.push((String::from("New personal best!"), true));
+        }
+        if let Some(rating) = state.online.as_ref().and_then(|online| online.rating) {
+            lines.push((format!("Your rating: {}", rating), true));
+        }
+        for (game, stats, label) in games {
+            if !label.is_empty() {
+                lines.push((label.to_string(), true));
+            }
+            let secs = game.time_ms() as f64 / 1000.0;
+            let pieces_per_second = if secs > 0.0 {
+                stats.pieces as f64 / secs
+            } else {
+                0.0
+            };
+            let [singles, doubles, triples, tetrises] = stats.clears;
+            lines.push((
+                format!(
+                    "Score {}   Lines {}   Level {}",
+                    stats.score, stats.lines, stats.level
+                ),
+                false,
+            ));
+            lines.push((
+                format!(
+                    "Time {}   Pieces {} ({:.2}/s)",
+                    history::duration(game.time_ms()),
+                    stats.pieces,
+                    pieces_per_second
+                ),
+                false,
+            ));
+            if stats.keys > 0 {
+                lines.push((
+                    format!(
+                        "Keys {}   KPP {:.2}   APM {:.0}",
+                        stats.keys,
+                        stats.keys_per_piece(),
+                        stats.actions_per_minute(game.time_ms())
+                    ),
+                    false,
+                ));
+            }
+            lines.push((
+                format!(
+                    "Singles {}  Doubles {}  Triples {}  Tetrises {}",
+                    singles, doubles, triples, tetrises
+                ),
+                false,
+            ));
+        }
+        lines
+    }
+}
+
+impl ScreenState for Results {
+    fn handle_input(&mut self, state: &mut State, key: VirtualKeyCode, _: input::Key) -> bool {
+        finished_input(state, key, true);
+        true
+    }
+
+    fn update(&mut self, state: &mut State) {
+        update_finished(state);
+    }
+
+    fn render(&self, state: &mut State) {
+        let lines = self.lines(state);
+        let help = if state.versus.is_some() {
+            "Space: Play alone  V: Rematch  Enter/Escape: Title screen"
+        } else if state.online.is_some() {
+            "Space: Play alone  Tab: Chat  Enter/Escape: Title screen"
+        } else {
+            "Space: Play again  Enter/Escape: Title screen"
+        };
+        let ui_scale = state.config.graphics.ui_scale();
+        queue_list_screen(&mut state.glyph_brush, "Results", &lines, help, ui_scale);
+    }
+
+    fn focused_line(&self, _: &State) -> Option<String> {
+        Some(String::from("Results"))
+    }
+}
+
+impl ScreenState for Playback {
+    fn handle_input(&mut self, state: &mut State, key: VirtualKeyCode, _: input::Key) -> bool {
+        match key {
+            VirtualKeyCode::Escape => state.screen = Screen::Menu(Menu),
+            VirtualKeyCode::P => self.paused = !self.paused,
+            VirtualKeyCode::Up => self.change_speed(1),
+            VirtualKeyCode::Down => self.change_speed(-1),
+            VirtualKeyCode::R => self.restart(),
+            _ => {}
+        }
+        true
+    }
+
+    fn update(&mut self, state: &mut State) {
+        Playback::update(self);
+        self.dispatch_events(&mut [&mut state.audio, &mut state.celebrations[0]]);
+        state.celebrations[0].update(self.game().time_ms());
+    }
+
+    fn render(&self, state: &mut State) {
+        let status = if self.is_finished() {
+            String::from("Replay finished")
+        } else if self.paused {
+            String::from("Replay paused")
+        } else {
+            format!("Replay {}x", self.speed())
+        };
+        queue_help(
+            state,
+            &format!(
+                "{}\nP: Pause  Up/Down: Speed  R: Restart  Escape: Back",
+                status
+            ),
+        );
+    }
+}
+
+impl ScreenState for Spectate {
+    fn handle_input(&mut self, state: &mut State, key: VirtualKeyCode, _: input::Key) -> bool {
+        if key == VirtualKeyCode::Escape {
+            // Dropping the match tells the lobby to stop sending it
+            state.screen = Screen::Menu(Menu);
+        }
+        true
+    }
+
+    fn update(&mut self, _: &mut State) {
+        Spectate::update(self);
+    }
+
+    fn render(&self, state: &mut State) {
+        queue_help(state, &format!("{}\nEscape: Back", self.status()));
+    }
+}
+
+impl ScreenState for Exhibition {
+    fn handle_input(&mut self, state: &mut State, key: VirtualKeyCode, _: input::Key) -> bool {
+        match key {
+            VirtualKeyCode::Escape => state.screen = Screen::Menu(Menu),
+            VirtualKeyCode::Up => self.change_speed(1),
+            VirtualKeyCode::Down => self.change_speed(-1),
+            VirtualKeyCode::R => self.restart(),
+            _ => {}
+        }
+        true
+    }
+
+    fn update(&mut self, state: &mut State) {
+        let graphics = &state.config.graphics;
+        Exhibition::update(self, graphics.previews(), !graphics.reduced_motion);
+    }
+
+    fn render(&self, state: &mut State) {
+        queue_help(
+            state,
+            &format!(
+                "{}\nUp/Down: Speed  R: New match  Escape: Back",
+                self.status()
+            ),
+        );
+    }
+}
+
+impl ScreenState for Crash {
+    fn handle_input(&mut self, state: &mut State, key: VirtualKeyCode, _: input::Key) -> bool {
+        if !matches!(key, VirtualKeyCode::Return | VirtualKeyCode::Escape) {
+            return true;
+        }
+        state.screen = Screen::Menu(Menu);
+        self.discard();
+        if key == VirtualKeyCode::Escape {
+            return true;
+        }
+        match self.resume() {
+            Ok(playback) => {
+                state.start_game(false);
+                let (game, stats) = playback.into_parts();
+                state.game = game;
+                state.stats = stats;
+                if state.is_practice() {
+                    state.game.set_speed(PRACTICE_SPEEDS[state.practice_speed]);
+                }
+                if state.game.rhythm().is_some() {
+                    // The game is nudged back onto the music's beat as it plays
+                    state.audio.start_rhythm();
+                }
+                // Gravity shouldn't catch up on the time since the crash
+                state.game.resume();
+            }
+            Err(e) => eprintln!("Failed to resume the crashed game: {}", e),
+        }
+        true
+    }
+
+    fn render(&self, state: &mut State) {
+        let recovery_string = format!(
+            "The game crashed during your last run.\n\
+             If you report the bug, please attach {}\n\n\
+             Press enter to carry on playing, or escape to discard the run.",
+            self.report.display()
+        );
+        queue_text(state, &recovery_string, (200.0, 250.0), 20.0);
+    }
+}
@@ -0,0 +1,62 @@
+use crate::tetromino::TetrominoKind;
+use anyhow::*;
+use std::fmt;
+
+/// Word which ends a sequence to deal it over and over.
+const REPEAT: &str = "repeat";
+
+/// A fixed order of shapes to deal, e.g. for drilling an opener.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sequence {
+    /// The shapes, first dealt first.
+    pub kinds: Vec<TetrominoKind>,
+    /// Whether the shapes are dealt again from the start once they run out, instead
+    /// of random shapes following.
+    pub repeat: bool,
+}
+
+impl Sequence {
+    /// Reads a sequence written as shape letters, e.g. `LJSZTOI repeat`. Letters may
+    /// be lower case and separated by spaces or commas.
+    ///
+    /// # Parameters
+    ///
+    /// - `text`: The sequence, optionally followed by `repeat`.
+    pub fn parse(text: &str) -> Result<Self> {
+        let text = text.trim();
+        let repeat = text.to_ascii_lowercase().ends_with(REPEAT);
+        let letters = if repeat {
+            &text[..text.len() - REPEAT.len()]
+        } else {
+            text
+        };
+        let kinds = letters
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != ',')
+            .map(|c| {
+                TetrominoKind::ALL
+                    .iter()
+                    .copied()
+                    .find(|kind| format!("{:?}", kind).eq_ignore_ascii_case(&c.to_string()))
+                    .ok_or_else(|| anyhow!("Unknown shape in sequence: {}", c))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if kinds.is_empty() {
+            bail!("The sequence has no shapes");
+        }
+        Ok(Self { kinds, repeat })
+    }
+}
+
+impl fmt::Display for Sequence {
+    /// Writes the sequence the way `Sequence::parse` reads it.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for kind in &self.kinds {
+            write!(f, "{:?}", kind)?;
+        }
+        if self.repeat {
+            write!(f, " {}", REPEAT)?;
+        }
+        std::result::Result::Ok(())
+    }
+}
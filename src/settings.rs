@@ -0,0 +1,478 @@
+use crate::config::{Config, Resolution, TextureFilter, MAX_UI_SCALE, MIN_UI_SCALE};
+use wgputris::ai::{self, Difficulty};
+use wgputris::attack::AttackPreset;
+use wgputris::game::{HoldLimit, MAX_PREVIEWS, MAX_SOFT_DROP_FACTOR};
+use wgputris::kicks::KickPreset;
+use wgputris::palette::Palette;
+use wgputris::speed::SpeedPreset;
+use winit::event::VirtualKeyCode;
+
+const VOLUME_STEP: f32 = 0.1;
+const DAS_STEP_MS: u64 = 10;
+const MAX_DAS_MS: u64 = 500;
+const ARR_STEP_MS: u64 = 5;
+const MAX_ARR_MS: u64 = 200;
+const UI_SCALE_STEP: f32 = 0.25;
+
+/// An adjustable entry on the settings screen.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SettingsItem {
+    MasterVolume,
+    MusicVolume,
+    SfxVolume,
+    AudioCues,
+    ScreenReader,
+    Previews,
+    KeyProfile,
+    DelayedAutoShift,
+    AutoRepeatRate,
+    SoftDropFactor,
+    ToggleSoftDrop,
+    HoldLimit,
+    KickTable,
+    SpeedCurve,
+    VersusProfile1,
+    VersusProfile2,
+    VersusSpeedCurve,
+    AttackTable,
+    CpuBot,
+    CpuDifficulty,
+    ExhibitionPlayer1,
+    ExhibitionPlayer2,
+    Resolution,
+    UiScale,
+    TextureFilter,
+    Palette,
+    BlockSymbols,
+    HighContrast,
+    ReducedMotion,
+    Chat,
+    DiscordStatus,
+    Telemetry,
+}
+
+impl SettingsItem {
+    /// Every item, in the order they are listed on screen.
+    pub const ALL: [SettingsItem; 32] = [
+        SettingsItem::KeyProfile,
+        SettingsItem::DelayedAutoShift,
+        SettingsItem::AutoRepeatRate,
+        SettingsItem::SoftDropFactor,
+        SettingsItem::ToggleSoftDrop,
+        SettingsItem::HoldLimit,
+        SettingsItem::KickTable,
+        SettingsItem::SpeedCurve,
+        SettingsItem::VersusProfile1,
+        SettingsItem::VersusProfile2,
+        SettingsItem::VersusSpeedCurve,
+        SettingsItem::AttackTable,
+        SettingsItem::CpuBot,
+        SettingsItem::CpuDifficulty,
+        SettingsItem::ExhibitionPlayer1,
+        SettingsItem::ExhibitionPlayer2,
+        SettingsItem::MasterVolume,
+        SettingsItem::MusicVolume,
+        SettingsItem::SfxVolume,
+        SettingsItem::AudioCues,
+        SettingsItem::ScreenReader,
+        SettingsItem::Previews,
+        SettingsItem::Resolution,
+        SettingsItem::UiScale,
+        SettingsItem::TextureFilter,
+        SettingsItem::Palette,
+        SettingsItem::BlockSymbols,
+        SettingsItem::HighContrast,
+        SettingsItem::ReducedMotion,
+        SettingsItem::Chat,
+        SettingsItem::DiscordStatus,
+        SettingsItem::Telemetry,
+    ];
+
+    /// Name shown on the settings screen.
+    pub fn label(self) -> &'static str {
+        match self {
+            SettingsItem::MasterVolume => "Master Volume",
+            SettingsItem::MusicVolume => "Music Volume",
+            SettingsItem::SfxVolume => "Sound Effects Volume",
+            SettingsItem::AudioCues => "Piece Audio Cues",
+            SettingsItem::ScreenReader => "Screen Reader",
+            SettingsItem::KeyProfile => "Controls",
+            SettingsItem::DelayedAutoShift => "  Delayed Auto Shift",
+            SettingsItem::AutoRepeatRate => "  Auto Repeat Rate",
+            SettingsItem::SoftDropFactor => "  Soft Drop Factor",
+            SettingsItem::ToggleSoftDrop => "Toggle Soft Drop",
+            SettingsItem::VersusProfile1 => "Versus Player 1 Controls",
+            SettingsItem::VersusProfile2 => "Versus Player 2 Controls",
+            SettingsItem::HoldLimit => "Hold",
+            SettingsItem::KickTable => "Wall Kicks",
+            SettingsItem::SpeedCurve => "Speed Curve",
+            SettingsItem::VersusSpeedCurve => "Versus Speed Curve",
+            SettingsItem::AttackTable => "Versus Attack Table",
+            SettingsItem::CpuBot => "Computer Player",
+            SettingsItem::CpuDifficulty => "Computer Difficulty",
+            SettingsItem::ExhibitionPlayer1 => "Exhibition Left Player",
+            SettingsItem::ExhibitionPlayer2 => "Exhibition Right Player",
+            SettingsItem::UiScale => "Text and HUD Size",
+            SettingsItem::Resolution => "Resolution",
+            SettingsItem::TextureFilter => "Block Texture Filter",
+            SettingsItem::Palette => "Block Colors",
+            SettingsItem::BlockSymbols => "Block Symbols",
+            SettingsItem::HighContrast => "High Contrast",
+            SettingsItem::ReducedMotion => "Reduced Motion",
+            SettingsItem::Previews => "Preview Pieces",
+            SettingsItem::Chat => "Online Chat",
+            SettingsItem::DiscordStatus => "Show Game in Discord Status",
+            SettingsItem::Telemetry => "Share Anonymous Usage Data",
+        }
+    }
+
+    /// Current value formatted for display.
+    ///
+    /// # Parameters
+    ///
+    /// - `config`: Config to read the value from.
+    pub fn value(self, config: &Config) -> String {
+        match self {
+            SettingsItem::MasterVolume => slider(config.volume),
+            SettingsItem::MusicVolume => slider(config.music_volume),
+            SettingsItem::SfxVolume => slider(config.sfx_volume),
+            SettingsItem::AudioCues => on_off(config.audio_cues).to_string(),
+            SettingsItem::ScreenReader => on_off(config.screen_reader).to_string(),
+            SettingsItem::ToggleSoftDrop => on_off(config.toggle_soft_drop).to_string(),
+            SettingsItem::Chat => on_off(config.chat).to_string(),
+            SettingsItem::DiscordStatus => on_off(config.discord_status).to_string(),
+            SettingsItem::Telemetry => on_off(config.telemetry.enabled).to_string(),
+            SettingsItem::BlockSymbols => on_off(config.graphics.block_symbols).to_string(),
+            SettingsItem::HighContrast => on_off(config.graphics.high_contrast).to_string(),
+            SettingsItem::ReducedMotion => on_off(config.graphics.reduced_motion).to_string(),
+            SettingsItem::KeyProfile => format!("< {} >", config.key_profile().name),
+            SettingsItem::DelayedAutoShift => {
+                format!("{} ms", config.key_profile().handling.das_ms)
+            }
+            SettingsItem::AutoRepeatRate => match config.key_profile().handling.arr_ms {
+                0 => String::from("Instant"),
+                arr_ms => format!("{} ms", arr_ms),
+            },
+            SettingsItem::SoftDropFactor => {
+                format!("{}x", config.key_profile().handling.soft_drop_factor)
+            }
+            SettingsItem::VersusProfile1 => format!("< {} >", config.versus_profile(0).name),
+            SettingsItem::VersusProfile2 => format!("< {} >", config.versus_profile(1).name),
+            SettingsItem::AttackTable => format!("< {} >", config.attack_table.label()),
+            SettingsItem::HoldLimit => format!("< {} >", config.hold_limit.label()),
+            SettingsItem::KickTable => format!("< {} >", config.kick_table.label()),
+            SettingsItem::SpeedCurve => format!("< {} >", config.speed_curve.label()),
+            SettingsItem::VersusSpeedCurve => {
+                format!("< {} >", config.versus_speed_curve.label())
+            }
+            SettingsItem::CpuBot => format!("< {} >", config.cpu_bot_name()),
+            SettingsItem::CpuDifficulty => format!("< {} >", config.cpu_difficulty.label()),
+            SettingsItem::ExhibitionPlayer1 => exhibition_player(config, 0),
+            SettingsItem::ExhibitionPlayer2 => exhibition_player(config, 1),
+            SettingsItem::TextureFilter => match config.graphics.texture_filter {
+                TextureFilter::Nearest => String::from("< Sharp >"),
+                TextureFilter::Linear => String::from("< Smooth >"),
+            },
+            SettingsItem::Resolution => format!("< {} >", config.graphics.resolution.label()),
+            SettingsItem::Palette => format!("< {} >", config.graphics.palette.label()),
+            SettingsItem::UiScale => format!("{:.0}%", config.graphics.ui_scale() * 100.0),
+            SettingsItem::Previews => match config.graphics.previews() {
+                0 => String::from("None"),
+                previews => previews.to_string(),
+            },
+        }
+    }
+
+    /// Steps the value up or down.
+    ///
+    /// # Parameters
+    ///
+    /// - `config`: Config to modify.
+    /// - `direction`: `1` to increase, `-1` to decrease.
+    pub fn adjust(self, config: &mut Config, direction: i32) {
+        let step = VOLUME_STEP * direction as f32;
+        match self {
+            SettingsItem::MasterVolume => config.volume = (config.volume + step).clamp(0.0, 1.0),
+            SettingsItem::MusicVolume => {
+                config.music_volume = (config.music_volume + step).clamp(0.0, 1.0)
+            }
+            SettingsItem::SfxVolume => {
+                config.sfx_volume = (config.sfx_volume + step).clamp(0.0, 1.0)
+            }
+            SettingsItem::AudioCues => config.audio_cues = !config.audio_cues,
+            SettingsItem::ScreenReader => config.screen_reader = !config.screen_reader,
+            SettingsItem::ToggleSoftDrop => config.toggle_soft_drop = !config.toggle_soft_drop,
+            SettingsItem::Chat => config.chat = !config.chat,
+            SettingsItem::DiscordStatus => config.discord_status = !config.discord_status,
+            SettingsItem::Telemetry => config.telemetry.enabled = !config.telemetry.enabled,
+            SettingsItem::BlockSymbols => {
+                config.graphics.block_symbols = !config.graphics.block_symbols
+            }
+            SettingsItem::HighContrast => {
+                config.graphics.high_contrast = !config.graphics.high_contrast
+            }
+            SettingsItem::ReducedMotion => {
+                config.graphics.reduced_motion = !config.graphics.reduced_motion
+            }
+            SettingsItem::KeyProfile => {
+                config.profile = cycle_profile(config, &config.key_profile().name, direction)
+            }
+            SettingsItem::DelayedAutoShift => {
+                let handling = &mut config.key_profile_mut().handling;
+                handling.das_ms = step_u64(handling.das_ms, DAS_STEP_MS, direction, MAX_DAS_MS);
+            }
+            SettingsItem::AutoRepeatRate => {
+                let handling = &mut config.key_profile_mut().handling;
+                handling.arr_ms = step_u64(handling.arr_ms, ARR_STEP_MS, direction, MAX_ARR_MS);
+            }
+            SettingsItem::SoftDropFactor => {
+                let handling = &mut config.key_profile_mut().handling;
+                handling.soft_drop_factor = (handling.soft_drop_factor as i32 + direction)
+                    .clamp(1, MAX_SOFT_DROP_FACTOR as i32)
+                    as u32;
+            }
+            SettingsItem::VersusProfile1 => {
+                config.versus_profiles[0] =
+                    cycle_profile(config, &config.versus_profile(0).name, direction)
+            }
+            SettingsItem::VersusProfile2 => {
+                config.versus_profiles[1] =
+                    cycle_profile(config, &config.versus_profile(1).name, direction)
+            }
+            SettingsItem::AttackTable => {
+                let count = AttackPreset::ALL.len() as i32;
+                let current = AttackPreset::ALL
+                    .iter()
+                    .position(|&preset| preset == config.attack_table)
+                    .unwrap_or(0) as i32;
+                config.attack_table =
+                    AttackPreset::ALL[(current + direction).rem_euclid(count) as usize];
+            }
+            SettingsItem::HoldLimit => {
+                let count = HoldLimit::ALL.len() as i32;
+                let current = HoldLimit::ALL
+                    .iter()
+                    .position(|&limit| limit == config.hold_limit)
+                    .unwrap_or(0) as i32;
+                config.hold_limit =
+                    HoldLimit::ALL[(current + direction).rem_euclid(count) as usize];
+            }
+            SettingsItem::KickTable => {
+                let count = KickPreset::ALL.len() as i32;
+                let current = KickPreset::ALL
+                    .iter()
+                    .position(|&preset| preset == config.kick_table)
+                    .unwrap_or(0) as i32;
+                config.kick_table =
+                    KickPreset::ALL[(current + direction).rem_euclid(count) as usize];
+            }
+            SettingsItem::SpeedCurve | SettingsItem::VersusSpeedCurve => {
+                let preset = if self == SettingsItem::SpeedCurve {
+                    &mut config.speed_curve
+                } else {
+                    &mut config.versus_speed_curve
+                };
+                let count = SpeedPreset::ALL.len() as i32;
+                let current = SpeedPreset::ALL
+                    .iter()
+                    .position(|&p| p == *preset)
+                    .unwrap_or(0) as i32;
+                *preset = SpeedPreset::ALL[(current + direction).rem_euclid(count) as usize];
+            }
+            SettingsItem::CpuBot => {
+                let names = ai::bot_names();
+                let current = config.cpu_bot_name();
+                let current = names.iter().position(|name| *name == current).unwrap_or(0) as i32;
+                let next = (current + direction).rem_euclid(names.len() as i32) as usize;
+                config.cpu_bot = names[next].clone();
+            }
+            SettingsItem::CpuDifficulty => {
+                let count = Difficulty::ALL.len() as i32;
+                let current = Difficulty::ALL
+                    .iter()
+                    .position(|&d| d == config.cpu_difficulty)
+                    .unwrap_or(0) as i32;
+                config.cpu_difficulty =
+                    Difficulty::ALL[(current + direction).rem_euclid(count) as usize];
+            }
+            SettingsItem::ExhibitionPlayer1 => cycle_exhibition_player(config, 0, direction),
+            SettingsItem::ExhibitionPlayer2 => cycle_exhibition_player(config, 1, direction),
+            SettingsItem::Resolution => {
+                let count = Resolution::ALL.len() as i32;
+                let current = Resolution::ALL
+                    .iter()
+                    .position(|&resolution| resolution == config.graphics.resolution)
+                    .unwrap_or(0) as i32;
+                config.graphics.resolution =
+                    Resolution::ALL[(current + direction).rem_euclid(count) as usize];
+            }
+            SettingsItem::TextureFilter => {
+                config.graphics.texture_filter = match config.graphics.texture_filter {
+                    TextureFilter::Nearest => TextureFilter::Linear,
+                    TextureFilter::Linear => TextureFilter::Nearest,
+                }
+            }
+            SettingsItem::UiScale => {
+                config.graphics.ui_scale = (config.graphics.ui_scale()
+                    + UI_SCALE_STEP * direction as f32)
+                    .clamp(MIN_UI_SCALE, MAX_UI_SCALE)
+            }
+            SettingsItem::Previews => {
+                config.graphics.previews = (config.graphics.previews() as i32 + direction)
+                    .clamp(0, MAX_PREVIEWS as i32)
+                    as usize
+            }
+            SettingsItem::Palette => {
+                let count = Palette::ALL.len() as i32;
+                let current = Palette::ALL
+                    .iter()
+                    .position(|&palette| palette == config.graphics.palette)
+                    .unwrap_or(0) as i32;
+                config.graphics.palette =
+                    Palette::ALL[(current + direction).rem_euclid(count) as usize];
+            }
+        }
+    }
+}
+
+/// Steps a value up or down by `step`, keeping it between 0 and `max`.
+fn step_u64(value: u64, step: u64, direction: i32, max: u64) -> u64 {
+    if direction < 0 {
+        value.saturating_sub(step)
+    } else {
+        (value + step).min(max)
+    }
+}
+
+/// Returns the name of the profile before or after `current` in the config's list,
+/// wrapping around at either end.
+fn cycle_profile(config: &Config, current: &str, direction: i32) -> String {
+    let count = config.profiles.len() as i32;
+    let current = config
+        .profiles
+        .iter()
+        .position(|p| p.name == current)
+        .unwrap_or(0) as i32;
+    let next = (current + direction).rem_euclid(count) as usize;
+    config.profiles[next].name.clone()
+}
+
+/// Formats the bot and difficulty of one side of exhibition matches for display.
+fn exhibition_player(config: &Config, side: usize) -> String {
+    format!(
+        "< {} ({}) >",
+        config.exhibition_bot_name(side),
+        config.exhibition_difficulties[side].label()
+    )
+}
+
+/// Steps one side of exhibition matches through every difficulty of each bot in turn,
+/// wrapping around at either end.
+fn cycle_exhibition_player(config: &mut Config, side: usize, direction: i32) {
+    let players: Vec<(String, Difficulty)> = ai::bot_names()
+        .into_iter()
+        .flat_map(|name| Difficulty::ALL.iter().map(move |&d| (name.clone(), d)))
+        .collect();
+    let current = (
+        config.exhibition_bot_name(side),
+        config.exhibition_difficulties[side],
+    );
+    let current = players.iter().position(|p| *p == current).unwrap_or(0) as i32;
+    let next = (current + direction).rem_euclid(players.len() as i32) as usize;
+    let (bot, difficulty) = players[next].clone();
+    config.exhibition_bots[side] = bot;
+    config.exhibition_difficulties[side] = difficulty;
+}
+
+/// Draws a volume from 0.0 to 1.0 as a ten segment text slider.
+fn slider(volume: f32) -> String {
+    let filled = (volume * 10.0).round() as usize;
+    format!(
+        "[{}{}] {:.0}%",
+        "#".repeat(filled),
+        "-".repeat(10 - filled),
+        volume * 100.0
+    )
+}
+
+/// Formats a toggle for display.
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "On"
+    } else {
+        "Off"
+    }
+}
+
+/// The settings screen, listing every `SettingsItem` with one selected.
+pub struct SettingsMenu {
+    selected: usize,
+}
+
+impl SettingsMenu {
+    /// Creates a new `SettingsMenu` with the first item selected.
+    pub fn new() -> Self {
+        Self { selected: 0 }
+    }
+
+    /// Handles a key press on the settings screen.
+    ///
+    /// # Parameters
+    ///
+    /// - `key`: The key which was pressed.
+    /// - `config`: Config to modify.
+    ///
+    /// # Return Value
+    ///
+    /// `true` if `config` was changed
+    pub fn process_input(&mut self, key: VirtualKeyCode, config: &mut Config) -> bool {
+        let item = SettingsItem::ALL[self.selected];
+        match key {
+            VirtualKeyCode::Up => {
+                self.selected =
+                    (self.selected + SettingsItem::ALL.len() - 1) % SettingsItem::ALL.len();
+                false
+            }
+            VirtualKeyCode::Down => {
+                self.selected = (self.selected + 1) % SettingsItem::ALL.len();
+                false
+            }
+            VirtualKeyCode::Left => {
+                item.adjust(config, -1);
+                true
+            }
+            VirtualKeyCode::Right => {
+                item.adjust(config, 1);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns each line of the settings screen along with whether it is selected.
+    ///
+    /// # Parameters
+    ///
+    /// - `config`: Config to read values from.
+    pub fn lines(&self, config: &Config) -> Vec<(String, bool)> {
+        let mut lines: Vec<(String, bool)> = SettingsItem::ALL
+            .iter()
+            .enumerate()
+            .map(|(i, item)| {
+                (
+                    format!("{}: {}", item.label(), item.value(config)),
+                    i == self.selected,
+                )
+            })
+            .collect();
+        let conflicts = config.versus_profile(0).conflicts(config.versus_profile(1));
+        if !conflicts.is_empty() {
+            let keys: Vec<String> = conflicts.iter().map(|k| format!("{:?}", k)).collect();
+            lines.push((
+                format!("Warning: both versus players use {}", keys.join(", ")),
+                false,
+            ));
+        }
+        lines
+    }
+}
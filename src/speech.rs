@@ -0,0 +1,246 @@
+//! Spoken announcements of what happens in a game and which menu line is selected, for
+//! blind and low vision players. Speech goes through the system's own text to speech:
+//! `say` on macOS, PowerShell's speech synthesizer on Windows and speech-dispatcher's
+//! `spd-say` elsewhere, which screen readers such as Orca also speak through.
+
+use std::collections::VecDeque;
+use std::io;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+use wgputris::game::{ClearKind, EventHandler, GameEvent};
+
+/// Most announcements waiting behind the one being spoken. Older ones are dropped,
+/// as they're out of date by the time they would be spoken.
+const MAX_QUEUED: usize = 2;
+/// How often the speech thread checks whether the current announcement has finished.
+const POLL_MS: u64 = 20;
+
+/// Something for the speech thread to do.
+enum Request {
+    /// Speak once everything before it has been spoken.
+    Say(String),
+    /// Stop speaking, forget anything waiting, and speak this instead.
+    Interrupt(String),
+}
+
+/// Speaks game events and menu focus changes while turned on. Speech runs on its own
+/// thread, so a slow speech command never holds up a frame.
+pub struct Announcer {
+    enabled: bool,
+    sender: Option<Sender<Request>>,
+    /// The selected menu line last announced, so it's only spoken when it changes.
+    focus: Option<String>,
+}
+
+impl Announcer {
+    /// Creates a new `Announcer`, starting the speech thread the first time it's
+    /// turned on.
+    ///
+    /// # Parameters
+    ///
+    /// - `enabled`: Whether anything is spoken.
+    pub fn new(enabled: bool) -> Self {
+        let mut announcer = Self {
+            enabled: false,
+            sender: None,
+            focus: None,
+        };
+        announcer.set_enabled(enabled);
+        announcer
+    }
+
+    /// Turns announcements on or off.
+    ///
+    /// # Parameters
+    ///
+    /// - `enabled`: Whether anything is spoken.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if enabled && self.sender.is_none() {
+            let (sender, receiver) = mpsc::channel();
+            thread::spawn(move || run(receiver));
+            self.sender = Some(sender);
+        }
+        if enabled && !self.enabled {
+            self.interrupt("Screen reader on");
+        }
+        self.enabled = enabled;
+    }
+
+    /// Speaks a line after anything already being spoken.
+    ///
+    /// # Parameters
+    ///
+    /// - `text`: What to say.
+    pub fn say(&self, text: &str) {
+        self.send(Request::Say(text.to_string()));
+    }
+
+    /// Stops whatever is being spoken and speaks a line straight away.
+    ///
+    /// # Parameters
+    ///
+    /// - `text`: What to say.
+    pub fn interrupt(&self, text: &str) {
+        self.send(Request::Interrupt(text.to_string()));
+    }
+
+    /// Announces the selected line of a menu if it has changed since the last call.
+    /// Moving within a menu cuts off anything still being spoken, as the player has
+    /// moved on, while a newly shown menu waits, e.g. for "Game over".
+    ///
+    /// # Parameters
+    ///
+    /// - `line`: The selected line, or `None` if the screen has no menu.
+    pub fn focus(&mut self, line: Option<String>) {
+        if line == self.focus {
+            return;
+        }
+        match (&line, &self.focus) {
+            (Some(line), Some(_)) => self.interrupt(line),
+            (Some(line), None) => self.say(line),
+            (None, _) => {}
+        }
+        self.focus = line;
+    }
+
+    fn send(&self, request: Request) {
+        if !self.enabled {
+            return;
+        }
+        if let Some(sender) = &self.sender {
+            // The thread only stops if speaking panicked, which leaves the game silent
+            let _ = sender.send(request);
+        }
+    }
+}
+
+impl EventHandler for Announcer {
+    /// Announces each new shape, line clears, level ups and the end of the game.
+    fn handle_event(&mut self, event: &GameEvent) {
+        match *event {
+            GameEvent::Spawned(kind) => self.say(&format!("{:?}", kind)),
+            GameEvent::LinesCleared {
+                kind,
+                t_spin,
+                perfect_clear,
+                ..
+            } => {
+                let name = match kind {
+                    ClearKind::Single => "Single",
+                    ClearKind::Double => "Double",
+                    ClearKind::Triple => "Triple",
+                    ClearKind::Tetris => "Tetris",
+                };
+                let mut text = String::new();
+                if t_spin {
+                    text += "T-spin ";
+                }
+                text += name;
+                if perfect_clear {
+                    text += ", perfect clear";
+                }
+                self.say(&text);
+            }
+            GameEvent::LevelUp(level) => self.say(&format!("Level {}", level)),
+            GameEvent::GameOver => self.interrupt("Game over"),
+            _ => {}
+        }
+    }
+}
+
+/// Speaks requests one after another until the `Announcer` is dropped.
+fn run(receiver: Receiver<Request>) {
+    let mut queue = VecDeque::new();
+    let mut speaking: Option<Child> = None;
+    // Failing once most likely means there's no speech command, so only say so once
+    let mut warned = false;
+    loop {
+        match receiver.recv_timeout(Duration::from_millis(POLL_MS)) {
+            Ok(Request::Say(text)) => {
+                queue.push_back(text);
+                if queue.len() > MAX_QUEUED {
+                    queue.pop_front();
+                }
+            }
+            Ok(Request::Interrupt(text)) => {
+                if let Some(mut child) = speaking.take() {
+                    stop(&mut child);
+                }
+                queue.clear();
+                queue.push_back(text);
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+        if let Some(child) = &mut speaking {
+            if let Ok(None) = child.try_wait() {
+                continue;
+            }
+        }
+        speaking = None;
+        if let Some(text) = queue.pop_front() {
+            match speak(&text) {
+                Ok(child) => speaking = Some(child),
+                Err(e) if !warned => {
+                    eprintln!("Failed to start text to speech: {}", e);
+                    warned = true;
+                }
+                Err(_) => {}
+            }
+        }
+    }
+}
+
+/// Starts speaking a line with the system's text to speech.
+///
+/// # Parameters
+///
+/// - `text`: What to say.
+///
+/// # Return Value
+///
+/// The speech command, which exits once the line has been spoken.
+fn speak(text: &str) -> io::Result<Child> {
+    let mut command = if cfg!(target_os = "macos") {
+        let mut command = Command::new("say");
+        command.arg(text);
+        command
+    } else if cfg!(windows) {
+        let mut command = Command::new("powershell");
+        command.args([
+            "-NoProfile",
+            "-Command",
+            &format!(
+                "Add-Type -AssemblyName System.Speech; \
+                 (New-Object System.Speech.Synthesis.SpeechSynthesizer).Speak('{}')",
+                text.replace('\'', "''")
+            ),
+        ]);
+        command
+    } else {
+        let mut command = Command::new("spd-say");
+        command.args(["--wait", "--", text]);
+        command
+    };
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+}
+
+/// Stops a line being spoken part way through.
+///
+/// # Parameters
+///
+/// - `child`: The speech command speaking it.
+fn stop(child: &mut Child) {
+    let _ = child.kill();
+    let _ = child.wait();
+    if !cfg!(any(target_os = "macos", windows)) {
+        // speech-dispatcher keeps speaking after `spd-say` is gone
+        let _ = Command::new("spd-say").arg("--cancel").status();
+    }
+}
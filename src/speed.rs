@@ -0,0 +1,206 @@
+//! Speed curves: how strong gravity is at each level, how long shapes may rest on the
+//! stack before locking and how long the next shape takes to appear. Curves are read
+//! from TOML files, with presets timed like a few well known games.
+
+use crate::game::GRAVITY_UNIT;
+use anyhow::*;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fs::read_to_string;
+use std::path::Path;
+
+/// File the custom speed curve is loaded from, in the config directory.
+pub const SPEED_FILE: &str = "speed.toml";
+/// Speed curves of the presets, as files to load or copy.
+pub const NES_CURVE: &str = include_str!("../assets/speed/nes.toml");
+pub const GUIDELINE_CURVE: &str = include_str!("../assets/speed/guideline.toml");
+pub const TGM_CURVE: &str = include_str!("../assets/speed/tgm.toml");
+/// Strongest gravity a curve may have, in cells per frame.
+const MAX_CURVE_GRAVITY: f64 = 20.0;
+
+/// Which speed curve a kind of game is played with.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpeedPreset {
+    /// Gravity speeding up steadily each level, with shapes locking on the next row of
+    /// gravity and no wait for the next shape.
+    Classic,
+    /// The NES original's gravity, with a short wait for each shape.
+    Nes,
+    /// The Tetris Guideline's gravity, with half a second of lock delay.
+    Guideline,
+    /// Tetris The Grand Master's gravity, lock delay and wait for each shape.
+    Tgm,
+    /// The curve in `SPEED_FILE`.
+    Custom,
+}
+
+impl SpeedPreset {
+    /// Every preset, in the order they are listed.
+    pub const ALL: [SpeedPreset; 5] = [
+        SpeedPreset::Classic,
+        SpeedPreset::Nes,
+        SpeedPreset::Guideline,
+        SpeedPreset::Tgm,
+        SpeedPreset::Custom,
+    ];
+
+    /// Name shown in menus.
+    pub fn label(self) -> &'static str {
+        match self {
+            SpeedPreset::Classic => "Classic",
+            SpeedPreset::Nes => "NES",
+            SpeedPreset::Guideline => "Guideline",
+            SpeedPreset::Tgm => "TGM",
+            SpeedPreset::Custom => "Custom",
+        }
+    }
+}
+
+/// Timings from one level of a speed curve on.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SpeedLevel {
+    /// First level these timings apply at.
+    pub level: usize,
+    /// Distance a shape falls each frame, in `GRAVITY_UNIT`ths of a cell.
+    pub gravity: u64,
+    /// Frames a shape may rest on the stack before it locks, counted again whenever
+    /// it falls a row, or `None` to lock on the next row of gravity.
+    pub lock_delay: Option<u32>,
+    /// Frames between a shape locking and the next one appearing.
+    pub are: u32,
+}
+
+/// One level of a speed curve file, as written, with gravity in cells per frame.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+struct LevelFile {
+    level: usize,
+    gravity: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    lock_delay: Option<u32>,
+    #[serde(default)]
+    are: u32,
+}
+
+/// A speed curve file, as written.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CurveFile {
+    level: Vec<LevelFile>,
+}
+
+/// Timings for every level, from a speed curve file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "CurveFile", into = "CurveFile")]
+pub struct SpeedCurve {
+    /// Timings in order of the level they start at, the first at level 1.
+    levels: Vec<SpeedLevel>,
+}
+
+impl SpeedCurve {
+    /// Returns the NES preset's curve.
+    pub fn nes() -> Self {
+        Self::parse(NES_CURVE).unwrap()
+    }
+
+    /// Returns the guideline preset's curve.
+    pub fn guideline() -> Self {
+        Self::parse(GUIDELINE_CURVE).unwrap()
+    }
+
+    /// Returns the TGM preset's curve.
+    pub fn tgm() -> Self {
+        Self::parse(TGM_CURVE).unwrap()
+    }
+
+    /// Reads a curve from the contents of a speed curve file.
+    ///
+    /// # Parameters
+    ///
+    /// - `text`: The file's contents, in the format of `GUIDELINE_CURVE`.
+    pub fn parse(text: &str) -> Result<Self> {
+        Ok(toml::from_str(text)?)
+    }
+
+    /// Loads a curve from a file, e.g. `SPEED_FILE`.
+    ///
+    /// # Parameters
+    ///
+    /// - `path`: The curve file.
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::parse(&read_to_string(path)?)
+    }
+
+    /// Returns the timings of a level.
+    ///
+    /// # Parameters
+    ///
+    /// - `level`: The level, from 1.
+    pub fn at(&self, level: usize) -> &SpeedLevel {
+        self.levels
+            .iter()
+            .rev()
+            .find(|timings| timings.level <= level)
+            .unwrap_or(&self.levels[0])
+    }
+}
+
+impl TryFrom<CurveFile> for SpeedCurve {
+    type Error = Error;
+
+    fn try_from(file: CurveFile) -> Result<Self> {
+        match file.level.first() {
+            Some(first) if first.level == 1 => {}
+            Some(_) => bail!("The speed curve doesn't start at level 1"),
+            None => bail!("The speed curve has no levels"),
+        }
+        if file
+            .level
+            .windows(2)
+            .any(|pair| pair[0].level >= pair[1].level)
+        {
+            bail!("The speed curve's levels aren't in order");
+        }
+        if let Some(timings) = file
+            .level
+            .iter()
+            .find(|timings| !(0.0..=MAX_CURVE_GRAVITY).contains(&timings.gravity))
+        {
+            bail!(
+                "Gravity of {} at level {} isn't between 0 and {}",
+                timings.gravity,
+                timings.level,
+                MAX_CURVE_GRAVITY
+            );
+        }
+        // Converted to whole units once here, rounding up so shapes never fall slower
+        // than the file asks, so games only ever add up gravity in integers
+        let levels = file
+            .level
+            .iter()
+            .map(|timings| SpeedLevel {
+                level: timings.level,
+                gravity: (timings.gravity * GRAVITY_UNIT as f64).ceil() as u64,
+                lock_delay: timings.lock_delay,
+                are: timings.are,
+            })
+            .collect();
+        Ok(Self { levels })
+    }
+}
+
+impl From<SpeedCurve> for CurveFile {
+    fn from(curve: SpeedCurve) -> Self {
+        Self {
+            level: curve
+                .levels
+                .iter()
+                .map(|timings| LevelFile {
+                    level: timings.level,
+                    gravity: timings.gravity as f64 / GRAVITY_UNIT as f64,
+                    lock_delay: timings.lock_delay,
+                    are: timings.are,
+                })
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use crate::game::{EventHandler, GameEvent};
+
+/// Running totals of one game, kept up to date from its events.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stats {
+    pub score: usize,
+    pub lines: usize,
+    pub level: usize,
+    /// Number of shapes locked to the board.
+    pub pieces: usize,
+    /// Number of singles, doubles, triples and tetrises cleared.
+    pub clears: [usize; 4],
+    /// Number of keys pressed for actions, not counting auto repeats. Only counted for
+    /// players at the keyboard, not replays or the computer.
+    pub keys: usize,
+}
+
+impl Stats {
+    /// Creates a new `Stats` for a game which hasn't started.
+    pub fn new() -> Self {
+        Self {
+            score: 0,
+            lines: 0,
+            level: 1,
+            pieces: 0,
+            clears: [0; 4],
+            keys: 0,
+        }
+    }
+
+    /// Returns the keys pressed for each shape locked, or 0 before the first.
+    pub fn keys_per_piece(&self) -> f64 {
+        if self.pieces == 0 {
+            return 0.0;
+        }
+        self.keys as f64 / self.pieces as f64
+    }
+
+    /// Returns the keys pressed for actions each minute.
+    ///
+    /// # Parameters
+    ///
+    /// - `time_ms`: Game time played so far, in milliseconds.
+    pub fn actions_per_minute(&self, time_ms: u64) -> f64 {
+        if time_ms == 0 {
+            return 0.0;
+        }
+        self.keys as f64 * 60_000.0 / time_ms as f64
+    }
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventHandler for Stats {
+    fn handle_event(&mut self, event: &GameEvent) {
+        match *event {
+            GameEvent::PieceLocked { .. } => self.pieces += 1,
+            GameEvent::LinesCleared { n, kind, .. } => {
+                self.lines += n;
+                self.clears[kind as usize] += 1;
+            }
+            GameEvent::ScoreChanged(score) => self.score = score,
+            GameEvent::LevelUp(level) => self.level = level,
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,293 @@
+//! The Tetris Bot Protocol (TBP), which lets bots written for other games play as the
+//! computer: messages are JSON objects, one a line, over the bot's standard input and
+//! output, or over a TCP connection.
+//!
+//! Each shape is a fresh `start`, `suggest` and `stop`, so the bot never has to follow
+//! the game between shapes, and a bot which falls behind only costs one shape.
+
+use crate::action::Action;
+use crate::ai::{self, Bot, BotView, Decision, Difficulty, Heuristic, Weights};
+use crate::tetromino::TetrominoKind;
+use crate::GAMEBOARD_HEIGHT;
+
+use anyhow::*;
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+use web_time::Instant;
+
+/// Prefix of a target which is a bot to connect to, rather than a command to run.
+const TCP_PREFIX: &str = "tcp://";
+/// How long a bot has to introduce itself and accept the rules.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a bot has to suggest a move before the shape is hard dropped.
+const SUGGEST_TIMEOUT: Duration = Duration::from_secs(1);
+/// Rows of the board sent to bots, which TBP fixes at 40 whatever the visible height.
+const BOARD_ROWS: usize = 40;
+
+/// A bot in another program, spoken to over TBP.
+pub struct ExternalBot {
+    /// The name the bot gave.
+    name: String,
+    writer: Box<dyn Write + Send>,
+    /// Messages read from the bot by a thread, so waiting for one can time out.
+    messages: Receiver<Value>,
+    /// The bot's process, if it was started by `launch`.
+    child: Option<Child>,
+}
+
+impl ExternalBot {
+    /// Starts a bot and waits until it is ready to play.
+    ///
+    /// # Parameters
+    ///
+    /// - `target`: A command which runs the bot, or `tcp://host:port` to connect to one.
+    pub fn open(target: &str) -> Result<Self> {
+        match target.strip_prefix(TCP_PREFIX) {
+            Some(address) => Self::connect(address),
+            None => Self::launch(target),
+        }
+    }
+
+    /// Runs a bot, speaking to it over its standard input and output.
+    ///
+    /// # Parameters
+    ///
+    /// - `command`: The program and its arguments, separated by spaces.
+    pub fn launch(command: &str) -> Result<Self> {
+        let mut parts = command.split_whitespace();
+        let program = parts.next().context("No bot command given")?;
+        let mut child = Command::new(program)
+            .args(parts)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to run {}", program))?;
+        let stdin = child.stdin.take().context("The bot has no input")?;
+        let stdout = child.stdout.take().context("The bot has no output")?;
+        Self::start(Box::new(stdin), Box::new(stdout), Some(child))
+    }
+
+    /// Connects to a bot listening for TCP connections.
+    ///
+    /// # Parameters
+    ///
+    /// - `address`: The bot's address, e.g. `localhost:5000`.
+    pub fn connect(address: &str) -> Result<Self> {
+        let stream = TcpStream::connect(address)
+            .with_context(|| format!("Failed to connect to {}", address))?;
+        stream.set_nodelay(true)?;
+        let reader = stream.try_clone()?;
+        Self::start(Box::new(stream), Box::new(reader), None)
+    }
+
+    /// Waits for the bot to introduce itself, then sends the rules and waits for it
+    /// to accept them.
+    fn start(
+        writer: Box<dyn Write + Send>,
+        reader: Box<dyn Read + Send>,
+        child: Option<Child>,
+    ) -> Result<Self> {
+        let (sender, messages) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(reader).lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => break,
+                };
+                match serde_json::from_str::<Value>(&line) {
+                    Ok(message) => {
+                        if sender.send(message).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => eprintln!("Ignored a message from the bot: {}", e),
+                }
+            }
+        });
+        let mut bot = Self {
+            name: String::new(),
+            writer,
+            messages,
+            child,
+        };
+        let info = bot.receive("info", HANDSHAKE_TIMEOUT)?;
+        bot.name = info["name"].as_str().unwrap_or("External bot").to_string();
+        bot.send(json!({ "type": "rules" }))?;
+        bot.receive("ready", HANDSHAKE_TIMEOUT)?;
+        Ok(bot)
+    }
+
+    /// Returns the name the bot gave.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Sends a message to the bot.
+    fn send(&mut self, message: Value) -> Result<()> {
+        writeln!(self.writer, "{}", message)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Waits for a message of one type from the bot, skipping any others.
+    ///
+    /// # Parameters
+    ///
+    /// - `kind`: The `type` of the message to wait for.
+    /// - `timeout`: How long to wait.
+    fn receive(&mut self, kind: &str, timeout: Duration) -> Result<Value> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let message = self
+                .messages
+                .recv_timeout(left)
+                .map_err(|_| anyhow!("The bot didn't send {} in time", kind))?;
+            match message["type"].as_str() {
+                Some(t) if t == kind => return Ok(message),
+                Some("error") => bail!("The bot refused: {}", message["reason"]),
+                _ => {}
+            }
+        }
+    }
+
+    /// Asks the bot where to put the current shape.
+    ///
+    /// # Return Value
+    ///
+    /// The placement, or an error if the bot failed to suggest one it can reach.
+    fn suggest(&mut self, view: &BotView) -> Result<ai::Placement> {
+        let mut queue = vec![letter(view.current)];
+        queue.extend(view.queue.iter().map(|&kind| letter(kind)));
+        let board: Vec<Vec<Value>> = (0..BOARD_ROWS)
+            .map(|row| {
+                (0..view.board[0].len())
+                    .map(|x| {
+                        // TBP counts rows up from the bottom
+                        let filled =
+                            row < GAMEBOARD_HEIGHT && view.board[GAMEBOARD_HEIGHT - 1 - row][x];
+                        if filled {
+                            json!("G")
+                        } else {
+                            Value::Null
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        self.send(json!({
+            "type": "start",
+            "hold": view.hold.map(letter),
+            "queue": queue,
+            "combo": 0,
+            "back_to_back": false,
+            "board": board,
+        }))?;
+        self.send(json!({ "type": "suggest" }))?;
+        let suggestion = self.receive("suggestion", SUGGEST_TIMEOUT);
+        self.send(json!({ "type": "stop" }))?;
+        let suggestion = suggestion?;
+        let location = &suggestion["moves"][0]["location"];
+        let kind = location["type"]
+            .as_str()
+            .and_then(parse_letter)
+            .context("The bot suggested no move")?;
+        let cells = cells(location).context("The bot suggested a move it can't make")?;
+        // The bot holds by suggesting a move for a shape other than the current one
+        let hold = kind != view.current;
+        ai::placement_for(&view.board, kind, hold, cells)
+            .context("The bot suggested a place out of reach")
+    }
+}
+
+impl Bot for ExternalBot {
+    fn decide(&mut self, view: &BotView) -> Decision {
+        match self.suggest(view) {
+            Ok(placement) => Decision::Place(placement),
+            Err(e) => {
+                eprintln!("{}: {:#}", self.name, e);
+                Decision::Actions(vec![Action::HardDrop])
+            }
+        }
+    }
+}
+
+impl Drop for ExternalBot {
+    fn drop(&mut self) {
+        let _ = self.send(json!({ "type": "quit" }));
+        if let Some(child) = &mut self.child {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+/// Lets a TBP bot be picked as the computer player, under the target it's opened from.
+/// Each match starts the bot afresh; if it can't be started, the built-in bot plays
+/// instead.
+///
+/// # Parameters
+///
+/// - `target`: The bot's command or address, see `ExternalBot::open`.
+pub fn register(target: &str) {
+    let owned = target.to_string();
+    ai::register_bot(target, move |difficulty: Difficulty| -> Box<dyn Bot> {
+        match ExternalBot::open(&owned) {
+            Ok(bot) => Box::new(bot),
+            Err(e) => {
+                eprintln!("Failed to start bot {}: {:#}", owned, e);
+                Box::new(Heuristic::new(Weights::trained(), difficulty.depth()))
+            }
+        }
+    });
+}
+
+/// Returns the TBP letter of a shape.
+fn letter(kind: TetrominoKind) -> String {
+    format!("{:?}", kind)
+}
+
+/// Reads a TBP shape letter.
+fn parse_letter(letter: &str) -> Option<TetrominoKind> {
+    TetrominoKind::ALL
+        .iter()
+        .copied()
+        .find(|kind| format!("{:?}", kind) == letter)
+}
+
+/// Returns the board cells covered by a shape at a TBP location, in the game's
+/// coordinates: rows counted down from the top of the visible board.
+///
+/// # Parameters
+///
+/// - `location`: The location's JSON, with its `type`, `orientation`, `x` and `y`.
+pub fn cells(location: &Value) -> Option<[(i32, i32); 4]> {
+    let kind = parse_letter(location["type"].as_str()?)?;
+    let x = location["x"].as_i64()? as i32;
+    let y = location["y"].as_i64()? as i32;
+    // Cells of each shape pointing north, with y counting up
+    let north = match kind {
+        TetrominoKind::I => [(-1, 0), (0, 0), (1, 0), (2, 0)],
+        TetrominoKind::O => [(0, 0), (1, 0), (0, 1), (1, 1)],
+        TetrominoKind::T => [(-1, 0), (0, 0), (1, 0), (0, 1)],
+        TetrominoKind::L => [(-1, 0), (0, 0), (1, 0), (1, 1)],
+        TetrominoKind::J => [(-1, 0), (0, 0), (1, 0), (-1, 1)],
+        TetrominoKind::S => [(-1, 0), (0, 0), (0, 1), (1, 1)],
+        TetrominoKind::Z => [(-1, 1), (0, 1), (0, 0), (1, 0)],
+    };
+    let turn: fn((i32, i32)) -> (i32, i32) = match location["orientation"].as_str()? {
+        "north" => |(dx, dy)| (dx, dy),
+        "east" => |(dx, dy)| (dy, -dx),
+        "south" => |(dx, dy)| (-dx, -dy),
+        "west" => |(dx, dy)| (-dy, dx),
+        _ => return None,
+    };
+    Some(north.map(|offset| {
+        let (dx, dy) = turn(offset);
+        (x + dx, GAMEBOARD_HEIGHT as i32 - 1 - (y + dy))
+    }))
+}
@@ -0,0 +1,315 @@
+//! Anonymous usage data, only collected once turned on in the settings: which graphics
+//! backend the game runs on, how long frames take, how many sessions end without the
+//! game closing normally and which modes are played. Nothing identifies the player.
+//! Totals are batched on disk and posted to the configured endpoint about once a day.
+
+use crate::config::TelemetryConfig;
+use crate::history::Mode;
+use anyhow::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{read_to_string, remove_file, write};
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use web_time::{Instant, SystemTime, UNIX_EPOCH};
+use wgputris::paths;
+
+/// File the batch waiting to be uploaded is kept in, in the data directory.
+const BATCH_FILE: &str = "telemetry.json";
+/// File which exists while a session is running, so a session which crashed or was
+/// killed is noticed the next time the game starts.
+const SESSION_FILE: &str = "telemetry-session";
+/// How long totals are batched before they're uploaded, in seconds.
+const UPLOAD_SECS: u64 = 24 * 60 * 60;
+/// Most frame times kept for a session's percentiles, about ten minutes at 60 FPS.
+const MAX_FRAME_SAMPLES: usize = 36_000;
+/// How long uploading waits for the endpoint to connect and answer.
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Anonymous totals since the last upload.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Batch {
+    /// When the batch was started, in seconds since the Unix epoch.
+    pub started_at: u64,
+    /// Sessions started.
+    pub sessions: usize,
+    /// Sessions which ended without the game closing normally.
+    pub crashed_sessions: usize,
+    /// Sessions on each graphics backend, e.g. `Vulkan`.
+    pub backends: BTreeMap<String, usize>,
+    /// Games finished in each mode.
+    pub modes: BTreeMap<String, usize>,
+    /// The 50th, 95th and 99th percentile frame times of each session, in
+    /// milliseconds.
+    pub frame_times: Vec<[f32; 3]>,
+}
+
+impl Batch {
+    /// Adds another batch's totals to this one's, keeping the earlier start.
+    ///
+    /// # Parameters
+    ///
+    /// - `other`: The other batch.
+    fn merge(&mut self, other: Batch) {
+        self.started_at = self.started_at.min(other.started_at);
+        self.sessions += other.sessions;
+        self.crashed_sessions += other.crashed_sessions;
+        for (backend, sessions) in other.backends {
+            *self.backends.entry(backend).or_default() += sessions;
+        }
+        for (mode, games) in other.modes {
+            *self.modes.entry(mode).or_default() += games;
+        }
+        self.frame_times.extend(other.frame_times);
+    }
+}
+
+/// Collects usage data for the session, if turned on.
+pub struct Telemetry {
+    enabled: bool,
+    /// Graphics backend the session runs on.
+    backend: String,
+    /// Where batches are posted, an `http://` URL, or empty to keep them on disk.
+    endpoint: String,
+    batch: Batch,
+    /// Time each frame of the session took, in milliseconds.
+    frame_ms: Vec<f32>,
+    /// When the last frame was counted, or `None` if the next frame starts afresh.
+    last_frame: Option<Instant>,
+    /// A batch being uploaded in the background, kept to put back if it fails.
+    upload: Option<(Batch, JoinHandle<Result<()>>)>,
+}
+
+impl Telemetry {
+    /// Starts collecting for a new session, if turned on, and uploads the batch in the
+    /// background if it's due.
+    ///
+    /// # Parameters
+    ///
+    /// - `config`: Whether telemetry is turned on, and where to upload.
+    /// - `backend`: Graphics backend the session runs on.
+    pub fn start(config: &TelemetryConfig, backend: &str) -> Self {
+        let mut telemetry = Self {
+            enabled: false,
+            backend: backend.to_string(),
+            endpoint: config.endpoint.clone(),
+            batch: Batch::default(),
+            frame_ms: Vec::new(),
+            last_frame: None,
+            upload: None,
+        };
+        if config.enabled {
+            telemetry.enable();
+        }
+        telemetry
+    }
+
+    /// Turns collection on or off. Turning it off deletes everything not yet uploaded.
+    ///
+    /// # Parameters
+    ///
+    /// - `config`: Whether telemetry is turned on, and where to upload.
+    pub fn apply_config(&mut self, config: &TelemetryConfig) {
+        self.endpoint = config.endpoint.clone();
+        if config.enabled && !self.enabled {
+            self.enable();
+        } else if !config.enabled && self.enabled {
+            self.enabled = false;
+            self.batch = Batch::default();
+            self.frame_ms.clear();
+            let _ = remove_file(paths::data_path(BATCH_FILE));
+            let _ = remove_file(paths::data_path(SESSION_FILE));
+        }
+    }
+
+    /// Loads the batch and counts a new session in it.
+    fn enable(&mut self) {
+        self.enabled = true;
+        self.batch = load_batch();
+        if self.batch.started_at == 0 {
+            self.batch.started_at = now_secs();
+        }
+        let session_path = paths::data_path(SESSION_FILE);
+        if session_path.exists() {
+            self.batch.crashed_sessions += 1;
+        }
+        if let Err(e) = write(&session_path, "") {
+            eprintln!("Failed to write {}: {}", session_path.display(), e);
+        }
+        self.batch.sessions += 1;
+        *self.batch.backends.entry(self.backend.clone()).or_default() += 1;
+        self.upload_if_due();
+        self.save();
+    }
+
+    /// Posts the batch in the background once it's a day old, starting a new one.
+    fn upload_if_due(&mut self) {
+        let due = now_secs().saturating_sub(self.batch.started_at) >= UPLOAD_SECS;
+        if !due || self.endpoint.is_empty() || self.upload.is_some() {
+            return;
+        }
+        let batch = std::mem::take(&mut self.batch);
+        self.batch.started_at = now_secs();
+        let endpoint = self.endpoint.clone();
+        let body = serde_json::to_string(&batch).unwrap_or_default();
+        let handle = thread::spawn(move || post(&endpoint, &body));
+        self.upload = Some((batch, handle));
+    }
+
+    /// Counts a frame's time, and checks on the upload. Should be called once a frame.
+    ///
+    /// # Parameters
+    ///
+    /// - `idle`: `true` while frames are held back to save power, which aren't
+    ///   counted.
+    pub fn frame(&mut self, idle: bool) {
+        if !self.enabled {
+            return;
+        }
+        let now = Instant::now();
+        if let (Some(last_frame), false) = (self.last_frame, idle) {
+            if self.frame_ms.len() < MAX_FRAME_SAMPLES {
+                self.frame_ms
+                    .push(now.duration_since(last_frame).as_secs_f32() * 1000.0);
+            }
+        }
+        self.last_frame = Some(now).filter(|_| !idle);
+        if self
+            .upload
+            .as_ref()
+            .is_some_and(|(_, handle)| handle.is_finished())
+        {
+            let (batch, handle) = self.upload.take().unwrap();
+            match handle.join() {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => {
+                    eprintln!("Failed to upload telemetry: {:#}", e);
+                    self.batch.merge(batch);
+                    self.save();
+                }
+                Err(_) => self.batch.merge(batch),
+            }
+        }
+    }
+
+    /// Counts a finished game, and uploads the batch in the background if it's due.
+    ///
+    /// # Parameters
+    ///
+    /// - `mode`: The kind of game.
+    pub fn game_finished(&mut self, mode: Mode) {
+        if !self.enabled {
+            return;
+        }
+        *self.batch.modes.entry(format!("{:?}", mode)).or_default() += 1;
+        self.upload_if_due();
+        self.save();
+    }
+
+    /// Ends the session normally, adding its frame times to the batch. Should be called
+    /// as the game closes.
+    pub fn finish(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        if let Some(percentiles) = percentiles(&mut self.frame_ms) {
+            self.batch.frame_times.push(percentiles);
+        }
+        // An upload still going is lost with the game, so its batch is kept to resend
+        if let Some((batch, _)) = self.upload.take() {
+            self.batch.merge(batch);
+        }
+        self.save();
+        let _ = remove_file(paths::data_path(SESSION_FILE));
+    }
+
+    /// Writes the batch to disk.
+    fn save(&self) {
+        let path = paths::data_path(BATCH_FILE);
+        let result = serde_json::to_string_pretty(&self.batch)
+            .map_err(Error::from)
+            .and_then(|json| Ok(write(&path, json)?));
+        if let Err(e) = result {
+            eprintln!("Failed to save {}: {:#}", path.display(), e);
+        }
+    }
+}
+
+/// Loads the batch waiting to be uploaded, or an empty one if there isn't one.
+fn load_batch() -> Batch {
+    read_to_string(paths::data_path(BATCH_FILE))
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+/// Returns the current time in seconds since the Unix epoch.
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
+
+/// Returns the 50th, 95th and 99th percentiles of some frame times, or `None` if there
+/// are none.
+///
+/// # Parameters
+///
+/// - `frame_ms`: The frame times, which are sorted.
+fn percentiles(frame_ms: &mut [f32]) -> Option<[f32; 3]> {
+    if frame_ms.is_empty() {
+        return None;
+    }
+    frame_ms.sort_unstable_by(|a, b| a.total_cmp(b));
+    let at = |fraction: f32| frame_ms[((frame_ms.len() - 1) as f32 * fraction).round() as usize];
+    Some([at(0.5), at(0.95), at(0.99)])
+}
+
+/// Posts JSON to an `http://` URL, waiting for the answer.
+///
+/// # Parameters
+///
+/// - `url`: Where to post, e.g. `http://example.com:8080/telemetry`.
+/// - `body`: The JSON to post.
+fn post(url: &str, body: &str) -> Result<()> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| anyhow!("Only http:// endpoints are supported: {}", url))?;
+    let (host, path) = match rest.find('/') {
+        Some(slash) => rest.split_at(slash),
+        None => (rest, "/"),
+    };
+    let address = if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{}:80", host)
+    };
+    let addr = address
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| anyhow!("Unknown address {}", address))?;
+    let mut stream = TcpStream::connect_timeout(&addr, UPLOAD_TIMEOUT)
+        .with_context(|| format!("Failed to connect to {}", address))?;
+    stream.set_read_timeout(Some(UPLOAD_TIMEOUT))?;
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        host,
+        body.len(),
+        body
+    )?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+    let response = String::from_utf8_lossy(&response);
+    // The status line reads like `HTTP/1.1 204 No Content`
+    let status = response.split_whitespace().nth(1).unwrap_or_default();
+    if !status.starts_with('2') {
+        bail!("{} answered {}", url, status);
+    }
+    Ok(())
+}
@@ -5,12 +5,43 @@ use crate::GAMEBOARD_OFFSET;
 
 use rand::prelude::*;
 
+/// The seven one-sided tetromino shapes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TetrominoKind {
+    O,
+    I,
+    S,
+    Z,
+    L,
+    J,
+    T,
+}
+
+impl TetrominoKind {
+    /// Every kind of tetromino.
+    pub const ALL: [TetrominoKind; 7] = [
+        TetrominoKind::O,
+        TetrominoKind::I,
+        TetrominoKind::S,
+        TetrominoKind::Z,
+        TetrominoKind::L,
+        TetrominoKind::J,
+        TetrominoKind::T,
+    ];
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct Tetromino {
     x: i32,
     y: i32,
+    kind: TetrominoKind,
     color: [f32; 4],
     block_locs: [(i32, i32); 4],
+    /// Point the shape rotates around relative to its position, in half blocks so it
+    /// can fall between cells. `(1, 1)` is the centre of the block at `(0, 0)`.
+    pivot: (i32, i32),
+    /// Quarter turns clockwise from the spawn orientation, from 0 to 3.
+    rotation: usize,
 }
 
 struct Block {
@@ -24,8 +55,11 @@ impl Tetromino {
         Self {
             x: 0,
             y: 0,
+            kind: TetrominoKind::O,
             color: [1.0, 1.0, 0.0, 1.0],
-            block_locs: [(0, 1), (1, 1), (0, 0), (1, 0)],
+            block_locs: [(0, -1), (1, -1), (0, 0), (1, 0)],
+            pivot: (2, 0),
+            rotation: 0,
         }
     }
 
@@ -34,8 +68,11 @@ impl Tetromino {
         Self {
             x: 0,
             y: 0,
+            kind: TetrominoKind::I,
             color: [0.0, 1.0, 1.0, 1.0],
-            block_locs: [(0, 0), (0, 1), (0, 2), (0, -1)],
+            block_locs: [(-1, 0), (0, 0), (1, 0), (2, 0)],
+            pivot: (2, 2),
+            rotation: 0,
         }
     }
 
@@ -44,8 +81,11 @@ impl Tetromino {
         Self {
             x: 0,
             y: 0,
+            kind: TetrominoKind::S,
             color: [1.0, 0.0, 0.0, 1.0],
-            block_locs: [(0, 1), (-1, 1), (0, 0), (1, 0)],
+            block_locs: [(0, -1), (1, -1), (-1, 0), (0, 0)],
+            pivot: (1, 1),
+            rotation: 0,
         }
     }
 
@@ -54,8 +94,11 @@ impl Tetromino {
         Self {
             x: 0,
             y: 0,
+            kind: TetrominoKind::Z,
             color: [0.0, 1.0, 0.0, 1.0],
-            block_locs: [(0, 0), (0, 1), (-1, 0), (1, 1)],
+            block_locs: [(-1, -1), (0, -1), (0, 0), (1, 0)],
+            pivot: (1, 1),
+            rotation: 0,
         }
     }
 
@@ -64,8 +107,11 @@ impl Tetromino {
         Self {
             x: 0,
             y: 0,
+            kind: TetrominoKind::L,
             color: [1.0, 0.55, 0.0, 1.0],
-            block_locs: [(0, 1), (0, 0), (0, -1), (-1, -1)],
+            block_locs: [(1, -1), (-1, 0), (0, 0), (1, 0)],
+            pivot: (1, 1),
+            rotation: 0,
         }
     }
 
@@ -74,8 +120,11 @@ impl Tetromino {
         Self {
             x: 0,
             y: 0,
+            kind: TetrominoKind::J,
             color: [1.0, 0.0, 1.0, 1.0],
-            block_locs: [(0, 1), (0, 0), (0, -1), (1, -1)],
+            block_locs: [(-1, -1), (-1, 0), (0, 0), (1, 0)],
+            pivot: (1, 1),
+            rotation: 0,
         }
     }
 
@@ -84,8 +133,29 @@ impl Tetromino {
         Self {
             x: 0,
             y: 0,
+            kind: TetrominoKind::T,
             color: [0.0, 0.0, 1.0, 1.0],
             block_locs: [(1, 0), (0, 0), (-1, 0), (0, -1)],
+            pivot: (1, 1),
+            rotation: 0,
+        }
+    }
+
+    /// Creates a new Tetromino of the given kind in its spawn orientation: lying flat,
+    /// with its lowest row level with its position.
+    ///
+    /// # Parameters
+    ///
+    /// - `kind`: Shape of the Tetromino to create.
+    pub fn new(kind: TetrominoKind) -> Self {
+        match kind {
+            TetrominoKind::O => Tetromino::new_o(),
+            TetrominoKind::I => Tetromino::new_i(),
+            TetrominoKind::S => Tetromino::new_s(),
+            TetrominoKind::Z => Tetromino::new_z(),
+            TetrominoKind::L => Tetromino::new_l(),
+            TetrominoKind::J => Tetromino::new_j(),
+            TetrominoKind::T => Tetromino::new_t(),
         }
     }
 
@@ -93,9 +163,8 @@ impl Tetromino {
     ///
     /// # Parameters
     ///
-    /// - `rng`: An initialized `ChaChaRng` random number generator from the
-    /// `rand_chacha` crate.
-    pub fn new_random(rng: &mut ThreadRng) -> Self {
+    /// - `rng`: An initialized random number generator.
+    pub fn new_random<R: Rng>(rng: &mut R) -> Self {
         let rand_num = rng.gen_range(0, 7);
         match rand_num {
             1 => Tetromino::new_o(),
@@ -108,25 +177,23 @@ impl Tetromino {
         }
     }
 
+    /// Returns the shape of this Tetromino.
+    pub fn kind(&self) -> TetrominoKind {
+        self.kind
+    }
+
+    /// Returns the colour of this Tetromino's blocks.
+    pub fn color(&self) -> [f32; 4] {
+        self.color
+    }
+
     fn as_blocks(&self) -> [Block; 4] {
-        [
-            Block {
-                x: (self.block_locs[0].0 + self.x) as f32 * BLOCK_SIZE as f32,
-                y: (self.block_locs[0].1 + self.y) as f32 * BLOCK_SIZE as f32,
-            },
-            Block {
-                x: (self.block_locs[1].0 + self.x) as f32 * BLOCK_SIZE as f32,
-                y: (self.block_locs[1].1 + self.y) as f32 * BLOCK_SIZE as f32,
-            },
-            Block {
-                x: (self.block_locs[2].0 + self.x) as f32 * BLOCK_SIZE as f32,
-                y: (self.block_locs[2].1 + self.y) as f32 * BLOCK_SIZE as f32,
-            },
-            Block {
-                x: (self.block_locs[3].0 + self.x) as f32 * BLOCK_SIZE as f32,
-                y: (self.block_locs[3].1 + self.y) as f32 * BLOCK_SIZE as f32,
-            },
-        ]
+        // Positions are on the board, while vertices are in screen blocks
+        let block = |(x, y): (i32, i32)| Block {
+            x: (x + GAMEBOARD_OFFSET.0 as i32) as f32 * BLOCK_SIZE as f32,
+            y: (y + GAMEBOARD_OFFSET.1 as i32) as f32 * BLOCK_SIZE as f32,
+        };
+        self.board_locs().map(block)
     }
 
     pub fn as_vertices(&self, buf: &mut [Vertex]) {
@@ -174,8 +241,8 @@ impl Tetromino {
     }
 
     /// Sets the position of a `Tetromino`.
-    /// Position is in block units, not screen units, i.e. screen units divided by
-    /// `BLOCK_SIZE`.
+    /// Position is in cells from the top left corner of the gameboard, and may be
+    /// outside it, e.g. for the next and held shapes beside the board.
     ///
     /// # Parameters
     ///
@@ -186,34 +253,42 @@ impl Tetromino {
         self.y = y;
     }
 
-    /// Rotates a `Tetromino` counter-clockwise.
+    /// Rotates a `Tetromino` counter-clockwise about its pivot.
     pub fn rotate_ccw(&mut self) {
-        for i in 0..4 {
-            self.block_locs[i] = (self.block_locs[i].1, 0 - self.block_locs[i].0);
-        }
+        self.rotate(|(x, y)| (y, -x));
+        self.rotation = (self.rotation + 3) % 4;
     }
 
-    /// Rotates a `Tetromino` clockwise.
+    /// Rotates a `Tetromino` clockwise about its pivot.
     pub fn rotate_cw(&mut self) {
-        for i in 0..4 {
-            self.block_locs[i] = (0 - self.block_locs[i].1, self.block_locs[i].0);
+        self.rotate(|(x, y)| (-y, x));
+        self.rotation = (self.rotation + 1) % 4;
+    }
+
+    /// Turns each block about the shape's pivot. The I and O shapes turn about the
+    /// corner between blocks, as the guideline has them, so they don't shift.
+    ///
+    /// # Parameters
+    ///
+    /// - `turn`: Quarter turn to apply to a block's offset from the pivot.
+    fn rotate(&mut self, turn: impl Fn((i32, i32)) -> (i32, i32)) {
+        let (px, py) = self.pivot;
+        for loc in self.block_locs.iter_mut() {
+            // Turn the block's centre, in half blocks, and map it back to its corner
+            let (x, y) = turn((2 * loc.0 + 1 - px, 2 * loc.1 + 1 - py));
+            *loc = ((x + px - 1) / 2, (y + py - 1) / 2);
         }
     }
 
-    /// Locks a `Tetromino` in place to a `Gameboard`
+    /// Locks a `Tetromino` in place to a `Gameboard`. Blocks outside the gameboard
+    /// are left out.
     ///
     /// # Parameters
     ///
     /// - `gameboard`: Mutable reference to a `Gameboard`.
     pub fn lock_to_gameboard(&self, gameboard: &mut Gameboard) {
-        for block_loc in self.block_locs.iter() {
-            gameboard
-                .set_content(
-                    (block_loc.0 + self.x - GAMEBOARD_OFFSET.0 as i32) as usize,
-                    (block_loc.1 + self.y - GAMEBOARD_OFFSET.1 as i32) as usize,
-                    Some(self.color),
-                )
-                .unwrap();
+        for (x, y) in self.board_locs().iter() {
+            let _ = gameboard.set_content_at(*x, *y, Some(self.color));
         }
     }
 
@@ -228,26 +303,25 @@ impl Tetromino {
         self.y += y;
     }
 
-    /// Returns the position of each block subtracted from `GAMEBOARD_OFFSET`.
-    /// Effectively, the position within a Gameboard.
-    pub fn get_mapped_locs(&self) -> [(usize, usize); 4] {
-        [
-            (
-                (self.block_locs[0].0 + self.x) as usize - GAMEBOARD_OFFSET.0,
-                (self.block_locs[0].1 + self.y) as usize - GAMEBOARD_OFFSET.1,
-            ),
-            (
-                (self.block_locs[1].0 + self.x) as usize - GAMEBOARD_OFFSET.0,
-                (self.block_locs[1].1 + self.y) as usize - GAMEBOARD_OFFSET.1,
-            ),
-            (
-                (self.block_locs[2].0 + self.x) as usize - GAMEBOARD_OFFSET.0,
-                (self.block_locs[2].1 + self.y) as usize - GAMEBOARD_OFFSET.1,
-            ),
-            (
-                (self.block_locs[3].0 + self.x) as usize - GAMEBOARD_OFFSET.0,
-                (self.block_locs[3].1 + self.y) as usize - GAMEBOARD_OFFSET.1,
-            ),
-        ]
+    /// Returns the position of the shape's centre on the gameboard.
+    pub fn pos(&self) -> (i32, i32) {
+        (self.x, self.y)
+    }
+
+    /// Returns how many quarter turns clockwise the shape is from its spawn
+    /// orientation, from 0 to 3.
+    pub fn rotation(&self) -> usize {
+        self.rotation
+    }
+
+    /// Returns the position of each block relative to the shape's centre.
+    pub fn block_locs(&self) -> [(i32, i32); 4] {
+        self.block_locs
+    }
+
+    /// Returns the position of each block on the gameboard. Positions past the left
+    /// or top edge are negative.
+    pub fn board_locs(&self) -> [(i32, i32); 4] {
+        self.block_locs.map(|(x, y)| (x + self.x, y + self.y))
     }
 }
@@ -1,79 +1,335 @@
+use crate::config::TextureFilter;
 use anyhow::*;
+use std::borrow::Cow;
 use std::io::Cursor;
+use wgputris::palette;
+
+/// Widest and tallest texture every graphics device can create.
+const MAX_TEXTURE_SIZE: u32 = 8192;
 
 pub struct Texture {
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
     pub sampler: wgpu::Sampler,
+    pub filter: TextureFilter,
 }
 
 impl Texture {
-    pub fn from_png_bytes(
+    /// Decodes the PNG image of one block into a new block atlas texture with a full
+    /// set of mipmaps, laid out by `wgputris::palette::block_atlas`. Grayscale, palette
+    /// and 16-bit images are converted to 8-bit RGBA.
+    ///
+    /// # Parameters
+    ///
+    /// - `device`: Device to create the texture on.
+    /// - `queue`: Queue to upload the image with.
+    /// - `bytes`: Contents of the PNG file.
+    /// - `filter`: How the texture is sampled.
+    pub fn block_atlas(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         bytes: &[u8],
-        label: &str,
+        filter: TextureFilter,
     ) -> Result<Self> {
+        // The decoder expands palettes and low bit depths, and strips 16-bit samples to
+        // 8 bits, by default
         let decoder = png::Decoder::new(Cursor::new(bytes));
-        let (info, mut reader) = decoder.read_info().unwrap();
+        let (info, mut reader) = decoder.read_info()?;
         let mut img = vec![0; info.buffer_size()];
-        reader.next_frame(&mut img).unwrap();
-        Self::from_image(device, queue, &img, info, Some(label))
+        reader.next_frame(&mut img)?;
+        let rgba = to_rgba(&img, &info)?;
+        if info.width == 0 || info.height == 0 {
+            bail!("The image is empty");
+        }
+        let most = MAX_TEXTURE_SIZE / palette::ATLAS_ROWS as u32;
+        if info.width > most || info.height > most {
+            bail!("The image is bigger than {}x{}", most, most);
+        }
+        let (atlas, width, height) = palette::block_atlas(&rgba, info.width, info.height);
+        Self::from_rgba(device, queue, &atlas, width, height, Some("block"), filter)
     }
 
-    pub fn from_image(
+    /// Decodes the PNG image of one block into a new block atlas texture, or returns a
+    /// plain white texture if the image can't be used, so a bad file leaves blocks flat
+    /// colored instead of stopping the game.
+    ///
+    /// # Parameters
+    ///
+    /// - `device`: Device to create the texture on.
+    /// - `queue`: Queue to upload the image with.
+    /// - `bytes`: Contents of the PNG file.
+    /// - `filter`: How the texture is sampled.
+    pub fn block_atlas_or_white(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        img: &[u8],
-        info: png::OutputInfo,
+        bytes: &[u8],
+        filter: TextureFilter,
+    ) -> Self {
+        match Self::block_atlas(device, queue, bytes, filter) {
+            Ok(texture) => texture,
+            Err(e) => {
+                eprintln!("Failed to load the block texture, using plain white: {}", e);
+                Self::white(device, queue, filter)
+            }
+        }
+    }
+
+    /// Creates a 1x1 white texture, which leaves whatever is drawn with it its vertex
+    /// color.
+    ///
+    /// # Parameters
+    ///
+    /// - `device`: Device to create the texture on.
+    /// - `queue`: Queue to upload the image with.
+    /// - `filter`: How the texture is sampled.
+    pub fn white(device: &wgpu::Device, queue: &wgpu::Queue, filter: TextureFilter) -> Self {
+        Self::upload(device, queue, &[255; 4], 1, 1, Some("white"), filter)
+    }
+
+    /// Creates a new texture with a full set of mipmaps from an 8-bit RGBA image.
+    ///
+    /// # Parameters
+    ///
+    /// - `device`: Device to create the texture on.
+    /// - `queue`: Queue to upload the image with.
+    /// - `rgba`: Pixels of the image, row by row with no padding.
+    /// - `width`: Width of the image in pixels.
+    /// - `height`: Height of the image in pixels.
+    /// - `label`: Name of the texture, for debugging.
+    /// - `filter`: How the texture is sampled.
+    pub fn from_rgba(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        rgba: &[u8],
+        width: u32,
+        height: u32,
         label: Option<&str>,
+        filter: TextureFilter,
     ) -> Result<Self> {
-        let size = wgpu::Extent3d {
-            width: info.width,
-            height: info.height,
-            depth: 1,
-        };
+        if width == 0 || height == 0 {
+            bail!("The image is empty");
+        }
+        let row_bytes = 4 * width as usize;
+        if rgba.len() != row_bytes * height as usize {
+            bail!(
+                "Expected {} bytes for a {}x{} image, found {}",
+                row_bytes * height as usize,
+                width,
+                height,
+                rgba.len()
+            );
+        }
+        Ok(Self::upload(
+            device, queue, rgba, width, height, label, filter,
+        ))
+    }
 
+    /// Creates a new texture with a full set of mipmaps from an 8-bit RGBA image whose
+    /// size is already checked.
+    ///
+    /// # Parameters
+    ///
+    /// - `device`: Device to create the texture on.
+    /// - `queue`: Queue to upload the image with.
+    /// - `rgba`: Pixels of the image, `width` by `height`, row by row with no padding.
+    /// - `width`: Width of the image in pixels, at least 1.
+    /// - `height`: Height of the image in pixels, at least 1.
+    /// - `label`: Name of the texture, for debugging.
+    /// - `filter`: How the texture is sampled.
+    fn upload(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        rgba: &[u8],
+        width: u32,
+        height: u32,
+        label: Option<&str>,
+        filter: TextureFilter,
+    ) -> Self {
+        // Each level is half the size of the one before, down to 1x1
+        let mip_level_count = 32 - width.max(height).leading_zeros();
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label,
-            size,
-            mip_level_count: 1,
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth: 1,
+            },
+            mip_level_count,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format: wgpu::TextureFormat::Rgba8Unorm,
             usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
         });
 
-        queue.write_texture(
-            wgpu::TextureCopyView {
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-            },
-            img,
-            wgpu::TextureDataLayout {
-                offset: 0,
-                bytes_per_row: 4 * info.width,
-                rows_per_image: info.height,
-            },
-            size,
-        );
+        let mut level = (Cow::Borrowed(rgba), width, height);
+        for mip_level in 0..mip_level_count {
+            let (img, width, height) = &level;
+            let (padded, bytes_per_row) = pad_rows(img, 4 * *width as usize);
+            queue.write_texture(
+                wgpu::TextureCopyView {
+                    texture: &texture,
+                    mip_level,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                &padded,
+                wgpu::TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row,
+                    rows_per_image: *height,
+                },
+                wgpu::Extent3d {
+                    width: *width,
+                    height: *height,
+                    depth: 1,
+                },
+            );
+            if mip_level + 1 < mip_level_count {
+                let (smaller, width, height) = downsample(img, *width, *height);
+                level = (smaller.into(), width, height);
+            }
+        }
 
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::Repeat,
-            address_mode_v: wgpu::AddressMode::Repeat,
-            address_mode_w: wgpu::AddressMode::Repeat,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            ..Default::default()
-        });
-
-        Ok(Self {
+        Self {
             texture,
             view,
-            sampler,
-        })
+            sampler: create_sampler(device, filter),
+            filter,
+        }
+    }
+
+    /// Changes how the texture is sampled. Bind groups using the texture have to be
+    /// created again to pick up the change.
+    ///
+    /// # Parameters
+    ///
+    /// - `device`: Device the texture was created on.
+    /// - `filter`: How the texture is sampled.
+    pub fn set_filter(&mut self, device: &wgpu::Device, filter: TextureFilter) {
+        self.sampler = create_sampler(device, filter);
+        self.filter = filter;
+    }
+}
+
+/// Creates a sampler which repeats the texture and filters it as chosen in the
+/// settings.
+///
+/// # Parameters
+///
+/// - `device`: Device to create the sampler on.
+/// - `filter`: How the texture is sampled.
+fn create_sampler(device: &wgpu::Device, filter: TextureFilter) -> wgpu::Sampler {
+    let mode = match filter {
+        TextureFilter::Nearest => wgpu::FilterMode::Nearest,
+        TextureFilter::Linear => wgpu::FilterMode::Linear,
+    };
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        address_mode_u: wgpu::AddressMode::Repeat,
+        address_mode_v: wgpu::AddressMode::Repeat,
+        address_mode_w: wgpu::AddressMode::Repeat,
+        mag_filter: mode,
+        min_filter: mode,
+        mipmap_filter: mode,
+        ..Default::default()
+    })
+}
+
+/// Halves an 8-bit RGBA image to make the next mipmap level, averaging each 2x2
+/// square of pixels. A side of odd length keeps its last row or column out.
+///
+/// # Parameters
+///
+/// - `img`: The image, row by row with no padding.
+/// - `width`: Width of the image in pixels.
+/// - `height`: Height of the image in pixels.
+///
+/// # Return Value
+///
+/// The smaller image and its width and height, each at least 1.
+fn downsample(img: &[u8], width: u32, height: u32) -> (Vec<u8>, u32, u32) {
+    let (new_width, new_height) = ((width / 2).max(1), (height / 2).max(1));
+    let pixel = |x: u32, y: u32, channel: usize| {
+        let x = x.min(width - 1) as usize;
+        let y = y.min(height - 1) as usize;
+        img[4 * (y * width as usize + x) + channel] as u32
+    };
+    let mut smaller = Vec::with_capacity(4 * new_width as usize * new_height as usize);
+    for y in 0..new_height {
+        for x in 0..new_width {
+            for channel in 0..4 {
+                let sum = pixel(2 * x, 2 * y, channel)
+                    + pixel(2 * x + 1, 2 * y, channel)
+                    + pixel(2 * x, 2 * y + 1, channel)
+                    + pixel(2 * x + 1, 2 * y + 1, channel);
+                smaller.push(((sum + 2) / 4) as u8);
+            }
+        }
+    }
+    (smaller, new_width, new_height)
+}
+
+/// Converts a decoded PNG image to 8-bit RGBA.
+///
+/// # Parameters
+///
+/// - `img`: The decoded image, as laid out by `info`.
+/// - `info`: Size and format of the decoded image.
+///
+/// # Return Value
+///
+/// The pixels row by row with no padding, or an error if the format isn't supported.
+fn to_rgba(img: &[u8], info: &png::OutputInfo) -> Result<Vec<u8>> {
+    if info.bit_depth != png::BitDepth::Eight {
+        bail!("Unsupported bit depth: {:?}", info.bit_depth);
+    }
+    let channels = match info.color_type {
+        png::ColorType::Grayscale => 1,
+        png::ColorType::GrayscaleAlpha => 2,
+        png::ColorType::RGB => 3,
+        png::ColorType::RGBA => 4,
+        color_type => bail!("Unsupported color type: {:?}", color_type),
+    };
+    let pixel_bytes = channels * info.width as usize;
+    if info.line_size < pixel_bytes || img.len() < info.buffer_size() {
+        bail!("The decoded image is smaller than its size");
+    }
+    let mut rgba = Vec::with_capacity(4 * info.width as usize * info.height as usize);
+    for row in img.chunks_exact(info.line_size).take(info.height as usize) {
+        for pixel in row[..pixel_bytes].chunks_exact(channels) {
+            match *pixel {
+                [gray] => rgba.extend_from_slice(&[gray, gray, gray, 255]),
+                [gray, alpha] => rgba.extend_from_slice(&[gray, gray, gray, alpha]),
+                [r, g, b] => rgba.extend_from_slice(&[r, g, b, 255]),
+                _ => rgba.extend_from_slice(pixel),
+            }
+        }
+    }
+    Ok(rgba)
+}
+
+/// Pads each row of an image to a multiple of `wgpu::COPY_BYTES_PER_ROW_ALIGNMENT`, as
+/// copies between buffers and textures require.
+///
+/// # Parameters
+///
+/// - `img`: The image, row by row with no padding.
+/// - `row_bytes`: Length of each row in bytes.
+///
+/// # Return Value
+///
+/// The padded image, and the length of each padded row in bytes.
+fn pad_rows(img: &[u8], row_bytes: usize) -> (Cow<'_, [u8]>, u32) {
+    let alignment = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as usize;
+    let padded_bytes = row_bytes.div_ceil(alignment) * alignment;
+    if padded_bytes == row_bytes {
+        return (img.into(), row_bytes as u32);
+    }
+    let mut padded = vec![0; padded_bytes * (img.len() / row_bytes)];
+    for (src, dst) in img
+        .chunks_exact(row_bytes)
+        .zip(padded.chunks_exact_mut(padded_bytes))
+    {
+        dst[..row_bytes].copy_from_slice(src);
     }
+    (padded.into(), padded_bytes as u32)
 }
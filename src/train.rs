@@ -0,0 +1,198 @@
+use anyhow::*;
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::cmp::Reverse;
+use std::path::Path;
+use web_time::Instant;
+use wgputris::ai::{self, BotView, Weights};
+use wgputris::game::Game;
+use wgputris::stats::Stats;
+
+/// Share of the population replaced by offspring each generation.
+const OFFSPRING: f64 = 0.3;
+/// Share of the population drawn for each tournament, whose two best are the parents.
+const TOURNAMENT: f64 = 0.1;
+/// Chance of an offspring having one weight nudged.
+const MUTATION_CHANCE: f64 = 0.05;
+/// Furthest a mutation nudges a weight, before the weights are scaled back to length 1.
+const MUTATION_SIZE: f64 = 0.2;
+
+/// Weights with how well they played.
+#[derive(Clone)]
+struct Candidate {
+    weights: [f64; 4],
+    /// Lines cleared over all of a generation's games.
+    lines: usize,
+}
+
+/// Evolves the built-in bot's weights with a genetic algorithm, then saves the best to
+/// the weights file the computer player loads.
+///
+/// Every candidate plays the same games each generation, so they're compared on the
+/// same shapes, and scored by the lines they clear before topping out. As boards are
+/// only ever compared with each other, weights are kept at length 1. The best weights
+/// are saved after every generation, so stopping early keeps the best so far.
+///
+/// # Parameters
+///
+/// - `population`: Number of candidates alive at once.
+/// - `generations`: Number of generations to breed after the first.
+/// - `games`: Games each candidate plays per generation.
+/// - `max_pieces`: Most shapes placed in one game, as good weights may never top out.
+/// - `seed`: Seed of the first game and of the algorithm's own choices.
+/// - `output`: Where the best weights are written, after every generation.
+pub fn run(
+    population: usize,
+    generations: usize,
+    games: usize,
+    max_pieces: usize,
+    seed: u64,
+    output: &Path,
+) -> Result<()> {
+    ensure!(
+        population >= 2,
+        "The population needs at least 2 candidates"
+    );
+    let mut rng = StdRng::seed_from_u64(seed);
+    let start = Instant::now();
+    let mut candidates: Vec<Candidate> = (0..population)
+        .map(|_| Candidate {
+            weights: normalize([(); 4].map(|_| rng.gen_range(-1.0, 1.0))),
+            lines: 0,
+        })
+        .collect();
+    let mut game_seed = seed;
+    evaluate(&mut candidates, game_seed, games, max_pieces);
+
+    for generation in 0..=generations {
+        if generation > 0 {
+            let count = ((population as f64 * OFFSPRING) as usize).max(1);
+            let mut offspring: Vec<Candidate> =
+                (0..count).map(|_| breed(&candidates, &mut rng)).collect();
+            // Everyone plays the new generation's games, so the survivors aren't kept
+            // for being lucky once
+            game_seed += games as u64;
+            candidates.truncate(population - count);
+            candidates.append(&mut offspring);
+            evaluate(&mut candidates, game_seed, games, max_pieces);
+        }
+        let best = &candidates[0];
+        let weights = to_weights(best.weights);
+        weights
+            .save(output)
+            .with_context(|| format!("Failed to save weights to {}", output.display()))?;
+        println!(
+            "Generation {}: {:.1} lines per game, {:?} ({:.0} s)",
+            generation,
+            best.lines as f64 / games.max(1) as f64,
+            weights,
+            start.elapsed().as_secs_f64()
+        );
+    }
+    println!("Saved the best weights to {}", output.display());
+    Ok(())
+}
+
+/// Plays every candidate's games, spread across the CPU's cores, and sorts them best
+/// first.
+///
+/// # Parameters
+///
+/// - `candidates`: The candidates, whose `lines` are replaced.
+/// - `seed`: Seed of the first game. Each game after it uses the next seed.
+/// - `games`: Games each candidate plays.
+/// - `max_pieces`: Most shapes placed in one game.
+fn evaluate(candidates: &mut [Candidate], seed: u64, games: usize, max_pieces: usize) {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk = candidates.len().div_ceil(threads).max(1);
+    std::thread::scope(|scope| {
+        for chunk in candidates.chunks_mut(chunk) {
+            scope.spawn(move || {
+                for candidate in chunk {
+                    let weights = to_weights(candidate.weights);
+                    candidate.lines = (seed..seed + games as u64)
+                        .map(|seed| play(Game::with_seed(seed), &weights, max_pieces))
+                        .sum();
+                }
+            });
+        }
+    });
+    candidates.sort_by_key(|candidate| Reverse(candidate.lines));
+}
+
+/// Plays one game with the built-in bot until it tops out or places `max_pieces`
+/// shapes.
+///
+/// # Parameters
+///
+/// - `game`: The game to play.
+/// - `weights`: How the bot rates boards.
+/// - `max_pieces`: Most shapes to place.
+///
+/// # Return Value
+///
+/// Lines cleared.
+fn play(mut game: Game, weights: &Weights, max_pieces: usize) -> usize {
+    let mut stats = Stats::new();
+    while !game.game_over && stats.pieces < max_pieces {
+        match ai::plan(&BotView::of(&game), weights, 1) {
+            Some(placement) => {
+                for action in placement.actions() {
+                    game.process_action(action);
+                }
+            }
+            None => break,
+        }
+        // The next shape spawns on the next step
+        game.advance(1);
+        game.dispatch_events(&mut [&mut stats]);
+    }
+    stats.lines
+}
+
+/// Creates an offspring of the two best of a random few candidates, leaning towards
+/// the better of the two.
+///
+/// # Parameters
+///
+/// - `candidates`: The population, best first.
+/// - `rng`: Source of the algorithm's choices.
+fn breed(candidates: &[Candidate], rng: &mut StdRng) -> Candidate {
+    let size = ((candidates.len() as f64 * TOURNAMENT) as usize).clamp(2, candidates.len());
+    let mut drawn = rand::seq::index::sample(rng, candidates.len(), size).into_vec();
+    // Lower indices are better, as the population is sorted
+    drawn.sort_unstable();
+    let (a, b) = (&candidates[drawn[0]], &candidates[drawn[1]]);
+    // Parents which cleared nothing still breed, evenly
+    let (share_a, share_b) = ((a.lines + 1) as f64, (b.lines + 1) as f64);
+    let mut weights = [0.0; 4];
+    for (i, weight) in weights.iter_mut().enumerate() {
+        *weight = a.weights[i] * share_a + b.weights[i] * share_b;
+    }
+    let mut weights = normalize(weights);
+    if rng.gen_bool(MUTATION_CHANCE) {
+        weights[rng.gen_range(0, 4)] += rng.gen_range(-MUTATION_SIZE, MUTATION_SIZE);
+        weights = normalize(weights);
+    }
+    Candidate { weights, lines: 0 }
+}
+
+/// Scales weights to length 1, which rates boards in the same order.
+fn normalize(weights: [f64; 4]) -> [f64; 4] {
+    let length = weights.iter().map(|w| w * w).sum::<f64>().sqrt();
+    if length > 0.0 {
+        weights.map(|w| w / length)
+    } else {
+        weights
+    }
+}
+
+/// Returns weights of the form the bot plays with.
+fn to_weights([height, lines, holes, bumpiness]: [f64; 4]) -> Weights {
+    Weights {
+        height,
+        lines,
+        holes,
+        bumpiness,
+    }
+}
@@ -0,0 +1,81 @@
+use wgputris::achievements::{Achievement, Progress};
+use winit::event::VirtualKeyCode;
+
+/// How long each achievement unlocked is announced for, in seconds.
+pub const TOAST_SECS: f64 = 3.0;
+
+/// The trophy screen, listing every achievement and whether the active profile has
+/// unlocked it.
+pub struct TrophyMenu {
+    profile: String,
+    progress: Progress,
+    selected: usize,
+}
+
+impl TrophyMenu {
+    /// Creates a new `TrophyMenu`.
+    ///
+    /// # Parameters
+    ///
+    /// - `profile`: Name of the key binding profile the achievements belong to.
+    /// - `progress`: The profile's achievements.
+    pub fn new(profile: &str, progress: Progress) -> Self {
+        Self {
+            profile: profile.to_string(),
+            progress,
+            selected: 0,
+        }
+    }
+
+    /// Handles a key press on the trophy screen.
+    ///
+    /// # Parameters
+    ///
+    /// - `key`: The key which was pressed.
+    pub fn process_input(&mut self, key: VirtualKeyCode) {
+        let len = Achievement::ALL.len() as i32;
+        let step = match key {
+            VirtualKeyCode::Up => -1,
+            VirtualKeyCode::Down => 1,
+            _ => return,
+        };
+        self.selected = (self.selected as i32 + step).rem_euclid(len) as usize;
+    }
+
+    /// Returns the lines of the trophy screen, each paired with whether it is
+    /// selected.
+    pub fn lines(&self) -> Vec<(String, bool)> {
+        let mut lines = vec![(
+            format!(
+                "{}: {} of {} unlocked, {} games",
+                self.profile,
+                self.progress.unlocked.len(),
+                Achievement::ALL.len(),
+                self.progress.games
+            ),
+            false,
+        )];
+        lines.extend(
+            Achievement::ALL
+                .iter()
+                .enumerate()
+                .map(|(i, &achievement)| {
+                    let mark = if self.progress.unlocked.contains(&achievement) {
+                        "[x]"
+                    } else {
+                        "[ ]"
+                    };
+                    (
+                        format!(
+                            "{} {} - {}",
+                            mark,
+                            achievement.name(),
+                            achievement.description()
+                        ),
+                        i == self.selected,
+                    )
+                }),
+        );
+        lines
+    }
+}
@@ -0,0 +1,483 @@
+use crate::config::TwitchConfig;
+use anyhow::*;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use web_time::Instant;
+use wgputris::tetromino::TetrominoKind;
+use winit::event::VirtualKeyCode;
+
+/// Twitch's IRC server, which lets anyone read a channel's chat without logging in.
+const SERVER: &str = "irc.chat.twitch.tv:6667";
+/// How long connecting waits for the server to answer.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+/// Rows of garbage a garbage vote sends.
+pub const GARBAGE_ROWS: usize = 2;
+/// How fast the game runs during a speed burst, as a multiple of normal speed.
+const BURST_SPEED: f64 = 1.5;
+/// How long a speed burst lasts.
+const BURST_TIME: Duration = Duration::from_secs(10);
+/// Longest a vote or cooldown can be set to, in seconds.
+const MAX_VOTE_SECS: u64 = 120;
+/// Step the vote and cooldown lengths are adjusted by, in seconds.
+const VOTE_STEP_SECS: u64 = 5;
+
+/// Something chat can vote to do to the game.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Effect {
+    /// Sends `GARBAGE_ROWS` rows of garbage.
+    Garbage,
+    /// Speeds the game up for a while.
+    Speed,
+    /// Picks the next shape.
+    Piece(TetrominoKind),
+}
+
+impl Effect {
+    /// Reads a chat message as a vote, if it is one: `!garbage`, `!speed`, or
+    /// `!piece` followed by a shape letter.
+    ///
+    /// # Parameters
+    ///
+    /// - `text`: The chat message.
+    /// - `config`: Which votes the streamer allows.
+    fn parse(text: &str, config: &TwitchConfig) -> Option<Self> {
+        let mut words = text.split_whitespace();
+        let effect = match words.next()?.to_ascii_lowercase().as_str() {
+            "!garbage" => Effect::Garbage,
+            "!speed" => Effect::Speed,
+            "!piece" => {
+                let letter = words.next()?;
+                let kind = TetrominoKind::ALL
+                    .iter()
+                    .copied()
+                    .find(|kind| format!("{:?}", kind).eq_ignore_ascii_case(letter))?;
+                Effect::Piece(kind)
+            }
+            _ => return None,
+        };
+        let allowed = match effect {
+            Effect::Garbage => config.garbage_votes,
+            Effect::Speed => config.speed_votes,
+            Effect::Piece(_) => config.piece_votes,
+        };
+        Some(effect).filter(|_| allowed)
+    }
+
+    /// Name shown in the vote tally and when the effect happens.
+    pub fn label(self) -> String {
+        match self {
+            Effect::Garbage => String::from("garbage"),
+            Effect::Speed => String::from("speed"),
+            Effect::Piece(kind) => format!("piece {:?}", kind),
+        }
+    }
+}
+
+/// A read-only connection to a channel's chat.
+struct Connection {
+    stream: TcpStream,
+    /// Bytes received which don't make up a whole line yet.
+    incoming: Vec<u8>,
+}
+
+impl Connection {
+    /// Connects to the chat of a channel, logging in anonymously.
+    ///
+    /// # Parameters
+    ///
+    /// - `channel`: Name of the channel, with or without the `#`.
+    fn open(channel: &str) -> Result<Self> {
+        let addr = SERVER
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| anyhow!("Unknown address {}", SERVER))?;
+        let mut stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)
+            .with_context(|| format!("Failed to connect to {}", SERVER))?;
+        // Anonymous logins are a "justinfan" followed by any number
+        let login = format!(
+            "NICK justinfan{}\r\nJOIN #{}\r\n",
+            rand::random::<u32>() % 100_000,
+            channel.trim_start_matches('#').to_ascii_lowercase()
+        );
+        stream.write_all(login.as_bytes())?;
+        stream.set_nonblocking(true)?;
+        Ok(Self {
+            stream,
+            incoming: Vec::new(),
+        })
+    }
+
+    /// Reads every chat message which has arrived, without waiting, and answers the
+    /// server's pings.
+    ///
+    /// # Return Value
+    ///
+    /// Who sent each message and what it said, oldest first.
+    fn receive(&mut self) -> Result<Vec<(String, String)>> {
+        let mut buffer = [0; 4096];
+        loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => bail!("Twitch closed the connection"),
+                Ok(read) => self.incoming.extend(&buffer[..read]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        let mut messages = Vec::new();
+        while let Some(end) = self.incoming.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.incoming.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end();
+            if let Some(server) = line.strip_prefix("PING") {
+                // Pongs are tiny, so they always fit in the socket's buffer
+                let _ = self
+                    .stream
+                    .write_all(format!("PONG{}\r\n", server).as_bytes());
+            } else if let Some(message) = parse_privmsg(line) {
+                messages.push(message);
+            }
+        }
+        Ok(messages)
+    }
+}
+
+/// Reads a chat message out of an IRC line such as
+/// `:name!name@name.tmi.twitch.tv PRIVMSG #channel :text`.
+///
+/// # Return Value
+///
+/// Who sent the message and what it said, or `None` if the line isn't a chat message.
+fn parse_privmsg(line: &str) -> Option<(String, String)> {
+    let (prefix, rest) = line.strip_prefix(':')?.split_once(' ')?;
+    let (command, rest) = rest.split_once(' ')?;
+    if command != "PRIVMSG" {
+        return None;
+    }
+    let (_, text) = rest.split_once(" :")?;
+    let user = prefix.split('!').next()?;
+    Some((user.to_string(), text.to_string()))
+}
+
+/// Votes from a channel's chat on chaos to cause in single player games. Each vote
+/// lasts `vote_secs`, with each viewer counted once, and the winning effect happens as
+/// it ends. The next vote starts `cooldown_secs` later.
+pub struct Chaos {
+    connection: Option<Connection>,
+    /// Channel connected to.
+    channel: String,
+    /// Why the connection failed or dropped, if it did.
+    error: Option<String>,
+    /// Each viewer's vote in the current round, by name.
+    votes: BTreeMap<String, Effect>,
+    /// When the current round started, or `None` between games.
+    round_started: Option<Instant>,
+    /// When the last effect happened, for the cooldown.
+    last_effect: Option<Instant>,
+    /// When the current speed burst ends.
+    burst_until: Option<Instant>,
+}
+
+impl Chaos {
+    /// Creates a new `Chaos`, not connected to any chat.
+    pub fn new() -> Self {
+        Self {
+            connection: None,
+            channel: String::new(),
+            error: None,
+            votes: BTreeMap::new(),
+            round_started: None,
+            last_effect: None,
+            burst_until: None,
+        }
+    }
+
+    /// Returns `true` while connected to a channel's chat.
+    pub fn is_connected(&self) -> bool {
+        self.connection.is_some()
+    }
+
+    /// Connects to a channel's chat, dropping any earlier connection.
+    ///
+    /// # Parameters
+    ///
+    /// - `channel`: Name of the channel.
+    pub fn connect(&mut self, channel: &str) {
+        self.disconnect();
+        match Connection::open(channel) {
+            Ok(connection) => {
+                self.connection = Some(connection);
+                self.channel = channel.to_string();
+            }
+            Err(e) => self.error = Some(format!("{:#}", e)),
+        }
+    }
+
+    /// Leaves the chat, forgetting the votes so far.
+    pub fn disconnect(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Describes the connection for the control panel.
+    pub fn status(&self) -> String {
+        match (&self.connection, &self.error) {
+            (Some(_), _) => format!("Connected to #{}", self.channel),
+            (None, Some(error)) => error.clone(),
+            (None, None) => String::from("Not connected"),
+        }
+    }
+
+    /// Reads the votes which have arrived, and ends the round once it has lasted long
+    /// enough.
+    ///
+    /// # Parameters
+    ///
+    /// - `config`: The streamer's settings.
+    /// - `playing`: Whether a game chat can vote on is being played. Votes are ignored
+    ///   otherwise.
+    ///
+    /// # Return Value
+    ///
+    /// The effect which won a round that just ended, if any.
+    pub fn update(&mut self, config: &TwitchConfig, playing: bool) -> Option<Effect> {
+        let received = match self.connection.as_mut()?.receive() {
+            Ok(received) => received,
+            Err(e) => {
+                self.connection = None;
+                self.error = Some(format!("{:#}", e));
+                return None;
+            }
+        };
+        if !playing {
+            self.round_started = None;
+            self.votes.clear();
+            self.burst_until = None;
+            return None;
+        }
+        let cooling_down = self.last_effect.is_some_and(|last_effect| {
+            last_effect.elapsed() < Duration::from_secs(config.cooldown_secs)
+        });
+        if cooling_down {
+            return None;
+        }
+        let round_started = *self.round_started.get_or_insert_with(Instant::now);
+        for (user, text) in received {
+            if let Some(effect) = Effect::parse(&text, config) {
+                // A viewer's latest vote replaces any earlier one in the round
+                self.votes.insert(user, effect);
+            }
+        }
+        if round_started.elapsed() < Duration::from_secs(config.vote_secs) {
+            return None;
+        }
+        self.round_started = None;
+        let winner = self.winner();
+        self.votes.clear();
+        if let Some(effect) = winner {
+            self.last_effect = Some(Instant::now());
+            if effect == Effect::Speed {
+                self.burst_until = Some(Instant::now() + BURST_TIME);
+            }
+        }
+        winner
+    }
+
+    /// Returns the effect with the most votes in the current round, if any.
+    fn winner(&self) -> Option<Effect> {
+        self.tally().into_iter().next().map(|(effect, _)| effect)
+    }
+
+    /// Returns how many votes each effect has in the current round, most first.
+    fn tally(&self) -> Vec<(Effect, usize)> {
+        let mut tally: Vec<(Effect, usize)> = Vec::new();
+        for effect in self.votes.values() {
+            match tally.iter_mut().find(|(counted, _)| counted == effect) {
+                Some((_, votes)) => *votes += 1,
+                None => tally.push((*effect, 1)),
+            }
+        }
+        tally.sort_by_key(|(_, votes)| Reverse(*votes));
+        tally
+    }
+
+    /// Returns how fast the game should run: faster during a speed burst.
+    pub fn speed(&self) -> f64 {
+        match self.burst_until {
+            Some(until) if Instant::now() < until => BURST_SPEED,
+            _ => 1.0,
+        }
+    }
+
+    /// Returns the lines shown beside the board while chat is voting.
+    ///
+    /// # Parameters
+    ///
+    /// - `config`: The streamer's settings.
+    pub fn hud(&self, config: &TwitchConfig) -> Vec<String> {
+        let round_started = match (&self.connection, self.round_started) {
+            (Some(_), Some(round_started)) => round_started,
+            (Some(_), None) => return vec![String::from("Chat vote soon")],
+            (None, _) => return Vec::new(),
+        };
+        let left = Duration::from_secs(config.vote_secs).saturating_sub(round_started.elapsed());
+        let mut lines = vec![format!("Chat vote: {}s", left.as_secs() + 1)];
+        for (effect, votes) in self.tally() {
+            lines.push(format!("  {} {}", effect.label(), votes));
+        }
+        lines
+    }
+}
+
+/// An entry on the Twitch control panel.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum PanelItem {
+    Channel,
+    GarbageVotes,
+    SpeedVotes,
+    PieceVotes,
+    VoteLength,
+    Cooldown,
+}
+
+impl PanelItem {
+    /// Every item, in the order they are listed on screen.
+    const ALL: [PanelItem; 6] = [
+        PanelItem::Channel,
+        PanelItem::GarbageVotes,
+        PanelItem::SpeedVotes,
+        PanelItem::PieceVotes,
+        PanelItem::VoteLength,
+        PanelItem::Cooldown,
+    ];
+}
+
+/// The streamer's control panel, for connecting to a channel's chat and choosing what
+/// it may vote on.
+pub struct TwitchPanel {
+    selected: usize,
+}
+
+impl TwitchPanel {
+    /// Creates a new `TwitchPanel` with the channel selected.
+    pub fn new() -> Self {
+        Self { selected: 0 }
+    }
+
+    /// Adds a typed character to the channel name, while it is selected.
+    ///
+    /// # Parameters
+    ///
+    /// - `c`: The character.
+    /// - `config`: The streamer's settings.
+    ///
+    /// # Return Value
+    ///
+    /// `true` if `config` was changed
+    pub fn type_char(&self, c: char, config: &mut TwitchConfig) -> bool {
+        if PanelItem::ALL[self.selected] != PanelItem::Channel
+            || !(c.is_ascii_alphanumeric() || c == '_')
+        {
+            return false;
+        }
+        config.channel.push(c);
+        true
+    }
+
+    /// Handles a key press on the control panel.
+    ///
+    /// # Parameters
+    ///
+    /// - `key`: The key which was pressed.
+    /// - `config`: The streamer's settings.
+    /// - `chaos`: The chat connection, connected or dropped on enter.
+    ///
+    /// # Return Value
+    ///
+    /// `true` if `config` was changed
+    pub fn process_input(
+        &mut self,
+        key: VirtualKeyCode,
+        config: &mut TwitchConfig,
+        chaos: &mut Chaos,
+    ) -> bool {
+        let item = PanelItem::ALL[self.selected];
+        let direction = match key {
+            VirtualKeyCode::Up => {
+                self.selected = (self.selected + PanelItem::ALL.len() - 1) % PanelItem::ALL.len();
+                return false;
+            }
+            VirtualKeyCode::Down => {
+                self.selected = (self.selected + 1) % PanelItem::ALL.len();
+                return false;
+            }
+            VirtualKeyCode::Return => {
+                if chaos.is_connected() {
+                    chaos.disconnect();
+                } else if !config.channel.is_empty() {
+                    chaos.connect(&config.channel);
+                }
+                return false;
+            }
+            VirtualKeyCode::Back if item == PanelItem::Channel => {
+                config.channel.pop();
+                return true;
+            }
+            VirtualKeyCode::Left => -1,
+            VirtualKeyCode::Right => 1,
+            _ => return false,
+        };
+        let step = |secs: u64| {
+            if direction < 0 {
+                secs.saturating_sub(VOTE_STEP_SECS)
+            } else {
+                (secs + VOTE_STEP_SECS).min(MAX_VOTE_SECS)
+            }
+        };
+        match item {
+            PanelItem::Channel => return false,
+            PanelItem::GarbageVotes => config.garbage_votes = !config.garbage_votes,
+            PanelItem::SpeedVotes => config.speed_votes = !config.speed_votes,
+            PanelItem::PieceVotes => config.piece_votes = !config.piece_votes,
+            PanelItem::VoteLength => config.vote_secs = step(config.vote_secs).max(VOTE_STEP_SECS),
+            PanelItem::Cooldown => config.cooldown_secs = step(config.cooldown_secs),
+        }
+        true
+    }
+
+    /// Returns each line of the control panel along with whether it is selected.
+    ///
+    /// # Parameters
+    ///
+    /// - `config`: The streamer's settings.
+    /// - `chaos`: The chat connection.
+    pub fn lines(&self, config: &TwitchConfig, chaos: &Chaos) -> Vec<(String, bool)> {
+        let on_off = |value: bool| if value { "On" } else { "Off" };
+        let mut lines: Vec<(String, bool)> = PanelItem::ALL
+            .iter()
+            .enumerate()
+            .map(|(i, item)| {
+                let line = match item {
+                    PanelItem::Channel => format!("Channel: #{}_", config.channel),
+                    PanelItem::GarbageVotes => {
+                        format!("!garbage votes: {}", on_off(config.garbage_votes))
+                    }
+                    PanelItem::SpeedVotes => {
+                        format!("!speed votes: {}", on_off(config.speed_votes))
+                    }
+                    PanelItem::PieceVotes => {
+                        format!("!piece votes: {}", on_off(config.piece_votes))
+                    }
+                    PanelItem::VoteLength => format!("Vote length: {} s", config.vote_secs),
+                    PanelItem::Cooldown => {
+                        format!("Time between votes: {} s", config.cooldown_secs)
+                    }
+                };
+                (line, i == self.selected)
+            })
+            .collect();
+        lines.push((chaos.status(), false));
+        lines
+    }
+}
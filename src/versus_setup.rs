@@ -0,0 +1,173 @@
+use wgputris::handicap::{Handicap, MAX_STARTING_GARBAGE, MIN_ROWS};
+use wgputris::GAMEBOARD_HEIGHT;
+use winit::event::VirtualKeyCode;
+
+/// Step between attack percentages.
+const ATTACK_STEP: u32 = 25;
+/// Strongest attack, as a percentage of the usual attack.
+const MAX_ATTACK_PERCENT: u32 = 300;
+/// Step between gravity percentages.
+const GRAVITY_STEP: u32 = 25;
+/// Weakest gravity, as a percentage of normal gravity.
+const MIN_GRAVITY_PERCENT: u32 = 25;
+
+/// An adjustable part of a player's handicap on the versus setup screen.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum HandicapItem {
+    StartingGarbage,
+    Attack,
+    Gravity,
+    BoardRows,
+}
+
+impl HandicapItem {
+    /// Every item, in the order they are listed under each player.
+    const ALL: [HandicapItem; 4] = [
+        HandicapItem::StartingGarbage,
+        HandicapItem::Attack,
+        HandicapItem::Gravity,
+        HandicapItem::BoardRows,
+    ];
+
+    /// Name shown on the versus setup screen.
+    fn label(self) -> &'static str {
+        match self {
+            HandicapItem::StartingGarbage => "Starting Garbage",
+            HandicapItem::Attack => "Attack",
+            HandicapItem::Gravity => "Gravity",
+            HandicapItem::BoardRows => "Board Rows",
+        }
+    }
+
+    /// Current value formatted for display.
+    fn value(self, handicap: &Handicap) -> String {
+        match self {
+            HandicapItem::StartingGarbage => match handicap.starting_garbage {
+                0 => String::from("None"),
+                rows => format!("{} rows", rows),
+            },
+            HandicapItem::Attack => format!("{}%", handicap.attack_percent),
+            HandicapItem::Gravity => format!("{}%", handicap.gravity_percent),
+            HandicapItem::BoardRows => format!("{}", handicap.rows),
+        }
+    }
+
+    /// Steps the value up or down, keeping it within the handicap's limits.
+    ///
+    /// # Parameters
+    ///
+    /// - `handicap`: Handicap to modify.
+    /// - `direction`: `1` to increase, `-1` to decrease.
+    fn adjust(self, handicap: &mut Handicap, direction: i32) {
+        match self {
+            HandicapItem::StartingGarbage => {
+                handicap.starting_garbage = (handicap.starting_garbage as i32 + direction)
+                    .clamp(0, MAX_STARTING_GARBAGE as i32)
+                    as usize
+            }
+            HandicapItem::Attack => {
+                handicap.attack_percent =
+                    (handicap.attack_percent as i32 + direction * ATTACK_STEP as i32)
+                        .clamp(0, MAX_ATTACK_PERCENT as i32) as u32
+            }
+            HandicapItem::Gravity => {
+                handicap.gravity_percent =
+                    (handicap.gravity_percent as i32 + direction * GRAVITY_STEP as i32)
+                        .clamp(MIN_GRAVITY_PERCENT as i32, 100) as u32
+            }
+            HandicapItem::BoardRows => {
+                handicap.rows = (handicap.rows as i32 + direction)
+                    .clamp(MIN_ROWS as i32, GAMEBOARD_HEIGHT as i32)
+                    as usize
+            }
+        }
+    }
+}
+
+/// The screen shown before a versus match, where each player's handicap is set, so
+/// players of different skill can have a close match.
+pub struct VersusSetup {
+    /// Whether the second player is the computer.
+    cpu: bool,
+    /// Index of the selected line, counting every item of both players.
+    selected: usize,
+}
+
+impl VersusSetup {
+    /// Creates a new `VersusSetup` with the first item selected.
+    ///
+    /// # Parameters
+    ///
+    /// - `cpu`: `true` for a match against the computer.
+    pub fn new(cpu: bool) -> Self {
+        Self { cpu, selected: 0 }
+    }
+
+    /// Returns `true` if the match is against the computer.
+    pub fn is_cpu(&self) -> bool {
+        self.cpu
+    }
+
+    /// Handles a key press on the versus setup screen.
+    ///
+    /// # Parameters
+    ///
+    /// - `key`: The key which was pressed.
+    /// - `handicaps`: Handicaps of player 1 and player 2 to modify.
+    ///
+    /// # Return Value
+    ///
+    /// `true` if `handicaps` were changed
+    pub fn process_input(&mut self, key: VirtualKeyCode, handicaps: &mut [Handicap; 2]) -> bool {
+        let count = 2 * HandicapItem::ALL.len();
+        let player = self.selected / HandicapItem::ALL.len();
+        let item = HandicapItem::ALL[self.selected % HandicapItem::ALL.len()];
+        match key {
+            VirtualKeyCode::Up => {
+                self.selected = (self.selected + count - 1) % count;
+                false
+            }
+            VirtualKeyCode::Down => {
+                self.selected = (self.selected + 1) % count;
+                false
+            }
+            VirtualKeyCode::Left => {
+                item.adjust(&mut handicaps[player], -1);
+                true
+            }
+            VirtualKeyCode::Right => {
+                item.adjust(&mut handicaps[player], 1);
+                true
+            }
+            VirtualKeyCode::Back => {
+                handicaps[player] = Handicap::default();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns each line of the versus setup screen along with whether it is selected.
+    ///
+    /// # Parameters
+    ///
+    /// - `handicaps`: Handicaps of player 1 and player 2.
+    pub fn lines(&self, handicaps: &[Handicap; 2]) -> Vec<(String, bool)> {
+        let names = if self.cpu {
+            ["You", "Computer"]
+        } else {
+            ["Player 1", "Player 2"]
+        };
+        let mut lines = Vec::new();
+        for (player, (name, handicap)) in names.iter().zip(handicaps.iter()).enumerate() {
+            lines.push((format!("{}:", name), false));
+            for (i, item) in HandicapItem::ALL.iter().enumerate() {
+                lines.push((
+                    format!("  {}: < {} >", item.label(), item.value(handicap)),
+                    player * HandicapItem::ALL.len() + i == self.selected,
+                ));
+            }
+        }
+        lines
+    }
+}
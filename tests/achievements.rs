@@ -0,0 +1,100 @@
+mod harness;
+
+use harness::Harness;
+use wgputris::achievements::{Achievement, Achievements, Tracker};
+use wgputris::game::{ClearKind, EventHandler, GameEvent};
+use wgputris::tetromino::TetrominoKind::*;
+
+/// Returns the achievements earned by everything a game reported.
+fn earned(h: &Harness) -> Vec<Achievement> {
+    let mut tracker = Tracker::new();
+    for event in h.events() {
+        tracker.handle_event(event);
+    }
+    tracker.update(&h.game);
+    tracker.take()
+}
+
+/// Returns a line clear of `n` rows, which is a T-spin if `t_spin` is set.
+fn clear(n: usize, t_spin: bool) -> GameEvent {
+    GameEvent::LinesCleared {
+        n,
+        kind: ClearKind::from_lines(n),
+        t_spin,
+        combo: 0,
+        perfect_clear: false,
+    }
+}
+
+#[test]
+fn a_tetris_unlocks_first_tetris() {
+    let mut h = Harness::new(&["#########."; 4], I, &[O, O, O]);
+    h.run("cw right*5 drop");
+    h.assert_board(&[]);
+    assert_eq!(
+        earned(&h),
+        [Achievement::FirstTetris, Achievement::PerfectClear]
+    );
+}
+
+#[test]
+fn clearing_with_shapes_in_a_row_unlocks_combo() {
+    let mut h = Harness::new(&["########.."; 10], O, &[O; 5]);
+    h.run("right*4 drop right*4 drop right*4 drop right*4 drop");
+    assert!(!earned(&h).contains(&Achievement::Combo));
+    h.run("right*4 drop");
+    assert!(earned(&h).contains(&Achievement::Combo));
+}
+
+#[test]
+fn back_to_back_needs_five_big_clears_with_nothing_smaller_between() {
+    let mut tracker = Tracker::new();
+    for event in [
+        clear(4, false),
+        clear(2, true),
+        clear(4, false),
+        clear(4, false),
+    ] {
+        tracker.handle_event(&event);
+    }
+    tracker.handle_event(&clear(1, false));
+    tracker.handle_event(&clear(4, false));
+    assert!(!tracker.take().contains(&Achievement::BackToBack));
+    for _ in 0..4 {
+        tracker.handle_event(&clear(4, false));
+    }
+    assert_eq!(tracker.take(), [Achievement::BackToBack]);
+    // Each achievement is only reported once a game
+    tracker.handle_event(&clear(1, true));
+    assert!(tracker.take().is_empty());
+}
+
+#[test]
+fn achievements_are_kept_for_each_profile() {
+    let mut achievements = Achievements::default();
+    assert!(achievements.unlock("Arrows", Achievement::TSpin));
+    assert!(!achievements.unlock("Arrows", Achievement::TSpin));
+    assert!(achievements.unlock("WASD", Achievement::TSpin));
+    for _ in 0..99 {
+        assert_eq!(achievements.record_game("Arrows"), None);
+    }
+    assert_eq!(
+        achievements.record_game("Arrows"),
+        Some(Achievement::Regular)
+    );
+    assert_eq!(achievements.record_game("Arrows"), None);
+
+    let path =
+        std::env::temp_dir().join(format!("wgputris-achievements-{}.toml", std::process::id()));
+    achievements.save_to(&path).unwrap();
+    let loaded = Achievements::load_from(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded, achievements);
+    let progress = loaded.progress("Arrows");
+    assert_eq!(progress.games, 101);
+    assert_eq!(
+        progress.unlocked,
+        [Achievement::TSpin, Achievement::Regular]
+    );
+    assert_eq!(loaded.progress("Nobody").games, 0);
+}
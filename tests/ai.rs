@@ -0,0 +1,120 @@
+mod harness;
+
+use harness::Harness;
+use wgputris::action::Action;
+use wgputris::ai::{self, Bot, BotView, Cpu, Decision, Difficulty, Placement, Weights};
+use wgputris::game::Game;
+use wgputris::stats::Stats;
+use wgputris::tetromino::TetrominoKind::*;
+
+#[test]
+fn placements_turn_then_slide_then_drop() {
+    let placement = Placement {
+        hold: true,
+        rotation: 3,
+        shift: -2,
+    };
+    let script: Vec<String> = placement
+        .actions()
+        .iter()
+        .map(|action| format!("{:?}", action))
+        .collect();
+    assert_eq!(
+        script,
+        ["Hold", "RotateCcw", "MoveLeft", "MoveLeft", "HardDrop"]
+    );
+}
+
+#[test]
+fn plan_fills_the_gap_to_clear_lines() {
+    let mut h = Harness::new(&["######..##", "######..##"], O, &[T]);
+    let placement = ai::plan(&BotView::of(&h.game), &Weights::default(), 1).expect("No placement");
+    assert_eq!(
+        placement,
+        Placement {
+            hold: false,
+            rotation: 0,
+            shift: 2
+        }
+    );
+    for action in placement.actions() {
+        h.game.process_action(action);
+    }
+    h.run("wait:1");
+    h.assert_board(&[]);
+    assert_eq!(h.stats.lines, 2);
+}
+
+#[test]
+fn plan_holds_when_the_next_shape_fits_better() {
+    let h = Harness::new(&["#########.", "#########."], S, &[I]);
+    let placement = ai::plan(&BotView::of(&h.game), &Weights::default(), 1).expect("No placement");
+    assert!(placement.hold, "{:?} doesn't hold for the I", placement);
+}
+
+#[test]
+fn cpu_keeps_clearing_lines_on_its_own() {
+    for &difficulty in Difficulty::ALL.iter() {
+        let mut game = Game::with_seed(7);
+        let mut cpu = Cpu::new(difficulty);
+        let mut stats = Stats::new();
+        // A minute of game time, a frame at a time
+        for _ in 0..60 * 60 {
+            cpu.update(&mut game);
+            game.advance(16);
+            game.dispatch_events(&mut [&mut cpu, &mut stats]);
+        }
+        assert!(!game.game_over, "{:?} topped out", difficulty);
+        assert!(
+            stats.lines >= 10,
+            "{:?} cleared {} lines",
+            difficulty,
+            stats.lines
+        );
+    }
+}
+
+/// Slides every shape against the left wall.
+struct Lefty;
+
+impl Bot for Lefty {
+    fn decide(&mut self, _view: &BotView) -> Decision {
+        let mut actions = vec![Action::MoveLeft; 5];
+        actions.push(Action::HardDrop);
+        Decision::Actions(actions)
+    }
+}
+
+#[test]
+fn registered_bots_play_by_their_own_decisions() {
+    ai::register_bot("Lefty", |_| Box::new(Lefty));
+    assert_eq!(ai::bot_names(), [ai::HEURISTIC_BOT, "Lefty"]);
+
+    let mut h = Harness::new(&[], O, &[O, O]);
+    let mut cpu = Cpu::with_bot(
+        "Lefty",
+        ai::create_bot("Lefty", Difficulty::Hard),
+        Difficulty::Hard,
+    );
+    while h.stats.pieces < 2 {
+        cpu.update(&mut h.game);
+        h.game.advance(16);
+        h.game.dispatch_events(&mut [&mut cpu, &mut h.stats]);
+    }
+    h.assert_board(&["##........", "##........", "##........", "##........"]);
+}
+
+#[test]
+fn weights_survive_saving_and_loading() {
+    let path = std::env::temp_dir().join(format!("wgputris-weights-{}.toml", std::process::id()));
+    let weights = Weights {
+        height: -0.8,
+        lines: 0.5,
+        holes: -0.04,
+        bumpiness: -0.3,
+    };
+    weights.save(&path).unwrap();
+    let loaded = Weights::load(&path);
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(loaded.unwrap(), weights);
+}
@@ -0,0 +1,177 @@
+mod harness;
+
+use bevy_ecs::entity::Entity;
+use harness::{Harness, QUEUE};
+use std::time::Duration;
+use wgputris::action::Action;
+use wgputris::ai::{Cpu, Difficulty};
+use wgputris::arena::{Arena, Celebrates, Controls, Garbage, Particle, Widget, WidgetKind};
+use wgputris::attack::{Attack, AttackTable};
+use wgputris::celebration::{CONFETTI_MS, GLOW_MS};
+use wgputris::clock::MockClock;
+use wgputris::replay::Input;
+use wgputris::tetromino::TetrominoKind::*;
+
+/// Adds a harness's game to an arena as a board, following a clock the test moves.
+fn spawn(arena: &mut Arena, h: Harness, clock: &MockClock, shift: i32) -> Entity {
+    let mut game = h.game;
+    game.set_clock(Box::new(clock.clone()));
+    arena.spawn_board(game, shift)
+}
+
+/// Moves the clock on and plays a frame.
+fn play(arena: &mut Arena, clock: &MockClock, ms: u64) {
+    clock.advance(Duration::from_millis(ms));
+    arena.update();
+}
+
+/// Returns the number of particles over every board.
+fn particles(arena: &mut Arena) -> usize {
+    let mut particles = arena.world_mut().query::<&Particle>();
+    particles.iter(arena.world()).count()
+}
+
+/// Returns the number of locked blocks on a board.
+fn blocks(arena: &Arena, board: Entity) -> usize {
+    let occupied = arena.board(board).game.occupied();
+    occupied.iter().flatten().filter(|&&cell| cell).count()
+}
+
+#[test]
+fn controls_are_applied_before_the_game_steps() {
+    let clock = MockClock::new();
+    let mut arena = Arena::new(0);
+    let board = spawn(&mut arena, Harness::new(&[], O, &QUEUE), &clock, 0);
+    let inputs = vec![
+        Input::Action(Action::MoveLeft),
+        Input::Action(Action::HardDrop),
+    ];
+    arena
+        .world_mut()
+        .entity_mut(board)
+        .insert(Controls { inputs });
+    play(&mut arena, &clock, 1);
+    let board = arena.board(board);
+    assert_eq!(board.stats.pieces, 1);
+    // Both inputs reached the game at its first millisecond
+    let times: Vec<u64> = board
+        .game
+        .replay()
+        .inputs
+        .iter()
+        .map(|(ms, _)| *ms)
+        .collect();
+    assert_eq!(times, [0, 0]);
+}
+
+#[test]
+fn boards_are_extracted_from_left_to_right() {
+    let clock = MockClock::new();
+    let mut arena = Arena::new(0);
+    let right = spawn(&mut arena, Harness::new(&[], O, &QUEUE), &clock, 26);
+    let left = spawn(&mut arena, Harness::new(&[], O, &QUEUE), &clock, 0);
+    play(&mut arena, &clock, 1);
+    let boards: Vec<Entity> = arena.frame().iter().map(|frame| frame.board).collect();
+    assert_eq!(boards, [left, right]);
+    // The right board is drawn 26 blocks over
+    let [left, right] = [&arena.frame()[0], &arena.frame()[1]];
+    assert_eq!(
+        right.vertices[0].position[0] - left.vertices[0].position[0],
+        26.0 * wgputris::BLOCK_SIZE as f32
+    );
+}
+
+/// Sets up a board about to clear a tetris, which sends its garbage to an empty board
+/// on the right.
+fn tetris_match(clock: &MockClock) -> (Arena, [Entity; 2]) {
+    let mut arena = Arena::new(0);
+    let left = spawn(
+        &mut arena,
+        Harness::new(&["#########."; 4], I, &QUEUE),
+        clock,
+        0,
+    );
+    let right = spawn(&mut arena, Harness::new(&[], O, &QUEUE), clock, 26);
+    let mut inputs = vec![Input::Action(Action::RotateCw)];
+    inputs.extend(vec![Input::Action(Action::MoveRight); 5]);
+    inputs.push(Input::Action(Action::HardDrop));
+    let attack = Attack::new(AttackTable::classic(), 100);
+    arena.world_mut().entity_mut(left).insert((
+        Controls { inputs },
+        Garbage {
+            attack,
+            target: right,
+        },
+        Celebrates,
+    ));
+    arena.world_mut().spawn(Widget {
+        board: left,
+        row: 0,
+        kind: WidgetKind::Sent,
+    });
+    (arena, [left, right])
+}
+
+#[test]
+fn garbage_goes_to_the_target_board() {
+    let clock = MockClock::new();
+    let (mut arena, [left, right]) = tetris_match(&clock);
+    play(&mut arena, &clock, 1);
+    assert_eq!(arena.board(left).stats.lines, 4);
+    assert_eq!(blocks(&arena, left), 0);
+    // Clearing the whole board makes it a perfect clear too
+    let rows = AttackTable::classic().rows(4, false, 0, true) as usize;
+    assert_eq!(blocks(&arena, right), rows * 9);
+    assert_eq!(arena.frame()[0].hud, [format!("Sent: {}", rows)]);
+}
+
+#[test]
+fn celebrations_are_particles_which_fade_in_game_time() {
+    let clock = MockClock::new();
+    let (mut arena, [left, _]) = tetris_match(&clock);
+    play(&mut arena, &clock, 1);
+    // A gold glow and confetti for the perfect clear, over the left board only
+    assert_eq!(particles(&mut arena), 1 + 80);
+    let frame = arena.frame();
+    assert_eq!(frame[0].board, left);
+    assert_eq!(frame[0].effects[2].color, [1.0, 0.84, 0.0, 1.0]);
+    assert!(frame[1].effects.iter().all(|vertex| vertex.color[3] == 0.0));
+
+    play(&mut arena, &clock, GLOW_MS);
+    assert_eq!(particles(&mut arena), 80);
+    play(&mut arena, &clock, CONFETTI_MS - GLOW_MS);
+    assert_eq!(particles(&mut arena), 0);
+}
+
+#[test]
+fn computers_play_their_boards() {
+    let clock = MockClock::new();
+    let mut arena = Arena::new(0);
+    let board = spawn(&mut arena, Harness::new(&[], O, &QUEUE), &clock, 0);
+    arena.add_computer(board, Cpu::new(Difficulty::Hard));
+    for _ in 0..300 {
+        play(&mut arena, &clock, 17);
+    }
+    assert!(arena.board(board).stats.pieces > 0);
+}
+
+#[test]
+fn widgets_are_shown_in_order_unless_hidden() {
+    let clock = MockClock::new();
+    let mut arena = Arena::new(0);
+    let board = spawn(&mut arena, Harness::new(&[], O, &QUEUE), &clock, 0);
+    for (row, kind) in [
+        (2, WidgetKind::Speed),
+        (1, WidgetKind::Text(String::from("Second"))),
+        (0, WidgetKind::Text(String::from("First"))),
+        (3, WidgetKind::Sent),
+    ] {
+        arena.world_mut().spawn(Widget { board, row, kind });
+    }
+    play(&mut arena, &clock, 1);
+    // The board runs in real time and sends no garbage
+    assert_eq!(arena.frame()[0].hud, ["First", "Second"]);
+    arena.board_mut(board).game.set_speed(2.0);
+    play(&mut arena, &clock, 1);
+    assert_eq!(arena.frame()[0].hud, ["First", "Second", "Speed: 2x"]);
+}
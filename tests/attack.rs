@@ -0,0 +1,81 @@
+mod harness;
+
+use harness::{Harness, QUEUE};
+use wgputris::attack::{Attack, AttackTable};
+use wgputris::game::{EventHandler, GameEvent};
+use wgputris::tetromino::TetrominoKind::*;
+
+/// A slot a T can drop into upright, then turn to point down into.
+const T_SLOT: [&str; 3] = ["####......", "###...####", "####.#####"];
+
+/// Returns every line clear reported so far, as `(n, t_spin, combo, perfect_clear)`.
+fn clears(h: &Harness) -> Vec<(usize, bool, usize, bool)> {
+    h.events()
+        .iter()
+        .filter_map(|event| match *event {
+            GameEvent::LinesCleared {
+                n,
+                t_spin,
+                combo,
+                perfect_clear,
+                ..
+            } => Some((n, t_spin, combo, perfect_clear)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns the rows of garbage a table sends for everything a game reported.
+fn sent(h: &Harness, table: AttackTable) -> usize {
+    let mut attack = Attack::new(table, 100);
+    for event in h.events() {
+        attack.handle_event(event);
+    }
+    attack.take()
+}
+
+#[test]
+fn turning_a_t_into_a_slot_is_a_t_spin() {
+    let mut h = Harness::new(&T_SLOT, T, &QUEUE);
+    h.run("cw down*25 cw drop");
+    h.assert_board(&["####......"]);
+    assert_eq!(clears(&h), [(2, true, 0, false)]);
+    assert_eq!(sent(&h, AttackTable::classic()), 1);
+    assert_eq!(sent(&h, AttackTable::guideline()), 4);
+}
+
+#[test]
+fn a_t_which_moved_last_is_not_a_t_spin() {
+    let mut h = Harness::new(&T_SLOT, T, &QUEUE);
+    h.run("cw down*25 drop");
+    assert_eq!(clears(&h), [(1, false, 0, false)]);
+    assert_eq!(sent(&h, AttackTable::guideline()), 0);
+}
+
+#[test]
+fn clears_in_a_row_combo_until_the_board_is_empty() {
+    let mut h = Harness::new(&["########.."; 4], O, &QUEUE);
+    h.run("right*4 drop right*4 drop");
+    h.assert_board(&[]);
+    assert_eq!(clears(&h), [(2, false, 0, false), (2, false, 1, true)]);
+    assert_eq!(sent(&h, AttackTable::classic()), 2);
+    // A double each, one row for the combo and ten for the perfect clear
+    assert_eq!(sent(&h, AttackTable::guideline()), 13);
+}
+
+#[test]
+fn a_shape_which_clears_nothing_ends_the_combo() {
+    let mut h = Harness::new(&["########.."; 4], O, &QUEUE);
+    h.run("right*4 drop drop right*4 drop");
+    assert_eq!(clears(&h), [(2, false, 0, false), (2, false, 0, false)]);
+}
+
+#[test]
+fn custom_tables_fill_in_missing_values_from_the_classic_table() {
+    let table = AttackTable::parse("perfect_clear = 5\ncombos = [1, 2]").unwrap();
+    assert_eq!(table.lines, AttackTable::classic().lines);
+    assert_eq!(table.rows(4, false, 0, true), 9);
+    // The last combo entry carries on for longer runs
+    assert_eq!(table.rows(1, false, 7, false), 2);
+    assert!(AttackTable::parse("lines = \"lots\"").is_err());
+}
@@ -0,0 +1,85 @@
+mod harness;
+
+use harness::Harness;
+use wgputris::celebration::{Celebration, CELEBRATION_VERTICES, CONFETTI_MS};
+use wgputris::game::EventHandler;
+use wgputris::tetromino::TetrominoKind::*;
+use wgputris::Vertex;
+
+/// Returns a celebration of everything a game reported so far.
+fn celebrate(h: &Harness) -> Celebration {
+    let mut celebration = Celebration::new();
+    for event in h.events() {
+        celebration.handle_event(event);
+    }
+    celebration.update(h.game.time_ms());
+    celebration
+}
+
+/// Returns the colour of each quad drawn, the glow first and then the confetti.
+fn quads(celebration: &Celebration, confetti: bool) -> Vec<[f32; 4]> {
+    let mut buf = vec![bytemuck::Zeroable::zeroed(); CELEBRATION_VERTICES];
+    celebration.render(&mut buf, 0, confetti);
+    buf.chunks(6)
+        .filter(|quad: &&[Vertex]| quad[0].position != quad[2].position)
+        .map(|quad| quad[2].color)
+        .collect()
+}
+
+#[test]
+fn tetrises_throw_confetti_and_light_up_the_border() {
+    let mut h = Harness::new(&["#########."; 4], I, &[O, O, O]);
+    h.run("cw right*5");
+    assert!(!celebrate(&h).is_active());
+    h.run("drop");
+    let quads = quads(&celebrate(&h), true);
+    // Clearing the whole board makes it a perfect clear too, with a gold glow
+    assert_eq!(quads.len(), 4 + 80);
+    assert_eq!(quads[0], [1.0, 0.84, 0.0, 1.0]);
+}
+
+#[test]
+fn a_tetris_on_a_full_board_gets_the_smaller_celebration() {
+    let mut rows = vec!["#########."; 4];
+    rows.insert(0, "##........");
+    let mut h = Harness::new(&rows, I, &[O, O, O]);
+    h.run("cw right*5 drop");
+    let quads = quads(&celebrate(&h), true);
+    assert_eq!(quads.len(), 4 + 40);
+    assert_eq!(quads[0], [0.0, 1.0, 1.0, 1.0]);
+}
+
+#[test]
+fn smaller_clears_are_not_celebrated() {
+    let mut h = Harness::new(&["#.........", "########..", "########.."], O, &[O, O, O]);
+    h.run("right*4 drop");
+    assert_eq!(h.game.get_lines(), 2);
+    assert!(!celebrate(&h).is_active());
+}
+
+#[test]
+fn reduced_motion_keeps_only_the_glow() {
+    let mut h = Harness::new(&["#########."; 4], I, &[O, O, O]);
+    h.run("cw right*5 drop");
+    assert_eq!(quads(&celebrate(&h), false).len(), 4);
+}
+
+#[test]
+fn celebrations_fade_and_end() {
+    let mut h = Harness::new(&["#########."; 4], I, &[O, O, O]);
+    h.run("cw right*5 drop");
+    let mut celebration = celebrate(&h);
+    let start = h.game.time_ms();
+    celebration.update(start + CONFETTI_MS - 1);
+    let quads = quads(&celebration, true);
+    // The glow has gone, and the confetti has almost faded out
+    assert_eq!(quads.len(), 80);
+    assert!(quads.iter().all(|color| color[3] > 0.0 && color[3] < 0.01));
+    celebration.update(start + CONFETTI_MS);
+    assert!(!celebration.is_active());
+
+    // A new game ends the last game's celebration
+    let mut celebration = celebrate(&h);
+    celebration.update(0);
+    assert!(!celebration.is_active());
+}
@@ -0,0 +1,83 @@
+use wgputris::action::Action;
+use wgputris::finesse::{Attempt, Press, Target, Trainer};
+use wgputris::tetromino::TetrominoKind::{self, *};
+use wgputris::GAMEBOARD_WIDTH;
+
+/// Returns the target covering the given columns of the floor with a shape turned a
+/// number of times.
+fn target(kind: TetrominoKind, rotation: u8, left: i32) -> Target {
+    Target::all()
+        .into_iter()
+        .find(|t| t.kind == kind && t.rotation == rotation && t.cells[0].0 == left)
+        .unwrap_or_else(|| panic!("No target for {:?} {} {}", kind, rotation, left))
+}
+
+#[test]
+fn every_shape_has_its_distinct_placements() {
+    let count = |kind| Target::all().iter().filter(|t| t.kind == kind).count();
+    assert_eq!(count(O), 9);
+    assert_eq!(count(I), 7 + 10);
+    assert_eq!(count(S), 8 + 9);
+    assert_eq!(count(T), 8 + 9 + 8 + 9);
+}
+
+#[test]
+fn fewest_presses_use_the_walls_and_turn_the_short_way() {
+    assert_eq!(target(O, 0, 0).fewest_presses(), [Press::DasLeft]);
+    assert_eq!(target(O, 0, 8).fewest_presses(), [Press::DasRight]);
+    assert_eq!(
+        target(T, 3, 0).fewest_presses(),
+        [Press::DasLeft, Press::RotateCcw]
+    );
+    let spawn = Target::all().into_iter().find(|t| t.kind == T).unwrap();
+    assert_eq!(spawn.fewest_presses().len(), 1);
+}
+
+/// Places the trainer's target with its fewest presses, as a player holding keys
+/// for auto shift would, plus some taps which go nowhere.
+///
+/// # Parameters
+///
+/// - `trainer`: The trainer.
+/// - `wasted`: Number of taps left and right on top of the fewest presses.
+fn place(trainer: &mut Trainer, wasted: usize) -> Attempt {
+    let mut game = trainer.game();
+    for &press in trainer.target().fewest_presses().iter() {
+        let (action, repeats) = match press {
+            Press::Left => (Action::MoveLeft, 1),
+            Press::Right => (Action::MoveRight, 1),
+            Press::DasLeft => (Action::MoveLeft, GAMEBOARD_WIDTH),
+            Press::DasRight => (Action::MoveRight, GAMEBOARD_WIDTH),
+            Press::RotateCw => (Action::RotateCw, 1),
+            Press::RotateCcw => (Action::RotateCcw, 1),
+        };
+        trainer.press(action);
+        for _ in 0..repeats {
+            game.process_action(action);
+        }
+    }
+    for _ in 0..wasted {
+        trainer.press(Action::MoveLeft);
+        trainer.press(Action::MoveRight);
+    }
+    trainer.press(Action::HardDrop);
+    game.process_action(Action::HardDrop);
+    game.advance(1);
+    game.dispatch_events(&mut [trainer]);
+    trainer.finish(&game).expect("The shape didn't lock")
+}
+
+#[test]
+fn trainer_counts_presses_and_where_the_shape_landed() {
+    let mut trainer = Trainer::new(3);
+    for _ in 0..20 {
+        let attempt = place(&mut trainer, 0);
+        assert!(attempt.is_correct(), "{:?}", attempt);
+    }
+    assert_eq!(trainer.total().correct, 20);
+
+    let attempt = place(&mut trainer, 1);
+    assert!(attempt.placed && !attempt.is_correct());
+    assert_eq!(trainer.total().attempts, 21);
+    assert_eq!(trainer.weakest()[0].0, attempt.target);
+}
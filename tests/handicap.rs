@@ -0,0 +1,88 @@
+mod harness;
+
+use harness::{Harness, QUEUE};
+use wgputris::attack::{Attack, AttackTable};
+use wgputris::game::{ClearKind, EventHandler, GameEvent};
+use wgputris::handicap::Handicap;
+use wgputris::tetromino::TetrominoKind::*;
+use wgputris::{Vertex, GAMEBOARD_WIDTH, VERTEX_COUNT};
+
+#[test]
+fn handicaps_start_with_garbage_on_a_smaller_board() {
+    let mut h = Harness::new(&[], O, &QUEUE);
+    let handicap = Handicap {
+        starting_garbage: 3,
+        rows: 14,
+        ..Handicap::default()
+    };
+    handicap.apply(&mut h.game, 0);
+    h.run("wait:0");
+    h.assert_board(&[".#########"; 3]);
+    // Shapes spawn in the top row of the 14 left in play
+    assert_eq!(h.shape_cells(), [(4, 5), (4, 6), (5, 5), (5, 6)]);
+
+    // 11 empty rows hold five Os stacked in one column, and the sixth locks above them
+    h.run("drop*5");
+    assert!(!h.game.game_over);
+    h.run("drop");
+    assert!(h.game.game_over);
+    assert_eq!(h.events().last(), Some(&GameEvent::GameOver));
+}
+
+#[test]
+fn blocks_locked_above_a_smaller_board_are_hidden() {
+    // The O locks half in the hidden rows over the 14 in play, at the top left
+    let mut h = Harness::new(&["##........"; 13], O, &QUEUE);
+    let handicap = Handicap {
+        rows: 14,
+        ..Handicap::default()
+    };
+    handicap.apply(&mut h.game, 0);
+    h.run("left*4 drop");
+    assert!(!h.game.game_over);
+    let mut buf = vec![bytemuck::Zeroable::zeroed(); VERTEX_COUNT];
+    h.game.render(&mut buf, 0, 1);
+    let drawn = |x: usize, y: usize| {
+        let quad: &[Vertex] = &buf[6 + 6 * (x + y * GAMEBOARD_WIDTH)..][..6];
+        quad[0].position != quad[2].position
+    };
+    assert!(!drawn(0, 5) && !drawn(1, 5));
+    assert!(drawn(0, 6) && drawn(1, 6));
+}
+
+#[test]
+fn handicaps_slow_gravity_down() {
+    let mut h = Harness::new(&[], O, &QUEUE);
+    let normal = h.game.debug_info().gravity;
+    let handicap = Handicap {
+        gravity_percent: 50,
+        ..Handicap::default()
+    };
+    handicap.apply(&mut h.game, 0);
+    assert!((h.game.debug_info().gravity - normal / 2.0).abs() < 1e-4);
+}
+
+#[test]
+fn scaled_attacks_carry_parts_of_a_row() {
+    let clear = |n| GameEvent::LinesCleared {
+        n,
+        kind: ClearKind::from_lines(n),
+        t_spin: false,
+        combo: 0,
+        perfect_clear: false,
+    };
+    let mut attack = Attack::new(AttackTable::classic(), 100);
+    attack.handle_event(&clear(1));
+    assert_eq!(attack.take(), 0);
+    attack.handle_event(&clear(4));
+    assert_eq!(attack.take(), 4);
+
+    let mut attack = Attack::new(AttackTable::classic(), 50);
+    attack.handle_event(&clear(4));
+    assert_eq!(attack.take(), 2);
+    attack.handle_event(&clear(2));
+    assert_eq!(attack.take(), 0);
+    attack.handle_event(&clear(2));
+    assert_eq!(attack.take(), 1);
+    assert_eq!(attack.sent(), 3);
+}
@@ -0,0 +1,157 @@
+//! Drives a `Game` with scripted actions and time steps, so tests can set up a board,
+//! play a few moves and check what's left.
+
+// Each test binary uses only part of the harness
+#![allow(dead_code)]
+
+use wgputris::action::Action;
+use wgputris::fumen::{Cell, Page};
+use wgputris::game::{EventHandler, Game, GameEvent};
+use wgputris::stats::Stats;
+use wgputris::tetromino::TetrominoKind;
+use wgputris::{GAMEBOARD_HEIGHT, GAMEBOARD_WIDTH};
+
+/// Upcoming shapes for tests which don't care what comes next.
+pub const QUEUE: [TetrominoKind; 3] = [TetrominoKind::O; 3];
+
+/// Collects every event, so tests can check what the game reported.
+struct EventLog(Vec<GameEvent>);
+
+impl EventHandler for EventLog {
+    fn handle_event(&mut self, event: &GameEvent) {
+        self.0.push(*event);
+    }
+}
+
+/// A game under test, with its totals and every event it has reported.
+pub struct Harness {
+    pub game: Game,
+    pub stats: Stats,
+    events: EventLog,
+}
+
+impl Harness {
+    /// Starts a game on a prepared board.
+    ///
+    /// # Parameters
+    ///
+    /// - `rows`: The bottom rows of the board, top to bottom, with `#` for a block and
+    /// `.` for an empty cell. Rows above them are empty.
+    /// - `current`: The shape to start with.
+    /// - `next`: Shapes to come after it, soonest first.
+    pub fn new(rows: &[&str], current: TetrominoKind, next: &[TetrominoKind]) -> Self {
+        assert!(rows.len() <= GAMEBOARD_HEIGHT, "Too many rows");
+        let mut field = vec![None; (GAMEBOARD_HEIGHT - rows.len()) * GAMEBOARD_WIDTH];
+        for row in rows {
+            assert_eq!(
+                row.len(),
+                GAMEBOARD_WIDTH,
+                "Row {:?} is the wrong width",
+                row
+            );
+            field.extend(row.chars().map(|c| match c {
+                '#' => Some(Cell::Garbage),
+                '.' => None,
+                _ => panic!("Unknown cell {:?} in row {:?}", c, row),
+            }));
+        }
+        let page = Page {
+            field,
+            hold: None,
+            current: Some(current),
+            next: next.to_vec(),
+        };
+        let game = Game::from_fumen(0, &page.encode()).expect("The board can't be loaded");
+        let mut harness = Self {
+            game,
+            stats: Stats::new(),
+            events: EventLog(Vec::new()),
+        };
+        harness.dispatch();
+        harness
+    }
+
+    /// Plays a script of whitespace separated steps:
+    ///
+    /// - `left`, `right`, `down`, `cw`, `ccw`, `hold`: the action.
+    /// - `drop`: a hard drop, then one millisecond so the next shape spawns and full
+    /// rows clear, as they would in a live game.
+    /// - `wait:N`: lets `N` milliseconds pass.
+    /// - `left*N` etc.: repeats a step `N` times.
+    pub fn run(&mut self, script: &str) -> &mut Self {
+        for step in script.split_whitespace() {
+            let (step, times) = match step.split_once('*') {
+                Some((step, times)) => (step, times.parse().expect("Bad repeat count")),
+                None => (step, 1),
+            };
+            for _ in 0..times {
+                self.step(step);
+            }
+        }
+        self
+    }
+
+    /// Plays a single step of a script, see `run`.
+    fn step(&mut self, step: &str) {
+        match step {
+            "left" => self.game.process_action(Action::MoveLeft),
+            "right" => self.game.process_action(Action::MoveRight),
+            "down" => self.game.process_action(Action::SoftDrop),
+            "cw" => self.game.process_action(Action::RotateCw),
+            "ccw" => self.game.process_action(Action::RotateCcw),
+            "hold" => self.game.process_action(Action::Hold),
+            "drop" => {
+                self.game.process_action(Action::HardDrop);
+                self.game.advance(1);
+            }
+            _ => match step.strip_prefix("wait:") {
+                Some(ms) => self.game.advance(ms.parse().expect("Bad wait")),
+                None => panic!("Unknown step {:?}", step),
+            },
+        }
+        self.dispatch();
+    }
+
+    /// Passes the game's new events to the totals and the event log.
+    fn dispatch(&mut self) {
+        self.game
+            .dispatch_events(&mut [&mut self.stats, &mut self.events]);
+    }
+
+    /// Returns every event reported so far, oldest first.
+    pub fn events(&self) -> &[GameEvent] {
+        &self.events.0
+    }
+
+    /// Returns the cells of the board covered by the current shape, sorted.
+    pub fn shape_cells(&self) -> Vec<(i32, i32)> {
+        let mut cells = self.game.debug_info().board_locs.to_vec();
+        cells.sort_unstable();
+        cells
+    }
+
+    /// Asserts the locked blocks on the board, ignoring the current shape.
+    ///
+    /// # Parameters
+    ///
+    /// - `rows`: The bottom rows of the board in the format taken by `new`. Every row
+    /// above them must be empty.
+    pub fn assert_board(&self, rows: &[&str]) {
+        let occupied = self.game.debug_info().occupied;
+        let actual: Vec<String> = occupied
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|&cell| if cell { '#' } else { '.' })
+                    .collect()
+            })
+            .collect();
+        let mut expected = vec![".".repeat(GAMEBOARD_WIDTH); GAMEBOARD_HEIGHT - rows.len()];
+        expected.extend(rows.iter().map(|row| row.to_string()));
+        assert_eq!(
+            actual.join("\n"),
+            expected.join("\n"),
+            "\nThe board doesn't match"
+        );
+    }
+}
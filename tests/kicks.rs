@@ -0,0 +1,141 @@
+mod harness;
+
+use harness::Harness;
+use wgputris::action::Action;
+use wgputris::game::Game;
+use wgputris::kicks::{KickTable, EXAMPLE_TABLE};
+use wgputris::replay::{Playback, Replay};
+use wgputris::stats::Stats;
+use wgputris::tetromino::TetrominoKind::*;
+
+/// Turns an upright I against the left wall, which needs two cells of room to lie flat.
+const I_AT_THE_WALL: &str = "cw left*9";
+
+#[test]
+fn shapes_turn_in_place_without_kicks() {
+    let mut h = Harness::new(&[], I, &[O, O]);
+    h.game.set_kick_table(KickTable::none());
+    h.run(I_AT_THE_WALL);
+    let upright = h.shape_cells();
+    assert!(!h.game.attempt_rotate_cw());
+    assert_eq!(h.shape_cells(), upright);
+}
+
+#[test]
+fn kick_tables_move_turning_shapes_off_the_wall() {
+    let mut h = Harness::new(&[], I, &[O, O]);
+    h.run(I_AT_THE_WALL);
+    // The example only kicks one cell, which isn't enough
+    h.game
+        .set_kick_table(KickTable::parse(EXAMPLE_TABLE).unwrap());
+    assert!(!h.game.attempt_rotate_cw());
+    h.game
+        .set_kick_table(KickTable::parse("[I]\n\"R>2\" = [[0, 0], [1, 0], [2, 0]]").unwrap());
+    assert!(h.game.attempt_rotate_cw());
+    assert_eq!(h.shape_cells(), [(0, 1), (1, 1), (2, 1), (3, 1)]);
+    assert_eq!(h.game.debug_info().shape.rotation(), 2);
+}
+
+#[test]
+fn kicks_are_written_with_y_up_and_shapes_override_the_default() {
+    let table = "[t]\n\"0>R\" = [[0, 1]]\n\n[default]\n\"0>R\" = [[0, -1]]";
+    let mut h = Harness::new(&[], T, &[O, O]);
+    h.game.set_kick_table(KickTable::parse(table).unwrap());
+    let (x, y) = h.game.debug_info().position;
+    assert!(h.game.attempt_rotate_cw());
+    assert_eq!(h.game.debug_info().position, (x, y - 1));
+}
+
+#[test]
+fn invalid_kick_tables_are_rejected() {
+    for table in [
+        "[X]\n\"0>R\" = [[0, 0]]",
+        "[T]\n\"0>2\" = [[0, 0]]",
+        "[T]\n\"0-R\" = [[0, 0]]",
+        "[T]\n\"0>R\" = []",
+    ] {
+        assert!(KickTable::parse(table).is_err(), "{}", table);
+    }
+}
+
+#[test]
+fn replays_keep_the_kick_table() {
+    let mut h = Harness::new(&[], I, &[O, O]);
+    h.game
+        .set_kick_table(KickTable::parse("[default]\n\"R>2\" = [[0, 0], [2, 0]]").unwrap());
+    h.run(&format!("{} cw drop", I_AT_THE_WALL));
+    h.assert_board(&["####......"]);
+
+    let path = std::env::temp_dir().join(format!("wgputris-kicks-{}.replay", std::process::id()));
+    h.game.replay().save_to(&path).unwrap();
+    let replay = Replay::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(replay.kicks, h.game.replay().kicks);
+    let mut playback = Playback::new(replay.clone()).unwrap();
+    playback.advance_to(replay.length_ms);
+    assert_eq!(playback.game().to_fumen(), h.game.to_fumen());
+    assert!(replay.verify(h.stats.score).is_err());
+}
+
+#[test]
+fn replays_without_kicks_play_back_without_kicks() {
+    let mut h = Harness::new(&[], I, &[O, O]);
+    h.game.set_kick_table(KickTable::none());
+    // The second turn would be kicked off the wall with SRS
+    h.run(&format!("{} cw drop", I_AT_THE_WALL));
+    h.assert_board(&["#........."; 4]);
+
+    let path =
+        std::env::temp_dir().join(format!("wgputris-no-kicks-{}.replay", std::process::id()));
+    h.game.replay().save_to(&path).unwrap();
+    let replay = Replay::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(replay.kicks, None);
+    let mut playback = Playback::new(replay.clone()).unwrap();
+    playback.advance_to(replay.length_ms);
+    assert_eq!(playback.game().to_fumen(), h.game.to_fumen());
+}
+
+#[test]
+fn srs_kicks_the_i_two_cells_off_the_wall() {
+    let mut h = Harness::new(&[], I, &[O, O]);
+    h.run(I_AT_THE_WALL);
+    // One cell to the left doesn't fit, so the next offset, two to the right, is taken
+    assert!(h.game.attempt_rotate_cw());
+    assert_eq!(h.shape_cells(), [(0, 1), (1, 1), (2, 1), (3, 1)]);
+}
+
+#[test]
+fn srs_kicks_other_shapes_from_their_own_table() {
+    let mut h = Harness::new(&[], T, &[O, O]);
+    h.run("cw left*9");
+    let (x, y) = h.game.debug_info().position;
+    assert_eq!(x, 0);
+    // Lying flat needs a cell left of the wall, so it's kicked a cell right
+    assert!(h.game.attempt_rotate_ccw());
+    assert_eq!(h.game.debug_info().position, (x + 1, y));
+    assert_eq!(h.game.debug_info().shape.rotation(), 0);
+    // The O turns in place however it's blocked
+    let mut h = Harness::new(&["#.........", "#........."], O, &[O, O]);
+    h.run("left*9 down*20");
+    let cells = h.shape_cells();
+    assert!(h.game.attempt_rotate_cw());
+    assert_eq!(h.shape_cells(), cells);
+}
+
+#[test]
+fn games_start_with_srs_kicks_and_can_be_verified() {
+    let mut game = Game::with_seed(0);
+    let mut stats = Stats::new();
+    for action in [Action::RotateCw, Action::MoveLeft, Action::RotateCw] {
+        for _ in 0..5 {
+            game.process_action(action);
+        }
+        game.process_action(Action::HardDrop);
+        game.advance(100);
+        game.dispatch_events(&mut [&mut stats]);
+    }
+    let replay = game.replay();
+    assert_eq!(replay.kicks.as_ref(), Some(KickTable::srs()));
+    assert_eq!(replay.verify(stats.score).unwrap().score, stats.score);
+}
@@ -0,0 +1,452 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+use wgputris::action::Action;
+use wgputris::attack::AttackTable;
+use wgputris::game::{self, Game};
+use wgputris::kicks::KickTable;
+use wgputris::lobby::Lobby;
+use wgputris::netplay::{
+    step_input_delay, Connection, Handshake, Host, InputDelay, Message, MAX_INPUT_DELAY,
+};
+use wgputris::replay::{Input, Playback};
+
+/// Hosts on free ports and joins over TCP, or a WebSocket if `web` is set, returning
+/// the host's and joiner's ends.
+fn connect(web: bool) -> (Connection, Connection) {
+    let host = Host::listen(0, 0).unwrap();
+    let address = if web {
+        format!("ws://127.0.0.1:{}", host.web_port())
+    } else {
+        format!("127.0.0.1:{}", host.port())
+    };
+    // Joining over a WebSocket waits for the host's half of the handshake
+    let joiner = std::thread::spawn(move || Connection::join(&address).unwrap());
+    loop {
+        if let Some(connection) = host.accept().unwrap() {
+            return (connection, joiner.join().unwrap());
+        }
+    }
+}
+
+/// Receives until at least `count` messages have arrived.
+fn receive(connection: &mut Connection, count: usize) -> Vec<Message> {
+    let mut messages = Vec::new();
+    while messages.len() < count {
+        messages.extend(connection.receive().unwrap());
+    }
+    messages
+}
+
+/// Sends a message of each kind from the host and checks the joiner gets them all.
+fn exchange(web: bool) {
+    let (mut host, mut joiner) = connect(web);
+    let sent = vec![
+        Message::Start { seed: 42 },
+        Message::Input(16, Input::Action(Action::MoveLeft)),
+        Message::Input(33, Input::SoftDropFactor(20)),
+        Message::Input(50, Input::InitialActions(vec![Action::RotateCw])),
+        Message::Time(66),
+        Message::Hash(1000, 0x0123_4567_89ab_cdef),
+    ];
+    for message in &sent {
+        host.send(message);
+    }
+    host.receive().unwrap();
+    assert_eq!(receive(&mut joiner, sent.len()), sent);
+}
+
+#[test]
+fn messages_arrive_in_order() {
+    exchange(false);
+}
+
+#[test]
+fn messages_arrive_in_order_over_websockets() {
+    exchange(true);
+}
+
+#[test]
+fn receiving_fails_once_the_other_player_leaves() {
+    let (host, mut joiner) = connect(false);
+    drop(host);
+    let error = loop {
+        if let Err(e) = joiner.receive() {
+            break e;
+        }
+    };
+    assert_eq!(error.to_string(), "The other player left");
+}
+
+#[test]
+fn clients_speaking_another_protocol_version_are_refused() {
+    let host = Host::listen(0, 0).unwrap();
+    let mut other = TcpStream::connect(("127.0.0.1", host.port())).unwrap();
+    let mut handshake = Handshake::local();
+    handshake.version += 1;
+    writeln!(other, "{}", Message::Hello(handshake).to_line()).unwrap();
+    let mut connection = loop {
+        if let Some(connection) = host.accept().unwrap() {
+            break connection;
+        }
+    };
+    let error = loop {
+        if let Err(e) = connection.receive() {
+            break e;
+        }
+    };
+    assert!(error.to_string().contains("protocol version"));
+    // After the host's own handshake, the other end is told why
+    let line = BufReader::new(other).lines().nth(1).unwrap().unwrap();
+    assert_eq!(
+        Message::parse(&line).unwrap(),
+        Message::Refused(error.to_string())
+    );
+}
+
+#[test]
+fn the_rules_cover_kicks_holding_speed_and_attacks() {
+    let rules = game::rules();
+    let kicks = serde_json::to_string(KickTable::srs()).unwrap();
+    let attack = serde_json::to_string(&AttackTable::classic()).unwrap();
+    assert!(rules.contains(&format!("kicks {}", kicks)), "{}", rules);
+    assert!(rules.contains(&format!("attack {}", attack)), "{}", rules);
+    assert!(rules.contains("hold once_per_piece"), "{}", rules);
+    assert!(rules.contains("speed curve null"), "{}", rules);
+}
+
+/// Runs the lobby until `client` receives a message matching `wanted`.
+///
+/// # Return Value
+///
+/// Every message the client received, up to and including the wanted one.
+fn wait_for(
+    lobby: &mut Lobby,
+    client: &mut Connection,
+    wanted: impl Fn(&Message) -> bool,
+) -> Vec<Message> {
+    let mut messages = Vec::new();
+    while !messages.iter().any(&wanted) {
+        lobby.update();
+        messages.extend(client.receive().unwrap());
+    }
+    messages
+}
+
+#[test]
+fn lobby_pairs_players_relays_their_games_and_lets_them_rejoin() {
+    let mut lobby = Lobby::listen(0, 0).unwrap();
+    let address = format!("127.0.0.1:{}", lobby.host().port());
+    let mut alice = Connection::join(&address).unwrap();
+    let mut bob = Connection::join(&address).unwrap();
+    wait_for(&mut lobby, &mut alice, |m| *m == Message::Lobby);
+    wait_for(&mut lobby, &mut bob, |m| *m == Message::Lobby);
+
+    alice.send(&Message::Create);
+    let entered = wait_for(&mut lobby, &mut alice, |m| {
+        matches!(m, Message::Entered { .. })
+    });
+    let (room, token) = match entered.last() {
+        Some(Message::Entered { room, token }) => (*room, *token),
+        _ => unreachable!(),
+    };
+    bob.send(&Message::List);
+    let listed = wait_for(&mut lobby, &mut bob, |m| *m == Message::Listed);
+    assert!(listed.contains(&Message::Room {
+        room,
+        players: 1,
+        rating: 1500
+    }));
+
+    bob.send(&Message::Quick);
+    wait_for(&mut lobby, &mut bob, |m| *m == Message::Joined);
+    wait_for(&mut lobby, &mut alice, |m| *m == Message::Joined);
+    alice.send(&Message::Ready);
+    bob.send(&Message::Ready);
+    bob.receive().unwrap();
+    let start = wait_for(&mut lobby, &mut alice, |m| {
+        matches!(m, Message::Start { .. })
+    });
+    assert_eq!(
+        wait_for(&mut lobby, &mut bob, |m| matches!(m, Message::Start { .. })).last(),
+        start.last()
+    );
+
+    alice.send(&Message::Chat(String::from("good luck")));
+    alice.receive().unwrap();
+    let chat = Message::Chat(String::from("good luck"));
+    wait_for(&mut lobby, &mut bob, |m| *m == chat);
+
+    let input = Message::Input(100, Input::Action(Action::HardDrop));
+    alice.send(&input);
+    alice.send(&Message::Time(200));
+    alice.receive().unwrap();
+    wait_for(&mut lobby, &mut bob, |m| *m == Message::Time(200));
+
+    drop(alice);
+    wait_for(&mut lobby, &mut bob, |m| *m == Message::Away);
+    let mut alice = Connection::join(&address).unwrap();
+    bob.send(&Message::Time(300));
+    bob.receive().unwrap();
+    alice.send(&Message::Rejoin { room, token });
+    let rejoined = wait_for(&mut lobby, &mut alice, |m| *m == Message::Time(300));
+    assert!(rejoined.contains(&Message::Rejoined(1)));
+    wait_for(&mut lobby, &mut bob, |m| *m == Message::Back);
+}
+
+#[test]
+fn lobby_decides_ranked_matches_and_updates_both_ratings() {
+    let mut lobby = Lobby::listen(0, 0).unwrap();
+    let address = format!("127.0.0.1:{}", lobby.host().port());
+    let mut alice = Connection::join(&address).unwrap();
+    let mut bob = Connection::join(&address).unwrap();
+    for (player, id) in [(&mut alice, 1), (&mut bob, 2)] {
+        player.send(&Message::Profile(id));
+        player.receive().unwrap();
+        wait_for(&mut lobby, player, |m| *m == Message::Rating(1500));
+    }
+    alice.send(&Message::Create);
+    alice.receive().unwrap();
+    wait_for(&mut lobby, &mut alice, |m| {
+        matches!(m, Message::Entered { .. })
+    });
+    bob.send(&Message::Quick);
+    bob.receive().unwrap();
+    wait_for(&mut lobby, &mut bob, |m| *m == Message::Joined);
+    alice.send(&Message::Ready);
+    bob.send(&Message::Ready);
+    bob.receive().unwrap();
+    let seed = match wait_for(&mut lobby, &mut alice, |m| {
+        matches!(m, Message::Start { .. })
+    })
+    .last()
+    {
+        Some(Message::Start { seed }) => *seed,
+        _ => unreachable!(),
+    };
+
+    // Alice hard drops every shape straight away and soon tops out
+    let mut game = Game::with_seed(seed);
+    while !game.game_over {
+        game.process_action(Action::HardDrop);
+        game.advance(1);
+    }
+    for (time_ms, input) in &game.replay().inputs {
+        alice.send(&Message::Input(*time_ms, input.clone()));
+    }
+    alice.send(&Message::Time(game.time_ms()));
+    alice.receive().unwrap();
+    bob.send(&Message::Time(game.time_ms()));
+    bob.receive().unwrap();
+    let won = wait_for(&mut lobby, &mut bob, |m| matches!(m, Message::Rating(_)));
+    assert_eq!(won.last(), Some(&Message::Rating(1516)));
+    let lost = wait_for(&mut lobby, &mut alice, |m| matches!(m, Message::Rating(_)));
+    assert_eq!(lost.last(), Some(&Message::Rating(1484)));
+}
+
+#[test]
+fn lobby_starts_a_rematch_once_both_players_ask() {
+    let mut lobby = Lobby::listen(0, 0).unwrap();
+    let address = format!("127.0.0.1:{}", lobby.host().port());
+    let mut alice = Connection::join(&address).unwrap();
+    let mut bob = Connection::join(&address).unwrap();
+    alice.send(&Message::Create);
+    alice.receive().unwrap();
+    wait_for(&mut lobby, &mut alice, |m| {
+        matches!(m, Message::Entered { .. })
+    });
+    bob.send(&Message::Quick);
+    bob.receive().unwrap();
+    wait_for(&mut lobby, &mut bob, |m| *m == Message::Joined);
+    alice.send(&Message::Ready);
+    bob.send(&Message::Ready);
+    bob.receive().unwrap();
+    let seed = match wait_for(&mut lobby, &mut alice, |m| {
+        matches!(m, Message::Start { .. })
+    })
+    .last()
+    {
+        Some(Message::Start { seed }) => *seed,
+        _ => unreachable!(),
+    };
+    wait_for(&mut lobby, &mut bob, |m| matches!(m, Message::Start { .. }));
+
+    // Alice hard drops every shape straight away and soon tops out, while Bob asks for
+    // a rematch too early, which is ignored
+    let mut game = Game::with_seed(seed);
+    while !game.game_over {
+        game.process_action(Action::HardDrop);
+        game.advance(1);
+    }
+    bob.send(&Message::Rematch);
+    bob.send(&Message::Time(game.time_ms()));
+    bob.send(&Message::Chat(String::from("gg")));
+    bob.receive().unwrap();
+    wait_for(&mut lobby, &mut alice, |m| matches!(m, Message::Chat(_)));
+    for (time_ms, input) in &game.replay().inputs {
+        alice.send(&Message::Input(*time_ms, input.clone()));
+    }
+    alice.send(&Message::Time(game.time_ms()));
+    alice.send(&Message::Rematch);
+    alice.receive().unwrap();
+    let asked = wait_for(&mut lobby, &mut bob, |m| *m == Message::Rematch);
+    assert!(!asked.iter().any(|m| matches!(m, Message::Start { .. })));
+
+    bob.send(&Message::Rematch);
+    bob.receive().unwrap();
+    let start = wait_for(&mut lobby, &mut alice, |m| {
+        matches!(m, Message::Start { .. })
+    });
+    assert!(start.contains(&Message::Rematch));
+    assert_ne!(start.last(), Some(&Message::Start { seed }));
+    assert_eq!(
+        wait_for(&mut lobby, &mut bob, |m| matches!(m, Message::Start { .. })).last(),
+        start.last()
+    );
+}
+
+#[test]
+fn spectators_watch_lobby_matches_behind_the_players() {
+    let delay = Duration::from_millis(300);
+    let mut lobby = Lobby::listen(0, 0).unwrap();
+    lobby.set_spectator_delay(delay);
+    let address = format!("127.0.0.1:{}", lobby.host().port());
+    let mut alice = Connection::join(&address).unwrap();
+    let mut bob = Connection::join(&address).unwrap();
+    let mut carol = Connection::join(&address).unwrap();
+    alice.send(&Message::Create);
+    alice.receive().unwrap();
+    let room = match wait_for(&mut lobby, &mut alice, |m| {
+        matches!(m, Message::Entered { .. })
+    })
+    .last()
+    {
+        Some(Message::Entered { room, .. }) => *room,
+        _ => unreachable!(),
+    };
+    bob.send(&Message::Enter(room));
+    bob.receive().unwrap();
+    wait_for(&mut lobby, &mut bob, |m| *m == Message::Joined);
+    alice.send(&Message::Ready);
+    bob.send(&Message::Ready);
+    bob.receive().unwrap();
+    let start = wait_for(&mut lobby, &mut alice, |m| {
+        matches!(m, Message::Start { .. })
+    });
+    let input = Message::Input(100, Input::Action(Action::HardDrop));
+    alice.send(&input);
+    alice.receive().unwrap();
+    wait_for(&mut lobby, &mut bob, |m| *m == input);
+    let sent = Instant::now();
+
+    carol.send(&Message::List);
+    carol.receive().unwrap();
+    let listed = wait_for(&mut lobby, &mut carol, |m| *m == Message::Listed);
+    assert!(listed.contains(&Message::Playing(room)));
+    carol.send(&Message::Watch(room));
+    carol.receive().unwrap();
+    let watching = wait_for(&mut lobby, &mut carol, |m| matches!(m, Message::Player(..)));
+    assert!(sent.elapsed() >= delay);
+    let seed = match start.last() {
+        Some(Message::Start { seed }) => *seed,
+        _ => unreachable!(),
+    };
+    assert!(watching.contains(&Message::Watching { room, seed }));
+    assert_eq!(watching.last(), Some(&Message::Player(0, Box::new(input))));
+
+    // Once both players leave, the spectator is told the match is over
+    alice.send(&Message::Leave);
+    alice.receive().unwrap();
+    bob.send(&Message::Leave);
+    bob.receive().unwrap();
+    wait_for(&mut lobby, &mut carol, |m| *m == Message::Leave);
+}
+
+#[test]
+fn input_delay_holds_inputs_back_and_records_them_when_applied() {
+    let mut game = Game::with_seed(1);
+    let mut delay = InputDelay::new(3);
+    assert_eq!(delay.ms(), 50);
+    delay.push(&game, Input::Action(Action::MoveLeft));
+    delay.apply(&mut game);
+    assert!(game.replay().inputs.is_empty());
+    game.advance(49);
+    delay.apply(&mut game);
+    assert!(game.replay().inputs.is_empty());
+    game.advance(1);
+    delay.apply(&mut game);
+    assert_eq!(
+        game.replay().inputs,
+        [(50, Input::Action(Action::MoveLeft))]
+    );
+
+    // Off, then every delay from 2 frames up, and back
+    assert_eq!(step_input_delay(0, 1), 2);
+    assert_eq!(step_input_delay(2, -1), 0);
+    assert_eq!(step_input_delay(0, -1), 0);
+    assert_eq!(step_input_delay(5, 1), 6);
+    assert_eq!(step_input_delay(MAX_INPUT_DELAY, 1), MAX_INPUT_DELAY);
+}
+
+#[test]
+fn input_delay_hides_jitter_up_to_the_delay() {
+    // The other player's game time reaches this side each frame, 20 to 60 ms late
+    let latencies = [20, 55, 30, 60, 25, 45, 35, 50];
+    let frame_ms = |frame: u64| frame * 1000 / game::FRAMES_PER_SECOND;
+    let steps = |delay: &InputDelay| {
+        let shown: Vec<u64> = (10..130)
+            .map(|frame| {
+                let now = frame_ms(frame);
+                let remote_ms = (0..=frame)
+                    .filter(|&sent| frame_ms(sent) + latencies[sent as usize % 8] <= now)
+                    .map(frame_ms)
+                    .max()
+                    .unwrap_or(0);
+                delay.shown_ms(now, remote_ms)
+            })
+            .collect();
+        shown
+            .windows(2)
+            .map(|pair| pair[1] - pair[0])
+            .collect::<Vec<_>>()
+    };
+    // Without a delay, or with one shorter than the jitter, the other board stalls
+    // and then jumps ahead
+    for frames in [0, 2] {
+        let steps = steps(&InputDelay::new(frames));
+        assert!(steps.contains(&0), "{} frames", frames);
+        assert!(steps.iter().any(|&step| step > 17), "{} frames", frames);
+    }
+    // A delay longer than the worst latency moves it on a frame at a time
+    let steps = steps(&InputDelay::new(4));
+    assert!(steps.iter().all(|&step| step == 16 || step == 17));
+}
+
+#[test]
+fn a_game_played_again_from_its_inputs_hashes_the_same() {
+    let mut game = Game::with_seed(7);
+    let mut hashes = Vec::new();
+    let actions = [Action::MoveLeft, Action::RotateCw, Action::HardDrop];
+    for i in 0..15 {
+        game.process_action(actions[i % actions.len()]);
+        game.set_soft_drop_factor(if i % 4 == 0 { 5 } else { 1 });
+        game.advance(100);
+        // Hashes are taken before any inputs at the same time, as they're sent
+        hashes.push((game.time_ms(), game.state_hash()));
+        if i == 10 {
+            game.add_garbage(2, 3);
+        }
+    }
+    assert!(!game.game_over);
+    let mut playback = Playback::new(game.replay().clone()).unwrap();
+    for (time_ms, hash) in hashes {
+        playback.extend_to(time_ms);
+        assert_eq!(playback.game().state_hash(), hash, "at {} ms", time_ms);
+    }
+
+    // Any difference shows
+    let mut other = Game::with_seed(7);
+    let hash = other.state_hash();
+    other.process_action(Action::MoveLeft);
+    assert_ne!(other.state_hash(), hash);
+}
@@ -0,0 +1,153 @@
+mod harness;
+
+use harness::Harness;
+use wgputris::game::{GHOST_SHAPE_ALPHA, GHOST_SHAPE_VERTICES};
+use wgputris::palette::{
+    self, Palette, Skin, ATLAS_COLUMNS, ATLAS_ROWS, OUTLINE_COLUMN, PLAIN_ROW, SYMBOL_COLUMN,
+};
+use wgputris::tetromino::TetrominoKind::{self, *};
+use wgputris::{Vertex, GAMEBOARD_HEIGHT, GAMEBOARD_WIDTH, VERTEX_COUNT};
+
+/// Renders a board with an S and a Z locked on a row of garbage.
+fn render(skin: Skin) -> Vec<Vertex> {
+    let mut h = Harness::new(&["#########."], S, &[Z, O]);
+    h.run("drop drop");
+    let mut buf = vec![bytemuck::Zeroable::zeroed(); VERTEX_COUNT];
+    h.game.render(&mut buf, 0, 1);
+    skin.apply(&mut buf);
+    buf
+}
+
+/// Returns the first corner of every block on the board drawn in a colour, ignoring
+/// alpha.
+fn corners(buf: &[Vertex], color: [f32; 4]) -> Vec<Vertex> {
+    buf[6..6 + 6 * GAMEBOARD_WIDTH * GAMEBOARD_HEIGHT]
+        .chunks(6)
+        .map(|quad| quad[0])
+        .filter(|vertex| vertex.color[..3] == color[..3])
+        .collect()
+}
+
+#[test]
+fn plain_blocks_use_the_first_block_of_the_atlas() {
+    let skin = Skin {
+        palette: Palette::Classic,
+        patterns: false,
+        high_contrast: false,
+    };
+    let buf = render(skin);
+    assert_eq!(
+        buf[2].tex_coords,
+        [
+            GAMEBOARD_WIDTH as f32 / ATLAS_COLUMNS as f32,
+            20.0 / ATLAS_ROWS as f32
+        ]
+    );
+    let s = corners(&buf, Palette::Classic.color(S));
+    assert_eq!(s.len(), 4);
+    assert!(s.iter().all(|vertex| vertex.tex_coords == [0.0, 0.0]));
+}
+
+#[test]
+fn palettes_recolour_shapes_but_not_garbage() {
+    let skin = Skin {
+        palette: Palette::Colorblind,
+        patterns: false,
+        high_contrast: false,
+    };
+    let buf = render(skin);
+    assert!(corners(&buf, Palette::Classic.color(S)).is_empty());
+    assert_eq!(corners(&buf, Palette::Colorblind.color(S)).len(), 4);
+    assert_eq!(corners(&buf, Palette::Colorblind.color(Z)).len(), 4);
+    assert_eq!(corners(&buf, [0.5, 0.5, 0.5, 1.0]).len(), 9);
+}
+
+#[test]
+fn symbols_give_each_kind_its_own_block() {
+    let skin = Skin {
+        palette: Palette::Monochrome,
+        patterns: true,
+        high_contrast: false,
+    };
+    let buf = render(skin);
+    let tile = |kind| corners(&buf, Palette::Monochrome.color(kind))[0].tex_coords;
+    let column = GAMEBOARD_WIDTH as f32 / ATLAS_COLUMNS as f32;
+    assert_eq!(tile(S), [column, 2.0 / ATLAS_ROWS as f32]);
+    assert_eq!(tile(Z), [column, 3.0 / ATLAS_ROWS as f32]);
+    // Garbage stays plain
+    assert_eq!(
+        corners(&buf, [0.5, 0.5, 0.5, 1.0])[0].tex_coords,
+        [0.0, 0.0]
+    );
+}
+
+#[test]
+fn high_contrast_outlines_blocks_on_a_solid_background() {
+    let skin = Skin {
+        palette: Palette::Classic,
+        patterns: false,
+        high_contrast: true,
+    };
+    let buf = render(skin);
+    let solid = [
+        (SYMBOL_COLUMN as f32 + 0.5) / ATLAS_COLUMNS as f32,
+        (PLAIN_ROW as f32 + 0.5) / ATLAS_ROWS as f32,
+    ];
+    assert!(buf[..6].iter().all(|vertex| vertex.tex_coords == solid));
+    assert_eq!(buf[0].color, [0.0, 0.0, 0.0, 1.0]);
+    // Shapes are recoloured whatever the palette, and garbage is outlined too
+    assert!(corners(&buf, Palette::Classic.color(Z)).is_empty());
+    let outlined = [
+        OUTLINE_COLUMN as f32 / ATLAS_COLUMNS as f32,
+        PLAIN_ROW as f32 / ATLAS_ROWS as f32,
+    ];
+    assert_eq!(corners(&buf, [0.5, 0.5, 0.5, 1.0])[0].tex_coords, outlined);
+    // The ghost of the current shape is more solid, to stand out on the black
+    let ghost = &buf[GHOST_SHAPE_VERTICES];
+    assert!(ghost
+        .iter()
+        .any(|vertex| vertex.position != ghost[0].position));
+    assert!(ghost
+        .iter()
+        .all(|vertex| vertex.color[3] > GHOST_SHAPE_ALPHA));
+}
+
+#[test]
+fn every_symbol_is_different() {
+    let mask = |kind: TetrominoKind| -> Vec<bool> {
+        (0..256)
+            .map(|i| {
+                let (x, y) = ((i % 16) as f32 / 8.0 - 0.97, (i / 16) as f32 / 8.0 - 0.97);
+                palette::symbol_covers(kind, x, y)
+            })
+            .collect()
+    };
+    for (i, &a) in TetrominoKind::ALL.iter().enumerate() {
+        assert!(mask(a).contains(&true), "{:?} has no symbol", a);
+        for &b in &TetrominoKind::ALL[i + 1..] {
+            assert_ne!(mask(a), mask(b), "{:?} and {:?} look the same", a, b);
+        }
+    }
+}
+
+#[test]
+fn the_atlas_tiles_the_block_and_stamps_symbols_beside_it() {
+    let block: Vec<u8> = (0..16 * 16).flat_map(|_| [200, 100, 50, 255]).collect();
+    let (atlas, width, height) = palette::block_atlas(&block, 16, 16);
+    assert_eq!(
+        (width, height),
+        (16 * ATLAS_COLUMNS as u32, 16 * ATLAS_ROWS as u32)
+    );
+    let pixel = |x: u32, y: u32| &atlas[4 * (y * width + x) as usize..][..4];
+    assert_eq!(pixel(5, 300), [200, 100, 50, 255]);
+    // The middle of the I's bar is darkened, while the block's edge isn't
+    let i_row = 16;
+    assert_eq!(
+        pixel(16 * GAMEBOARD_WIDTH as u32 + 8, i_row + 8),
+        [80, 40, 20, 255]
+    );
+    assert_eq!(
+        pixel(16 * GAMEBOARD_WIDTH as u32, i_row),
+        [200, 100, 50, 255]
+    );
+}
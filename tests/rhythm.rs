@@ -0,0 +1,109 @@
+mod harness;
+
+use harness::Harness;
+use wgputris::game::GameEvent;
+use wgputris::replay::Playback;
+use wgputris::rhythm::{vorbis_bpm, Rhythm, ON_BEAT_BONUS};
+use wgputris::tetromino::TetrominoKind::*;
+
+/// A game on a prepared board with a beat every half second.
+fn rhythm_game(rows: &[&str]) -> Harness {
+    let mut h = Harness::new(rows, O, &[O, O, O]);
+    h.game.set_rhythm(Rhythm::new(120.0));
+    h
+}
+
+/// Returns how many shapes gravity has locked so far.
+fn gravity_locks(h: &Harness) -> usize {
+    h.events()
+        .iter()
+        .filter(|event| **event == GameEvent::PieceLocked { hard_drop: false })
+        .count()
+}
+
+#[test]
+fn gravity_moves_shapes_on_the_beat() {
+    let mut h = rhythm_game(&[]);
+    let start = h.shape_cells();
+    // Normal gravity at level 1 would have moved the shape by now
+    h.run("wait:499");
+    assert_eq!(h.shape_cells(), start);
+    h.run("wait:1");
+    let moved: Vec<(i32, i32)> = start.iter().map(|&(x, y)| (x, y + 1)).collect();
+    assert_eq!(h.shape_cells(), moved);
+}
+
+#[test]
+fn shapes_on_the_stack_wait_for_the_beat_to_lock() {
+    let mut h = rhythm_game(&[]);
+    h.run("down*25 wait:499");
+    assert_eq!(gravity_locks(&h), 0);
+    h.run("wait:1");
+    assert_eq!(gravity_locks(&h), 1);
+    h.assert_board(&["....##....", "....##...."]);
+}
+
+#[test]
+fn hard_drops_on_the_beat_score_a_bonus() {
+    let mut h = rhythm_game(&[]);
+    h.run("wait:250 drop");
+    assert_eq!(h.game.get_score(), 0);
+    h.run("wait:740 drop");
+    assert_eq!(h.game.get_score(), ON_BEAT_BONUS);
+}
+
+#[test]
+fn gravity_speeds_up_by_doubling_the_rows_each_beat() {
+    let rhythm = Rhythm::new(120.0);
+    let rows: Vec<usize> = [1, 4, 5, 9, 13, 40]
+        .iter()
+        .map(|&level| rhythm.rows_per_beat(level))
+        .collect();
+    assert_eq!(rows, [1, 1, 2, 4, 8, 8]);
+    // Only every other row at level 5 is on the beat
+    assert_eq!(rhythm.pulse(250, 5), Some(false));
+    assert_eq!(rhythm.pulse(500, 5), Some(true));
+    assert_eq!(rhythm.pulse(501, 5), None);
+}
+
+#[test]
+fn replays_keep_the_beat() {
+    let mut h = rhythm_game(&["#########."]);
+    h.run("wait:490 drop wait:1200 right*4 wait:300 drop wait:2000");
+    let replay = h.game.replay().clone();
+    assert_eq!(replay.bpm, Some(120.0));
+    let mut playback = Playback::new(replay.clone()).unwrap();
+    playback.advance_to(replay.length_ms);
+    assert_eq!(playback.game().state_hash(), h.game.state_hash());
+    assert_eq!(playback.game().get_score(), h.game.get_score());
+}
+
+#[test]
+fn the_game_is_nudged_into_time_with_the_music() {
+    let rhythm = Rhythm::new(120.0);
+    assert_eq!(rhythm.sync_speed(1000.0, 1000.0), 1.0);
+    // Music ahead catches the game up, and music behind slows it down
+    assert!(rhythm.sync_speed(1000.0, 1020.0) > 1.0);
+    assert!(rhythm.sync_speed(1000.0, 980.0) < 1.0);
+    // A whole number of beats apart is in time, e.g. after the music loops
+    assert_eq!(rhythm.sync_speed(1000.0, 40_000.0), 1.0);
+    assert_eq!(rhythm.sync_speed(1000.0, 1200.0), 1.05);
+}
+
+#[test]
+fn tempo_is_read_from_vorbis_comments() {
+    let mut bytes = b"OggS...\x01vorbis...".to_vec();
+    bytes.extend_from_slice(b"\x03vorbis");
+    let fields: [&[u8]; 3] = [b"encoder", b"ARTIST=Someone", b"bpm=128.5"];
+    bytes.extend_from_slice(&(fields[0].len() as u32).to_le_bytes());
+    bytes.extend_from_slice(fields[0]);
+    bytes.extend_from_slice(&2u32.to_le_bytes());
+    for field in &fields[1..] {
+        bytes.extend_from_slice(&(field.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(field);
+    }
+    assert_eq!(vorbis_bpm(&bytes), Some(128.5));
+    // Cut off part way through the tag
+    assert_eq!(vorbis_bpm(&bytes[..bytes.len() - 2]), None);
+    assert_eq!(vorbis_bpm(b"RIFF....WAVEfmt "), None);
+}
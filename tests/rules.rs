@@ -0,0 +1,628 @@
+mod harness;
+
+use harness::{Harness, QUEUE};
+use std::time::Duration;
+use wgputris::action::Action;
+use wgputris::clock::MockClock;
+use wgputris::game::{
+    previews_bottom, ClearKind, EventHandler, Game, GameEvent, HoldLimit, GHOST_SHAPE_ALPHA,
+    GHOST_SHAPE_VERTICES, MAX_PREVIEWS, ROTATION_ANIMATION_MS, SPAWN_ANIMATION_MS,
+};
+use wgputris::kicks::KickTable;
+use wgputris::replay::{Input, Playback, Replay};
+use wgputris::sequence::Sequence;
+use wgputris::stats::Stats;
+use wgputris::tetromino::Tetromino;
+use wgputris::tetromino::TetrominoKind::*;
+use wgputris::{
+    Vertex, BLOCK_SIZE, GAMEBOARD_HEIGHT, GAMEBOARD_OFFSET, GAMEBOARD_WIDTH, VERTEX_COUNT,
+};
+
+#[test]
+fn hard_drop_lands_on_the_floor() {
+    let mut h = Harness::new(&[], O, &QUEUE);
+    h.run("drop");
+    h.assert_board(&["....##....", "....##...."]);
+    assert_eq!(h.stats.pieces, 1);
+}
+
+#[test]
+fn shapes_spawn_flat_and_centred() {
+    let spawns = [
+        (O, vec![(4, -1), (4, 0), (5, -1), (5, 0)]),
+        (I, vec![(3, 0), (4, 0), (5, 0), (6, 0)]),
+        (T, vec![(3, 0), (4, -1), (4, 0), (5, 0)]),
+        (S, vec![(3, 0), (4, -1), (4, 0), (5, -1)]),
+        (Z, vec![(3, -1), (4, -1), (4, 0), (5, 0)]),
+        (L, vec![(3, 0), (4, 0), (5, -1), (5, 0)]),
+        (J, vec![(3, -1), (3, 0), (4, 0), (5, 0)]),
+    ];
+    for (kind, cells) in spawns.iter() {
+        let h = Harness::new(&[], *kind, &QUEUE);
+        assert_eq!(&h.shape_cells(), cells, "{:?}", kind);
+    }
+}
+
+#[test]
+fn walls_stop_moves() {
+    let mut h = Harness::new(&[], I, &QUEUE);
+    h.run("cw left*10 drop");
+    h.assert_board(&["#.........", "#.........", "#.........", "#........."]);
+}
+
+#[test]
+fn blocks_stop_moves() {
+    let rows = vec!["..#......."; 20];
+    let mut h = Harness::new(&rows, O, &QUEUE);
+    h.run("left*10");
+    assert_eq!(h.shape_cells(), vec![(3, -1), (3, 0), (4, -1), (4, 0)]);
+    h.run("drop");
+    let mut expected = vec!["..#......."; 18];
+    expected.extend(&["..###.....", "..###....."]);
+    h.assert_board(&expected);
+}
+
+#[test]
+fn shapes_may_enter_the_hidden_rows_but_not_past_the_edges() {
+    let h = Harness::new(&[], O, &QUEUE);
+    let mut shape = Tetromino::new(O);
+    for (x, y) in [(0, 0), (0, -1)] {
+        shape.set_pos(x, y);
+        assert!(h.game.is_position_legal(&shape), "illegal at {:?}", (x, y));
+    }
+    for (x, y) in [(-1, 0), (0, -2), (-20, 0), (0, -20)] {
+        shape.set_pos(x, y);
+        assert!(!h.game.is_position_legal(&shape), "legal at {:?}", (x, y));
+    }
+}
+
+#[test]
+fn gravity_moves_the_shape_down_each_tick() {
+    let mut h = Harness::new(&[], O, &QUEUE);
+    let start = h.shape_cells();
+    h.run("wait:200");
+    assert_eq!(h.shape_cells(), start);
+    h.run("wait:100");
+    let moved: Vec<_> = start.iter().map(|&(x, y)| (x, y + 1)).collect();
+    assert_eq!(h.shape_cells(), moved);
+}
+
+#[test]
+fn gravity_adds_up_fractions_of_a_cell_each_frame() {
+    // Soft drop turns level 1's 1/15 G into 8/15 G: a row every other frame
+    let mut h = Harness::new(&[], O, &QUEUE);
+    h.game.set_soft_drop_factor(8);
+    let start = h.shape_cells();
+    h.run("wait:17");
+    assert_eq!(h.shape_cells(), start);
+    h.run("wait:17");
+    let moved: Vec<_> = start.iter().map(|&(x, y)| (x, y + 1)).collect();
+    assert_eq!(h.shape_cells(), moved);
+
+    // And into 2 G: two rows every frame
+    let mut h = Harness::new(&[], O, &QUEUE);
+    h.game.set_soft_drop_factor(30);
+    h.run("wait:17");
+    let moved: Vec<_> = start.iter().map(|&(x, y)| (x, y + 2)).collect();
+    assert_eq!(h.shape_cells(), moved);
+}
+
+#[test]
+fn hard_drops_lock_once_however_strong_gravity_is() {
+    // The drop lands on the millisecond a 2 G frame starts
+    let mut h = Harness::new(&[], O, &QUEUE);
+    h.game.set_soft_drop_factor(30);
+    h.run("wait:16 drop");
+    let locks = h
+        .events()
+        .iter()
+        .filter(|event| matches!(event, GameEvent::PieceLocked { .. }))
+        .count();
+    assert_eq!(locks, 1);
+    assert_eq!(h.stats.pieces, 1);
+}
+
+#[test]
+fn game_loop_follows_the_clock() {
+    let mut h = Harness::new(&[], O, &QUEUE);
+    let clock = MockClock::new();
+    h.game.set_clock(Box::new(clock.clone()));
+    let start = h.shape_cells();
+    h.game.process_game_loop();
+    assert_eq!(h.game.time_ms(), 0);
+    clock.advance(Duration::from_millis(300));
+    h.game.process_game_loop();
+    assert_eq!(h.game.time_ms(), 300);
+    let moved: Vec<_> = start.iter().map(|&(x, y)| (x, y + 1)).collect();
+    assert_eq!(h.shape_cells(), moved);
+}
+
+#[test]
+fn speed_scales_game_time() {
+    let mut h = Harness::new(&[], O, &QUEUE);
+    let clock = MockClock::new();
+    h.game.set_clock(Box::new(clock.clone()));
+    h.game.set_speed(0.5);
+    clock.advance(Duration::from_millis(400));
+    h.game.process_game_loop();
+    assert_eq!(h.game.time_ms(), 200);
+}
+
+#[test]
+fn frame_advance_steps_a_frame_at_a_time() {
+    let mut h = Harness::new(&[], O, &QUEUE);
+    let clock = MockClock::new();
+    h.game.set_clock(Box::new(clock.clone()));
+    h.game.set_frame_advance(true);
+    clock.advance(Duration::from_millis(400));
+    h.game.process_game_loop();
+    assert_eq!(h.game.time_ms(), 0);
+    // Frames start on the first millisecond of each sixtieth of a second
+    h.game.advance_frame();
+    assert_eq!(h.game.time_ms(), 17);
+    h.game.advance_frame();
+    assert_eq!(h.game.time_ms(), 34);
+    h.game.advance_frame();
+    assert_eq!(h.game.time_ms(), 50);
+
+    // Time spent stepping isn't caught up on afterwards
+    h.game.set_frame_advance(false);
+    clock.advance(Duration::from_millis(100));
+    h.game.process_game_loop();
+    assert_eq!(h.game.time_ms(), 150);
+}
+
+#[test]
+fn single_scores_400() {
+    let mut h = Harness::new(&["###....###"], I, &QUEUE);
+    h.run("drop");
+    h.assert_board(&[]);
+    assert_eq!(h.stats.score, 400);
+    assert_eq!(h.stats.lines, 1);
+    assert!(h.events().contains(&GameEvent::LinesCleared {
+        n: 1,
+        kind: ClearKind::Single,
+        t_spin: false,
+        combo: 0,
+        perfect_clear: true,
+    }));
+}
+
+#[test]
+fn tetris_clears_four_rows() {
+    let mut h = Harness::new(
+        &[
+            "#.........",
+            "#########.",
+            "#########.",
+            "#########.",
+            "#########.",
+        ],
+        I,
+        &QUEUE,
+    );
+    h.run("cw right*4 drop");
+    h.assert_board(&["#........."]);
+    assert_eq!(h.stats.score, 1600);
+    assert_eq!(h.stats.clears, [0, 0, 0, 1]);
+}
+
+#[test]
+fn rotation_into_a_wall_is_blocked() {
+    let mut h = Harness::new(&[], I, &QUEUE);
+    h.game.set_kick_table(KickTable::none());
+    h.run("cw left*10");
+    let before = h.shape_cells();
+    h.run("cw");
+    assert_eq!(h.shape_cells(), before);
+    let rotation = h.game.debug_info().rotation.unwrap();
+    assert!(rotation.clockwise);
+    assert!(!rotation.passed);
+}
+
+#[test]
+fn rotating_four_times_returns_to_the_start() {
+    let mut h = Harness::new(&[], T, &QUEUE);
+    let start = h.shape_cells();
+    h.run("cw*4");
+    assert_eq!(h.shape_cells(), start);
+    h.run("ccw cw");
+    assert_eq!(h.shape_cells(), start);
+}
+
+#[test]
+fn i_and_o_rotate_about_their_guideline_pivots() {
+    let mut h = Harness::new(&[], O, &QUEUE);
+    let start = h.shape_cells();
+    h.run("cw");
+    assert_eq!(h.shape_cells(), start);
+    h.run("ccw*2");
+    assert_eq!(h.shape_cells(), start);
+
+    // The I shape's cells move around the corner at its pivot rather than drifting
+    let mut h = Harness::new(&[], I, &QUEUE);
+    h.run("down*4");
+    let (x, y) = h.game.debug_info().position;
+    h.run("cw");
+    let column: Vec<_> = (-1..3).map(|dy| (x + 1, y + dy)).collect();
+    assert_eq!(h.shape_cells(), column);
+    h.run("cw");
+    let row: Vec<_> = (-1..3).map(|dx| (x + dx, y + 1)).collect();
+    assert_eq!(h.shape_cells(), row);
+}
+
+#[test]
+fn hold_swaps_in_the_next_shape_once_per_drop() {
+    let mut h = Harness::new(&[], T, &[I, O, O]);
+    h.run("hold");
+    assert_eq!(h.game.hold_kind(), Some(T));
+    assert_eq!(h.game.debug_info().block_locs.len(), 4);
+    assert!(h.events().contains(&GameEvent::Spawned(I)));
+    // A second hold before the shape locks is ignored
+    h.run("hold");
+    assert_eq!(h.game.hold_kind(), Some(T));
+    h.run("drop hold");
+    assert_eq!(h.game.hold_kind(), Some(O));
+    assert_eq!(h.events().last(), Some(&GameEvent::Held));
+}
+
+#[test]
+fn hold_limits_allow_unlimited_swaps_or_none() {
+    // The held shape's first corner, greyed out while it can't be swapped back
+    let held_color = |h: &Harness| {
+        let mut buf = vec![bytemuck::Zeroable::zeroed(); VERTEX_COUNT];
+        h.game.render(&mut buf, 0, 1);
+        let quad: &[Vertex] = &buf[1254..1260];
+        quad[0].color
+    };
+    let mut h = Harness::new(&[], T, &[I, O, O]);
+    h.run("hold");
+    assert!(!h.game.can_hold());
+    assert_eq!(held_color(&h), [0.5, 0.5, 0.5, 1.0]);
+
+    let mut h = Harness::new(&[], T, &[I, O, O]);
+    h.game.set_hold_limit(HoldLimit::Unlimited);
+    h.run("hold hold hold");
+    assert_eq!(h.game.hold_kind(), Some(T));
+    assert_eq!(h.game.debug_info().shape.kind(), I);
+    assert_eq!(held_color(&h), Tetromino::new(T).color());
+
+    let mut h = Harness::new(&[], T, &[I, O, O]);
+    h.game.set_hold_limit(HoldLimit::Disabled);
+    h.run("hold drop hold");
+    assert!(!h.game.can_hold());
+    assert_eq!(h.game.hold_kind(), None);
+    assert!(!h.events().contains(&GameEvent::Held));
+
+    // Replays keep the limit they were played with
+    let path = std::env::temp_dir().join(format!("wgputris-hold-{}.replay", std::process::id()));
+    h.game.replay().save_to(&path).unwrap();
+    let replay = Replay::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(replay.hold_limit, HoldLimit::Disabled);
+    let playback = Playback::new(replay).unwrap();
+    assert_eq!(playback.game().hold_limit(), HoldLimit::Disabled);
+}
+
+#[test]
+fn new_and_swapped_shapes_fade_and_grow_in() {
+    // Opacity and width of the last block of the current and held shapes, which is
+    // below the hidden rows
+    let looks = |h: &Harness| {
+        let mut buf = vec![bytemuck::Zeroable::zeroed(); VERTEX_COUNT];
+        h.game.render(&mut buf, 0, 1);
+        h.game.animate_spawn(&mut buf);
+        let look = |quad: &[Vertex]| (quad[0].color[3], quad[2].position[0] - quad[0].position[0]);
+        (look(&buf[1224..1230]), look(&buf[1272..1278]))
+    };
+    let mut h = Harness::new(&[], O, &QUEUE);
+    h.run("hold");
+    assert_eq!(looks(&h), ((0.0, 6.0), (0.0, 6.0)));
+    h.run(&format!("wait:{}", SPAWN_ANIMATION_MS / 2));
+    assert_eq!(looks(&h), ((0.5, 9.0), (0.5, 9.0)));
+    h.run(&format!("wait:{}", SPAWN_ANIMATION_MS));
+    assert_eq!(looks(&h), ((1.0, 12.0), (1.0, 12.0)));
+    h.run("drop");
+    assert_eq!(looks(&h), ((0.0, 6.0), (1.0, 12.0)));
+}
+
+#[test]
+fn turned_shapes_swing_round_to_their_new_orientation() {
+    // Width and height of the current shape as drawn, and whether it's drawn as it is
+    let looks = |h: &Harness| {
+        let mut buf = vec![bytemuck::Zeroable::zeroed(); VERTEX_COUNT];
+        h.game.render(&mut buf, 0, 1);
+        let plain = buf[1206..1230].to_vec();
+        h.game.animate_rotation(&mut buf);
+        let extent = |axis: usize| {
+            let positions = buf[1206..1230].iter().map(|v| v.position[axis]);
+            let (min, max) = positions.fold((f32::MAX, f32::MIN), |(min, max), p| {
+                (min.min(p), max.max(p))
+            });
+            (max - min).round()
+        };
+        let unchanged = plain
+            .iter()
+            .zip(&buf[1206..1230])
+            .all(|(a, b)| a.position == b.position);
+        (extent(0), extent(1), unchanged)
+    };
+    let mut h = Harness::new(&[], I, &QUEUE);
+    h.run(&format!("down*4 wait:{} cw", SPAWN_ANIMATION_MS));
+    // Still drawn lying down, as it was before the turn, though it already stands up
+    let (width, height, unchanged) = looks(&h);
+    assert!(width > height && !unchanged);
+    let cells = h.shape_cells();
+    assert!(cells.iter().all(|&(x, _)| x == cells[0].0));
+    h.run(&format!("wait:{}", ROTATION_ANIMATION_MS / 2));
+    let (width, height, _) = looks(&h);
+    assert_eq!(width, height);
+    h.run(&format!("wait:{}", ROTATION_ANIMATION_MS / 2));
+    let (width, height, unchanged) = looks(&h);
+    assert!(height > width && unchanged);
+    // The next shape doesn't carry on the last one's turn
+    h.run("cw drop");
+    assert!(looks(&h).2);
+}
+
+#[test]
+fn the_ghost_shows_where_a_hard_drop_lands() {
+    // Top left corners of the ghost's blocks, in cells of the board
+    let ghost = |h: &Harness| {
+        let mut buf = vec![bytemuck::Zeroable::zeroed(); VERTEX_COUNT];
+        h.game.render(&mut buf, 0, 1);
+        let mut cells: Vec<(i32, i32)> = buf[GHOST_SHAPE_VERTICES]
+            .chunks(6)
+            .filter(|quad| quad[0].position != quad[2].position)
+            .map(|quad| {
+                assert_eq!(quad[0].color[3], GHOST_SHAPE_ALPHA);
+                let cell = |axis: usize, offset: usize| {
+                    (quad[0].position[axis] / BLOCK_SIZE as f32) as i32 - offset as i32
+                };
+                (cell(0, GAMEBOARD_OFFSET.0), cell(1, GAMEBOARD_OFFSET.1))
+            })
+            .collect();
+        cells.sort();
+        cells
+    };
+    let mut h = Harness::new(&["...#......", "##########"], O, &QUEUE);
+    assert_eq!(ghost(&h), [(4, 17), (4, 18), (5, 17), (5, 18)]);
+    h.run("left");
+    assert_eq!(ghost(&h), [(3, 16), (3, 17), (4, 16), (4, 17)]);
+    // Nothing is moved until the shape is dropped there
+    assert_ne!(h.shape_cells(), h.game.landing_shape().board_locs());
+    let mut landing = h.game.landing_shape().board_locs();
+    landing.sort();
+    h.run("down*20");
+    assert_eq!(h.shape_cells(), landing);
+    // Blocks under the shape itself are left out
+    assert!(ghost(&h).is_empty());
+}
+
+#[test]
+fn previews_show_the_shapes_to_come_without_dealing_them() {
+    let mut h = Harness::new(&[], O, &[I, T]);
+    let upcoming = h.game.upcoming_kinds(MAX_PREVIEWS);
+    assert_eq!(upcoming[..2], [I, T]);
+    let hash = h.game.state_hash();
+    // Bottom edges of the shapes drawn in the next shape's slot and the smaller slots
+    // after it
+    let previews = |h: &Harness, previews| {
+        let mut buf = vec![bytemuck::Zeroable::zeroed(); VERTEX_COUNT];
+        h.game.render(&mut buf, 0, previews);
+        buf[1230..1254]
+            .chunks(24)
+            .chain(buf[1278..GHOST_SHAPE_VERTICES.start].chunks(24))
+            .filter(|shape| shape[0].position != shape[2].position)
+            .map(|shape| shape.iter().map(|v| v.position[1]).fold(0.0, f32::max))
+            .collect::<Vec<f32>>()
+    };
+    assert!(previews(&h, 0).is_empty());
+    assert_eq!(previews(&h, 1).len(), 1);
+    let bottoms = previews(&h, MAX_PREVIEWS);
+    assert_eq!(bottoms.len(), MAX_PREVIEWS);
+    assert!(bottoms.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!(bottoms[MAX_PREVIEWS - 1] <= previews_bottom(MAX_PREVIEWS));
+    assert_eq!(h.game.state_hash(), hash);
+
+    // Random shapes after the queue are dealt as they were previewed
+    let mut dealt = Vec::new();
+    for _ in 0..MAX_PREVIEWS {
+        dealt.push(h.game.next_kind());
+        h.run("drop");
+    }
+    assert_eq!(dealt, upcoming);
+}
+
+#[test]
+fn spawning_into_the_stack_tops_out() {
+    let rows = vec!["....##...."; 18];
+    let mut h = Harness::new(&rows, O, &QUEUE);
+    assert!(!h.game.game_over);
+    h.run("drop");
+    assert!(h.game.game_over);
+    assert_eq!(h.events().last(), Some(&GameEvent::GameOver));
+}
+
+#[test]
+fn holding_into_the_stack_tops_out() {
+    // Garbage buries the T's spawn once it's held, though the O still fits
+    let rows = vec!["...#......"; 19];
+    let mut h = Harness::new(&rows, T, &QUEUE);
+    h.game.set_hold_limit(HoldLimit::Unlimited);
+    h.run("hold");
+    h.game.add_garbage(1, 0);
+    assert!(!h.game.game_over);
+    h.run("hold");
+    assert!(h.game.game_over);
+    assert!(h.game.topped_out());
+    assert!(h.events().contains(&GameEvent::GameOver));
+    // Only the first T spawned
+    let spawns = h
+        .events()
+        .iter()
+        .filter(|&&event| event == GameEvent::Spawned(T));
+    assert_eq!(spawns.count(), 1);
+}
+
+#[test]
+fn topped_out_boards_fill_with_grey_rows_from_the_bottom() {
+    let mut h = Harness::new(&["....##...."; 18], O, &QUEUE);
+    h.run("drop");
+    assert!(h.game.topped_out());
+    let filled_rows = |rows| {
+        let mut buf = vec![bytemuck::Zeroable::zeroed(); VERTEX_COUNT];
+        h.game.render_topped_out(&mut buf, 0, rows, 1);
+        let cells: &[Vertex] = &buf[6..6 + 6 * GAMEBOARD_WIDTH * GAMEBOARD_HEIGHT];
+        let row_is_grey = |row: &[Vertex]| {
+            row.chunks(6)
+                .all(|quad| quad[0].color == [0.5, 0.5, 0.5, 1.0])
+        };
+        let grey = cells
+            .chunks(6 * GAMEBOARD_WIDTH)
+            .rev()
+            .take_while(|row| row_is_grey(row))
+            .count();
+        // The current shape, half in the hidden rows, only shows above the filled rows
+        let shape_blocks = buf[1206..1230]
+            .chunks(6)
+            .filter(|quad| quad[0].position != quad[2].position)
+            .count();
+        (grey, shape_blocks)
+    };
+    assert_eq!(filled_rows(0), (0, 2));
+    assert_eq!(filled_rows(5), (5, 2));
+    assert_eq!(filled_rows(19), (19, 2));
+    assert_eq!(filled_rows(100), (GAMEBOARD_HEIGHT, 0));
+
+    // Ending the game some other way isn't topping out
+    let mut h = Harness::new(&[], O, &QUEUE);
+    h.game.game_over = true;
+    assert!(!h.game.topped_out());
+}
+
+#[test]
+fn locking_in_the_hidden_rows_tops_out() {
+    // Garbage pushes the O up into the hidden rows, where it locks, though the next
+    // shape has room to spawn
+    let rows = vec![".......##."; 19];
+    let mut h = Harness::new(&rows, O, &QUEUE);
+    h.run("right*3");
+    h.game.add_garbage(1, 0);
+    assert_eq!(h.shape_cells(), [(7, -2), (7, -1), (8, -2), (8, -1)]);
+    assert!(!h.game.game_over);
+    h.run("wait:1000");
+    assert!(h.game.game_over);
+    assert_eq!(h.events().last(), Some(&GameEvent::GameOver));
+}
+
+#[test]
+fn clearing_rows_brings_blocks_locked_in_the_hidden_rows_into_view() {
+    // The upright T reaches into the hidden rows, and clears the row under its top
+    let mut rows = vec!["..........", "#######.##"];
+    rows.extend(&["#########."; 18]);
+    let mut h = Harness::new(&rows, T, &QUEUE);
+    h.run("right*3 cw");
+    assert_eq!(h.shape_cells(), [(7, -1), (7, 0), (7, 1), (8, 0)]);
+    h.run("drop");
+    assert!(!h.game.game_over);
+    let mut expected = vec![".......#..", ".......##."];
+    expected.extend(&["#########."; 18]);
+    h.assert_board(&expected);
+}
+
+#[test]
+fn clearing_rows_makes_room_for_the_next_shape() {
+    // The I fills the top row where it spawned, which would block the next shape if
+    // the row weren't cleared first
+    let mut rows = vec!["###....###"];
+    rows.extend(&["#########."; 19]);
+    let mut h = Harness::new(&rows, I, &QUEUE);
+    h.run("drop");
+    assert!(!h.game.game_over);
+    h.assert_board(&["#########."; 19]);
+}
+
+#[test]
+fn sequences_deal_shapes_in_order_and_repeat() {
+    let sequence = Sequence::parse("t, i repeat").unwrap();
+    assert_eq!(sequence.to_string(), "TI repeat");
+    assert!(Sequence::parse("TX").is_err());
+    assert!(Sequence::parse("repeat").is_err());
+
+    let mut h = Harness::new(&[], O, &QUEUE);
+    h.game.set_sequence(sequence);
+    let mut dealt = Vec::new();
+    for _ in 0..4 {
+        dealt.push(h.game.debug_info().shape.kind());
+        h.run("drop");
+    }
+    assert_eq!(dealt, [T, I, T, I]);
+}
+
+#[test]
+fn verifying_a_replay_plays_it_again_to_check_the_score() {
+    let mut game = Game::with_seed(7);
+    let mut stats = Stats::new();
+    while !game.game_over {
+        if game.time_ms() % 250 == 0 {
+            game.process_action(Action::HardDrop);
+        }
+        game.advance(1);
+        game.dispatch_events(&mut [&mut stats]);
+    }
+    let replay = game.replay().clone();
+    assert!(stats.pieces > 0);
+    assert_eq!(replay.verify(stats.score).unwrap().pieces, stats.pieces);
+    assert!(replay.verify(stats.score + 1).is_err());
+
+    let mut tampered = replay.clone();
+    tampered.inputs.push((0, Input::SoftDropFactor(1000)));
+    assert!(tampered.verify(stats.score).is_err());
+}
+
+#[test]
+fn keys_are_counted_per_piece_and_per_minute() {
+    let mut h = Harness::new(&[], O, &QUEUE);
+    let mut stats = Stats::new();
+    assert_eq!(stats.keys_per_piece(), 0.0);
+    assert_eq!(stats.actions_per_minute(0), 0.0);
+    h.run("left drop right right drop");
+    for event in h.events() {
+        stats.handle_event(event);
+    }
+    stats.keys = 5;
+    assert_eq!(stats.keys_per_piece(), 2.5);
+    assert_eq!(stats.actions_per_minute(30_000), 10.0);
+}
+
+#[test]
+fn garbage_pushes_the_stack_up_with_a_hole() {
+    let mut h = Harness::new(&["##..######"], O, &QUEUE);
+    h.game.add_garbage(2, 9);
+    h.run("wait:0");
+    h.assert_board(&["##..######", "#########.", "#########."]);
+    assert!(!h.game.game_over);
+
+    h.game.add_garbage(GAMEBOARD_HEIGHT, 0);
+    h.run("wait:0");
+    assert!(h.game.game_over);
+    assert_eq!(h.events().last(), Some(&GameEvent::GameOver));
+}
+
+#[test]
+fn replays_keep_garbage_and_picked_shapes() {
+    let mut h = Harness::new(&[], O, &QUEUE);
+    h.game.set_next_kind(T);
+    assert_eq!(h.game.next_kind(), T);
+    h.run("drop");
+    assert_eq!(h.game.debug_info().shape.kind(), T);
+    h.game.add_garbage(1, 3);
+    h.run("drop wait:100");
+
+    let replay = h.game.replay().clone();
+    let mut playback = Playback::new(replay.clone()).unwrap();
+    playback.advance_to(replay.length_ms);
+    assert_eq!(playback.game().to_fumen(), h.game.to_fumen());
+    assert!(replay.verify(h.stats.score).is_err());
+}
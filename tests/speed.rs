@@ -0,0 +1,108 @@
+mod harness;
+
+use harness::Harness;
+use wgputris::action::Action;
+use wgputris::game::{GameEvent, GRAVITY_UNIT};
+use wgputris::replay::{Playback, Replay};
+use wgputris::speed::SpeedCurve;
+use wgputris::tetromino::TetrominoKind::*;
+
+/// Shapes fall to the stack at once, and rest for half a second before locking.
+const INSTANT_DROP: &str = "[[level]]\nlevel = 1\ngravity = 20\nlock_delay = 30";
+
+/// Returns the number of shapes locked so far.
+fn locked(h: &Harness) -> usize {
+    h.events()
+        .iter()
+        .filter(|event| matches!(event, GameEvent::PieceLocked { .. }))
+        .count()
+}
+
+#[test]
+fn presets_time_each_level() {
+    let nes = SpeedCurve::nes();
+    assert_eq!(nes.at(1).lock_delay, None);
+    assert_eq!(nes.at(1).are, 10);
+    let guideline = SpeedCurve::guideline();
+    assert_eq!(guideline.at(1).lock_delay, Some(30));
+    assert_eq!(guideline.at(19).gravity, 20 * GRAVITY_UNIT);
+    // Timings carry on from the last level listed
+    assert_eq!(guideline.at(99), guideline.at(19));
+    let tgm = SpeedCurve::tgm();
+    assert!(tgm.at(7).gravity < tgm.at(6).gravity);
+}
+
+#[test]
+fn invalid_speed_curves_are_rejected() {
+    for curve in [
+        "",
+        "[[level]]\nlevel = 2\ngravity = 1",
+        "[[level]]\nlevel = 1\ngravity = 1\n\n[[level]]\nlevel = 1\ngravity = 2",
+        "[[level]]\nlevel = 1\ngravity = 21",
+        "[[level]]\nlevel = 1\ngravity = -1",
+    ] {
+        assert!(SpeedCurve::parse(curve).is_err(), "{}", curve);
+    }
+}
+
+#[test]
+fn shapes_rest_on_the_stack_for_the_lock_delay() {
+    let mut h = Harness::new(&[], O, &[T, O]);
+    h.game
+        .set_speed_curve(Some(SpeedCurve::parse(INSTANT_DROP).unwrap()));
+    h.run("wait:20");
+    assert_eq!(h.shape_cells(), [(4, 18), (4, 19), (5, 18), (5, 19)]);
+    // Sliding along the stack doesn't put the lock off
+    h.run("wait:200 left wait:250");
+    assert_eq!(locked(&h), 0);
+    h.run("wait:60");
+    assert_eq!(locked(&h), 1);
+    h.assert_board(&["...##.....", "...##....."]);
+}
+
+#[test]
+fn the_next_shape_appears_after_are() {
+    let mut h = Harness::new(&[], O, &[T, O]);
+    let curve = "[[level]]\nlevel = 1\ngravity = 0\nare = 30";
+    h.game
+        .set_speed_curve(Some(SpeedCurve::parse(curve).unwrap()));
+    h.run("drop wait:400");
+    assert_eq!(h.game.debug_info().shape.kind(), O);
+    // Nothing can be done with the locked shape while waiting
+    h.run("left");
+    h.assert_board(&["....##....", "....##...."]);
+    h.run("wait:100");
+    assert_eq!(h.game.debug_info().shape.kind(), T);
+}
+
+#[test]
+fn shapes_lock_once_without_are() {
+    let mut h = Harness::new(&[], O, &[T, O]);
+    let curve = "[[level]]\nlevel = 1\ngravity = 0";
+    h.game
+        .set_speed_curve(Some(SpeedCurve::parse(curve).unwrap()));
+    // The second drop comes in the same millisecond, before the next shape appears
+    h.game.process_action(Action::HardDrop);
+    h.game.process_action(Action::HardDrop);
+    h.run("wait:1");
+    assert_eq!(locked(&h), 1);
+    h.assert_board(&["....##....", "....##...."]);
+    assert_eq!(h.game.debug_info().shape.kind(), T);
+}
+
+#[test]
+fn replays_keep_the_speed_curve() {
+    let mut h = Harness::new(&[], O, &[T, O]);
+    h.game.set_speed_curve(Some(SpeedCurve::tgm()));
+    h.run("left drop wait:600 right drop wait:600");
+
+    let path = std::env::temp_dir().join(format!("wgputris-speed-{}.replay", std::process::id()));
+    h.game.replay().save_to(&path).unwrap();
+    let replay = Replay::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(replay.speed_curve, Some(SpeedCurve::tgm()));
+    let mut playback = Playback::new(replay.clone()).unwrap();
+    playback.advance_to(replay.length_ms);
+    assert_eq!(playback.game().to_fumen(), h.game.to_fumen());
+    assert!(replay.verify(h.stats.score).is_err());
+}
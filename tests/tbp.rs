@@ -0,0 +1,83 @@
+mod harness;
+
+use harness::Harness;
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::thread;
+use wgputris::ai::{Bot, BotView, Decision};
+use wgputris::tbp::{self, ExternalBot};
+use wgputris::tetromino::TetrominoKind::*;
+
+#[test]
+fn locations_turn_about_the_centre_with_rows_counted_up() {
+    let t = tbp::cells(&json!({ "type": "T", "orientation": "north", "x": 4, "y": 0 }));
+    assert_eq!(t, Some([(3, 19), (4, 19), (5, 19), (4, 18)]));
+    let i = tbp::cells(&json!({ "type": "I", "orientation": "east", "x": 9, "y": 2 }));
+    assert_eq!(i, Some([(9, 16), (9, 17), (9, 18), (9, 19)]));
+    let bad = tbp::cells(&json!({ "type": "T", "orientation": "up", "x": 4, "y": 0 }));
+    assert_eq!(bad, None);
+}
+
+/// Accepts one connection and plays as a bot which puts every I upright in the
+/// rightmost column, returning the first `start` message it was sent.
+fn fake_bot(listener: TcpListener) -> thread::JoinHandle<Value> {
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut writer = stream.try_clone().unwrap();
+        let mut send = |message: Value| writeln!(writer, "{}", message).unwrap();
+        let mut start = Value::Null;
+        send(
+            json!({ "type": "info", "name": "Fake", "version": "1", "author": "", "features": [] }),
+        );
+        for line in BufReader::new(stream).lines() {
+            let message: Value = serde_json::from_str(&line.unwrap()).unwrap();
+            match message["type"].as_str().unwrap() {
+                "rules" => send(json!({ "type": "ready" })),
+                "start" if start.is_null() => start = message,
+                "suggest" => send(json!({
+                    "type": "suggestion",
+                    "moves": [{
+                        "location": { "type": "I", "orientation": "east", "x": 9, "y": 2 },
+                        "spin": "none",
+                    }],
+                })),
+                "quit" => break,
+                _ => {}
+            }
+        }
+        start
+    })
+}
+
+#[test]
+fn external_bots_place_shapes_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let fake = fake_bot(listener);
+    let mut h = Harness::new(
+        &["#########.", "#########.", "#########.", "#########."],
+        I,
+        &[O],
+    );
+    let mut bot = ExternalBot::open(&format!("tcp://{}", address)).expect("Failed to connect");
+    assert_eq!(bot.name(), "Fake");
+    let placement = match bot.decide(&BotView::of(&h.game)) {
+        Decision::Place(placement) => placement,
+        Decision::Actions(actions) => panic!("Fell back to {:?}", actions),
+    };
+    for action in placement.actions() {
+        h.game.process_action(action);
+    }
+    h.run("wait:1");
+    h.assert_board(&[]);
+    assert_eq!(h.stats.lines, 4);
+    drop(bot);
+    let start = fake.join().unwrap();
+    assert_eq!(start["queue"], json!(["I", "O"]));
+    let board = start["board"].as_array().unwrap();
+    assert_eq!(board.len(), 40);
+    assert_eq!(board[0][0], json!("G"));
+    assert_eq!(board[0][9], Value::Null);
+    assert_eq!(board[4][0], Value::Null);
+}